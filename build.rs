@@ -0,0 +1,30 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/* Builds in every file in resources/prefabs, so a new prefab (or a REX Paint file one draws
+   from) only has to be dropped in there */
+fn main () {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("Cargo sets CARGO_MANIFEST_DIR");
+    let prefab_dir = Path::new(&manifest_dir).join("resources").join("prefabs");
+    println!("cargo:rerun-if-changed={}", prefab_dir.display());
+
+    let mut files: Vec<(String, String)> = fs::read_dir(&prefab_dir)
+        .unwrap_or_else(|e| panic!("Can't read {}: {}", prefab_dir.display(), e))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path().display().to_string()))
+        .collect();
+    /* Name order, so the table is the same wherever it's built */
+    files.sort();
+
+    let mut table = String::from("const PREFAB_FILES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in files.iter() {
+        println!("cargo:rerun-if-changed={}", path);
+        table.push_str(&format!("    ({:?}, include_bytes!({:?})),\n", name, path));
+    };
+    table.push_str("];\n");
+
+    let out_dir = env::var("OUT_DIR").expect("Cargo sets OUT_DIR");
+    fs::write(Path::new(&out_dir).join("prefab_files.rs"), table).expect("Unable to write the prefab table");
+}
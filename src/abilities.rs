@@ -0,0 +1,340 @@
+use specs::prelude::*;
+use rltk::{Point, RGB};
+use crate::{Abilities, Ability, AbilityEffect, WantsToUseAbility, Name, Pools, Position, SufferDamage,
+    Confusion, Held, MoveMode, Movement, Map, ParticleBuilder, gamelog::GameLog, tile_walkable,
+    raws::{RAWS, SpawnType}};
+
+const PARTICLE_LIFETIME: f32 = 200.0;
+const SUMMON_FOLLOW_DISTANCE: i32 = 2;
+
+fn name_of (ecs: &World, ent: Entity) -> String {
+    ecs.read_storage::<Name>().get(ent).map_or("Something".to_string(), |n| n.name.clone())
+}
+
+fn particle (ecs: &World, x: i32, y: i32, fg: RGB, glyph: char) {
+    ecs.fetch_mut::<ParticleBuilder>().request(x, y, fg, RGB::named(rltk::BLACK), rltk::to_cp437(glyph), PARTICLE_LIFETIME);
+}
+
+/// Carries out the abilities mobs chose (or were provoked into) this turn
+pub fn use_abilities (ecs: &mut World) {
+    let pending: Vec<(Entity, usize, Point)> = (&ecs.entities(), &ecs.read_storage::<WantsToUseAbility>()).join()
+        .map(|(ent, wants)| (ent, wants.ability, Point::new(wants.x, wants.y)))
+        .collect();
+    ecs.write_storage::<WantsToUseAbility>().clear();
+
+    for (user, idx, target) in pending.iter() {
+        let ability = match ecs.read_storage::<Abilities>().get(*user).and_then(|a| a.abilities.get(*idx)) {
+            Some(ability) => ability.clone(),
+            None => continue,
+        };
+        /* Killed before it got the chance */
+        if ecs.read_storage::<Pools>().get(*user).is_none_or(|p| p.hit_points.current < 1) { continue; }
+
+        let user_name = name_of(ecs, *user);
+        ecs.fetch_mut::<GameLog>().entries.push(format!("{} uses {}!", user_name, ability.name));
+        let victims = affected(ecs, *user, &ability, *target);
+        for effect in ability.effects.iter() {
+            match effect {
+                AbilityEffect::Damage { n_dice, die_type, bonus } => {
+                    for victim in victims.iter() {
+                        let damage = i32::max(0, ecs.write_resource::<rltk::RandomNumberGenerator>().roll_dice(*n_dice, *die_type) + bonus);
+                        SufferDamage::new_dmg(&mut ecs.write_storage::<SufferDamage>(), *victim, damage, false);
+                        let victim_name = name_of(ecs, *victim);
+                        ecs.fetch_mut::<GameLog>().entries.push(format!("{} takes {} hp.", victim_name, damage));
+                        if let Some(pos) = ecs.read_storage::<Position>().get(*victim) {
+                            particle(ecs, pos.x, pos.y, RGB::named(rltk::RED), '‼');
+                        }
+                    };
+                }
+                AbilityEffect::Confuse { turns } => {
+                    for victim in victims.iter() {
+                        ecs.write_storage::<Confusion>().insert(*victim, Confusion { turns: *turns }).expect("Unable to insert");
+                        if let Some(pos) = ecs.read_storage::<Position>().get(*victim) {
+                            particle(ecs, pos.x, pos.y, RGB::named(rltk::MAGENTA), '?');
+                        }
+                    };
+                }
+                AbilityEffect::Hold { turns } => {
+                    for victim in victims.iter() {
+                        ecs.write_storage::<Held>().insert(*victim, Held { turns: *turns }).expect("Unable to insert");
+                        let victim_name = name_of(ecs, *victim);
+                        ecs.fetch_mut::<GameLog>().entries.push(format!("{} is held fast!", victim_name));
+                        if let Some(pos) = ecs.read_storage::<Position>().get(*victim) {
+                            particle(ecs, pos.x, pos.y, RGB::named(rltk::WHITE), '#');
+                        }
+                    };
+                }
+                AbilityEffect::Summon { name, count } => summon(ecs, *user, name, *count),
+                AbilityEffect::Split { mob } => split(ecs, *user, mob, &user_name),
+            }
+        };
+    };
+}
+
+/// Everything standing where the ability lands, other than its user
+fn affected (ecs: &World, user: Entity, ability: &Ability, target: Point) -> Vec<Entity> {
+    let map = ecs.fetch::<Map>();
+    let tiles = if ability.area > 0 {
+        let mut blast = rltk::field_of_view(target, ability.area, &*map);
+        blast.retain(|p| p.x > 0 && p.x < map.width-1 && p.y > 0 && p.y < map.height-1);
+        for tile in blast.iter() {
+            particle(ecs, tile.x, tile.y, RGB::named(rltk::ORANGE), '░');
+        };
+        blast
+    } else {
+        vec![target]
+    };
+
+    let mut victims = Vec::new();
+    let pools = ecs.read_storage::<Pools>();
+    for tile in tiles.iter() {
+        crate::spatial::for_each_tile_content(map.xy_idx(tile.x, tile.y), |ent| {
+            if ent != user && pools.get(ent).is_some() && !victims.contains(&ent) { victims.push(ent); }
+        });
+    };
+    victims
+}
+
+/// Free tiles around an entity, nearest first
+fn free_tiles_around (ecs: &World, center: &Position, wanted: usize) -> Vec<Point> {
+    let map = ecs.fetch::<Map>();
+    let mut free = Vec::new();
+    for radius in 1 ..= 2 {
+        for y in center.y - radius ..= center.y + radius {
+            for x in center.x - radius ..= center.x + radius {
+                if free.len() >= wanted { return free; }
+                if x < 1 || x > map.width-2 || y < 1 || y > map.height-2 { continue; }
+                let pt = Point::new(x, y);
+                let idx = map.xy_idx(x, y);
+                if tile_walkable(map.tiles[idx]) && !crate::spatial::is_blocked(idx) && !free.contains(&pt) {
+                    free.push(pt);
+                }
+            };
+        };
+    };
+    free
+}
+
+/// Calls in help, who then stick close to the summoner
+fn summon (ecs: &mut World, user: Entity, name: &str, count: i32) {
+    let center = match ecs.read_storage::<Position>().get(user) {
+        Some(pos) => pos.clone(),
+        None => return,
+    };
+    let spots = free_tiles_around(ecs, &center, count as usize);
+    /* Only the spawning needs the raws; the rest is done once they're let go */
+    let summoned: Vec<(Entity, Point)> = {
+        let raws = RAWS.lock().unwrap();
+        spots.iter()
+            .filter_map(|spot| crate::raws::spawn_named_mob(&raws, ecs, name, SpawnType::AtPosition { x: spot.x, y: spot.y })
+                .map(|summoned| (summoned, *spot)))
+            .collect()
+    };
+    for (summoned, spot) in summoned.iter() {
+        ecs.write_storage::<MoveMode>().insert(*summoned, MoveMode { mode: Movement::Follow { leader: user, distance: SUMMON_FOLLOW_DISTANCE } })
+            .expect("Unable to insert");
+        particle(ecs, spot.x, spot.y, RGB::named(rltk::CYAN), '*');
+        ecs.fetch_mut::<GameLog>().entries.push(format!("{} appears!", name));
+    };
+}
+
+/// Divides the user's remaining health between itself and a fresh copy, which picks up
+/// where the user's cooldowns are rather than with everything ready again
+fn split (ecs: &mut World, user: Entity, mob: &str, name: &str) {
+    let hp = ecs.read_storage::<Pools>().get(user).map_or(0, |p| p.hit_points.current);
+    if hp < 2 { return; }
+    let center = match ecs.read_storage::<Position>().get(user) {
+        Some(pos) => pos.clone(),
+        None => return,
+    };
+    let spot = match free_tiles_around(ecs, &center, 1).first() {
+        Some(spot) => *spot,
+        None => return,
+    };
+    let copy = crate::raws::spawn_offshoot(&RAWS.lock().unwrap(), ecs, mob, SpawnType::AtPosition { x: spot.x, y: spot.y });
+    if let Some(copy) = copy {
+        let mut pools = ecs.write_storage::<Pools>();
+        if let Some(pools) = pools.get_mut(user) {
+            pools.hit_points.current = hp - hp / 2;
+        }
+        if let Some(pools) = pools.get_mut(copy) {
+            pools.hit_points.current = hp / 2;
+        }
+        std::mem::drop(pools);
+        let mut abilities = ecs.write_storage::<Abilities>();
+        let cooldowns: Vec<i32> = abilities.get(user).map_or(Vec::new(), |a| a.abilities.iter().map(|ability| ability.cooldown_left).collect());
+        if let Some(copied) = abilities.get_mut(copy) {
+            for (ability, cooldown_left) in copied.abilities.iter_mut().zip(cooldowns.iter()) {
+                ability.cooldown_left = *cooldown_left;
+            };
+        }
+        std::mem::drop(abilities);
+        particle(ecs, spot.x, spot.y, RGB::named(rltk::GREEN), '*');
+        ecs.fetch_mut::<GameLog>().entries.push(format!("The {} splits in two!", name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AbilityTrigger, Pool, Offshoot, particle_sys::ParticleBuilder, dmg_sys::DamageSystem};
+    use crate::test_support::{lock_globals, open_map, world};
+
+    fn room () -> World {
+        let mut ecs = world(open_map(1, 16, 16));
+        ecs.insert(ParticleBuilder::new());
+        ecs
+    }
+
+    fn creature (ecs: &mut World, x: i32, y: i32, hp: i32) -> Entity {
+        let ent = ecs.create_entity()
+            .with(Position { x, y })
+            .with(Pools {
+                hit_points: Pool { max: 10, current: hp }, mana: Pool { max: 10, current: 10 },
+                xp: 0, level: 1, total_weight: 0.0, total_initiative_penalty: 0.0, gold: 0.0, god_mode: false,
+            })
+            .build();
+        crate::spatial::index_entity(ent, ecs.fetch::<Map>().xy_idx(x, y), true);
+        ent
+    }
+
+    fn web (area: i32) -> Abilities {
+        Abilities { abilities: vec![Ability {
+            name: "Web".to_string(), trigger: AbilityTrigger::Turn, chance: 1.0, cooldown: 3, cooldown_left: 0,
+            range: 6.0, area, effects: vec![AbilityEffect::Hold { turns: 2 }],
+        }]}
+    }
+
+    fn ability (name: &str, trigger: AbilityTrigger, cooldown: i32, effect: AbilityEffect) -> Abilities {
+        Abilities { abilities: vec![Ability {
+            name: name.to_string(), trigger, chance: 1.0, cooldown, cooldown_left: 0, range: 6.0, area: 0, effects: vec![effect],
+        }]}
+    }
+
+    fn cast (ecs: &mut World, user: Entity, x: i32, y: i32) {
+        ecs.write_storage::<WantsToUseAbility>().insert(user, WantsToUseAbility { ability: 0, x, y }).expect("Unable to insert");
+        use_abilities(ecs);
+    }
+
+    #[test]
+    fn single_target_abilities_hit_only_the_target () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let spider = creature(&mut ecs, 2, 2, 10);
+        let target = creature(&mut ecs, 5, 5, 10);
+        let bystander = creature(&mut ecs, 6, 5, 10);
+        ecs.write_storage::<Abilities>().insert(spider, web(0)).expect("Unable to insert");
+        cast(&mut ecs, spider, 5, 5);
+        assert!(ecs.read_storage::<Held>().get(target).is_some_and(|h| h.turns == 2));
+        assert!(ecs.read_storage::<Held>().get(bystander).is_none());
+        assert!(ecs.read_storage::<WantsToUseAbility>().is_empty());
+    }
+
+    #[test]
+    fn blasts_catch_everyone_near_but_the_user () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let spider = creature(&mut ecs, 5, 4, 10);
+        let target = creature(&mut ecs, 5, 5, 10);
+        let bystander = creature(&mut ecs, 6, 6, 10);
+        let far_away = creature(&mut ecs, 12, 12, 10);
+        ecs.write_storage::<Abilities>().insert(spider, web(2)).expect("Unable to insert");
+        cast(&mut ecs, spider, 5, 5);
+        let held = ecs.read_storage::<Held>();
+        assert!(held.get(target).is_some() && held.get(bystander).is_some());
+        assert!(held.get(spider).is_none() && held.get(far_away).is_none());
+    }
+
+    #[test]
+    fn the_dead_use_nothing () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let spider = creature(&mut ecs, 2, 2, 0);
+        let target = creature(&mut ecs, 3, 2, 10);
+        ecs.write_storage::<Abilities>().insert(spider, web(0)).expect("Unable to insert");
+        cast(&mut ecs, spider, 3, 2);
+        assert!(ecs.read_storage::<Held>().get(target).is_none());
+        assert!(ecs.fetch::<GameLog>().entries.is_empty());
+    }
+
+    #[test]
+    fn damage_lands_on_the_target () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let mage = creature(&mut ecs, 2, 2, 10);
+        let target = creature(&mut ecs, 5, 5, 10);
+        ecs.write_storage::<Abilities>().insert(mage, ability("Zap", AbilityTrigger::Turn, 3,
+            AbilityEffect::Damage { n_dice: 1, die_type: 1, bonus: 3 })).expect("Unable to insert");
+        cast(&mut ecs, mage, 5, 5);
+        assert!(ecs.read_storage::<SufferDamage>().get(target).is_some_and(|d| d.amount == vec![(4, false)]));
+        assert!(ecs.read_storage::<SufferDamage>().get(mage).is_none());
+    }
+
+    #[test]
+    fn summoned_help_appears_beside_and_follows_the_summoner () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let shaman = creature(&mut ecs, 8, 8, 10);
+        ecs.write_storage::<Abilities>().insert(shaman, ability("Call", AbilityTrigger::Turn, 20,
+            AbilityEffect::Summon { name: "Goblin".to_string(), count: 2 })).expect("Unable to insert");
+        cast(&mut ecs, shaman, 8, 8);
+        let names = ecs.read_storage::<Name>();
+        let positions = ecs.read_storage::<Position>();
+        let move_modes = ecs.read_storage::<MoveMode>();
+        let goblins: Vec<Entity> = (&ecs.entities(), &names).join().filter(|(_, n)| n.name == "Goblin").map(|(ent, _)| ent).collect();
+        assert_eq!(goblins.len(), 2);
+        for goblin in goblins.iter() {
+            let pos = positions.get(*goblin).unwrap();
+            assert!((pos.x - 8).abs() <= 1 && (pos.y - 8).abs() <= 1);
+            assert!(matches!(move_modes.get(*goblin).map(|m| &m.mode), Some(Movement::Follow { leader, .. }) if *leader == shaman));
+        };
+    }
+
+    #[test]
+    fn splitting_shares_the_health_and_the_cooldowns () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let slime = creature(&mut ecs, 8, 8, 7);
+        let mut abilities = ability("Split", AbilityTrigger::Hurt, 2, AbilityEffect::Split { mob: "Green Slime".to_string() });
+        abilities.abilities[0].cooldown_left = 2;
+        ecs.write_storage::<Abilities>().insert(slime, abilities).expect("Unable to insert");
+        cast(&mut ecs, slime, 8, 8);
+        let copy = (&ecs.entities(), &ecs.read_storage::<Offshoot>()).join().map(|(ent, _)| ent).next().expect("No copy");
+        let pools = ecs.read_storage::<Pools>();
+        assert_eq!(pools.get(slime).unwrap().hit_points.current, 4);
+        assert_eq!(pools.get(copy).unwrap().hit_points.current, 3);
+        assert_eq!(ecs.read_storage::<Abilities>().get(copy).unwrap().abilities[0].cooldown_left, 2);
+
+        /* Too weak to split any further */
+        std::mem::drop(pools);
+        let tiny = creature(&mut ecs, 2, 2, 1);
+        ecs.write_storage::<Abilities>().insert(tiny, ability("Split", AbilityTrigger::Hurt, 2,
+            AbilityEffect::Split { mob: "Green Slime".to_string() })).expect("Unable to insert");
+        cast(&mut ecs, tiny, 2, 2);
+        assert_eq!(ecs.read_storage::<Offshoot>().count(), 1);
+    }
+
+    #[test]
+    fn being_hurt_sets_off_hurt_abilities_when_ready () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let player = creature(&mut ecs, 1, 1, 10);
+        ecs.insert(player);
+        ecs.insert(Point::new(1, 1));
+        let slime = creature(&mut ecs, 8, 8, 10);
+        let resting = creature(&mut ecs, 10, 10, 10);
+        let split = || ability("Split", AbilityTrigger::Hurt, 2, AbilityEffect::Split { mob: "Green Slime".to_string() });
+        ecs.write_storage::<Abilities>().insert(slime, split()).expect("Unable to insert");
+        let mut cooling = split();
+        cooling.abilities[0].cooldown_left = 1;
+        ecs.write_storage::<Abilities>().insert(resting, cooling).expect("Unable to insert");
+        for victim in [slime, resting].iter() {
+            SufferDamage::new_dmg(&mut ecs.write_storage::<SufferDamage>(), *victim, 2, false);
+        };
+        DamageSystem {}.run_now(&ecs);
+        let wants = ecs.read_storage::<WantsToUseAbility>();
+        assert!(wants.get(slime).is_some_and(|w| w.ability == 0 && (w.x, w.y) == (8, 8)));
+        assert_eq!(ecs.read_storage::<Abilities>().get(slime).unwrap().abilities[0].cooldown_left, 2);
+        assert!(wants.get(resting).is_none());
+    }
+}
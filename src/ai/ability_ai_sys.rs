@@ -0,0 +1,73 @@
+use specs::prelude::*;
+use crate::{Abilities, AbilityTrigger, WantsToUseAbility, ChosenAbility};
+
+/// Sets off the abilities the utility AI chose this turn
+pub struct AbilityAI {}
+
+/// First ready ability that reaches something `distance` away (without catching the user in
+/// its blast) and passes its roll
+pub fn ready_ability (abilities: &Abilities, distance: f32, rng: &mut rltk::RandomNumberGenerator) -> Option<usize> {
+    abilities.abilities.iter().position(|ability| {
+        ability.trigger == AbilityTrigger::Turn
+            && ability.cooldown_left < 1
+            && distance <= ability.range
+            && (ability.area == 0 || distance > ability.area as f32 + 1.0)
+            && rng.roll_dice(1, 100) as f32 <= ability.chance * 100.0
+    })
+}
+
+impl<'a> System<'a> for AbilityAI {
+    type SystemData = (
+        WriteStorage<'a, Abilities>,
+        WriteStorage<'a, ChosenAbility>,
+        WriteStorage<'a, WantsToUseAbility>,
+        Entities<'a>,
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut abilities, mut chosen, mut wants_ability, entities) = data;
+
+        for (ent, choice, abilities) in (&entities, &chosen, &mut abilities).join() {
+            if let Some(ability) = abilities.abilities.get_mut(choice.ability) {
+                ability.cooldown_left = ability.cooldown;
+                wants_ability.insert(ent, WantsToUseAbility { ability: choice.ability, x: choice.x, y: choice.y })
+                    .expect("Unable to insert");
+            }
+        };
+        chosen.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ability;
+
+    fn ability (range: f32, area: i32, chance: f32) -> Ability {
+        Ability {
+            name: "Test".to_string(), trigger: AbilityTrigger::Turn, chance, cooldown: 5, cooldown_left: 0,
+            range, area, effects: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn picks_the_first_ability_that_reaches () {
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let abilities = Abilities { abilities: vec![ability(1.5, 0, 1.0), ability(6.0, 0, 1.0)] };
+        assert_eq!(ready_ability(&abilities, 1.0, &mut rng), Some(0));
+        assert_eq!(ready_ability(&abilities, 4.0, &mut rng), Some(1));
+        assert_eq!(ready_ability(&abilities, 8.0, &mut rng), None);
+    }
+
+    #[test]
+    fn skips_cooling_down_reactive_and_self_harming_abilities () {
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let mut abilities = Abilities { abilities: vec![ability(6.0, 0, 1.0), ability(6.0, 2, 1.0), ability(6.0, 0, 0.0)] };
+        abilities.abilities[0].cooldown_left = 1;
+        /* Too close for the blast */
+        assert_eq!(ready_ability(&abilities, 3.0, &mut rng), None);
+        assert_eq!(ready_ability(&abilities, 4.0, &mut rng), Some(1));
+        abilities.abilities[1].trigger = AbilityTrigger::Hurt;
+        assert_eq!(ready_ability(&abilities, 4.0, &mut rng), None);
+    }
+}
@@ -1,5 +1,6 @@
 use specs::prelude::*;
-use crate::{MyTurn, WantsToApproach, Position, Map, ApplyMove};
+use crate::{MyTurn, WantsToApproach, Position, Map, ApplyMove, Locomotion, TileSize, map::Pathing};
+use super::FlowFields;
 
 pub struct ApproachAI {}
 
@@ -9,25 +10,25 @@ impl<'a> System<'a> for ApproachAI {
         WriteStorage<'a, MyTurn>,
         WriteStorage<'a, WantsToApproach>,
         ReadStorage<'a, Position>,
-        WriteExpect<'a, Map>,
+        ReadExpect<'a, Map>,
         Entities<'a>,
         WriteStorage<'a, ApplyMove>,
+        WriteExpect<'a, FlowFields>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
     );
 
     fn run (&mut self, data: Self::SystemData) {
-        let (mut turns, mut wants_approach, positions, mut map, 
-            entities, mut apply_move) = data;
+        let (mut turns, mut wants_approach, positions, map, 
+            entities, mut apply_move, mut flow_fields, locomotions, sizes) = data;
 
         let mut turn_done: Vec<Entity> = Vec::new();
         for (ent, pos, approach, _myturn) in (&entities, &positions, &wants_approach, &turns).join() {
             turn_done.push(ent);
-            let path = rltk::a_star_search(
-                map.xy_idx(pos.x, pos.y), 
-                map.xy_idx(approach.idx % map.width, approach.idx / map.width),
-                &mut *map
-            );
-            if path.success && path.steps.len() > 1 {
-                apply_move.insert(ent, ApplyMove { dest_idx: path.steps[1] }).expect("Unable to insert");
+            let pathing = Pathing::of(ent, &locomotions, &sizes);
+            let step = flow_fields.step_toward(&map, pathing, map.xy_idx(pos.x, pos.y), &[approach.idx as usize]);
+            if let Some((dest_idx, _distance)) = step {
+                apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
             }
         };
         wants_approach.clear();
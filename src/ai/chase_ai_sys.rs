@@ -1,7 +1,13 @@
 use specs::prelude::*;
-use crate::{MyTurn, Chasing, Position, Map, ApplyMove};
+use crate::{MyTurn, Chasing, Position, Map, ApplyMove, MoveMode, Movement, Locomotion, TileSize, map::Pathing};
+use super::FlowFields;
 use std::collections::HashMap;
 
+/* Give up once the target is further than this along the flow field */
+const MAX_CHASE_DISTANCE: f32 = 14.0;
+/* Guards give up once they are this many guard radii from their post */
+const GUARD_LEASH: f32 = 2.0;
+
 pub struct ChaseAI {}
 
 impl<'a> System<'a> for ChaseAI {
@@ -10,14 +16,18 @@ impl<'a> System<'a> for ChaseAI {
         WriteStorage<'a, MyTurn>,
         WriteStorage<'a, Chasing>,
         ReadStorage<'a, Position>,
-        WriteExpect<'a, Map>,
+        ReadExpect<'a, Map>,
         Entities<'a>,
         WriteStorage<'a, ApplyMove>,
+        WriteExpect<'a, FlowFields>,
+        ReadStorage<'a, MoveMode>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
     );
 
     fn run (&mut self, data : Self::SystemData) {
-        let (mut turns, mut chasing, positions, mut map, entities,
-            mut apply_move) = data;
+        let (mut turns, mut chasing, positions, map, entities,
+            mut apply_move, mut flow_fields, move_modes, locomotions, sizes) = data;
 
         let mut targets: HashMap<Entity, (i32, i32)> = HashMap::new();
         let mut end_chase: Vec<Entity> = Vec::new();
@@ -32,19 +42,37 @@ impl<'a> System<'a> for ChaseAI {
 
         let mut turn_done: Vec<Entity> = Vec::new();
         for (ent, pos, _chase, _myturn) in (&entities, &positions, &chasing, &turns).join() {
+            if beyond_leash(move_modes.get(ent), &map, pos.x, pos.y) {
+                end_chase.push(ent);
+                continue;
+            }
             turn_done.push(ent);
             let target_pos = targets[&ent];
-            let path = rltk::a_star_search(
-                map.xy_idx(pos.x, pos.y) as i32,
-                map.xy_idx(target_pos.0, target_pos.1) as i32,
-                &mut *map
+            let step = flow_fields.step_toward(
+                &map,
+                Pathing::of(ent, &locomotions, &sizes),
+                map.xy_idx(pos.x, pos.y),
+                &[map.xy_idx(target_pos.0, target_pos.1)]
             );
-            if path.success && path.steps.len() > 1 && path.steps.len() < 15 {
-                apply_move.insert(ent, ApplyMove{ dest_idx: path.steps[1] }).expect("Unable to insert");
-                turn_done.push(ent);
-            } else { end_chase.push(ent); }
+            match step {
+                Some((dest_idx, distance)) if distance < MAX_CHASE_DISTANCE => {
+                    apply_move.insert(ent, ApplyMove{ dest_idx }).expect("Unable to insert");
+                    turn_done.push(ent);
+                }
+                _ => end_chase.push(ent),
+            }
         };
         for done in end_chase.iter() { chasing.remove(*done); };
         for done in turn_done.iter() { turns.remove(*done); };
     }
 }
+
+/// True if a guard would have to stray too far from its post to reach this tile
+pub fn beyond_leash (mode: Option<&MoveMode>, map: &Map, x: i32, y: i32) -> bool {
+    if let Some(MoveMode { mode: Movement::Guard { home, radius } }) = mode {
+        let home_pt = rltk::Point::new(*home as i32 % map.width, *home as i32 / map.width);
+        rltk::DistanceAlg::Pythagoras.distance2d(home_pt, rltk::Point::new(x, y)) > *radius as f32 * GUARD_LEASH
+    } else {
+        false
+    }
+}
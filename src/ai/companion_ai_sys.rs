@@ -0,0 +1,80 @@
+use specs::prelude::*;
+use crate::{MyTurn, Companion, CompanionOrder, Faction, Position, Map, ApplyMove, WantsToMelee, Locomotion,
+    TileSize, map::{Pathing, footprint_distance}, raws::{Reaction, COMPANION_FACTION}};
+use super::FlowFields;
+
+/// Carries out the orders that override a companion's own judgement: attacking a chosen
+/// target, and holding position. Following and staying are left to the usual AI.
+pub struct CompanionAI {}
+
+impl<'a> System<'a> for CompanionAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteStorage<'a, MyTurn>,
+        WriteStorage<'a, Companion>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, Position>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, ApplyMove>,
+        WriteStorage<'a, WantsToMelee>,
+        WriteExpect<'a, FlowFields>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut turns, mut companions, factions, positions, map, entities,
+            mut apply_move, mut wants_melee, mut flow_fields, locomotions, sizes) = data;
+
+        let mut turn_done: Vec<Entity> = Vec::new();
+        for (ent, companion, pos, _turn) in (&entities, &mut companions, &positions, &turns).join() {
+            match companion.order {
+                CompanionOrder::Attack { target } => {
+                    let target_pos = match positions.get(target) {
+                        Some(target_pos) => target_pos,
+                        None => {
+                            /* Job done (or it got away): fall back in */
+                            companion.order = CompanionOrder::Follow;
+                            continue;
+                        }
+                    };
+                    turn_done.push(ent);
+                    if footprint_distance(pos, sizes.get(ent), target_pos, sizes.get(target)) < 1.5 {
+                        wants_melee.insert(ent, WantsToMelee { target }).expect("Unable to insert");
+                    } else if let Some((dest_idx, _distance)) = flow_fields.step_toward(
+                        &map, Pathing::of(ent, &locomotions, &sizes),
+                        map.xy_idx(pos.x, pos.y), &[map.xy_idx(target_pos.x, target_pos.y)])
+                    {
+                        apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                    }
+                }
+                CompanionOrder::Hold => {
+                    turn_done.push(ent);
+                    let raws = crate::raws::RAWS.lock().unwrap();
+                    let mut adjacent_hostile = None;
+                    for y in pos.y - 1 ..= pos.y + 1 {
+                        for x in pos.x - 1 ..= pos.x + 1 {
+                            if x < 0 || x >= map.width || y < 0 || y >= map.height { continue; }
+                            crate::spatial::for_each_tile_content(map.xy_idx(x, y), |other| {
+                                if let Some(faction) = factions.get(other) {
+                                    if crate::raws::faction_reaction(COMPANION_FACTION, &faction.name, &raws) == Reaction::Attack {
+                                        adjacent_hostile = Some(other);
+                                    }
+                                }
+                            });
+                        };
+                    };
+                    if let Some(target) = adjacent_hostile {
+                        wants_melee.insert(ent, WantsToMelee { target }).expect("Unable to insert");
+                    }
+                }
+                CompanionOrder::Follow | CompanionOrder::Stay => {},
+            }
+        };
+
+        for done in turn_done.iter() {
+            turns.remove(*done);
+        };
+    }
+}
@@ -1,5 +1,8 @@
 use specs::prelude::*;
-use crate::{MyTurn, MoveMode, Movement, Position, Map, Viewshed, EntityMoved, map::tile_walkable};
+use crate::{MyTurn, MoveMode, Movement, Position, Map, Viewshed, EntityMoved, ApplyMove, Locomotion, Held,
+    TileSize, map::Pathing};
+use super::FlowFields;
+use std::collections::HashMap;
 
 pub struct DefaultMoveAI {}
 
@@ -9,20 +12,37 @@ impl<'a> System<'a> for DefaultMoveAI {
         WriteStorage<'a, MyTurn>,
         WriteStorage<'a, MoveMode>,
         WriteStorage<'a, Position>,
-        WriteExpect<'a, Map>,
+        ReadExpect<'a, Map>,
         WriteStorage<'a, Viewshed>,
         WriteStorage<'a, EntityMoved>,
         WriteExpect<'a, rltk::RandomNumberGenerator>,
-        Entities<'a>
+        Entities<'a>,
+        WriteStorage<'a, ApplyMove>,
+        WriteExpect<'a, FlowFields>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, Held>,
+        ReadStorage<'a, TileSize>,
     );
 
     fn run (&mut self, data : Self::SystemData) {
-        let (mut turns, mut move_mode, mut positions, mut map, 
-            mut viewsheds, mut entity_moved, mut rng, entities) = data;
+        let (mut turns, mut move_mode, mut positions, map, 
+            mut viewsheds, mut entity_moved, mut rng, entities, mut apply_move, mut flow_fields, locomotions, held, sizes) = data;
+
+        let mut leaders: HashMap<Entity, usize> = HashMap::new();
+        for mode in (&move_mode).join() {
+            if let Movement::Follow { leader, .. } = mode.mode {
+                if let Some(pos) = positions.get(leader) {
+                    leaders.insert(leader, map.xy_idx(pos.x, pos.y));
+                }
+            }
+        };
             
         let mut turn_done: Vec<Entity> = Vec::new();
         for (ent, mut pos, mut mode, mut viewshed, _myturn) in (&entities, &mut positions, &mut move_mode, &mut viewsheds, &turns).join() {
             turn_done.push(ent);
+            if held.get(ent).is_some() { continue; }
+            let pathing = Pathing::of(ent, &locomotions, &sizes);
+            let size = sizes.get(ent);
             match &mut mode.mode {
                 Movement::Static => {},
                 Movement::Random => {
@@ -36,28 +56,25 @@ impl<'a> System<'a> for DefaultMoveAI {
                         4 => y += 1,
                         _ => {},
                     }
-                    if x > 0 && x < map.width-1 && y > 0 && y < map.height-1 {
-                        let dest_idx = map.xy_idx(x, y);
-                        if !crate::spatial::is_blocked(dest_idx) {
-                            let idx = map.xy_idx(pos.x, pos.y);
-                            pos.x = x;
-                            pos.y = y;
-                            entity_moved.insert(ent, EntityMoved{}).expect("Unable to insert marker");
-                            crate::spatial::move_entity(ent, idx, dest_idx);
-                            viewshed.dirty = true;
-                        }
+                    if x > 0 && x < map.width-1 && y > 0 && y < map.height-1 && map.can_stand(x, y, &pathing) {
+                        let from = map.footprint(pos.x, pos.y, size);
+                        pos.x = x;
+                        pos.y = y;
+                        entity_moved.insert(ent, EntityMoved{}).expect("Unable to insert marker");
+                        crate::spatial::move_footprint(ent, &from, &map.footprint(x, y, size));
+                        viewshed.dirty = true;
                     }
                 },
                 Movement::RandomWaypoint { path } => {
                     if let Some(path) = path {
-                        let idx = map.xy_idx(pos.x, pos.y);
                         if path.len() > 1 {
-                            if !crate::spatial::is_blocked(path[1] as usize) {
-                                pos.x = path[1] as i32 % map.width;
-                                pos.y = path[1] as i32 / map.width;
+                            let (x, y) = (path[1] as i32 % map.width, path[1] as i32 / map.width);
+                            if map.can_stand(x, y, &pathing) {
+                                let from = map.footprint(pos.x, pos.y, size);
+                                pos.x = x;
+                                pos.y = y;
                                 entity_moved.insert(ent, EntityMoved{}).expect("Unable to insert marker");
-                                let new_idx = map.xy_idx(pos.x, pos.y);
-                                crate::spatial::move_entity(ent, idx, new_idx);
+                                crate::spatial::move_footprint(ent, &from, &map.footprint(x, y, size));
                                 viewshed.dirty = true;
                                 path.remove(0);
                             }
@@ -67,12 +84,11 @@ impl<'a> System<'a> for DefaultMoveAI {
                     } else {
                         let target_x = rng.roll_dice(1, map.width-2);
                         let target_y = rng.roll_dice(1, map.height-2);
-                        let idx = map.xy_idx(target_x, target_y);
-                        if tile_walkable(map.tiles[idx]) {
+                        if map.can_stand(target_x, target_y, &pathing) {
                             let path = rltk::a_star_search(
                                 map.xy_idx(pos.x, pos.y),
                                 map.xy_idx(target_x, target_y),
-                                &mut *map
+                                &map.for_pathing(pathing)
                             );
                             if path.success && path.steps.len() > 1 {
                                 mode.mode = Movement::RandomWaypoint { path: Some(path.steps) };
@@ -80,6 +96,63 @@ impl<'a> System<'a> for DefaultMoveAI {
                         }
                     }
                 },
+                Movement::Patrol { waypoints, next } => {
+                    if waypoints.is_empty() { continue; }
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    let mut step = None;
+                    /* Skip past waypoints that are reached or can't be reached */
+                    for _attempt in 0 .. waypoints.len() {
+                        if waypoints[*next] != idx {
+                            step = flow_fields.step_toward(&map, pathing, idx, &[waypoints[*next]]);
+                            if step.is_some() { break; }
+                        }
+                        *next = (*next + 1) % waypoints.len();
+                    };
+                    if let Some((dest_idx, _distance)) = step {
+                        apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                    }
+                },
+                Movement::Guard { home, radius } => {
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    let home_pt = rltk::Point::new(*home as i32 % map.width, *home as i32 / map.width);
+                    let from_home = |x: i32, y: i32| rltk::DistanceAlg::Pythagoras.distance2d(home_pt, rltk::Point::new(x, y));
+                    if from_home(pos.x, pos.y) > *radius as f32 {
+                        /* Wandered (or chased something) too far: head back to the post */
+                        if let Some((dest_idx, _distance)) = flow_fields.step_toward(&map, pathing, idx, &[*home]) {
+                            apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                        }
+                    } else {
+                        let (mut x, mut y) = (pos.x, pos.y);
+                        match rng.roll_dice(1, 8) {
+                            1 => x -= 1,
+                            2 => x += 1,
+                            3 => y -= 1,
+                            4 => y += 1,
+                            _ => {},
+                        }
+                        let dest_idx = map.xy_idx(x, y);
+                        if dest_idx != idx && from_home(x, y) <= *radius as f32 && map.can_stand(x, y, &pathing) {
+                            apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                        }
+                    }
+                },
+                Movement::Follow { leader, distance } => {
+                    let leader_idx = match leaders.get(leader) {
+                        Some(leader_idx) => *leader_idx,
+                        None => {
+                            /* Nobody left to follow */
+                            mode.mode = Movement::Random;
+                            continue;
+                        }
+                    };
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    let leader_pt = rltk::Point::new(leader_idx as i32 % map.width, leader_idx as i32 / map.width);
+                    if rltk::DistanceAlg::Pythagoras.distance2d(leader_pt, rltk::Point::new(pos.x, pos.y)) > *distance as f32 {
+                        if let Some((dest_idx, _distance)) = flow_fields.step_toward(&map, pathing, idx, &[leader_idx]) {
+                            apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                        }
+                    }
+                },
             }
         };
 
@@ -88,3 +161,119 @@ impl<'a> System<'a> for DefaultMoveAI {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileType;
+    use crate::test_support::{lock_globals, open_map, world};
+    use crate::ai::beyond_leash;
+
+    /// An open room with the flow fields the system steers by, and nothing in it
+    fn room () -> World {
+        let mut ecs = world(open_map(1, 20, 12));
+        ecs.insert(FlowFields::new());
+        ecs
+    }
+
+    fn mob (ecs: &mut World, x: i32, y: i32, mode: Movement) -> Entity {
+        ecs.create_entity()
+            .with(Position { x, y })
+            .with(MoveMode { mode })
+            .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: false })
+            .with(MyTurn {})
+            .build()
+    }
+
+    /// Takes a turn, and says where the mob wants to step (as x, y)
+    fn step (ecs: &mut World, ent: Entity) -> Option<(i32, i32)> {
+        DefaultMoveAI {}.run_now(ecs);
+        let width = ecs.fetch::<Map>().width;
+        ecs.write_storage::<ApplyMove>().remove(ent).map(|m| (m.dest_idx as i32 % width, m.dest_idx as i32 / width))
+    }
+
+    fn idx (ecs: &World, x: i32, y: i32) -> usize {
+        ecs.fetch::<Map>().xy_idx(x, y)
+    }
+
+    #[test]
+    fn patrols_head_for_the_next_waypoint () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let waypoints = vec![idx(&ecs, 2, 5), idx(&ecs, 10, 5)];
+        let guard = mob(&mut ecs, 2, 5, Movement::Patrol { waypoints, next: 0 });
+        /* Already at the first, so on to the second */
+        assert_eq!(step(&mut ecs, guard), Some((3, 5)));
+        assert!(matches!(ecs.read_storage::<MoveMode>().get(guard).unwrap().mode, Movement::Patrol { next: 1, .. }));
+        assert!(ecs.read_storage::<MyTurn>().get(guard).is_none());
+    }
+
+    #[test]
+    fn patrols_skip_waypoints_they_cant_reach () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        /* The second waypoint is walled in */
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            for y in 7 ..= 10 {
+                for x in 14 ..= 18 {
+                    let wall = map.xy_idx(x, y);
+                    if (x, y) != (16, 9) { map.tiles[wall] = TileType::Wall; }
+                };
+            };
+            map.populate_blocked();
+        }
+        let waypoints = vec![idx(&ecs, 2, 5), idx(&ecs, 16, 9), idx(&ecs, 2, 9)];
+        let guard = mob(&mut ecs, 2, 5, Movement::Patrol { waypoints, next: 1 });
+        assert_eq!(step(&mut ecs, guard), Some((2, 6)));
+        assert!(matches!(ecs.read_storage::<MoveMode>().get(guard).unwrap().mode, Movement::Patrol { next: 2, .. }));
+    }
+
+    #[test]
+    fn guards_stay_near_their_post () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let home = idx(&ecs, 5, 5);
+        let guard = mob(&mut ecs, 12, 5, Movement::Guard { home, radius: 2 });
+        assert_eq!(step(&mut ecs, guard), Some((11, 5)));
+
+        /* Near home they mill about, but never past the radius */
+        let near = mob(&mut ecs, 7, 5, Movement::Guard { home, radius: 2 });
+        for _turn in 0 .. 20 {
+            ecs.write_storage::<MyTurn>().insert(near, MyTurn {}).unwrap();
+            if let Some((x, y)) = step(&mut ecs, near) {
+                assert!(rltk::DistanceAlg::Pythagoras.distance2d(rltk::Point::new(5, 5), rltk::Point::new(x, y)) <= 2.0);
+            }
+        };
+    }
+
+    #[test]
+    fn followers_keep_up_and_wander_off_once_alone () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let leader = ecs.create_entity().with(Position { x: 10, y: 5 }).build();
+        let close = mob(&mut ecs, 9, 5, Movement::Follow { leader, distance: 2 });
+        let far = mob(&mut ecs, 4, 5, Movement::Follow { leader, distance: 2 });
+        DefaultMoveAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<ApplyMove>().get(close).is_none());
+        assert_eq!(ecs.read_storage::<ApplyMove>().get(far).map(|m| m.dest_idx), Some(idx(&ecs, 5, 5)));
+
+        ecs.delete_entity(leader).unwrap();
+        ecs.maintain();
+        ecs.write_storage::<MyTurn>().insert(far, MyTurn {}).unwrap();
+        DefaultMoveAI {}.run_now(&ecs);
+        assert!(matches!(ecs.read_storage::<MoveMode>().get(far).unwrap().mode, Movement::Random));
+    }
+
+    #[test]
+    fn guards_are_leashed_to_their_post () {
+        let _globals = lock_globals();
+        let ecs = room();
+        let map = ecs.fetch::<Map>();
+        let guard = MoveMode { mode: Movement::Guard { home: map.xy_idx(5, 5), radius: 2 } };
+        assert!(!beyond_leash(Some(&guard), &map, 9, 5));
+        assert!(beyond_leash(Some(&guard), &map, 10, 5));
+        assert!(!beyond_leash(Some(&MoveMode { mode: Movement::Random }), &map, 19, 11));
+        assert!(!beyond_leash(None, &map, 19, 11));
+    }
+}
@@ -1,5 +1,7 @@
 use specs::prelude::*;
-use crate::{MyTurn, WantsToFlee, Position, Map, ApplyMove};
+use crate::{MyTurn, WantsToFlee, Position, Map, ApplyMove, Morale, Name, Locomotion, TileSize, map::Pathing,
+    gamelog::GameLog};
+use super::FlowFields;
 
 pub struct FleeAI {}
 
@@ -9,28 +11,45 @@ impl<'a> System<'a> for FleeAI {
         WriteStorage<'a, MyTurn>,
         WriteStorage<'a, WantsToFlee>,
         ReadStorage<'a, Position>,
-        WriteExpect<'a, Map>,
+        ReadExpect<'a, Map>,
         Entities<'a>,
         WriteStorage<'a, ApplyMove>,
+        WriteStorage<'a, Morale>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, FlowFields>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
     );
 
     fn run (&mut self, data: Self::SystemData) {
-        let (mut turns, mut wants_flee, positions, mut map, 
-            entities, mut apply_move) = data;
+        let (mut turns, mut wants_flee, positions, map, 
+            entities, mut apply_move, mut morale, names, mut gamelog, mut flow_fields, locomotions, sizes) = data;
 
         let mut turn_done: Vec<Entity> = Vec::new();
         for (ent, pos, flee, _myturn) in (&entities, &positions, &wants_flee, &turns).join() {
             turn_done.push(ent);
             let my_idx = map.xy_idx(pos.x, pos.y);
-            map.populate_blocked();
-            let flee_map = rltk::DijkstraMap::new(map.width as usize, map.height as usize, &flee.indices, &*map, 100.0);
-            let flee_target = rltk::DijkstraMap::find_highest_exit(&flee_map, my_idx, &*map);
+            let pathing = Pathing::of(ent, &locomotions, &sizes);
+            let flee_target = flow_fields.step_away(&map, pathing, my_idx, &flee.indices);
+            let mut cornered = true;
             if let Some(flee_target) = flee_target {
-                if !crate::spatial::is_blocked(flee_target as usize) {
+                if map.can_stand(flee_target as i32 % map.width, flee_target as i32 / map.width, &pathing) {
                     apply_move.insert(ent, ApplyMove{ dest_idx : flee_target }).expect("Unable to insert");
                     turn_done.push(ent);
+                    cornered = false;
                 }
             }
+
+            /* Broken morale with nowhere to run: cower */
+            if let Some(morale) = morale.get_mut(ent) {
+                if cornered && morale.broken && !morale.cowering {
+                    if let Some(name) = names.get(ent) {
+                        gamelog.entries.push(format!("{} cowers in fear.", name.name));
+                    }
+                }
+                morale.cowering = cornered && morale.broken;
+            }
         };
         wants_flee.clear();
 
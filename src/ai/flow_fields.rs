@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use rltk::DijkstraMap;
+use crate::{Map, map::{Pathing, LevelId}};
+
+/// Shared Dijkstra maps keyed by their goal tiles and how the mob gets about. Every mob
+/// heading for (or running from) the same tiles the same way reads the same field, which
+/// is only rebuilt once the level or its blockers change. Big mobs path by their top-left
+/// corner, so each gets its own.
+pub struct FlowFields {
+    level: LevelId,
+    revision: u64,
+    fields: HashMap<(Vec<usize>, Pathing), DijkstraMap>,
+}
+
+impl FlowFields {
+    pub fn new () -> FlowFields {
+        FlowFields { level: LevelId::main(0), revision: u64::MAX, fields: HashMap::new() }
+    }
+
+    fn field (&mut self, map: &Map, pathing: Pathing, goals: &[usize]) -> &DijkstraMap {
+        let revision = crate::spatial::blocked_revision();
+        if revision != self.revision || map.depth != self.level.depth || map.branch != self.level.branch {
+            self.fields.clear();
+            self.revision = revision;
+            self.level = map.level_id();
+        }
+
+        let mut key = goals.to_vec();
+        key.sort_unstable();
+        key.dedup();
+        self.fields.entry((key, pathing)).or_insert_with_key(|(goals, pathing)| {
+            /* Something big has reached a goal once any part of it covers it */
+            let mut starts = goals.clone();
+            if let Some((size, _)) = pathing.footprint {
+                for goal in goals.iter() {
+                    let (x, y) = (*goal as i32 % map.width, *goal as i32 / map.width);
+                    for dy in 0 .. size.y {
+                        for dx in 0 .. size.x {
+                            if x - dx > 0 && y - dy > 0 { starts.push(map.xy_idx(x - dx, y - dy)); }
+                        };
+                    };
+                };
+            }
+            /* Far enough to reach anywhere on the map, however winding the way */
+            let max_depth = ((map.width + map.height) * 2) as f32;
+            DijkstraMap::new(map.width as usize, map.height as usize, &starts, &map.for_pathing(*pathing), max_depth)
+        })
+    }
+
+    /// Next tile on the way to the nearest goal, and how far that tile still is from it
+    pub fn step_toward (&mut self, map: &Map, pathing: Pathing, from: usize, goals: &[usize]) -> Option<(usize, f32)> {
+        let field = self.field(map, pathing, goals);
+        let exit = DijkstraMap::find_lowest_exit(field, from, &map.for_pathing(pathing))?;
+        let distance = field.map[exit];
+        if distance < f32::MAX { Some((exit, distance)) } else { None }
+    }
+
+    /// Next tile leading away from all of the given threats
+    pub fn step_away (&mut self, map: &Map, pathing: Pathing, from: usize, threats: &[usize]) -> Option<usize> {
+        let field = self.field(map, pathing, threats);
+        DijkstraMap::find_highest_exit(field, from, &map.for_pathing(pathing))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::prelude::*;
+    use crate::{TileType, Locomotion};
+    use crate::test_support::{lock_globals, open_map};
+
+    #[test]
+    fn steps_lead_toward_the_goal_and_away_from_threats () {
+        let _globals = lock_globals();
+        let map = open_map(1, 12, 12);
+        let mut fields = FlowFields::new();
+        let (from, goal) = (map.xy_idx(2, 2), map.xy_idx(9, 2));
+        let (step, distance) = fields.step_toward(&map, Pathing::default(), from, &[goal]).unwrap();
+        assert_eq!(step, map.xy_idx(3, 2));
+        assert_eq!(distance, 6.0);
+
+        let away = fields.step_away(&map, Pathing::default(), map.xy_idx(8, 2), &[goal]).unwrap();
+        assert!(away % map.width as usize == 7);
+    }
+
+    #[test]
+    fn fields_are_shared_until_something_moves () {
+        let _globals = lock_globals();
+        let map = open_map(1, 12, 12);
+        let mut fields = FlowFields::new();
+        let (a, b, from) = (map.xy_idx(9, 9), map.xy_idx(9, 2), map.xy_idx(2, 5));
+        fields.step_toward(&map, Pathing::default(), from, &[a, b]);
+        fields.step_toward(&map, Pathing::default(), from, &[b, a, b]);
+        assert_eq!(fields.fields.len(), 1);
+
+        let flyer = Pathing { locomotion: Locomotion { flying: true, ..Default::default() }, footprint: None };
+        fields.step_toward(&map, flyer, from, &[a, b]);
+        assert_eq!(fields.fields.len(), 2);
+
+        /* A blocker turning up throws the lot away */
+        let mut ecs = World::new();
+        crate::spatial::index_entity(ecs.create_entity().build(), map.xy_idx(5, 5), true);
+        fields.step_toward(&map, Pathing::default(), from, &[a, b]);
+        assert_eq!(fields.fields.len(), 1);
+    }
+
+    #[test]
+    fn a_walled_off_goal_has_no_step () {
+        let _globals = lock_globals();
+        let mut map = open_map(1, 12, 12);
+        for y in 1 .. 11 {
+            let idx = map.xy_idx(6, y);
+            map.tiles[idx] = TileType::Wall;
+        };
+        map.populate_blocked();
+        let mut fields = FlowFields::new();
+        assert_eq!(fields.step_toward(&map, Pathing::default(), map.xy_idx(2, 2), &[map.xy_idx(9, 2)]), None);
+    }
+}
@@ -0,0 +1,78 @@
+use specs::prelude::*;
+use crate::{MyTurn, TownGuard, Wanted, Position, Pools, Map, ApplyMove, WantsToMelee, Locomotion, TileSize,
+    Name, gamelog::GameLog, map::{Pathing, footprint_distance}};
+use super::FlowFields;
+
+/// Sends the watch after a wanted player: they close in, take the fine if the player can
+/// pay it and hasn't fought back, and otherwise cut them down
+pub struct GuardAI {}
+
+impl<'a> System<'a> for GuardAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, TownGuard>,
+        WriteStorage<'a, Wanted>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Pools>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, Entity>,
+        Entities<'a>,
+        WriteStorage<'a, ApplyMove>,
+        WriteStorage<'a, WantsToMelee>,
+        WriteExpect<'a, FlowFields>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut turns, guards, mut wanted, positions, mut pools, map, player, entities,
+            mut apply_move, mut wants_melee, mut flow_fields, locomotions, sizes, names, mut log) = data;
+
+        let player_pos = match (wanted.get(*player), positions.get(*player)) {
+            (Some(_), Some(pos)) => pos,
+            _ => return,
+        };
+        let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+
+        let mut turn_done: Vec<Entity> = Vec::new();
+        let mut fine_paid = false;
+        for (ent, _guard, pos, _turn) in (&entities, &guards, &positions, &turns).join() {
+            turn_done.push(ent);
+            if fine_paid { continue; }
+            if footprint_distance(pos, sizes.get(ent), player_pos, sizes.get(*player)) >= 1.5 {
+                if let Some((dest_idx, _distance)) = flow_fields.step_toward(
+                    &map, Pathing::of(ent, &locomotions, &sizes), map.xy_idx(pos.x, pos.y), &[player_idx])
+                {
+                    apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                }
+                continue;
+            }
+
+            let guard_name = names.get(ent).map_or("guard", |n| n.name.as_str());
+            let warrant = wanted.get_mut(*player).unwrap();
+            let purse = pools.get(*player).map_or(0.0, |p| p.gold);
+            if !warrant.resisting && purse >= warrant.fine {
+                if let Some(pools) = pools.get_mut(*player) { pools.gold -= warrant.fine; }
+                if let Some(pools) = pools.get_mut(ent) { pools.gold += warrant.fine; }
+                log.entries.push(format!("The {} takes your fine of {:.1} gold. \"Stay out of trouble.\"", guard_name, warrant.fine));
+                fine_paid = true;
+            } else {
+                if !warrant.resisting {
+                    log.entries.push(format!("You can't pay the fine, so the {} draws steel!", guard_name));
+                    warrant.resisting = true;
+                }
+                wants_melee.insert(ent, WantsToMelee { target: *player }).expect("Unable to insert");
+            }
+        };
+
+        if fine_paid {
+            wanted.remove(*player);
+        }
+        for done in turn_done.iter() {
+            turns.remove(*done);
+        };
+    }
+}
@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use crate::{Initiative, Position, MyTurn, Attributes, RunState, Pools};
+use crate::{Initiative, Position, MyTurn, Attributes, RunState, Pools, GameClock};
 
 pub struct InitiativeSystem {}
 
@@ -16,11 +16,12 @@ impl<'a> System<'a> for InitiativeSystem {
         ReadExpect<'a, Entity>,
         ReadExpect<'a, rltk::Point>,
         ReadStorage<'a, Pools>,
+        WriteExpect<'a, GameClock>,
     );
 
     fn run (&mut self, data: Self::SystemData) {
         let (mut initiatives, positions, mut turns, entities, mut rng, attributes,
-            mut runstate, player, player_pos, pools) = data;
+            mut runstate, player, player_pos, pools, mut clock) = data;
 
         if *runstate != RunState::Ticking { return; }
         turns.clear();
@@ -41,6 +42,7 @@ impl<'a> System<'a> for InitiativeSystem {
 
                 if ent == *player {
                     *runstate = RunState::AwaitingInput;
+                    clock.turn += 1;
                 } else {
                     let dist = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, rltk::Point::new(pos.x, pos.y));
                     if dist > 20.0 { myturn = false; }
@@ -1,20 +1,32 @@
 mod initiative_sys;
 mod turn_status;
 mod quip_sys;
-mod adjacent_ai_sys;
-mod visible_ai_sys;
 mod approach_ai_sys;
 mod flee_ai_sys;
 mod default_move_sys;
 mod chase_ai_sys;
+mod companion_ai_sys;
+mod pursuit_sys;
 mod encumbrance_sys;
+mod morale_sys;
+mod flow_fields;
+mod schedule_ai_sys;
+mod utility_ai_sys;
+mod ability_ai_sys;
+mod guard_ai_sys;
 pub use initiative_sys::InitiativeSystem;
 pub use turn_status::TurnStatusSystem;
 pub use quip_sys::QuipSystem;
-pub use adjacent_ai_sys::AdjacentAI;
-pub use visible_ai_sys::VisibleAI;
 pub use approach_ai_sys::ApproachAI;
 pub use flee_ai_sys::FleeAI;
 pub use default_move_sys::DefaultMoveAI;
-pub use chase_ai_sys::ChaseAI;
+pub use chase_ai_sys::{ChaseAI, beyond_leash};
 pub use encumbrance_sys::EncumbranceSystem;
+pub use morale_sys::MoraleSystem;
+pub use flow_fields::FlowFields;
+pub use schedule_ai_sys::{ScheduleAI, is_at_post};
+pub use utility_ai_sys::UtilityAI;
+pub use companion_ai_sys::CompanionAI;
+pub use pursuit_sys::PursuitSystem;
+pub use ability_ai_sys::{AbilityAI, ready_ability};
+pub use guard_ai_sys::GuardAI;
@@ -0,0 +1,111 @@
+use specs::prelude::*;
+use crate::{MyTurn, Faction, Position, Map, raws::Reaction, Viewshed, WantsToFlee, Chasing,
+    Morale, Surrendered, Pools, Name, gamelog::GameLog, morale_score, MORALE_BREAK_POINT};
+
+pub struct MoraleSystem {}
+
+impl<'a> System<'a> for MoraleSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, Position>,
+        ReadExpect<'a, Map>,
+        WriteStorage<'a, WantsToFlee>,
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadStorage<'a, Viewshed>,
+        WriteStorage<'a, Chasing>,
+        WriteStorage<'a, Morale>,
+        WriteStorage<'a, Surrendered>,
+        WriteStorage<'a, Pools>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, GameLog>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut turns, factions, positions, map, mut wants_flee, entities, player,
+            viewsheds, mut chasing, mut morale, mut surrendered, mut pools, names,
+            mut gamelog, lazy) = data;
+
+        let mut fleeing: Vec<(Entity, Vec<usize>)> = Vec::new();
+        let mut surrendering: Vec<Entity> = Vec::new();
+        {
+            let raws = crate::raws::RAWS.lock().unwrap();
+            for (ent, _turn, my_faction, pos, viewshed, morale, my_pools, _surrendered) in
+                (&entities, &turns, &factions, &positions, &viewsheds, &mut morale, &pools, !&surrendered).join()
+            {
+                if ent == *player { continue; }
+                let my_idx = map.xy_idx(pos.x, pos.y);
+                let mut allies = 0;
+                let mut threat = 0;
+                let mut threat_tiles: Vec<usize> = Vec::new();
+                let mut player_visible = false;
+                for visible_tile in viewshed.visible_tiles.iter() {
+                    let idx = map.xy_idx(visible_tile.x, visible_tile.y);
+                    if idx == my_idx { continue; }
+                    crate::spatial::for_each_tile_content(idx, |other_ent| {
+                        if let Some(their_faction) = factions.get(other_ent) {
+                            if their_faction.name == my_faction.name {
+                                allies += 1;
+                            } else if crate::raws::faction_reaction(&their_faction.name, &my_faction.name, &raws) == Reaction::Attack {
+                                threat += pools.get(other_ent).map_or(1, |p| p.level);
+                                threat_tiles.push(idx);
+                                if other_ent == *player { player_visible = true; }
+                            }
+                        }
+                    });
+                };
+
+                let hp_fraction = my_pools.hit_points.current as f32 / my_pools.hit_points.max as f32;
+                morale.current = morale_score(morale.base, hp_fraction, allies, threat - my_pools.level);
+                if threat_tiles.is_empty() || morale.current > MORALE_BREAK_POINT {
+                    morale.broken = false;
+                    morale.cowering = false;
+                    continue;
+                }
+
+                /* Morale has broken */
+                if morale.can_surrender && player_visible {
+                    surrendering.push(ent);
+                } else {
+                    if !morale.broken {
+                        if let Some(name) = names.get(ent) {
+                            gamelog.entries.push(format!("{} panics and tries to flee!", name.name));
+                        }
+                    }
+                    fleeing.push((ent, threat_tiles));
+                }
+                morale.broken = true;
+            };
+        }
+
+        for (ent, indices) in fleeing.drain(..) {
+            chasing.remove(ent);
+            wants_flee.insert(ent, WantsToFlee { indices }).expect("Unable to insert");
+        };
+
+        for ent in surrendering.iter() {
+            chasing.remove(*ent);
+            let mut gold = 0.0;
+            if let Some(my_pools) = pools.get_mut(*ent) {
+                gold = my_pools.gold;
+                my_pools.gold = 0.0;
+            }
+            /* Left at their feet: picking it up is the player's call */
+            if let (true, Some(pos)) = (gold > 0.0, positions.get(*ent)) {
+                crate::spawner::spawn_gold_pile(&lazy, &entities, pos.x, pos.y, gold, *ent);
+            }
+            if let Some(name) = names.get(*ent) {
+                if gold > 0.0 {
+                    gamelog.entries.push(format!("{} surrenders, throwing down {:.0} gold!", name.name, gold));
+                } else {
+                    gamelog.entries.push(format!("{} surrenders!", name.name));
+                }
+            }
+            surrendered.insert(*ent, Surrendered{}).expect("Unable to insert");
+            turns.remove(*ent);
+        };
+    }
+}
@@ -0,0 +1,147 @@
+use specs::prelude::*;
+use crate::{Pursuer, Position, OtherLevelPosition, Viewshed, Name, Map, GameClock, gamelog::GameLog,
+    Locomotion, TileSize, map::Pathing};
+
+/// Brings mobs that followed the player off another level out of the stairs
+pub struct PursuitSystem {}
+
+impl<'a> System<'a> for PursuitSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteStorage<'a, Pursuer>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, OtherLevelPosition>,
+        WriteStorage<'a, Viewshed>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, GameClock>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut pursuers, mut positions, mut other_level_positions, mut viewsheds, names,
+            locomotions, sizes, map, clock, mut gamelog, entities) = data;
+
+        let mut arrived: Vec<Entity> = Vec::new();
+        let mut taken: Vec<usize> = Vec::new();
+        for (ent, pursuer, _other_level) in (&entities, &pursuers, &other_level_positions).join() {
+            if pursuer.level != map.level_id() || clock.turn < pursuer.arrival_turn { continue; }
+
+            /* Come out on the stairs, or next to them if something is in the way */
+            let pathing = Pathing::of(ent, &locomotions, &sizes);
+            let mut spot = None;
+            'search: for radius in 0 ..= 1 {
+                for y in pursuer.y - radius ..= pursuer.y + radius {
+                    for x in pursuer.x - radius ..= pursuer.x + radius {
+                        let clear = || map.footprint(x, y, sizes.get(ent)).iter().all(|idx| !taken.contains(idx));
+                        if map.can_stand(x, y, &pathing) && clear() {
+                            spot = Some((x, y));
+                            break 'search;
+                        }
+                    };
+                };
+            };
+
+            /* Crowded stairs: try again next turn */
+            if let Some((x, y)) = spot {
+                taken.extend(map.footprint(x, y, sizes.get(ent)));
+                positions.insert(ent, Position { x, y }).expect("Insert Fail");
+                if let Some(vs) = viewsheds.get_mut(ent) {
+                    vs.dirty = true;
+                }
+                if let Some(name) = names.get(ent) {
+                    gamelog.entries.push(format!("The {} follows you!", name.name));
+                }
+                arrived.push(ent);
+            }
+        };
+
+        for ent in arrived.iter() {
+            pursuers.remove(*ent);
+            other_level_positions.remove(*ent);
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::LevelId;
+    use crate::test_support::{lock_globals, open_map, world};
+
+    /// A level three room, at the turn pursuers are due
+    fn landing () -> World {
+        let mut ecs = world(open_map(3, 12, 12));
+        ecs.insert(GameClock { turn: 100 });
+        ecs
+    }
+
+    fn pursuer (ecs: &mut World, depth: i32, arrival_turn: i32) -> Entity {
+        ecs.create_entity()
+            .with(Pursuer { x: 5, y: 5, level: LevelId::main(depth), arrival_turn })
+            .with(OtherLevelPosition { x: 2, y: 2, level: LevelId::main(2) })
+            .build()
+    }
+
+    fn position (ecs: &World, ent: Entity) -> Option<(i32, i32)> {
+        ecs.read_storage::<Position>().get(ent).map(|pos| (pos.x, pos.y))
+    }
+
+    #[test]
+    fn pursuers_come_out_of_the_stairs_on_time () {
+        let _globals = lock_globals();
+        let mut ecs = landing();
+        let due = pursuer(&mut ecs, 3, 100);
+        let late = pursuer(&mut ecs, 3, 101);
+        let elsewhere = pursuer(&mut ecs, 4, 100);
+        PursuitSystem {}.run_now(&ecs);
+        assert_eq!(position(&ecs, due), Some((5, 5)));
+        assert!(ecs.read_storage::<Pursuer>().get(due).is_none());
+        assert!(ecs.read_storage::<OtherLevelPosition>().get(due).is_none());
+        assert_eq!(position(&ecs, late), None);
+        assert_eq!(position(&ecs, elsewhere), None);
+
+        /* The first one is on the stairs by the time the next arrives */
+        crate::spatial::index_entity(due, ecs.fetch::<Map>().xy_idx(5, 5), true);
+        ecs.fetch_mut::<GameClock>().turn = 101;
+        PursuitSystem {}.run_now(&ecs);
+        /* So the next steps out beside them */
+        let (x, y) = position(&ecs, late).unwrap();
+        assert!((x, y) != (5, 5) && (x - 5).abs() <= 1 && (y - 5).abs() <= 1);
+        assert_eq!(position(&ecs, elsewhere), None);
+    }
+
+    #[test]
+    fn big_pursuers_come_out_where_they_fit () {
+        let _globals = lock_globals();
+        let mut ecs = landing();
+        crate::spatial::index_entity(ecs.entities().create(), ecs.fetch::<Map>().xy_idx(6, 6), true);
+        let big = pursuer(&mut ecs, 3, 100);
+        ecs.write_storage::<TileSize>().insert(big, TileSize { x: 2, y: 2 }).expect("Insert Fail");
+        PursuitSystem {}.run_now(&ecs);
+        let (x, y) = position(&ecs, big).unwrap();
+        let map = ecs.fetch::<Map>();
+        assert!(!map.footprint(x, y, Some(&TileSize { x: 2, y: 2 })).contains(&map.xy_idx(6, 6)));
+    }
+
+    #[test]
+    fn crowded_stairs_hold_pursuers_back () {
+        let _globals = lock_globals();
+        let mut ecs = landing();
+        {
+            let map = ecs.fetch::<Map>();
+            for y in 4 ..= 6 {
+                for x in 4 ..= 6 {
+                    crate::spatial::index_entity(ecs.entities().create(), map.xy_idx(x, y), true);
+                };
+            };
+        }
+        let stuck = pursuer(&mut ecs, 3, 90);
+        PursuitSystem {}.run_now(&ecs);
+        assert_eq!(position(&ecs, stuck), None);
+        assert!(ecs.read_storage::<Pursuer>().get(stuck).is_some());
+    }
+}
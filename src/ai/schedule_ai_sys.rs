@@ -0,0 +1,188 @@
+use specs::prelude::*;
+use crate::{MyTurn, Schedule, ScheduleEntry, Position, Map, ApplyMove, MoveMode, Movement, Door,
+    BlocksTile, BlocksVisibility, Renderable, Viewshed, GameClock, Locomotion, TileSize,
+    map::{BuildingTag, Pathing, tile_walkable}};
+use super::FlowFields;
+
+pub struct ScheduleAI {}
+
+impl<'a> System<'a> for ScheduleAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteStorage<'a, MyTurn>,
+        WriteStorage<'a, Schedule>,
+        ReadStorage<'a, Position>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, ApplyMove>,
+        ReadStorage<'a, MoveMode>,
+        ReadExpect<'a, GameClock>,
+        WriteExpect<'a, FlowFields>,
+        WriteExpect<'a, rltk::RandomNumberGenerator>,
+        WriteStorage<'a, Door>,
+        WriteStorage<'a, BlocksTile>,
+        WriteStorage<'a, BlocksVisibility>,
+        WriteStorage<'a, Renderable>,
+        WriteStorage<'a, Viewshed>,
+        ReadExpect<'a, Entity>,
+        ReadStorage<'a, Locomotion>,
+        ReadStorage<'a, TileSize>,
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut turns, mut schedules, positions, map, entities, mut apply_move, move_modes,
+            clock, mut flow_fields, mut rng, mut doors, mut blocks_tile, mut blocks_visibility,
+            mut renderables, mut viewsheds, player, locomotions, sizes) = data;
+
+        if map.buildings.is_empty() { return; }
+        let hour = clock.hour();
+
+        let mut closed_doors: Vec<(Entity, usize)> = Vec::new();
+        for (ent, door, pos) in (&entities, &doors, &positions).join() {
+            if !door.open { closed_doors.push((ent, map.xy_idx(pos.x, pos.y))); }
+        };
+
+        let mut turn_done: Vec<Entity> = Vec::new();
+        let mut open_doors: Vec<Entity> = Vec::new();
+        for (ent, pos, schedule, _turn) in (&entities, &positions, &mut schedules, &turns).join() {
+            if schedule.home.is_none() {
+                schedule.home = find_home(&map, pos, &mut rng);
+            }
+            let entry = match active_entry(schedule, hour) {
+                Some(entry) => entry.clone(),
+                None => continue, /* Free time: fall back to the default movement */
+            };
+            let targets = target_buildings(&map, schedule, &entry);
+            if targets.is_empty() { continue; }
+            turn_done.push(ent);
+            let pathing = Pathing::of(ent, &locomotions, &sizes);
+
+            /* Already there, so mill about inside */
+            if let Some(building) = map.building_at(pos.x, pos.y) {
+                if targets.contains(&building) {
+                    let stays_put = match move_modes.get(ent) {
+                        Some(mode) => mode.mode == Movement::Static,
+                        None => true,
+                    };
+                    if !stays_put {
+                        let (mut x, mut y) = (pos.x, pos.y);
+                        match rng.roll_dice(1, 5) {
+                            1 => x -= 1,
+                            2 => x += 1,
+                            3 => y -= 1,
+                            4 => y += 1,
+                            _ => {},
+                        }
+                        let dest_idx = map.xy_idx(x, y);
+                        if map.building_at(x, y) == Some(building) && map.can_stand(x, y, &pathing) {
+                            apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            let my_idx = map.xy_idx(pos.x, pos.y);
+            let goals = interior_tiles(&map, &targets);
+            if let Some((dest_idx, _distance)) = flow_fields.step_toward(&map, pathing, my_idx, &goals) {
+                apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                continue;
+            }
+
+            /* Shut in (or out): make for the nearest closed door and open it */
+            let my_pt = rltk::Point::new(pos.x, pos.y);
+            let adjacent_door = closed_doors.iter().find(|(_, idx)| {
+                let door_pt = rltk::Point::new(*idx as i32 % map.width, *idx as i32 / map.width);
+                rltk::DistanceAlg::Pythagoras.distance2d(my_pt, door_pt) < 1.5
+            });
+            if let Some((door, _)) = adjacent_door {
+                open_doors.push(*door);
+            } else if !closed_doors.is_empty() {
+                let door_tiles: Vec<usize> = closed_doors.iter().map(|(_, idx)| *idx).collect();
+                if let Some((dest_idx, _distance)) = flow_fields.step_toward(&map, pathing, my_idx, &door_tiles) {
+                    apply_move.insert(ent, ApplyMove { dest_idx }).expect("Unable to insert");
+                }
+            }
+        };
+
+        for door_ent in open_doors.iter() {
+            if let Some(door) = doors.get_mut(*door_ent) {
+                door.open = true;
+                blocks_visibility.remove(*door_ent);
+                blocks_tile.remove(*door_ent);
+                if let Some(glyph) = renderables.get_mut(*door_ent) {
+                    glyph.glyph = rltk::to_cp437('/');
+                }
+                if let Some(vs) = viewsheds.get_mut(*player) {
+                    vs.dirty = true;
+                }
+            }
+        };
+
+        for done in turn_done.iter() {
+            turns.remove(*done);
+        };
+    }
+}
+
+fn active_entry (schedule: &Schedule, hour: i32) -> Option<&ScheduleEntry> {
+    schedule.entries.iter().find(|entry| {
+        if entry.start_hour <= entry.end_hour {
+            hour >= entry.start_hour && hour < entry.end_hour
+        } else {
+            /* Wraps past midnight */
+            hour >= entry.start_hour || hour < entry.end_hour
+        }
+    })
+}
+
+fn target_buildings (map: &Map, schedule: &Schedule, entry: &ScheduleEntry) -> Vec<usize> {
+    match entry.location {
+        Some(tag) => map.buildings.iter().enumerate()
+            .filter(|(_, (building_tag, _))| *building_tag == tag)
+            .map(|(i, _)| i)
+            .collect(),
+        None => schedule.home.iter().copied().collect(),
+    }
+}
+
+fn interior_tiles (map: &Map, buildings: &[usize]) -> Vec<usize> {
+    let mut tiles = Vec::new();
+    for building in buildings.iter() {
+        let rect = &map.buildings[*building].1;
+        for y in rect.y1 .. rect.y2 {
+            for x in rect.x1 .. rect.x2 {
+                let idx = map.xy_idx(x, y);
+                if tile_walkable(map.tiles[idx]) { tiles.push(idx); }
+            };
+        };
+    };
+    tiles
+}
+
+/* Whoever lives in a house calls it home; everyone else bunks in a hovel */
+fn find_home (map: &Map, pos: &Position, rng: &mut rltk::RandomNumberGenerator) -> Option<usize> {
+    if let Some(building) = map.building_at(pos.x, pos.y) {
+        match map.buildings[building].0 {
+            BuildingTag::Hovel | BuildingTag::PlayerHouse => return Some(building),
+            _ => {},
+        }
+    }
+    let hovels: Vec<usize> = map.buildings.iter().enumerate()
+        .filter(|(_, (tag, _))| *tag == BuildingTag::Hovel)
+        .map(|(i, _)| i)
+        .collect();
+    if hovels.is_empty() { None } else { Some(hovels[rng.roll_dice(1, hovels.len() as i32) as usize - 1]) }
+}
+
+/// Vendors only trade while their schedule has them working and they are standing in the shop
+pub fn is_at_post (schedule: &Schedule, map: &Map, clock: &GameClock, x: i32, y: i32) -> bool {
+    if !schedule.entries.iter().any(|entry| entry.post) { return true; }
+    match active_entry(schedule, clock.hour()) {
+        Some(entry) if entry.post => match map.building_at(x, y) {
+            Some(building) => target_buildings(map, schedule, entry).contains(&building),
+            None => false,
+        },
+        _ => false,
+    }
+}
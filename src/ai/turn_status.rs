@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use crate::{MyTurn, Confusion, RunState};
+use crate::{MyTurn, Confusion, Held, Abilities, RunState};
 
 pub struct TurnStatusSystem {}
 
@@ -8,14 +8,23 @@ impl<'a> System<'a> for TurnStatusSystem {
     type SystemData = (
         WriteStorage<'a, MyTurn>,
         WriteStorage<'a, Confusion>,
+        WriteStorage<'a, Held>,
+        WriteStorage<'a, Abilities>,
         Entities<'a>,
         ReadExpect<'a, RunState>,
     );
 
     fn run (&mut self, data: Self::SystemData) {
-        let (mut turns, mut confusion, entities, runstate) = data;
+        let (mut turns, mut confusion, mut held, mut abilities, entities, runstate) = data;
         if *runstate != RunState::Ticking { return; }
 
+        /* Abilities recharge a turn at a time, confused or not */
+        for (_turn, abilities) in (&turns, &mut abilities).join() {
+            for ability in abilities.abilities.iter_mut() {
+                if ability.cooldown_left > 0 { ability.cooldown_left -= 1; }
+            };
+        };
+
         let mut not_my_turn: Vec<Entity> = Vec::new();
         let mut not_confused: Vec<Entity> = Vec::new();
         for (ent, _turn, confused) in (&entities, &mut turns, &mut confusion).join() {
@@ -24,7 +33,15 @@ impl<'a> System<'a> for TurnStatusSystem {
             else { not_my_turn.push(ent); }
         };
 
+        /* Held things still get a turn, they just can't go anywhere */
+        let mut freed: Vec<Entity> = Vec::new();
+        for (ent, _turn, hold) in (&entities, &turns, &mut held).join() {
+            hold.turns -= 1;
+            if hold.turns < 1 { freed.push(ent); }
+        };
+
         for e in not_my_turn { turns.remove(e); }
         for e in not_confused { confusion.remove(e); }
+        for e in freed { held.remove(e); }
     }
 }
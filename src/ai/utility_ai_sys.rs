@@ -0,0 +1,354 @@
+use specs::prelude::*;
+use crate::{MyTurn, Faction, Personality, Position, Map, raws::Reaction, Viewshed,
+    WantsToMelee, WantsToApproach, WantsToFlee, WantsToUseItem, WantsToPickupItem, Chasing,
+    Surrendered, Pools, Item, InBackpack, ProvidesHealing, MoveMode, Humanoid, Equippable, Equipped,
+    EquipmentSlot, MeleeWeapon, Wearable, Ranged, InflictsDamage, Confusion, AreaOfEffect, TileSize,
+    Abilities, ChosenAbility, map::footprint_distance};
+use super::{beyond_leash, ready_ability};
+
+pub struct UtilityAI {}
+
+/// How good a piece of kit is in its slot, or None if it can't be worn or wielded
+fn gear_rating (item: Entity, equippable: &ReadStorage<Equippable>, weapons: &ReadStorage<MeleeWeapon>,
+    wearables: &ReadStorage<Wearable>) -> Option<(EquipmentSlot, f32)>
+{
+    let slot = equippable.get(item)?.slot;
+    let rating = if let Some(weapon) = weapons.get(item) {
+        /* Average damage, plus a point per point of accuracy */
+        (weapon.dmg_n_dice * (weapon.dmg_die_type + 1)) as f32 / 2.0 + (weapon.dmg_bonus + weapon.hit_bonus) as f32
+    } else {
+        wearables.get(item).map_or(0.0, |armor| armor.armor_class)
+    };
+    Some((slot, rating))
+}
+
+/// Everything a mob can choose to do with its turn
+enum Action {
+    Attack { target: Entity },
+    Approach { idx: usize, target: Entity },
+    Flee { indices: Vec<usize> },
+    Heal { item: Entity },
+    PickUp { item: Entity, idx: usize },
+    Equip { item: Entity },
+    UseScroll { item: Entity, target: rltk::Point },
+    UseAbility { ability: usize, target: rltk::Point },
+    Wander,
+}
+
+impl<'a> System<'a> for UtilityAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, Personality>,
+        ReadStorage<'a, Position>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadStorage<'a, Viewshed>,
+        ReadStorage<'a, Pools>,
+        ReadStorage<'a, Surrendered>,
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, ProvidesHealing>,
+        WriteStorage<'a, WantsToMelee>,
+        WriteStorage<'a, WantsToApproach>,
+        WriteStorage<'a, WantsToFlee>,
+        WriteStorage<'a, WantsToUseItem>,
+        WriteStorage<'a, WantsToPickupItem>,
+        WriteStorage<'a, Chasing>,
+        ReadStorage<'a, MoveMode>,
+        ReadStorage<'a, Humanoid>,
+        ReadStorage<'a, TileSize>,
+        (ReadStorage<'a, Equippable>, ReadStorage<'a, Equipped>, ReadStorage<'a, MeleeWeapon>, ReadStorage<'a, Wearable>),
+        (ReadStorage<'a, Ranged>, ReadStorage<'a, InflictsDamage>, ReadStorage<'a, Confusion>, ReadStorage<'a, AreaOfEffect>),
+        (ReadStorage<'a, Abilities>, WriteStorage<'a, ChosenAbility>, WriteExpect<'a, rltk::RandomNumberGenerator>),
+    );
+
+    fn run (&mut self, data: Self::SystemData) {
+        let (mut turns, factions, personalities, positions, map, entities, player, viewsheds,
+            pools, surrendered, items, backpack, healing, mut wants_melee, mut wants_approach,
+            mut wants_flee, mut wants_use, mut wants_pickup, mut chasing, move_modes, humanoids, sizes,
+            (equippable, equipped, weapons, wearables), (ranged, inflicts_damage, confusion, aoe),
+            (abilities, mut chosen, mut rng)) = data;
+
+        /* Only a few things are worth a humanoid's while: better kit, potions and attack scrolls */
+        let is_offensive = |item: Entity| ranged.get(item).is_some()
+            && (inflicts_damage.get(item).is_some() || confusion.get(item).is_some());
+
+        let mut decisions: Vec<(Entity, Action)> = Vec::new();
+        {
+            let raws = crate::raws::RAWS.lock().unwrap();
+            for (ent, _turn, my_faction, personality, pos, viewshed, my_pools, _surrendered) in
+                (&entities, &turns, &factions, &personalities, &positions, &viewsheds, &pools, !&surrendered).join()
+            {
+                /* Broken morale already made the decision */
+                if ent == *player || wants_flee.get(ent).is_some() { continue; }
+
+                let my_idx = map.xy_idx(pos.x, pos.y);
+                let my_size = sizes.get(ent);
+                let hp_fraction = my_pools.hit_points.current as f32 / my_pools.hit_points.max as f32;
+                let uses_items = humanoids.get(ent).is_some();
+                let worn: Vec<(EquipmentSlot, f32)> = (&entities, &equipped).join()
+                    .filter(|(_, eq)| eq.owner == ent)
+                    .filter_map(|(item, _)| gear_rating(item, &equippable, &weapons, &wearables))
+                    .collect();
+                let is_upgrade = |item: Entity| match gear_rating(item, &equippable, &weapons, &wearables) {
+                    Some((slot, rating)) => worn.iter().filter(|w| w.0 == slot).all(|w| rating > w.1),
+                    None => false,
+                };
+                let wanted = |item: Entity| uses_items
+                    && (is_upgrade(item) || healing.get(item).is_some() || is_offensive(item));
+
+                /* Look around */
+                let mut adjacent_hostile: Option<Entity> = None;
+                let mut nearest_hostile: Option<(f32, usize, Entity)> = None;
+                let mut nearest_item: Option<(f32, usize, Entity)> = None;
+                let mut threats: Vec<usize> = Vec::new();
+                let mut frightened = false;
+                for visible_tile in viewshed.visible_tiles.iter() {
+                    let idx = map.xy_idx(visible_tile.x, visible_tile.y);
+                    if idx == my_idx { continue; }
+                    /* Measured from our nearest edge, so big creatures fight from any side */
+                    let distance = footprint_distance(pos, my_size, &Position{ x: visible_tile.x, y: visible_tile.y }, None);
+                    crate::spatial::for_each_tile_content(idx, |other_ent| {
+                        if other_ent == ent { return; }
+                        if let Some(their_faction) = factions.get(other_ent) {
+                            match crate::raws::faction_reaction(&my_faction.name, &their_faction.name, &raws) {
+                                Reaction::Attack => {
+                                    threats.push(idx);
+                                    if distance < 1.5 { adjacent_hostile = Some(other_ent); }
+                                    if nearest_hostile.is_none_or(|n| distance < n.0) {
+                                        nearest_hostile = Some((distance, idx, other_ent));
+                                    }
+                                }
+                                Reaction::Flee => {
+                                    threats.push(idx);
+                                    frightened = true;
+                                }
+                                Reaction::Ignore => {},
+                            }
+                        } else if items.get(other_ent).is_some() && wanted(other_ent) && nearest_item.is_none_or(|n| distance < n.0) {
+                            nearest_item = Some((distance, idx, other_ent));
+                        }
+                    });
+                };
+                crate::spatial::for_each_tile_content(my_idx, |other_ent| {
+                    if items.get(other_ent).is_some() && wanted(other_ent) { nearest_item = Some((0.0, my_idx, other_ent)); }
+                });
+                let carried: Vec<Entity> = (&entities, &backpack).join()
+                    .filter(|(_, carried)| carried.owner == ent)
+                    .map(|(item, _)| item)
+                    .collect();
+                let potion = carried.iter().find(|item| healing.get(**item).is_some()).copied();
+                let upgrade = if uses_items { carried.iter().find(|item| is_upgrade(**item)).copied() } else { None };
+                /* A scroll that reaches the nearest enemy without catching the reader in the blast */
+                let scroll = match nearest_hostile {
+                    Some((distance, _, _)) if uses_items => carried.iter().find(|item| {
+                        is_offensive(**item)
+                            && ranged.get(**item).is_some_and(|r| distance <= r.range as f32)
+                            && aoe.get(**item).is_none_or(|a| distance > a.radius as f32 + 1.0)
+                    }).copied(),
+                    _ => None,
+                };
+
+                let ability = match (abilities.get(ent), nearest_hostile) {
+                    (Some(abilities), Some((distance, idx, _))) => ready_ability(abilities, distance, &mut rng).map(|a| (a, idx)),
+                    _ => None,
+                };
+
+                /* Score every available action and keep the best */
+                let score = |action: &str, input: f32| crate::raws::utility_score(&personality.name, action, input, &raws);
+                let mut best = (score("wander", 1.0), Action::Wander);
+                let mut consider = |utility: f32, action: Action| {
+                    if utility > best.0 { best = (utility, action); }
+                };
+                if let Some(target) = adjacent_hostile {
+                    consider(score("attack", hp_fraction), Action::Attack { target });
+                } else if let Some((_, idx, target)) = nearest_hostile {
+                    /* Guards won't be lured away from their post */
+                    let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+                    if !beyond_leash(move_modes.get(ent), &map, x, y) {
+                        consider(score("approach", hp_fraction), Action::Approach { idx, target });
+                    }
+                }
+                if !threats.is_empty() {
+                    let danger = if frightened { 1.0 } else { 1.0 - hp_fraction };
+                    consider(score("flee", danger), Action::Flee { indices: threats });
+                }
+                if let Some(item) = potion {
+                    consider(score("heal", 1.0 - hp_fraction), Action::Heal { item });
+                }
+                if let Some(item) = upgrade {
+                    consider(score("equip", 1.0), Action::Equip { item });
+                }
+                if let (Some(item), Some((_, idx, _))) = (scroll, nearest_hostile) {
+                    let target = rltk::Point::new(idx as i32 % map.width, idx as i32 / map.width);
+                    consider(score("use_scroll", hp_fraction), Action::UseScroll { item, target });
+                }
+                if let Some((ability, idx)) = ability {
+                    let target = rltk::Point::new(idx as i32 % map.width, idx as i32 / map.width);
+                    consider(score("use_ability", hp_fraction), Action::UseAbility { ability, target });
+                }
+                if let Some((distance, idx, item)) = nearest_item {
+                    let closeness = 1.0 - (distance / (viewshed.range as f32 + 1.0));
+                    consider(score("pick_up", closeness), Action::PickUp { item, idx });
+                }
+                decisions.push((ent, best.1));
+            };
+        }
+
+        /* Emit the winners as the usual intents */
+        for (ent, action) in decisions.drain(..) {
+            match action {
+                Action::Attack { target } => {
+                    wants_melee.insert(ent, WantsToMelee { target }).expect("Error inserting melee");
+                    turns.remove(ent);
+                }
+                Action::Approach { idx, target } => {
+                    wants_approach.insert(ent, WantsToApproach { idx: idx as i32 }).expect("Unable to insert");
+                    chasing.insert(ent, Chasing { target }).expect("Unable to insert");
+                }
+                Action::Flee { indices } => {
+                    wants_flee.insert(ent, WantsToFlee { indices }).expect("Unable to insert");
+                }
+                Action::Heal { item } => {
+                    wants_use.insert(ent, WantsToUseItem { item, target: None }).expect("Unable to insert");
+                    turns.remove(ent);
+                }
+                Action::Equip { item } => {
+                    wants_use.insert(ent, WantsToUseItem { item, target: None }).expect("Unable to insert");
+                    turns.remove(ent);
+                }
+                Action::UseScroll { item, target } => {
+                    wants_use.insert(ent, WantsToUseItem { item, target: Some(target) }).expect("Unable to insert");
+                    turns.remove(ent);
+                }
+                Action::UseAbility { ability, target } => {
+                    chosen.insert(ent, ChosenAbility { ability, x: target.x, y: target.y }).expect("Unable to insert");
+                    turns.remove(ent);
+                }
+                Action::PickUp { item, idx } => {
+                    let here = positions.get(ent).is_some_and(|pos| map.xy_idx(pos.x, pos.y) == idx);
+                    if here {
+                        wants_pickup.insert(ent, WantsToPickupItem { collected_by: ent, item }).expect("Unable to insert");
+                        turns.remove(ent);
+                    } else {
+                        wants_approach.insert(ent, WantsToApproach { idx: idx as i32 }).expect("Unable to insert");
+                    }
+                }
+                Action::Wander => {},
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pool, WeaponAttribute};
+    use crate::test_support::{lock_globals, open_map, world};
+
+    /// An open room, with a player parked out of sight
+    fn room () -> World {
+        let mut ecs = world(open_map(1, 12, 12));
+        let player = ecs.create_entity().build();
+        ecs.insert(player);
+        ecs
+    }
+
+    fn mob (ecs: &mut World, x: i32, y: i32, humanoid: bool) -> Entity {
+        let mut visible_tiles = Vec::new();
+        for vy in 1 .. 11 {
+            for vx in 1 .. 11 { visible_tiles.push(rltk::Point::new(vx, vy)); };
+        };
+        let mut builder = ecs.create_entity()
+            .with(Position { x, y })
+            .with(MyTurn {})
+            .with(Faction { name: "Bandits".to_string() })
+            .with(Personality { name: "Default".to_string() })
+            .with(Viewshed { visible_tiles, range: 8, dirty: false })
+            .with(Pools {
+                hit_points: Pool { max: 10, current: 10 }, mana: Pool { max: 10, current: 10 },
+                xp: 0, level: 1, total_weight: 0.0, total_initiative_penalty: 0.0, gold: 0.0, god_mode: false,
+            });
+        if humanoid { builder = builder.with(Humanoid {}); }
+        builder.build()
+    }
+
+    fn sword (ecs: &mut World, dmg_bonus: i32) -> Entity {
+        ecs.create_entity()
+            .with(Item { initiative_penalty: 0.0, weight_lbs: 1.0, base_value: 1.0 })
+            .with(Equippable { slot: EquipmentSlot::Melee })
+            .with(MeleeWeapon { attribute: WeaponAttribute::Might, dmg_n_dice: 1, dmg_die_type: 6, dmg_bonus, hit_bonus: 0 })
+            .build()
+    }
+
+    fn drop_at (ecs: &mut World, item: Entity, x: i32, y: i32) {
+        ecs.write_storage::<Position>().insert(item, Position { x, y }).expect("Unable to insert");
+        crate::spatial::index_entity(item, ecs.fetch::<Map>().xy_idx(x, y), false);
+    }
+
+    #[test]
+    fn gear_is_rated_by_damage_or_armour () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let blade = sword(&mut ecs, 1);
+        let helmet = ecs.create_entity()
+            .with(Equippable { slot: EquipmentSlot::Head })
+            .with(Wearable { armor_class: 2.0, slot: EquipmentSlot::Head })
+            .build();
+        let rock = ecs.create_entity().with(Item { initiative_penalty: 0.0, weight_lbs: 1.0, base_value: 0.0 }).build();
+        let (equippable, weapons, wearables) =
+            (ecs.read_storage::<Equippable>(), ecs.read_storage::<MeleeWeapon>(), ecs.read_storage::<Wearable>());
+        let rate = |item| gear_rating(item, &equippable, &weapons, &wearables);
+        assert!(rate(blade).is_some_and(|(slot, rating)| slot == EquipmentSlot::Melee && rating == 4.5));
+        assert!(rate(helmet).is_some_and(|(slot, rating)| slot == EquipmentSlot::Head && rating == 2.0));
+        assert!(rate(rock).is_none());
+    }
+
+    #[test]
+    fn only_humanoids_go_for_better_kit_on_the_floor () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let bandit = mob(&mut ecs, 3, 3, true);
+        let wolf = mob(&mut ecs, 7, 7, false);
+        let blade = sword(&mut ecs, 1);
+        drop_at(&mut ecs, blade, 3, 3);
+        let other_blade = sword(&mut ecs, 1);
+        drop_at(&mut ecs, other_blade, 7, 7);
+        UtilityAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<WantsToPickupItem>().get(bandit).is_some_and(|w| w.item == blade));
+        assert!(ecs.read_storage::<WantsToPickupItem>().get(wolf).is_none());
+    }
+
+    #[test]
+    fn humanoids_skip_what_they_already_beat () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let bandit = mob(&mut ecs, 3, 3, true);
+        let wielded = sword(&mut ecs, 3);
+        ecs.write_storage::<Equipped>().insert(wielded, Equipped { owner: bandit, slot: EquipmentSlot::Melee })
+            .expect("Unable to insert");
+        let blade = sword(&mut ecs, 1);
+        drop_at(&mut ecs, blade, 3, 3);
+        UtilityAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<WantsToPickupItem>().get(bandit).is_none());
+        assert!(ecs.read_storage::<MyTurn>().get(bandit).is_some());
+    }
+
+    #[test]
+    fn carried_upgrades_get_equipped () {
+        let _globals = lock_globals();
+        let mut ecs = room();
+        let bandit = mob(&mut ecs, 3, 3, true);
+        let wielded = sword(&mut ecs, 0);
+        ecs.write_storage::<Equipped>().insert(wielded, Equipped { owner: bandit, slot: EquipmentSlot::Melee })
+            .expect("Unable to insert");
+        let blade = sword(&mut ecs, 2);
+        ecs.write_storage::<InBackpack>().insert(blade, InBackpack { owner: bandit }).expect("Unable to insert");
+        UtilityAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<WantsToUseItem>().get(bandit).is_some_and(|w| w.item == blade && w.target.is_none()));
+        assert!(ecs.read_storage::<MyTurn>().get(bandit).is_none());
+    }
+}
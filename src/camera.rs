@@ -1,13 +1,21 @@
 use specs::prelude::*;
-use super::{Map, Position, Renderable, Hidden};
+use super::{Map, Position, Renderable, Hidden, TileSize};
 use rltk::{Point, Rltk, RGB};
 use crate::map::tile_glyph;
 
 const SHOW_BOUNDARIES: bool = true;
 
+/* How much of the map the camera shows; maps of any size scroll around inside it */
+pub const VIEW_WIDTH: i32 = 48;
+pub const VIEW_HEIGHT: i32 = 44;
+
 pub fn render_debug_map (map: &Map, ctx: &mut Rltk) {
-    let player_pos = Point::new(map.width / 2, map.height / 2);
     let (x_chars, y_chars) = ctx.get_char_size();
+    /* A map too big for the screen is shrunk to fit, showing one tile in every step x step */
+    let step = i32::max(1, i32::max(
+        (map.width + x_chars as i32 - 1) / x_chars as i32,
+        (map.height + y_chars as i32 - 1) / y_chars as i32));
+    let player_pos = Point::new(map.width / (2 * step), map.height / (2 * step));
 
     let center_x = (x_chars / 2) as i32;
     let center_y = (y_chars / 2) as i32;
@@ -24,6 +32,7 @@ pub fn render_debug_map (map: &Map, ctx: &mut Rltk) {
     for ty in min_y .. max_y {
         let mut x = 0;
         for tx in min_x .. max_x {
+            let (tx, ty) = (tx * step, ty * step);
             if tx > 0 && tx < map_width && ty > 0 && ty < map_height {
                 let idx = map.xy_idx(tx, ty);
                 if map.revealed_tiles[idx] {
@@ -41,7 +50,7 @@ pub fn render_debug_map (map: &Map, ctx: &mut Rltk) {
 
 pub fn get_screen_bounds (ecs: &World, _ctx: &mut Rltk) -> (i32, i32, i32, i32) {
     let player_pos = ecs.fetch::<Point>();
-    let (x_chars, y_chars) = (48, 44);
+    let (x_chars, y_chars) = (VIEW_WIDTH, VIEW_HEIGHT);
 
     let center_x = (x_chars / 2) as i32;
     let center_y = (y_chars / 2) as i32;
@@ -81,18 +90,21 @@ pub fn render_camera (ecs: &World, ctx: &mut Rltk) {
     let positions = ecs.read_storage::<Position>();
     let renderables = ecs.read_storage::<Renderable>();
     let hidden = ecs.read_storage::<Hidden>();
+    let sizes = ecs.read_storage::<TileSize>();
     let map = ecs.fetch::<Map>();
 
-    let mut data = (&positions, &renderables, !&hidden).join().collect::<Vec<_>>();
+    let mut data = (&positions, &renderables, sizes.maybe(), !&hidden).join().collect::<Vec<_>>();
     data.sort_by(|&a, &b| b.1.render_order.cmp(&a.1.render_order));
-    for (pos, render, _hidden) in data.iter() {
-        let idx = map.xy_idx(pos.x, pos.y);
-        if map.visible_tiles[idx] {
-            let ent_screen_x = pos.x - min_x;
-            let ent_screen_y = pos.y - min_y;
-            if ent_screen_x > 0 && ent_screen_y < map_width && ent_screen_y > 0 && ent_screen_y < map_height {
-                ctx.set(ent_screen_x+1, ent_screen_y+1, render.fg, render.bg, render.glyph);
+    for (pos, render, size, _hidden) in data.iter() {
+        /* Big creatures show on every tile they fill that the player can see */
+        for idx in map.footprint(pos.x, pos.y, *size) {
+            if map.visible_tiles[idx] {
+                let ent_screen_x = idx as i32 % map.width - min_x;
+                let ent_screen_y = idx as i32 / map.width - min_y;
+                if (0 .. VIEW_WIDTH).contains(&ent_screen_x) && (0 .. VIEW_HEIGHT).contains(&ent_screen_y) {
+                    ctx.set(ent_screen_x+1, ent_screen_y+1, render.fg, render.bg, render.glyph);
+                }
             }
-        }
+        };
     };
 }
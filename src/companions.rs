@@ -0,0 +1,230 @@
+use specs::prelude::*;
+use crate::{Companion, CompanionOrder, Faction, MoveMode, Movement, Schedule, Surrendered, Hireling,
+    Tameable, Morale, Chasing, Name, Pools, Position, Viewshed, InBackpack, ProvidesFood, Map,
+    gamelog::GameLog, raws::{Reaction, COMPANION_FACTION}};
+
+const FOLLOW_DISTANCE: i32 = 2;
+const STAY_RADIUS: i32 = 3;
+
+/// Turns a bystander (or a beaten animal) into one of the player's companions
+fn recruit (ecs: &mut World, recruit: Entity) {
+    let player = *ecs.fetch::<Entity>();
+    ecs.write_storage::<Companion>().insert(recruit, Companion { order: CompanionOrder::Follow })
+        .expect("Unable to insert");
+    ecs.write_storage::<Faction>().insert(recruit, Faction { name: COMPANION_FACTION.to_string() })
+        .expect("Unable to insert");
+    ecs.write_storage::<MoveMode>().insert(recruit, MoveMode { mode: Movement::Follow { leader: player, distance: FOLLOW_DISTANCE } })
+        .expect("Unable to insert");
+    ecs.write_storage::<Schedule>().remove(recruit);
+    ecs.write_storage::<Surrendered>().remove(recruit);
+    ecs.write_storage::<Hireling>().remove(recruit);
+    ecs.write_storage::<Tameable>().remove(recruit);
+    ecs.write_storage::<Chasing>().remove(recruit);
+    if let Some(morale) = ecs.write_storage::<Morale>().get_mut(recruit) {
+        morale.current = morale.base;
+        morale.broken = false;
+        morale.cowering = false;
+    }
+}
+
+fn name_of (ecs: &World, ent: Entity) -> String {
+    ecs.read_storage::<Name>().get(ent).map_or("someone".to_string(), |n| n.name.clone())
+}
+
+/// Pays a hireling's fee out of the player's purse
+pub fn hire (ecs: &mut World, hireling: Entity) {
+    let player = *ecs.fetch::<Entity>();
+    let cost = match ecs.read_storage::<Hireling>().get(hireling) {
+        Some(hireling) => hireling.cost,
+        None => return,
+    };
+    let name = name_of(ecs, hireling);
+    let can_afford = ecs.read_storage::<Pools>().get(player).is_some_and(|p| p.gold >= cost);
+    if !can_afford {
+        ecs.fetch_mut::<GameLog>().entries.push(format!("You can't afford to hire the {}.", name));
+        return;
+    }
+    if let Some(pools) = ecs.write_storage::<Pools>().get_mut(player) {
+        pools.gold -= cost;
+    }
+    recruit(ecs, hireling);
+    ecs.fetch_mut::<GameLog>().entries.push(format!("The {} joins you for {:.1} gold.", name, cost));
+}
+
+/// A tameable creature that has given up can be won over with food
+pub fn try_tame (ecs: &mut World, animal: Entity) -> bool {
+    if ecs.read_storage::<Tameable>().get(animal).is_none() || ecs.read_storage::<Surrendered>().get(animal).is_none() {
+        return false;
+    }
+    let player = *ecs.fetch::<Entity>();
+    let food = (&ecs.entities(), &ecs.read_storage::<InBackpack>(), &ecs.read_storage::<ProvidesFood>()).join()
+        .find(|(_, carried, _)| carried.owner == player)
+        .map(|(ent, _, _)| ent);
+    let name = name_of(ecs, animal);
+    match food {
+        None => {
+            ecs.fetch_mut::<GameLog>().entries.push(format!("The {} looks hungry.", name));
+            false
+        }
+        Some(food) => {
+            ecs.delete_entity(food).expect("Delete failed");
+            recruit(ecs, animal);
+            ecs.fetch_mut::<GameLog>().entries.push(format!("You feed the {}. It decides to follow you.", name));
+            true
+        }
+    }
+}
+
+/// The closest hostile the player can see, for "attack" orders
+fn nearest_visible_hostile (ecs: &World) -> Option<Entity> {
+    let player = *ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let factions = ecs.read_storage::<Faction>();
+    let player_pos = positions.get(player)?;
+    let player_pt = rltk::Point::new(player_pos.x, player_pos.y);
+    let raws = crate::raws::RAWS.lock().unwrap();
+
+    let mut nearest: Option<(f32, Entity)> = None;
+    for tile in viewsheds.get(player)?.visible_tiles.iter() {
+        let distance = rltk::DistanceAlg::Pythagoras.distance2d(player_pt, *tile);
+        crate::spatial::for_each_tile_content(map.xy_idx(tile.x, tile.y), |ent| {
+            if let Some(faction) = factions.get(ent) {
+                let hostile = crate::raws::faction_reaction(COMPANION_FACTION, &faction.name, &raws) == Reaction::Attack;
+                if hostile && nearest.is_none_or(|n| distance < n.0) {
+                    nearest = Some((distance, ent));
+                }
+            }
+        });
+    };
+    nearest.map(|(_, ent)| ent)
+}
+
+/// Sets every companion on the level on the nearest enemy the player can see
+pub fn order_attack (ecs: &mut World) {
+    match nearest_visible_hostile(ecs) {
+        Some(target) => give_order(ecs, CompanionOrder::Attack { target }),
+        None => ecs.fetch_mut::<GameLog>().entries.push("There is nothing to attack.".to_string()),
+    }
+}
+
+/// Orders every companion on the level
+pub fn give_order (ecs: &mut World, order: CompanionOrder) {
+    let player = *ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let mut companions = ecs.write_storage::<Companion>();
+    let mut move_modes = ecs.write_storage::<MoveMode>();
+    let mut chasing = ecs.write_storage::<Chasing>();
+    let mut count = 0;
+    for (ent, companion, pos) in (&entities, &mut companions, &positions).join() {
+        count += 1;
+        companion.order = order.clone();
+        chasing.remove(ent);
+        let mode = match order {
+            CompanionOrder::Stay => Movement::Guard { home: map.xy_idx(pos.x, pos.y), radius: STAY_RADIUS },
+            CompanionOrder::Hold => Movement::Static,
+            _ => Movement::Follow { leader: player, distance: FOLLOW_DISTANCE },
+        };
+        move_modes.insert(ent, MoveMode { mode }).expect("Unable to insert");
+    };
+
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+    if count == 0 {
+        gamelog.entries.push("You have no companions here.".to_string());
+    } else {
+        gamelog.entries.push(match order {
+            CompanionOrder::Follow => "You tell your companions to follow you.",
+            CompanionOrder::Stay => "You tell your companions to stay here.",
+            CompanionOrder::Attack { .. } => "You order your companions to attack!",
+            CompanionOrder::Hold => "You tell your companions to hold their position.",
+        }.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pool;
+    use crate::test_support::{lock_globals, open_map, world};
+
+    /// A player with a purse, and nobody else about yet
+    fn party (player_gold: f32) -> World {
+        let mut ecs = world(open_map(1, 20, 20));
+        let player = ecs.create_entity()
+            .with(Position { x: 5, y: 5 })
+            .with(Pools {
+                hit_points: Pool { max: 10, current: 10 }, mana: Pool { max: 0, current: 0 },
+                xp: 0, level: 1, total_weight: 0.0, total_initiative_penalty: 0.0, gold: player_gold, god_mode: false,
+            })
+            .build();
+        ecs.insert(player);
+        ecs
+    }
+
+    fn is_companion (ecs: &World, ent: Entity) -> bool {
+        ecs.read_storage::<Companion>().get(ent).is_some()
+            && ecs.read_storage::<Faction>().get(ent).is_some_and(|f| f.name == COMPANION_FACTION)
+    }
+
+    #[test]
+    fn hirelings_join_for_their_fee () {
+        let _globals = lock_globals();
+        let mut ecs = party(30.0);
+        let cheap = ecs.create_entity().with(Hireling { cost: 20.0 }).with(Faction { name: "Townsfolk".to_string() }).build();
+        let dear = ecs.create_entity().with(Hireling { cost: 20.0 }).build();
+        hire(&mut ecs, cheap);
+        assert!(is_companion(&ecs, cheap));
+        assert!(ecs.read_storage::<Hireling>().get(cheap).is_none());
+        let player = *ecs.fetch::<Entity>();
+        assert_eq!(ecs.read_storage::<Pools>().get(player).unwrap().gold, 10.0);
+
+        hire(&mut ecs, dear);
+        assert!(!is_companion(&ecs, dear));
+        assert_eq!(ecs.read_storage::<Pools>().get(player).unwrap().gold, 10.0);
+    }
+
+    #[test]
+    fn beaten_animals_can_be_fed_into_following () {
+        let _globals = lock_globals();
+        let mut ecs = party(0.0);
+        let player = *ecs.fetch::<Entity>();
+        let wolf = ecs.create_entity().with(Tameable {})
+            .with(Morale { base: 8.0, current: 1.0, broken: true, cowering: true, can_surrender: true })
+            .build();
+        /* Not while it's still fighting */
+        assert!(!try_tame(&mut ecs, wolf));
+        ecs.write_storage::<Surrendered>().insert(wolf, Surrendered {}).unwrap();
+        /* Nor without food */
+        assert!(!try_tame(&mut ecs, wolf));
+
+        let food = ecs.create_entity().with(ProvidesFood {}).with(InBackpack { owner: player }).build();
+        assert!(try_tame(&mut ecs, wolf));
+        ecs.maintain();
+        assert!(!ecs.is_alive(food));
+        assert!(is_companion(&ecs, wolf));
+        assert!(ecs.read_storage::<Surrendered>().get(wolf).is_none());
+        let morale = ecs.read_storage::<Morale>().get(wolf).cloned().unwrap();
+        assert!(morale.current == morale.base && !morale.broken && !morale.cowering);
+        assert!(matches!(ecs.read_storage::<MoveMode>().get(wolf).unwrap().mode, Movement::Follow { leader, .. } if leader == player));
+    }
+
+    #[test]
+    fn orders_set_how_companions_move () {
+        let _globals = lock_globals();
+        let mut ecs = party(100.0);
+        let hireling = ecs.create_entity().with(Hireling { cost: 1.0 }).with(Position { x: 7, y: 3 }).build();
+        hire(&mut ecs, hireling);
+
+        give_order(&mut ecs, CompanionOrder::Stay);
+        let home = ecs.fetch::<Map>().xy_idx(7, 3);
+        assert!(ecs.read_storage::<MoveMode>().get(hireling).unwrap().mode == Movement::Guard { home, radius: STAY_RADIUS });
+        give_order(&mut ecs, CompanionOrder::Hold);
+        assert!(ecs.read_storage::<MoveMode>().get(hireling).unwrap().mode == Movement::Static);
+        give_order(&mut ecs, CompanionOrder::Follow);
+        assert!(matches!(ecs.read_storage::<MoveMode>().get(hireling).unwrap().mode, Movement::Follow { .. }));
+        assert!(ecs.read_storage::<Companion>().get(hireling).unwrap().order == CompanionOrder::Follow);
+    }
+}
@@ -2,9 +2,10 @@ use specs::saveload::{Marker, ConvertSaveload};
 use specs::error::NoError;
 use specs::prelude::*;
 use specs_derive::*;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use std::collections::HashMap;
 use rltk::RGB;
+use super::map::LevelId;
 
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Position {
@@ -31,6 +32,7 @@ pub struct Quips {
 #[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct Vendor {
     pub categories: Vec<String>,
+    pub restock_turn: i32,
 }
 
 #[derive(Component, Clone, ConvertSaveload, Debug)]
@@ -99,6 +101,126 @@ pub struct Faction {
     pub name: String,
 }
 
+/// The player's standing with each faction, from -100 (hated) to 100 (revered)
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Reputation {
+    pub standings: HashMap<String, i32>,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Personality {
+    pub name: String,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Morale {
+    pub base: f32,
+    pub current: f32,
+    pub broken: bool,
+    pub cowering: bool,
+    pub can_surrender: bool,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Surrendered {}
+
+/// Split off another mob: it has nothing to drop and is worth no experience, so splitting
+/// doesn't multiply the rewards
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Offshoot {}
+
+/// Coins on the ground; they go straight into the purse of whoever picks them up
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct GoldPile {
+    pub amount: f32,
+}
+
+/// Still someone's, so taking it is theft if they're law-abiding (and someone sees)
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct OwnedBy {
+    pub owner: Entity,
+}
+
+#[derive(PartialEq, Clone, ConvertSaveload, Debug)]
+pub enum CompanionOrder {
+    Follow,
+    Stay,
+    Attack { target: Entity },
+    Hold,
+}
+
+/// An ally travelling with the player
+#[derive(Component, Clone, ConvertSaveload, Debug)]
+pub struct Companion {
+    pub order: CompanionOrder,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Hireling {
+    pub cost: f32,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Tameable {}
+
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum AbilityTrigger { Turn, Hurt }
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum AbilityEffect {
+    Damage { n_dice: i32, die_type: i32, bonus: i32 },
+    Confuse { turns: i32 },
+    Hold { turns: i32 },
+    Summon { name: String, count: i32 },
+    /* The raw the copy is made from */
+    Split { mob: String },
+}
+
+/// A special move: used with `chance` when a foe is within `range` (or when hurt),
+/// then unavailable for `cooldown` of the mob's turns
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Ability {
+    pub name: String,
+    pub trigger: AbilityTrigger,
+    pub chance: f32,
+    pub cooldown: i32,
+    pub cooldown_left: i32,
+    pub range: f32,
+    pub area: i32,
+    pub effects: Vec<AbilityEffect>,
+}
+
+#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+pub struct Abilities {
+    pub abilities: Vec<Ability>,
+}
+
+/// Index into the user's `Abilities`, aimed at a tile
+#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+pub struct WantsToUseAbility {
+    pub ability: usize,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// The utility AI picked one of the user's `Abilities` for this turn; AbilityAI sets it off
+#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+pub struct ChosenAbility {
+    pub ability: usize,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Has hands: picks up, wears and uses items it finds
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Humanoid {}
+
+/// One of a kind: exists at most once per run, and makes an entrance
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Unique {
+    pub announced: bool,
+}
+
 #[derive(Component, Clone, ConvertSaveload, Debug)]
 pub struct WantsToMelee {
     pub target: Entity,
@@ -119,18 +241,122 @@ pub struct Chasing {
     pub target: Entity,
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum Movement {
     Static,
     Random,
     RandomWaypoint { path: Option<Vec<usize>> },
+    Patrol { waypoints: Vec<usize>, next: usize },
+    Guard { home: usize, radius: i32 },
+    Follow { leader: Entity, distance: i32 },
 }
 
-#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+/* The ConvertSaveload derive can't handle enum variants with several named fields */
+#[derive(Serialize, Deserialize, Clone)]
+pub enum MovementData<M> {
+    Static,
+    Random,
+    RandomWaypoint { path: Option<Vec<usize>> },
+    Patrol { waypoints: Vec<usize>, next: usize },
+    Guard { home: usize, radius: i32 },
+    Follow { leader: M, distance: i32 },
+}
+
+impl<M: Serialize + DeserializeOwned> ConvertSaveload<M> for Movement {
+    type Data = MovementData<M>;
+    type Error = NoError;
+
+    fn convert_into<F> (&self, mut ids: F) -> Result<Self::Data, Self::Error>
+        where F: FnMut(Entity) -> Option<M>
+    {
+        Ok(match self {
+            Movement::Static => MovementData::Static,
+            Movement::Random => MovementData::Random,
+            Movement::RandomWaypoint { path } => MovementData::RandomWaypoint { path: path.clone() },
+            Movement::Patrol { waypoints, next } => MovementData::Patrol { waypoints: waypoints.clone(), next: *next },
+            Movement::Guard { home, radius } => MovementData::Guard { home: *home, radius: *radius },
+            Movement::Follow { leader, distance } => MovementData::Follow {
+                leader: ConvertSaveload::convert_into(leader, &mut ids)?,
+                distance: *distance
+            },
+        })
+    }
+
+    fn convert_from<F> (data: Self::Data, mut ids: F) -> Result<Self, Self::Error>
+        where F: FnMut(M) -> Option<Entity>
+    {
+        Ok(match data {
+            MovementData::Static => Movement::Static,
+            MovementData::Random => Movement::Random,
+            MovementData::RandomWaypoint { path } => Movement::RandomWaypoint { path },
+            MovementData::Patrol { waypoints, next } => Movement::Patrol { waypoints, next },
+            MovementData::Guard { home, radius } => Movement::Guard { home, radius },
+            MovementData::Follow { leader, distance } => Movement::Follow {
+                leader: ConvertSaveload::convert_from(leader, &mut ids)?,
+                distance
+            },
+        })
+    }
+}
+
+#[derive(Component, Clone, ConvertSaveload, Debug)]
 pub struct MoveMode {
     pub mode: Movement,
 }
 
+/// How an entity gets about, if it isn't simply on foot
+#[derive(Component, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct Locomotion {
+    pub flying: bool,
+    pub swimming: bool,
+    pub phasing: bool,
+    pub heavy: bool,
+}
+
+/// Keeps the peace in town: comes running when the player is wanted
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct TownGuard {}
+
+/// The player has been seen breaking the law, and owes a fine. Anyone who fights
+/// back (or can't pay) gets no more chances.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Wanted {
+    pub fine: f32,
+    pub resisting: bool,
+}
+
+/// Something the player just did that the locals might take exception to
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct CrimeCommitted {
+    pub victim: Entity,
+    pub x: i32,
+    pub y: i32,
+    pub fine: f32,
+    pub violent: bool,
+    pub caught: bool,
+}
+
+/// Covers more than one tile: `Position` is the top-left corner of a `x` by `y` footprint
+#[derive(Component, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct TileSize {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScheduleEntry {
+    pub start_hour: i32,
+    pub end_hour: i32,
+    pub location: Option<super::map::BuildingTag>, /* None = home */
+    pub post: bool,
+}
+
+#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+pub struct Schedule {
+    pub entries: Vec<ScheduleEntry>,
+    pub home: Option<usize>,
+}
+
 #[derive(Component, Clone, Serialize, Deserialize, Debug)]
 pub struct ApplyMove {
     pub dest_idx: usize,
@@ -140,7 +366,7 @@ pub struct ApplyMove {
 pub struct ApplyTeleport {
     pub dest_x: i32,
     pub dest_y: i32,
-    pub dest_depth: i32,
+    pub dest_level: LevelId,
 }
 
 #[derive(Component, Clone, Serialize, Deserialize, Debug)]
@@ -150,7 +376,7 @@ pub struct TownPortal {}
 pub struct TeleportTo {
     pub x: i32,
     pub y: i32,
-    pub depth: i32,
+    pub level: LevelId,
     pub player_only: bool,
 }
 
@@ -174,7 +400,16 @@ impl SufferDamage {
 pub struct OtherLevelPosition {
     pub x: i32,
     pub y: i32,
-    pub depth: i32,
+    pub level: LevelId,
+}
+
+/// Followed the player off a level; turns up at (x, y) on `level` once the clock reaches `arrival_turn`
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct Pursuer {
+    pub x: i32,
+    pub y: i32,
+    pub level: LevelId,
+    pub arrival_turn: i32,
 }
 
 #[derive(Component, Clone, Serialize, Deserialize)]
@@ -205,6 +440,7 @@ pub struct SerializationHelper {
 #[derive(Component, Clone, Serialize, Deserialize)]
 pub struct DMSerializationHelper {
     pub map : super::map::MasterDungeonMap,
+    pub clock : super::GameClock,
 }
 
 /* Items */
@@ -215,6 +451,13 @@ pub struct Item {
     pub base_value: f32,
 }
 
+/// Wear and tear on equipment; items without one are as good as new
+#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+pub struct Durability {
+    pub current: i32,
+    pub max: i32,
+}
+
 #[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize, Debug)]
 pub enum MagicItemClass { Common, Rare, Legendary }
 
@@ -274,6 +517,12 @@ pub struct Confusion {
     pub turns : i32,
 }
 
+/// Stuck fast (engulfed, webbed) and unable to move for a while
+#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+pub struct Held {
+    pub turns: i32,
+}
+
 /* Equipment */
 #[derive(PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum EquipmentSlot { Melee, Shield, Head, Torso, Legs, Feet, Hands }
@@ -0,0 +1,309 @@
+use specs::prelude::*;
+use rltk::Point;
+use crate::{CrimeCommitted, Wanted, TownGuard, Faction, Reputation, Pools, Position, Viewshed, Attributes,
+    Name, InBackpack, EquipmentChanged, Map, gamelog::GameLog, map::tile_walkable,
+    raws::{RAWS, SpawnType, is_peaceful_faction}};
+
+/* What the watch charges */
+const ASSAULT_FINE: f32 = 50.0;
+const MURDER_FINE: f32 = 200.0;
+const THEFT_FINE_RATE: f32 = 2.0;
+const MIN_THEFT_FINE: f32 = 10.0;
+/* Picking a pocket: a d20 plus quickness has to beat this plus the victim's wits */
+const STEAL_DC: i32 = 10;
+/* Spotting someone else's pocket being picked: a d20 plus wits, less a point for every
+   two tiles away, has to beat this plus the thief's quickness */
+const SPOT_DC: i32 = 12;
+const CRIME_REPUTATION: i32 = 10;
+const GUARDS_SUMMONED: usize = 2;
+const GUARD_SPAWN_DISTANCE: f32 = 10.0;
+const GUARD_MOB: &str = "Town Guard";
+
+fn name_of (ecs: &World, ent: Entity) -> String {
+    ecs.read_storage::<Name>().get(ent).map_or("someone".to_string(), |n| n.name.clone())
+}
+
+fn is_peaceful (ecs: &World, ent: Entity) -> bool {
+    ecs.read_storage::<Faction>().get(ent)
+        .is_some_and(|faction| is_peaceful_faction(&faction.name, &RAWS.lock().unwrap()))
+}
+
+/// Notes down a crime for the locals to (maybe) notice at the end of the turn. Several
+/// crimes in one turn are rolled into one, at the total fine.
+pub fn record_crime (crimes: &mut WriteStorage<CrimeCommitted>, player: Entity, crime: CrimeCommitted) {
+    match crimes.get_mut(player) {
+        Some(existing) => {
+            existing.fine += crime.fine;
+            existing.violent |= crime.violent;
+            existing.caught |= crime.caught;
+        }
+        None => { crimes.insert(player, crime).expect("Unable to insert"); }
+    }
+}
+
+/// The fine for hurting (or killing) a law-abiding victim
+pub fn assault_fine (killed: bool) -> f32 {
+    if killed { MURDER_FINE } else { ASSAULT_FINE }
+}
+
+/// The fine for taking something worth `value` that wasn't yours
+pub fn theft_fine (value: f32) -> f32 {
+    f32::max(MIN_THEFT_FINE, value * THEFT_FINE_RATE)
+}
+
+/// The player tries to lift an item (or, with None, some gold) from the victim. Bungling it
+/// gets you caught red-handed; getting away with it only means the victim didn't notice.
+pub fn steal (ecs: &mut World, victim: Entity, item: Option<Entity>) {
+    let player = *ecs.fetch::<Entity>();
+    let victim_name = name_of(ecs, victim);
+    let (quickness, wits) = {
+        let attributes = ecs.read_storage::<Attributes>();
+        (attributes.get(player).map_or(0, |a| a.quickness.bonus), attributes.get(victim).map_or(0, |a| a.intelligence.bonus))
+    };
+    let caught = ecs.write_resource::<rltk::RandomNumberGenerator>().roll_dice(1, 20) + quickness < STEAL_DC + wits;
+
+    let value = match item {
+        Some(item) => {
+            let value = crate::vendors::item_value(ecs, item);
+            if !caught {
+                let item_name = name_of(ecs, item);
+                ecs.write_storage::<InBackpack>().insert(item, InBackpack { owner: player }).expect("Unable to insert");
+                let mut dirty = ecs.write_storage::<EquipmentChanged>();
+                dirty.insert(player, EquipmentChanged {}).expect("Unable to insert");
+                dirty.insert(victim, EquipmentChanged {}).expect("Unable to insert");
+                std::mem::drop(dirty);
+                ecs.fetch_mut::<GameLog>().entries.push(format!("You lift the {} from the {}.", item_name, victim_name));
+            }
+            value
+        }
+        None => {
+            let purse = ecs.read_storage::<Pools>().get(victim).map_or(0.0, |p| p.gold);
+            let most = i32::max(1, purse as i32 / 2);
+            let amount = f32::min(purse, ecs.write_resource::<rltk::RandomNumberGenerator>().roll_dice(1, most) as f32);
+            if !caught {
+                let mut pools = ecs.write_storage::<Pools>();
+                if let Some(theirs) = pools.get_mut(victim) { theirs.gold -= amount; }
+                if let Some(mine) = pools.get_mut(player) { mine.gold += amount; }
+                std::mem::drop(pools);
+                ecs.fetch_mut::<GameLog>().entries.push(format!("You lift {:.1} gold from the {}'s purse.", amount, victim_name));
+            }
+            amount
+        }
+    };
+    if caught {
+        ecs.fetch_mut::<GameLog>().entries.push(format!("The {} catches your hand in their pocket!", victim_name));
+    }
+
+    /* Robbing bandits is nobody's business */
+    if !is_peaceful(ecs, victim) { return; }
+    let (x, y) = match ecs.read_storage::<Position>().get(victim) {
+        Some(pos) => (pos.x, pos.y),
+        None => return,
+    };
+    let crime = CrimeCommitted {
+        victim, x, y,
+        fine: theft_fine(value),
+        violent: false,
+        caught,
+    };
+    record_crime(&mut ecs.write_storage::<CrimeCommitted>(), player, crime);
+}
+
+/// The first law-abiding onlooker to notice the crime, if anyone does. Violence is always
+/// noticed by anyone who can see it; sleight of hand has to be spotted.
+fn find_witness (ecs: &World, player: Entity, crime: &CrimeCommitted) -> Option<Entity> {
+    let entities = ecs.entities();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let positions = ecs.read_storage::<Position>();
+    let pools = ecs.read_storage::<Pools>();
+    let attributes = ecs.read_storage::<Attributes>();
+    let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
+    let quickness = attributes.get(player).map_or(0, |a| a.quickness.bonus);
+    let scene = Point::new(crime.x, crime.y);
+
+    for (ent, viewshed, pos, pool) in (&entities, &viewsheds, &positions, &pools).join() {
+        if ent == player || pool.hit_points.current < 1 || !is_peaceful(ecs, ent) { continue; }
+        if ent != crime.victim && !viewshed.visible_tiles.contains(&scene) { continue; }
+        if crime.violent || (crime.caught && ent == crime.victim) { return Some(ent); }
+        let distance = rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), scene);
+        let wits = attributes.get(ent).map_or(0, |a| a.intelligence.bonus);
+        if rng.roll_dice(1, 20) + wits - distance as i32 / 2 >= SPOT_DC + quickness {
+            return Some(ent);
+        }
+    };
+    None
+}
+
+/// Sends for the watch, from somewhere out of the player's sight but within reach
+fn summon_guards (ecs: &mut World) {
+    let on_duty = (&ecs.read_storage::<TownGuard>(), &ecs.read_storage::<Position>()).join().count();
+    if on_duty >= GUARDS_SUMMONED { return; }
+
+    let mut spots: Vec<(i32, i32)> = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = *ecs.fetch::<Point>();
+        let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+        let reach = rltk::DijkstraMap::new(map.width as usize, map.height as usize, &[player_idx], &*map, 200.0);
+        (0 .. map.tiles.len())
+            .filter(|idx| tile_walkable(map.tiles[*idx]) && !map.visible_tiles[*idx]
+                && !crate::spatial::is_blocked(*idx) && reach.map[*idx] < f32::MAX)
+            .map(|idx| (idx as i32 % map.width, idx as i32 / map.width))
+            .filter(|(x, y)| rltk::DistanceAlg::Pythagoras.distance2d(player_pos, Point::new(*x, *y)) >= GUARD_SPAWN_DISTANCE)
+            .collect()
+    };
+
+    let mut summoned = 0;
+    for _i in on_duty .. GUARDS_SUMMONED {
+        if spots.is_empty() { break; }
+        let pick = ecs.write_resource::<rltk::RandomNumberGenerator>().roll_dice(1, spots.len() as i32) as usize - 1;
+        let (x, y) = spots.remove(pick);
+        if crate::raws::spawn_named_mob(&RAWS.lock().unwrap(), ecs, GUARD_MOB, SpawnType::AtPosition { x, y }).is_some() {
+            summoned += 1;
+        }
+    };
+    if summoned > 0 {
+        ecs.fetch_mut::<GameLog>().entries.push("You hear the tramp of boots: the watch is on its way.".to_string());
+    }
+}
+
+/// Rolls for witnesses to whatever the player got up to this turn. Anyone who saw it raises
+/// the alarm: the fine goes up, the victim's people think less of the player, and the watch
+/// is sent for.
+pub fn report_crimes (ecs: &mut World) {
+    let player = *ecs.fetch::<Entity>();
+    let crime = match ecs.write_storage::<CrimeCommitted>().remove(player) {
+        Some(crime) => crime,
+        None => return,
+    };
+    let witness = match find_witness(ecs, player, &crime) {
+        Some(witness) => witness,
+        None => return,
+    };
+
+    let witness_name = name_of(ecs, witness);
+    ecs.fetch_mut::<GameLog>().entries.push(format!("The {} shouts for the guards!", witness_name));
+    /* Laying hands on the watch itself is past paying off */
+    let against_watch = crime.violent && ecs.read_storage::<TownGuard>().get(crime.victim).is_some();
+    {
+        let mut wanted = ecs.write_storage::<Wanted>();
+        match wanted.get_mut(player) {
+            Some(wanted) => {
+                wanted.fine += crime.fine;
+                wanted.resisting |= against_watch;
+            }
+            None => { wanted.insert(player, Wanted { fine: crime.fine, resisting: against_watch }).expect("Unable to insert"); }
+        }
+    }
+    let faction = ecs.read_storage::<Faction>().get(crime.victim).map(|f| f.name.clone());
+    if let (Some(faction), Some(reputation)) = (faction, ecs.write_storage::<Reputation>().get_mut(player)) {
+        *reputation.standings.entry(faction).or_insert(0) -= CRIME_REPUTATION;
+    }
+    summon_guards(ecs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pool;
+    use crate::test_support::{lock_globals, open_map, world};
+    use std::collections::HashMap;
+
+    /// A town square with two guards already on duty, so nobody new is sent for
+    fn square () -> World {
+        let mut ecs = world(open_map(1, 20, 20));
+        ecs.insert(Point::new(2, 2));
+        let player = ecs.create_entity()
+            .with(Position { x: 2, y: 2 })
+            .with(pools(10.0))
+            .with(Reputation { standings: HashMap::new() })
+            .build();
+        ecs.insert(player);
+        for x in 17 .. 19 {
+            ecs.create_entity().with(TownGuard {}).with(Position { x, y: 18 }).build();
+        };
+        ecs
+    }
+
+    fn pools (gold: f32) -> Pools {
+        Pools {
+            hit_points: Pool { max: 10, current: 10 }, mana: Pool { max: 10, current: 10 },
+            xp: 0, level: 1, total_weight: 0.0, total_initiative_penalty: 0.0, gold, god_mode: false,
+        }
+    }
+
+    /// Someone of the given faction at (x, y), who can see the tiles listed
+    fn local (ecs: &mut World, x: i32, y: i32, faction: &str, sees: &[(i32, i32)]) -> Entity {
+        let visible_tiles = sees.iter().map(|(x, y)| Point::new(*x, *y)).collect();
+        ecs.create_entity()
+            .with(Position { x, y })
+            .with(pools(20.0))
+            .with(Faction { name: faction.to_string() })
+            .with(Viewshed { visible_tiles, range: 8, dirty: false })
+            .build()
+    }
+
+    fn crime (victim: Entity, fine: f32, violent: bool) -> CrimeCommitted {
+        CrimeCommitted { victim, x: 5, y: 5, fine, violent, caught: false }
+    }
+
+    #[test]
+    fn fines_fit_the_crime () {
+        assert_eq!(assault_fine(false), ASSAULT_FINE);
+        assert_eq!(assault_fine(true), MURDER_FINE);
+        assert_eq!(theft_fine(30.0), 60.0);
+        assert_eq!(theft_fine(1.0), MIN_THEFT_FINE);
+    }
+
+    #[test]
+    fn crimes_in_one_turn_add_up () {
+        let _globals = lock_globals();
+        let ecs = square();
+        let player = *ecs.fetch::<Entity>();
+        let mut crimes = ecs.write_storage::<CrimeCommitted>();
+        record_crime(&mut crimes, player, crime(player, 10.0, false));
+        record_crime(&mut crimes, player, CrimeCommitted { caught: true, ..crime(player, 50.0, true) });
+        let total = crimes.get(player).unwrap();
+        assert!(total.fine == 60.0 && total.violent && total.caught);
+    }
+
+    #[test]
+    fn seen_violence_makes_the_player_wanted () {
+        let _globals = lock_globals();
+        let mut ecs = square();
+        let player = *ecs.fetch::<Entity>();
+        let victim = local(&mut ecs, 5, 5, "Townsfolk", &[]);
+        local(&mut ecs, 8, 8, "Townsfolk", &[(5, 5)]);
+        record_crime(&mut ecs.write_storage::<CrimeCommitted>(), player, crime(victim, 50.0, true));
+        report_crimes(&mut ecs);
+        assert!(ecs.read_storage::<Wanted>().get(player).is_some_and(|w| w.fine == 50.0 && !w.resisting));
+        assert_eq!(ecs.read_storage::<Reputation>().get(player).unwrap().standings["Townsfolk"], -CRIME_REPUTATION);
+        assert!(ecs.read_storage::<CrimeCommitted>().get(player).is_none());
+
+        /* A second offence adds to the fine, and striking the watch can't be paid off */
+        ecs.write_storage::<TownGuard>().insert(victim, TownGuard {}).expect("Unable to insert");
+        record_crime(&mut ecs.write_storage::<CrimeCommitted>(), player, crime(victim, 50.0, true));
+        report_crimes(&mut ecs);
+        assert!(ecs.read_storage::<Wanted>().get(player).is_some_and(|w| w.fine == 100.0 && w.resisting));
+    }
+
+    #[test]
+    fn unseen_or_lawless_victims_raise_no_alarm () {
+        let _globals = lock_globals();
+        let mut ecs = square();
+        let player = *ecs.fetch::<Entity>();
+        /* The victim was out cold, and the only onlooker was looking the other way */
+        let victim = local(&mut ecs, 5, 5, "Townsfolk", &[]);
+        ecs.write_storage::<Pools>().get_mut(victim).unwrap().hit_points.current = 0;
+        local(&mut ecs, 8, 8, "Townsfolk", &[(9, 9)]);
+        let bandit = local(&mut ecs, 6, 5, "Bandits", &[(5, 5)]);
+        record_crime(&mut ecs.write_storage::<CrimeCommitted>(), player, crime(victim, 200.0, true));
+        report_crimes(&mut ecs);
+        assert!(ecs.read_storage::<Wanted>().get(player).is_none());
+
+        /* Robbing a bandit is never a crime, caught or not, and the gold only changes hands */
+        steal(&mut ecs, bandit, None);
+        assert!(ecs.read_storage::<CrimeCommitted>().get(player).is_none());
+        let pools = ecs.read_storage::<Pools>();
+        assert_eq!(pools.get(player).unwrap().gold + pools.get(bandit).unwrap().gold, 30.0);
+    }
+}
@@ -1,11 +1,13 @@
 use specs::prelude::*;
 use super::{Pools, SufferDamage, Player, Name, gamelog::GameLog, RunState, Position, Map,
-    InBackpack, Equipped, LootTable, Attributes, ParticleBuilder};
+    InBackpack, Equipped, LootTable, Attributes, ParticleBuilder, Abilities, AbilityTrigger, WantsToUseAbility,
+    Unique, MasterDungeonMap, UniqueState, TileSize, Faction, CrimeCommitted, OwnedBy, Offshoot};
 use crate::gamesys::{player_hp_at_level, mana_at_level};
 
 pub struct DamageSystem { }
 
 impl<'a> System<'a> for DamageSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         WriteStorage<'a, Pools>,
         WriteStorage<'a, SufferDamage>,
@@ -17,11 +19,19 @@ impl<'a> System<'a> for DamageSystem {
         WriteExpect<'a, GameLog>,
         WriteExpect<'a, ParticleBuilder>,
         ReadExpect<'a, rltk::Point>,
+        WriteStorage<'a, Abilities>,
+        WriteStorage<'a, WantsToUseAbility>,
+        WriteExpect<'a, rltk::RandomNumberGenerator>,
+        ReadStorage<'a, TileSize>,
+        ReadStorage<'a, Faction>,
+        WriteStorage<'a, CrimeCommitted>,
+        ReadStorage<'a, Offshoot>,
     );
 
     fn run (&mut self, data : Self::SystemData) {
         let (mut stats, mut damage, positions, mut map, entities, player, attributes,
-            mut log, mut particles, player_pos) = data;
+            mut log, mut particles, player_pos, mut abilities, mut wants_ability, mut rng, sizes,
+            factions, mut crimes, offshoots) = data;
         let mut xp_gain = 0;
         let mut gold_gain = 0.0f32;
 
@@ -36,14 +46,45 @@ impl<'a> System<'a> for DamageSystem {
                     map.bloodstains.insert(idx);
                 }
                 if stats.hit_points.current < 1 && dmg.1 {
-                    xp_gain += stats.level * 100;
+                    if offshoots.get(ent).is_none() { xp_gain += stats.level * 100; }
                     gold_gain += stats.gold;
                     if let Some(pos) = pos {
-                        let idx = map.xy_idx(pos.x, pos.y);
-                        crate::spatial::remove_entity(ent, idx);
+                        for idx in map.footprint(pos.x, pos.y, sizes.get(ent)) {
+                            crate::spatial::remove_entity(ent, idx);
+                        };
                     }
                 }
             };
+
+            /* Hurting the law-abiding is a crime, if anyone sees it */
+            let by_player = damage.amount.iter().any(|dmg| dmg.1);
+            if by_player && ent != *player {
+                let peaceful = factions.get(ent)
+                    .is_some_and(|f| crate::raws::is_peaceful_faction(&f.name, &crate::raws::RAWS.lock().unwrap()));
+                if let (true, Some(pos)) = (peaceful, positions.get(ent)) {
+                    crate::crime::record_crime(&mut crimes, *player, CrimeCommitted {
+                        victim: ent, x: pos.x, y: pos.y,
+                        fine: crate::crime::assault_fine(stats.hit_points.current < 1),
+                        violent: true,
+                        caught: false,
+                    });
+                }
+            }
+
+            /* Some things react to being hurt (and surviving it) */
+            if stats.hit_points.current > 0 {
+                if let (Some(abilities), Some(pos)) = (abilities.get_mut(ent), positions.get(ent)) {
+                    let reaction = abilities.abilities.iter().position(|ability| {
+                        ability.trigger == AbilityTrigger::Hurt && ability.cooldown_left < 1
+                            && rng.roll_dice(1, 100) as f32 <= ability.chance * 100.0
+                    });
+                    if let Some(idx) = reaction {
+                        abilities.abilities[idx].cooldown_left = abilities.abilities[idx].cooldown;
+                        wants_ability.insert(ent, WantsToUseAbility { ability: idx, x: pos.x, y: pos.y })
+                            .expect("Unable to insert");
+                    }
+                }
+            }
         };
         if xp_gain != 0 || gold_gain != 0.0 {
             let mut player_stats = stats.get_mut(*player).unwrap();
@@ -79,8 +120,10 @@ pub fn delete_the_dead (ecs : &mut World) {
         let combat_stats = ecs.read_storage::<Pools>();
         let players = ecs.read_storage::<Player>();
         let names = ecs.read_storage::<Name>();
+        let uniques = ecs.read_storage::<Unique>();
         let entities = ecs.entities();
         let mut log = ecs.write_resource::<GameLog>();
+        let mut dungeon = ecs.write_resource::<MasterDungeonMap>();
         for (ent, stats) in (&entities, &combat_stats).join() {
             if stats.hit_points.current < 1 {
                 let player = players.get(ent);
@@ -89,6 +132,9 @@ pub fn delete_the_dead (ecs : &mut World) {
                         let victim_name = names.get(ent);
                         if let Some(victim_name) = victim_name {
                             log.entries.push(format!("{} is dead", &victim_name.name));
+                            if uniques.get(ent).is_some() {
+                                dungeon.set_unique_state(&victim_name.name, UniqueState::Dead);
+                            }
                         }
                         dead.push(ent) 
                     },
@@ -101,6 +147,17 @@ pub fn delete_the_dead (ecs : &mut World) {
         };
     }
 
+    /* Whatever the dead still had a claim on is anyone's now */
+    {
+        let entities = ecs.entities();
+        let mut owned = ecs.write_storage::<OwnedBy>();
+        let unclaimed: Vec<Entity> = (&entities, &owned).join()
+            .filter(|(_ent, owned)| dead.contains(&owned.owner))
+            .map(|(ent, _owned)| ent)
+            .collect();
+        for ent in unclaimed.iter() { owned.remove(*ent); };
+    }
+
     /* Drop everything held by dead person */
     let mut to_spawn: Vec<(String, Position)> = Vec::new();
     {
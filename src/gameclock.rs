@@ -0,0 +1,56 @@
+use serde::{Serialize, Deserialize};
+
+pub const TURNS_PER_HOUR: i32 = 30;
+pub const HOURS_PER_DAY: i32 = 24;
+const STARTING_HOUR: i32 = 8;
+
+/// Counts player turns since the start of the run, and maps them onto a day/night cycle
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameClock {
+    pub turn: i32,
+}
+
+impl Default for GameClock {
+    fn default () -> GameClock {
+        GameClock::new()
+    }
+}
+
+impl GameClock {
+    pub fn new () -> GameClock {
+        GameClock { turn: STARTING_HOUR * TURNS_PER_HOUR }
+    }
+
+    pub fn hour (&self) -> i32 {
+        (self.turn / TURNS_PER_HOUR) % HOURS_PER_DAY
+    }
+
+    pub fn day (&self) -> i32 {
+        1 + self.turn / (TURNS_PER_HOUR * HOURS_PER_DAY)
+    }
+
+    pub fn minute (&self) -> i32 {
+        ((self.turn % TURNS_PER_HOUR) * 60) / TURNS_PER_HOUR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_run_starts_in_the_morning_of_day_one () {
+        let clock = GameClock::new();
+        assert_eq!((clock.day(), clock.hour(), clock.minute()), (1, STARTING_HOUR, 0));
+    }
+
+    #[test]
+    fn turns_roll_over_into_hours_and_days () {
+        let mut clock = GameClock { turn: TURNS_PER_HOUR / 2 };
+        assert_eq!((clock.day(), clock.hour(), clock.minute()), (1, 0, 30));
+        clock.turn = TURNS_PER_HOUR * HOURS_PER_DAY - 1;
+        assert_eq!((clock.day(), clock.hour()), (1, HOURS_PER_DAY - 1));
+        clock.turn += 1;
+        assert_eq!((clock.day(), clock.hour(), clock.minute()), (2, 0, 0));
+    }
+}
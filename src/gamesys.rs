@@ -28,8 +28,42 @@ pub fn mana_at_level (intelligence:i32, level:i32) -> i32 {
     mana_per_level(intelligence) * level
 }
 
+/// Morale at or below this value breaks, sending the mob fleeing or surrendering
+pub const MORALE_BREAK_POINT: f32 = 3.0;
+
+pub fn morale_score (base: f32, hp_fraction: f32, allies: i32, threat: i32) -> f32 {
+    (base * hp_fraction) + (i32::min(allies, 3) as f32 * 2.0) - (i32::max(threat, 0) as f32 * 2.0)
+}
+
 pub fn skill_bonus (skill: Skill, skills: &Skills) -> i32 {
     if skills.skills.contains_key(&skill) {
         skills.skills[&skill]
     } else { -4 }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn healthy_and_alone_holds_firm () {
+        assert_eq!(morale_score(10.0, 1.0, 0, 0), 10.0);
+        assert!(morale_score(10.0, 1.0, 0, 0) > MORALE_BREAK_POINT);
+    }
+
+    #[test]
+    fn wounds_and_threats_break_morale () {
+        /* Half dead is 5, one level of threat takes it to 3, which breaks */
+        assert_eq!(morale_score(10.0, 0.5, 0, 1), MORALE_BREAK_POINT);
+        assert!(morale_score(10.0, 0.2, 0, 0) <= MORALE_BREAK_POINT);
+        assert!(morale_score(10.0, 0.6, 0, 0) > MORALE_BREAK_POINT);
+    }
+
+    #[test]
+    fn allies_help_up_to_a_point () {
+        assert_eq!(morale_score(10.0, 0.5, 1, 0), 7.0);
+        assert_eq!(morale_score(10.0, 0.5, 3, 0), morale_score(10.0, 0.5, 10, 0));
+        /* Something weaker than the mob doesn't count for or against it */
+        assert_eq!(morale_score(10.0, 0.5, 0, -4), morale_score(10.0, 0.5, 0, 0));
+    }
+}
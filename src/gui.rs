@@ -3,8 +3,8 @@ use super::camera;
 use specs::prelude::*;
 use super::{Pools, HungerState, gamelog::GameLog, Map, Name, Position, InBackpack,
     State, Viewshed, RunState, Equipped, HungerClock, Attribute, Attributes,
-    rex_assets::RexAssets, Hidden, Consumable, Item, Vendor, VendorMode, MagicItem,
-    MagicItemClass
+    rex_assets::RexAssets, Hidden, Consumable, Item, Durability, VendorMode, MagicItem,
+    MagicItemClass, GameClock, Hireling, TileSize, Wanted, MasterDungeonMap
 };
 
 #[derive(PartialEq, Copy, Clone)]
@@ -19,6 +19,29 @@ pub enum MainMenuResult { NoSelection { selected : MainMenuSelection }, Selected
 #[derive(PartialEq, Copy, Clone)]
 pub enum CheatMenuResult { NoResponse, Cancel, TeleportToExit, Heal, Reveal, GodMode }
 
+#[derive(PartialEq, Copy, Clone)]
+pub enum CompanionMenuResult { NoResponse, Cancel, Follow, Stay, Attack, Hold }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HireMenuResult { NoResponse, Cancel, Hire }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum StealResult { NoResponse, Cancel, Purse, Item }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum SeedEntryResult { NoResponse, Cancel, Accept }
+
+/* What can be typed into a seed */
+const SEED_KEYS: [(VirtualKeyCode, char); 36] = [
+    (VirtualKeyCode::Key0, '0'), (VirtualKeyCode::Key1, '1'), (VirtualKeyCode::Key2, '2'), (VirtualKeyCode::Key3, '3'), (VirtualKeyCode::Key4, '4'),
+    (VirtualKeyCode::Key5, '5'), (VirtualKeyCode::Key6, '6'), (VirtualKeyCode::Key7, '7'), (VirtualKeyCode::Key8, '8'), (VirtualKeyCode::Key9, '9'),
+    (VirtualKeyCode::A, 'a'), (VirtualKeyCode::B, 'b'), (VirtualKeyCode::C, 'c'), (VirtualKeyCode::D, 'd'), (VirtualKeyCode::E, 'e'), (VirtualKeyCode::F, 'f'),
+    (VirtualKeyCode::G, 'g'), (VirtualKeyCode::H, 'h'), (VirtualKeyCode::I, 'i'), (VirtualKeyCode::J, 'j'), (VirtualKeyCode::K, 'k'), (VirtualKeyCode::L, 'l'),
+    (VirtualKeyCode::M, 'm'), (VirtualKeyCode::N, 'n'), (VirtualKeyCode::O, 'o'), (VirtualKeyCode::P, 'p'), (VirtualKeyCode::Q, 'q'), (VirtualKeyCode::R, 'r'),
+    (VirtualKeyCode::S, 's'), (VirtualKeyCode::T, 't'), (VirtualKeyCode::U, 'u'), (VirtualKeyCode::V, 'v'), (VirtualKeyCode::W, 'w'), (VirtualKeyCode::X, 'x'),
+    (VirtualKeyCode::Y, 'y'), (VirtualKeyCode::Z, 'z'),
+];
+
 pub fn draw_hollow_box (console: &mut Rltk, sx:i32, sy:i32, width:i32, height:i32, fg:RGB, bg:RGB) {
     use rltk::to_cp437;
     console.set(sx, sy, fg, bg, to_cp437('┌'));
@@ -54,17 +77,19 @@ pub fn draw_ui (ecs: &World, ctx: &mut Rltk) {
     let white = RGB::named(rltk::WHITE);
 
     /* UI boxes */
+    /* The map box wraps the camera's view */
+    let (map_right, map_bottom) = (camera::VIEW_WIDTH + 1, camera::VIEW_HEIGHT + 1);
     draw_hollow_box(ctx, 0, 0, 79, 59,  box_gray, black); /* Main Box */
-    draw_hollow_box(ctx, 0, 0, 49, 45,  box_gray, black); /* Map Box */
-    draw_hollow_box(ctx, 0, 45, 79, 14, box_gray, black); /* Log Box */
-    draw_hollow_box(ctx, 49, 0, 30, 8,  box_gray, black); /* Top-right Panel */
-
-    ctx.set(0, 45, box_gray, black, to_cp437('├'));
-    ctx.set(49, 8, box_gray, black, to_cp437('├'));
-    ctx.set(49, 0, box_gray, black, to_cp437('┬'));
-    ctx.set(49, 45, box_gray, black, to_cp437('┴'));
+    draw_hollow_box(ctx, 0, 0, map_right, map_bottom,  box_gray, black); /* Map Box */
+    draw_hollow_box(ctx, 0, map_bottom, 79, 59 - map_bottom, box_gray, black); /* Log Box */
+    draw_hollow_box(ctx, map_right, 0, 79 - map_right, 8,  box_gray, black); /* Top-right Panel */
+
+    ctx.set(0, map_bottom, box_gray, black, to_cp437('├'));
+    ctx.set(map_right, 8, box_gray, black, to_cp437('├'));
+    ctx.set(map_right, 0, box_gray, black, to_cp437('┬'));
+    ctx.set(map_right, map_bottom, box_gray, black, to_cp437('┴'));
     ctx.set(79, 8, box_gray, black, to_cp437('┤'));
-    ctx.set(79, 45, box_gray, black, to_cp437('┤'));
+    ctx.set(79, map_bottom, box_gray, black, to_cp437('┤'));
 
     /* Town Name */
     let map = ecs.fetch::<Map>();
@@ -75,6 +100,13 @@ pub fn draw_ui (ecs: &World, ctx: &mut Rltk) {
     ctx.print_color(x_pos+1, 0, white, black, &map.name);
     std::mem::drop(map);
 
+    /* Run seed, so a layout (or a bug) can be shared */
+    let seed = format!("Seed {}", ecs.fetch::<MasterDungeonMap>().seed());
+    let seed_x = 78 - seed.len() as i32;
+    ctx.set(seed_x-1, 59, box_gray, black, to_cp437('┤'));
+    ctx.set(seed_x+seed.len() as i32, 59, box_gray, black, to_cp437('├'));
+    ctx.print_color(seed_x, 59, box_gray, black, &seed);
+
     /* Health / Mana */
     let player_entity = ecs.fetch::<Entity>();
     let pools = ecs.read_storage::<Pools>();
@@ -107,6 +139,8 @@ pub fn draw_ui (ecs: &World, ctx: &mut Rltk) {
         (attr.might.base + attr.might.modifiers) * 15));
     ctx.print_color(50, 10, white, black, &format!("Initiative Penalty: {:.0}", player_pools.total_initiative_penalty));
     ctx.print_color(50, 11, rltk::RGB::named(rltk::GOLD), black, &format!("Gold: {:.1}", player_pools.gold));
+    let clock = ecs.fetch::<GameClock>();
+    ctx.print_color(66, 11, white, black, format!("Day {} {:02}:{:02}", clock.day(), clock.hour(), clock.minute()));
 
     /* Equipped */
     let mut y = 13;
@@ -137,6 +171,9 @@ pub fn draw_ui (ecs: &World, ctx: &mut Rltk) {
     };
     let hunger = ecs.read_storage::<HungerClock>();
     let hc = hunger.get(*player_entity).unwrap();
+    if let Some(wanted) = ecs.read_storage::<Wanted>().get(*player_entity) {
+        ctx.print_color(50, 43, RGB::named(rltk::RED), black, format!("Wanted: {:.1} gp fine", wanted.fine));
+    }
     match hc.state {
         HungerState::WellFed => { ctx.print_color(50, 44, green, black, "Well Fed"); },
         HungerState::Normal => {},
@@ -202,20 +239,24 @@ fn draw_tooltips (ecs: &World, ctx : &mut Rltk) {
     let hidden = ecs.read_storage::<Hidden>();
     let attributes = ecs.read_storage::<Attributes>();
     let pools = ecs.read_storage::<Pools>();
+    let sizes = ecs.read_storage::<TileSize>();
     let entities = ecs.entities();
 
     let mouse_pos = ctx.mouse_pos();
     // ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::MAGENTA));
+    /* Off the edge of the view, the mouse is over the panels rather than the map beyond */
+    if mouse_pos.0 < 1 || mouse_pos.1 < 1 || mouse_pos.0 > camera::VIEW_WIDTH || mouse_pos.1 > camera::VIEW_HEIGHT { return; }
     let mut mouse_map_pos = mouse_pos;
     mouse_map_pos.0 += min_x - 1;
     mouse_map_pos.1 += min_y - 1;
     if mouse_map_pos.0 >= map.width-1 || mouse_map_pos.1 >= map.height-1 ||
         mouse_map_pos.0 < 1 || mouse_map_pos.1 < 1 { return; }
-    if !map.visible_tiles[map.xy_idx(mouse_map_pos.0, mouse_map_pos.1)] { return; }
+    let mouse_idx = map.xy_idx(mouse_map_pos.0, mouse_map_pos.1);
+    if !map.visible_tiles[mouse_idx] { return; }
 
     let mut tip_boxes: Vec<Tooltip> = Vec::new();
     for (ent, name, pos, _hidden) in (&entities, &names, &positions, !&hidden).join() {
-        if pos.x == mouse_map_pos.0 && pos.y == mouse_map_pos.1 {
+        if map.footprint(pos.x, pos.y, sizes.get(ent)).contains(&mouse_idx) {
             let mut tip = Tooltip::new();
             tip.add(name.name.to_string());
             /* Comment on attributes */
@@ -588,6 +629,143 @@ pub fn show_cheat_menu (_gs: &mut State, ctx: &mut Rltk) -> CheatMenuResult {
     }
 }
 
+pub fn show_companion_menu (_gs: &mut State, ctx: &mut Rltk) -> CompanionMenuResult {
+    let orders = [('F', "Follow me"), ('S', "Stay here"), ('A', "Attack!"), ('H', "Hold position")];
+    let count = orders.len() as i32;
+    let y = 25 - (count / 2);
+    let black = RGB::named(rltk::BLACK);
+    let white = RGB::named(rltk::WHITE);
+    let yellow = RGB::named(rltk::YELLOW);
+    ctx.draw_box(15, y-2, 31, count+3, white, black);
+    ctx.print_color(18, y-2, yellow, black, "Companions");
+    ctx.print_color(18, y+count+1, yellow, black, "ESCAPE to Cancel");
+
+    for (i, (key, label)) in orders.iter().enumerate() {
+        let row = y + i as i32;
+        ctx.set(17, row, white, black, rltk::to_cp437('('));
+        ctx.set(18, row, yellow, black, rltk::to_cp437(*key));
+        ctx.set(19, row, white, black, rltk::to_cp437(')'));
+        ctx.print(21, row, label);
+    };
+
+    match ctx.key {
+        None => CompanionMenuResult::NoResponse,
+        Some(key) => {
+            match key {
+                VirtualKeyCode::F => CompanionMenuResult::Follow,
+                VirtualKeyCode::S => CompanionMenuResult::Stay,
+                VirtualKeyCode::A => CompanionMenuResult::Attack,
+                VirtualKeyCode::H => CompanionMenuResult::Hold,
+                VirtualKeyCode::Escape => CompanionMenuResult::Cancel,
+                _ => CompanionMenuResult::NoResponse,
+            }
+        },
+    }
+}
+
+pub fn show_hire_menu (gs: &mut State, ctx: &mut Rltk, hireling: Entity) -> HireMenuResult {
+    let black = RGB::named(rltk::BLACK);
+    let white = RGB::named(rltk::WHITE);
+    let yellow = RGB::named(rltk::YELLOW);
+    let names = gs.ecs.read_storage::<Name>();
+    let hirelings = gs.ecs.read_storage::<Hireling>();
+    let name = names.get(hireling).map_or("stranger", |n| n.name.as_str());
+    let cost = hirelings.get(hireling).map_or(0.0, |h| h.cost);
+
+    ctx.draw_box(10, 22, 40, 4, white, black);
+    ctx.print_color(13, 22, yellow, black, "Hire");
+    ctx.print(12, 24, format!("Hire the {} for {:.1} gold?", name, cost));
+    ctx.print_color(13, 26, yellow, black, "Y to hire, ESCAPE to Cancel");
+
+    match ctx.key {
+        None => HireMenuResult::NoResponse,
+        Some(key) => {
+            match key {
+                VirtualKeyCode::Y => HireMenuResult::Hire,
+                VirtualKeyCode::Escape | VirtualKeyCode::N => HireMenuResult::Cancel,
+                _ => HireMenuResult::NoResponse,
+            }
+        },
+    }
+}
+
+pub fn show_steal_menu (gs: &mut State, ctx: &mut Rltk, victim: Entity) -> (StealResult, Option<Entity>) {
+    let black = RGB::named(rltk::BLACK);
+    let white = RGB::named(rltk::WHITE);
+    let yellow = RGB::named(rltk::YELLOW);
+    let name = gs.ecs.read_storage::<Name>().get(victim).map_or("stranger".to_string(), |n| n.name.clone());
+    let purse = gs.ecs.read_storage::<Pools>().get(victim).map_or(0.0, |p| p.gold);
+    /* Only what's in their pack: nobody gets the sword off someone's belt unnoticed */
+    let pockets = crate::vendors::stock(&gs.ecs, victim);
+    let has_purse = purse >= 1.0;
+    let count = pockets.len() + if has_purse { 1 } else { 0 };
+
+    let mut y = (25 - (count / 2)) as i32;
+    ctx.draw_box(15, y-2, 51, (count+3) as i32, white, black);
+    ctx.print_color(18, y-2, yellow, black, format!("Steal from the {}?", name));
+    ctx.print_color(18, y+count as i32+1, yellow, black, "ESCAPE to cancel");
+
+    let mut j = 0;
+    if has_purse {
+        ctx.set(17, y, white, black, rltk::to_cp437('('));
+        ctx.set(18, y, yellow, black, 97 as rltk::FontCharType);
+        ctx.set(19, y, white, black, rltk::to_cp437(')'));
+        ctx.print_color(21, y, RGB::named(rltk::GOLD), black, "Their purse");
+        y += 1;
+        j += 1;
+    }
+    for item in pockets.iter() {
+        ctx.set(17, y, white, black, rltk::to_cp437('('));
+        ctx.set(18, y, yellow, black, 97+j as rltk::FontCharType);
+        ctx.set(19, y, white, black, rltk::to_cp437(')'));
+        ctx.print_color(21, y, get_item_color(&gs.ecs, *item), black, trade_label(&gs.ecs, *item));
+        y += 1;
+        j += 1;
+    };
+
+    match ctx.key {
+        None => (StealResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (StealResult::Cancel, None),
+        Some(key) => {
+            let selection = rltk::letter_to_option(key);
+            if has_purse && selection == 0 {
+                return (StealResult::Purse, None);
+            }
+            let selection = selection - if has_purse { 1 } else { 0 };
+            if selection > -1 && selection < pockets.len() as i32 {
+                return (StealResult::Item, Some(pockets[selection as usize]));
+            }
+            (StealResult::NoResponse, None)
+        },
+    }
+}
+
+/// Asks for the run's seed at the start of a new game. Leaving it blank picks one at random.
+pub fn seed_entry (gs: &mut State, ctx: &mut Rltk, entry: &mut String) -> SeedEntryResult {
+    let assets = gs.ecs.fetch::<RexAssets>();
+    ctx.render_xp_sprite(&assets.menu, 0, 0);
+    ctx.print_color_centered(15, RGB::named(rltk::PINK), RGB::named(rltk::BLACK), "Shadorogue");
+    ctx.print_color_centered(23, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), "Enter a seed, or leave it blank for a random one:");
+    ctx.print_color_centered(25, RGB::named(rltk::MAGENTA), RGB::named(rltk::BLACK), format!("{}_", entry));
+    ctx.print_color_centered(27, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ENTER to begin, ESCAPE to go back");
+
+    match ctx.key {
+        None => SeedEntryResult::NoResponse,
+        Some(VirtualKeyCode::Return) => SeedEntryResult::Accept,
+        Some(VirtualKeyCode::Escape) => SeedEntryResult::Cancel,
+        Some(VirtualKeyCode::Back) => {
+            entry.pop();
+            SeedEntryResult::NoResponse
+        }
+        Some(key) => {
+            if let Some((_key, c)) = SEED_KEYS.iter().find(|(k, _c)| *k == key) {
+                if entry.len() < 20 { entry.push(*c); }
+            }
+            SeedEntryResult::NoResponse
+        }
+    }
+}
+
 /* Game End */
 #[derive(PartialEq, Copy, Clone)]
 pub enum GameOverResult { NoSelection, QuitToMenu }
@@ -605,47 +783,56 @@ pub fn game_over (ctx: &mut Rltk) -> GameOverResult {
 }
 
 
-fn vendor_sell_menu (gs: &mut State, ctx: &mut Rltk, _vendor: Entity, _mode: VendorMode) -> (VendorResult, Option<Entity>, Option<String>, Option<f32>) {
+/// Item names in the shop menus, flagging anything that's seen some use
+fn trade_label (ecs: &World, item: Entity) -> String {
+    let name = ecs.read_storage::<Name>().get(item).map_or(String::new(), |n| n.name.clone());
+    match ecs.read_storage::<Durability>().get(item) {
+        Some(d) if d.current < d.max => format!("{} ({}%)", name, i32::max(0, d.current) * 100 / d.max),
+        _ => name,
+    }
+}
+
+fn vendor_sell_menu (gs: &mut State, ctx: &mut Rltk, vendor: Entity, _mode: VendorMode) -> (VendorResult, Option<Entity>) {
     let player_entity = gs.ecs.fetch::<Entity>();
-    let names = gs.ecs.read_storage::<Name>();
     let backpack = gs.ecs.read_storage::<InBackpack>();
     let items = gs.ecs.read_storage::<Item>();
     let entities = gs.ecs.entities();
 
-    let inventory = (&backpack, &names).join().filter(|item| item.0.owner == *player_entity );
-    let count = inventory.count();
+    let inventory: Vec<Entity> = (&entities, &backpack, &items).join()
+        .filter(|item| item.1.owner == *player_entity)
+        .map(|item| item.0)
+        .collect();
+    let count = inventory.len();
+    let purse = gs.ecs.read_storage::<Pools>().get(vendor).map_or(0.0, |p| p.gold);
 
-    let mut y = (25 - (count / 2)) as i32;
-    ctx.draw_box(15, y-2, 51, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
-    ctx.print_color(18, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Sell Which Item? (space to switch to buy mode)");
-    ctx.print_color(18, y+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to cancel");
+    let top = (25 - (count / 2)) as i32;
+    ctx.draw_box(15, top-2, 51, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(18, top-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Sell Which Item? (space to switch to buy mode)");
+    ctx.print_color(18, top+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        format!("ESCAPE to cancel   Vendor has {:.1} gp", purse));
 
-    let mut equippable : Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (ent, _pack, name, item) in (&entities, &backpack, &names, &items).join().filter(|item| item.1.owner == *player_entity ) {
+    for (j, item) in inventory.iter().enumerate() {
+        let y = top + j as i32;
         ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
         ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as rltk::FontCharType);
         ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
 
-        ctx.print_color(21, y, get_item_color(&gs.ecs, ent), RGB::from_f32(0.0, 0.0, 0.0), &name.name.to_string());
-        ctx.print(50, y, &format!("{:.1} gp", item.base_value * 0.8));
-        equippable.push(ent);
-        y += 1;
-        j += 1;
+        ctx.print_color(21, y, get_item_color(&gs.ecs, *item), RGB::from_f32(0.0, 0.0, 0.0), trade_label(&gs.ecs, *item));
+        ctx.print(54, y, format!("{:.1} gp", crate::vendors::sell_price(&gs.ecs, vendor, *item)));
     };
 
     match ctx.key {
-        None => (VendorResult::NoResponse, None, None, None),
+        None => (VendorResult::NoResponse, None),
         Some(key) => {
             match key {
-                VirtualKeyCode::Space => { (VendorResult::BuyMode, None, None, None) },
-                VirtualKeyCode::Escape => { (VendorResult::Cancel, None, None, None) },
+                VirtualKeyCode::Space => { (VendorResult::BuyMode, None) },
+                VirtualKeyCode::Escape => { (VendorResult::Cancel, None) },
                 _ => { 
                     let selection = rltk::letter_to_option(key);
                     if selection > -1 && selection < count as i32 {
-                        return (VendorResult::Sell, Some(equippable[selection as usize]), None, None);
+                        return (VendorResult::Sell, Some(inventory[selection as usize]));
                     }
-                    (VendorResult::NoResponse, None, None, None)
+                    (VendorResult::NoResponse, None)
                 },
             }
         },
@@ -653,48 +840,57 @@ fn vendor_sell_menu (gs: &mut State, ctx: &mut Rltk, _vendor: Entity, _mode: Ven
 }
 
 
-fn vendor_buy_menu (gs: &mut State, ctx: &mut Rltk, vendor: Entity, _mode: VendorMode) -> (VendorResult, Option<Entity>, Option<String>, Option<f32>) {
-    use crate::raws::*;
-
-    let vendors = gs.ecs.read_storage::<Vendor>();
-
-    let inventory = crate::raws::get_vendor_items(&vendors.get(vendor).unwrap().categories, &RAWS.lock().unwrap());
+fn vendor_buy_menu (gs: &mut State, ctx: &mut Rltk, vendor: Entity, _mode: VendorMode) -> (VendorResult, Option<Entity>) {
+    /* Identical items share a line; the first of each is the one that gets sold */
+    let mut inventory: Vec<(String, Entity, f32, i32)> = Vec::new();
+    for item in crate::vendors::stock(&gs.ecs, vendor).iter() {
+        let label = trade_label(&gs.ecs, *item);
+        let price = crate::vendors::buy_price(&gs.ecs, vendor, *item);
+        match inventory.iter_mut().find(|line| line.0 == label && (line.2 - price).abs() < 0.05) {
+            Some(line) => line.3 += 1,
+            None => inventory.push((label, *item, price, 1)),
+        }
+    };
+    inventory.sort_by(|a, b| a.0.cmp(&b.0));
     let count = inventory.len();
+    let purse = gs.ecs.read_storage::<Pools>().get(*gs.ecs.fetch::<Entity>()).map_or(0.0, |p| p.gold);
 
-    let mut y = (25 - (count / 2)) as i32;
-    ctx.draw_box(15, y-2, 51, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
-    ctx.print_color(18, y-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Buy Which Item? (space to switch to sell mode)");
-    ctx.print_color(18, y+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to cancel");
+    let top = (25 - (count / 2)) as i32;
+    ctx.draw_box(15, top-2, 51, (count+3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(18, top-2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Buy Which Item? (space to switch to sell mode)");
+    ctx.print_color(18, top+count as i32+1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK),
+        format!("ESCAPE to cancel   You have {:.1} gp", purse));
 
-    for (j,sale) in inventory.iter().enumerate() {
+    for (j, sale) in inventory.iter().enumerate() {
+        let y = top + j as i32;
         ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
         ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as rltk::FontCharType);
         ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
 
-        ctx.print(21, y, &sale.0);
-        ctx.print(50, y, &format!("{:.1} gp", sale.1 * 1.2));
-        y += 1;
+        let label = if sale.3 > 1 { format!("{} x{}", sale.0, sale.3) } else { sale.0.clone() };
+        ctx.print_color(21, y, get_item_color(&gs.ecs, sale.1), RGB::from_f32(0.0, 0.0, 0.0), label);
+        ctx.print(54, y, format!("{:.1} gp", sale.2));
     };
 
     match ctx.key {
-        None => (VendorResult::NoResponse, None, None, None),
+        None => (VendorResult::NoResponse, None),
         Some(key) => {
             match key {
-                VirtualKeyCode::Space => { (VendorResult::SellMode, None, None, None) },
-                VirtualKeyCode::Escape => { (VendorResult::Cancel, None, None, None) },
+                VirtualKeyCode::Space => { (VendorResult::SellMode, None) },
+                VirtualKeyCode::Escape => { (VendorResult::Cancel, None) },
                 _ => {
                     let selection = rltk::letter_to_option(key);
                     if selection > -1 && selection < count as i32 {
-                        return (VendorResult::Buy, None, Some(inventory[selection as usize].0.clone()), Some(inventory[selection as usize].1));
+                        return (VendorResult::Buy, Some(inventory[selection as usize].1));
                     }
-                    (VendorResult::NoResponse, None, None, None)
+                    (VendorResult::NoResponse, None)
                 },
             }
         },
     }
 }
 
-pub fn show_vendor_menu (gs: &mut State, ctx: &mut Rltk, vendor: Entity, mode: VendorMode) -> (VendorResult, Option<Entity>, Option<String>, Option<f32>) {
+pub fn show_vendor_menu (gs: &mut State, ctx: &mut Rltk, vendor: Entity, mode: VendorMode) -> (VendorResult, Option<Entity>) {
     match mode {
         VendorMode::Buy => vendor_buy_menu(gs, ctx, vendor, mode),
         VendorMode::Sell => vendor_sell_menu(gs, ctx, vendor, mode),
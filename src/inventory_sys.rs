@@ -3,7 +3,7 @@ use super::{Map, Name, gamelog::GameLog, WantsToUseItem, ProvidesHealing, Pools,
     WantsToPickupItem, WantsToDropItem, Position, InBackpack, Consumable, SufferDamage,
     InflictsDamage, AreaOfEffect, Confusion, Equippable, Equipped, WantsToRemoveEquipment,
     ParticleBuilder, ProvidesFood, HungerState, HungerClock, MagicMapper, RunState,
-    EquipmentChanged, TownPortal
+    EquipmentChanged, TownPortal, GoldPile, OwnedBy, Faction, CrimeCommitted
 };
 
 pub struct ItemCollectionSystem {}
@@ -17,13 +17,42 @@ impl<'a> System<'a> for ItemCollectionSystem {
                         ReadStorage<'a, Name>,
                         WriteStorage<'a, InBackpack>,
                         WriteStorage<'a, EquipmentChanged>,
+                        Entities<'a>,
+                        ReadStorage<'a, GoldPile>,
+                        ReadStorage<'a, OwnedBy>,
+                        WriteStorage<'a, Pools>,
+                        ReadStorage<'a, Faction>,
+                        WriteStorage<'a, CrimeCommitted>,
                       );
 
     fn run(&mut self, data : Self::SystemData) {
         let (player_entity, mut gamelog, mut wants_pickup, mut positions, names,
-            mut backpack, mut dirty) = data;
+            mut backpack, mut dirty, entities, gold, owners, mut pools, factions, mut crimes) = data;
 
         for pickup in wants_pickup.join() {
+            /* Gold goes in the purse rather than the pack */
+            if let Some(pile) = gold.get(pickup.item) {
+                if let Some(purse) = pools.get_mut(pickup.collected_by) { purse.gold += pile.amount; }
+                if pickup.collected_by == *player_entity {
+                    gamelog.entries.push(format!("You pick up {:.0} gold.", pile.amount));
+                    /* Coins someone law-abiding threw down to save their skin are still theirs */
+                    if let Some(owner) = owners.get(pickup.item).map(|owned| owned.owner).filter(|owner| entities.is_alive(*owner)) {
+                        let peaceful = factions.get(owner)
+                            .is_some_and(|f| crate::raws::is_peaceful_faction(&f.name, &crate::raws::RAWS.lock().unwrap()));
+                        if let (true, Some(pos)) = (peaceful, positions.get(owner)) {
+                            crate::crime::record_crime(&mut crimes, *player_entity, CrimeCommitted {
+                                victim: owner, x: pos.x, y: pos.y,
+                                fine: crate::crime::theft_fine(pile.amount),
+                                violent: false,
+                                caught: false,
+                            });
+                        }
+                    }
+                }
+                entities.delete(pickup.item).expect("Unable to delete");
+                continue;
+            }
+
             positions.remove(pickup.item);
             backpack.insert(pickup.item, InBackpack { owner: pickup.collected_by }).expect("Unable to insert backpack entry");
             dirty.insert(pickup.collected_by, EquipmentChanged{}).expect("Unable to insert");
@@ -81,7 +110,7 @@ impl<'a> System<'a> for ItemUseSystem {
             /* Targeting */
             let mut targets : Vec<Entity> = Vec::new();
             match useitem.target {
-                None => { targets.push(*player_entity); },
+                None => { targets.push(ent); },
                 Some(target) => {
                     let area_effect = aoe.get(useitem.item);
                     match area_effect {
@@ -96,7 +125,8 @@ impl<'a> System<'a> for ItemUseSystem {
                             blast_tiles.retain(|p| p.x > 0 && p.x < map.width-1 && p.y > 0 && p.y < map.height-1);
                             for tile_idx in blast_tiles.iter() {
                                 let idx = map.xy_idx(tile_idx.x, tile_idx.y);
-                                crate::spatial::for_each_tile_content(idx, |mob| targets.push(mob));
+                                /* Something big can stand in several of the blast tiles, but is only hit once */
+                                crate::spatial::for_each_tile_content(idx, |mob| if !targets.contains(&mob) { targets.push(mob); });
                                 particle_builder.request(tile_idx.x, tile_idx.y, rltk::RGB::named(rltk::ORANGE), rltk::RGB::named(rltk::BLACK), rltk::to_cp437('░'), 200.0);
                             };
                         },
@@ -181,6 +211,9 @@ impl<'a> System<'a> for ItemUseSystem {
                             if ent == *player_entity {
                                 gamelog.entries.push(format!("You drank the {}, healing {} hp.",
                                         names.get(useitem.item).unwrap().name, healer.heal_amount));
+                            } else if let Some(name) = names.get(ent) {
+                                gamelog.entries.push(format!("{} drinks a {}.",
+                                        name.name, names.get(useitem.item).unwrap().name));
                             }
                             used_item = true;
                             let pos = positions.get(*target);
@@ -198,16 +231,19 @@ impl<'a> System<'a> for ItemUseSystem {
                 Some(damage) => {
                     used_item = false;
                     for mob in targets.iter() {
-                        SufferDamage::new_dmg(&mut suffer_damage, *mob, damage.damage, true);
+                        SufferDamage::new_dmg(&mut suffer_damage, *mob, damage.damage, ent == *player_entity);
+                        let item_name = names.get(useitem.item).unwrap();
+                        let mob_name = names.get(*mob).unwrap();
                         if ent == *player_entity {
-                            let item_name = names.get(useitem.item).unwrap();
-                            let mob_name = names.get(*mob).unwrap();
                             gamelog.entries.push(format!("You used {} on {}, inflicting {} hp.",
                                     item_name.name, mob_name.name, damage.damage));
-                            let pos = positions.get(*mob);
-                            if let Some(pos) = pos {
-                                particle_builder.request(pos.x, pos.y, rltk::RGB::named(rltk::RED), rltk::RGB::named(rltk::BLACK), rltk::to_cp437('‼'), 200.0);
-                            }
+                        } else if let Some(name) = names.get(ent) {
+                            gamelog.entries.push(format!("{} uses {} on {}, inflicting {} hp.",
+                                    name.name, item_name.name, mob_name.name, damage.damage));
+                        }
+                        let pos = positions.get(*mob);
+                        if let Some(pos) = pos {
+                            particle_builder.request(pos.x, pos.y, rltk::RGB::named(rltk::RED), rltk::RGB::named(rltk::BLACK), rltk::to_cp437('‼'), 200.0);
                         }
                         used_item = true;
                     };
@@ -223,15 +259,18 @@ impl<'a> System<'a> for ItemUseSystem {
                         used_item = false;
                         for mob in targets.iter() {
                             add_confusion.push((*mob, confusion.turns));
+                            let item_name = names.get(useitem.item).unwrap();
+                            let mob_name = names.get(*mob).unwrap();
                             if ent == *player_entity {
-                                let item_name = names.get(useitem.item).unwrap();
-                                let mob_name = names.get(*mob).unwrap();
                                 gamelog.entries.push(format!("You used {} on {}, inflicting confusion.",
                                         item_name.name, mob_name.name));
-                                let pos = positions.get(*mob);
-                                if let Some(pos) = pos {
-                                    particle_builder.request(pos.x, pos.y, rltk::RGB::named(rltk::MAGENTA), rltk::RGB::named(rltk::BLACK), rltk::to_cp437('?'), 200.0);
-                                }
+                            } else if let Some(name) = names.get(ent) {
+                                gamelog.entries.push(format!("{} uses {} on {}, inflicting confusion.",
+                                        name.name, item_name.name, mob_name.name));
+                            }
+                            let pos = positions.get(*mob);
+                            if let Some(pos) = pos {
+                                particle_builder.request(pos.x, pos.y, rltk::RGB::named(rltk::MAGENTA), rltk::RGB::named(rltk::BLACK), rltk::to_cp437('?'), 200.0);
                             }
                             used_item = true;
                         };
@@ -14,9 +14,16 @@ mod rect;
 pub use rect::Rect;
 mod gui;
 mod gamelog;
+mod gameclock;
+pub use gameclock::GameClock;
 mod spawner;
+mod companions;
+mod vendors;
+mod crime;
+mod abilities;
 mod random_table;
 mod rex_assets;
+mod mapgen_cli;
 pub mod camera;
 /* Systems */
 mod visibility_sys;
@@ -48,6 +55,8 @@ use map::*;
 
 mod ai;
 mod spatial;
+#[cfg(test)]
+mod test_support;
 
 #[macro_use]
 extern crate lazy_static;
@@ -67,6 +76,7 @@ pub enum RunState {
     ShowDropItem,
     ShowTargeting { range:i32, item : Entity },
     MainMenu { menu_selection : gui::MainMenuSelection },
+    EnterSeed,
     SaveGame,
     NextLevel,
     PreviousLevel,
@@ -77,7 +87,10 @@ pub enum RunState {
     MapGeneration,
     ShowCheatMenu,
     ShowVendor { vendor: Entity, mode: VendorMode },
-    TeleportingToOtherLevel { x:i32, y:i32, depth:i32 },
+    ShowHire { hireling: Entity },
+    ShowSteal { victim: Entity },
+    ShowCompanionOrders,
+    TeleportingToOtherLevel,
 }
 
 pub struct State {
@@ -86,6 +99,7 @@ pub struct State {
     mapgen_history: Vec<Map>,
     mapgen_index: usize,
     mapgen_timer: f32,
+    seed_entry: String,
 }
 
 impl State {
@@ -102,16 +116,26 @@ impl State {
         turnstatus.run_now(&self.ecs);
         let mut quipper = ai::QuipSystem{};
         quipper.run_now(&self.ecs);
-        let mut adjacent = ai::AdjacentAI{};
-        adjacent.run_now(&self.ecs);
-        let mut visible = ai::VisibleAI{};
-        visible.run_now(&self.ecs);
+        let mut pursuit = ai::PursuitSystem{};
+        pursuit.run_now(&self.ecs);
+        let mut morale = ai::MoraleSystem{};
+        morale.run_now(&self.ecs);
+        let mut companions = ai::CompanionAI{};
+        companions.run_now(&self.ecs);
+        let mut guards = ai::GuardAI{};
+        guards.run_now(&self.ecs);
+        let mut utility = ai::UtilityAI{};
+        utility.run_now(&self.ecs);
+        let mut ability_ai = ai::AbilityAI{};
+        ability_ai.run_now(&self.ecs);
         let mut approach = ai::ApproachAI{};
         approach.run_now(&self.ecs);
         let mut flee = ai::FleeAI{};
         flee.run_now(&self.ecs);
         let mut chase = ai::ChaseAI{};
         chase.run_now(&self.ecs);
+        let mut schedule = ai::ScheduleAI{};
+        schedule.run_now(&self.ecs);
         let mut defaultmove = ai::DefaultMoveAI{};
         defaultmove.run_now(&self.ecs);
         let mut moving = MovementSystem{};
@@ -126,6 +150,8 @@ impl State {
         pickup.run_now(&self.ecs);
         let mut itemuse = ItemUseSystem{};
         itemuse.run_now(&self.ecs);
+        abilities::use_abilities(&mut self.ecs);
+        crime::report_crimes(&mut self.ecs);
         let mut drop_items = ItemDropSystem{};
         drop_items.run_now(&self.ecs);
         let mut equip_remove = EquipmentRemoveSystem{};
@@ -152,6 +178,7 @@ impl GameState for State {
 
         match newrunstate {
             RunState::MainMenu {..} => {},
+            RunState::EnterSeed => {},
             RunState::GameOver {..} => {},
             _ => {
                 camera::render_camera(&self.ecs, ctx);
@@ -174,7 +201,7 @@ impl GameState for State {
                         RunState::AwaitingInput => newrunstate = RunState::AwaitingInput,
                         RunState::MagicMapReveal { .. } => newrunstate = RunState::MagicMapReveal { row: 0 },
                         RunState::TownPortal => newrunstate = RunState::TownPortal,
-                        RunState::TeleportingToOtherLevel { x, y, depth } => newrunstate = RunState::TeleportingToOtherLevel { x, y, depth },
+                        RunState::TeleportingToOtherLevel => newrunstate = RunState::TeleportingToOtherLevel,
                         _ => newrunstate = RunState::Ticking,
                     }
                 }
@@ -229,7 +256,10 @@ impl GameState for State {
                         RunState::MainMenu { menu_selection: selected },
                     gui::MainMenuResult::Selected { selected } => {
                         match selected {
-                            gui::MainMenuSelection::NewGame => newrunstate = RunState::PreRun,
+                            gui::MainMenuSelection::NewGame => {
+                                self.seed_entry.clear();
+                                newrunstate = RunState::EnterSeed;
+                            },
                             gui::MainMenuSelection::LoadGame => {
                                 saveload_sys::load_game(&mut self.ecs);
                                 newrunstate = RunState::AwaitingInput;
@@ -245,7 +275,8 @@ impl GameState for State {
                     gui::CheatMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
                     gui::CheatMenuResult::NoResponse => { },
                     gui::CheatMenuResult::TeleportToExit => {
-                        self.goto_level(1);
+                        let next = self.ecs.fetch::<Map>().level_id();
+                        self.goto_level(&Staircase { level: LevelId::new(&next.branch, next.depth + 1), arrival: None }, None);
                         self.mapgen_next_state = Some(RunState::PreRun);
                         newrunstate = RunState::MapGeneration;
                     },
@@ -272,46 +303,78 @@ impl GameState for State {
                     },
                 }
             } RunState::ShowVendor { vendor, mode } => {
+                vendors::restock(&mut self.ecs, vendor);
                 let result = gui::show_vendor_menu(self, ctx, vendor, mode);
                 match result.0 {
                     gui::VendorResult::Cancel => newrunstate = RunState::AwaitingInput,
                     gui::VendorResult::NoResponse => {},
-                    gui::VendorResult::Sell => {
-                        let price = self.ecs.read_storage::<Item>().get(result.1.unwrap()).unwrap().base_value * 0.8;
-                        self.ecs.write_storage::<Pools>().get_mut(*self.ecs.fetch::<Entity>()).unwrap().gold += price;
-                        self.ecs.delete_entity(result.1.unwrap()).expect("Unable to delete");
-                    },
-                    gui::VendorResult::Buy => {
-                        let tag = result.2.unwrap();
-                        let price = result.3.unwrap();
-                        let mut pools = self.ecs.write_storage::<Pools>();
-                        let player_pools = pools.get_mut(*self.ecs.fetch::<Entity>()).unwrap();
-                        if player_pools.gold >= price {
-                            player_pools.gold -= price;
-                            std::mem::drop(pools);
-                            let player_entity = *self.ecs.fetch::<Entity>();
-                            crate::raws::spawn_named_item(&raws::RAWS.lock().unwrap(),
-                                &mut self.ecs, &tag, raws::SpawnType::Carried { by: player_entity });
-                        }
-                    },
+                    gui::VendorResult::Sell => vendors::sell(&mut self.ecs, vendor, result.1.unwrap()),
+                    gui::VendorResult::Buy => vendors::buy(&mut self.ecs, vendor, result.1.unwrap()),
                     gui::VendorResult::BuyMode => newrunstate = RunState::ShowVendor { vendor, mode: VendorMode::Buy },
                     gui::VendorResult::SellMode => newrunstate = RunState::ShowVendor { vendor, mode: VendorMode::Sell },
                 }
+            } RunState::ShowHire { hireling } => {
+                let result = gui::show_hire_menu(self, ctx, hireling);
+                match result {
+                    gui::HireMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::HireMenuResult::NoResponse => {},
+                    gui::HireMenuResult::Hire => {
+                        companions::hire(&mut self.ecs, hireling);
+                        newrunstate = RunState::Ticking;
+                    },
+                }
+            } RunState::ShowSteal { victim } => {
+                if self.ecs.read_storage::<Vendor>().get(victim).is_some() {
+                    vendors::restock(&mut self.ecs, victim);
+                }
+                let result = gui::show_steal_menu(self, ctx, victim);
+                match result.0 {
+                    gui::StealResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::StealResult::NoResponse => {},
+                    gui::StealResult::Purse => {
+                        crime::steal(&mut self.ecs, victim, None);
+                        newrunstate = RunState::Ticking;
+                    },
+                    gui::StealResult::Item => {
+                        crime::steal(&mut self.ecs, victim, result.1);
+                        newrunstate = RunState::Ticking;
+                    },
+                }
+            } RunState::ShowCompanionOrders => {
+                let result = gui::show_companion_menu(self, ctx);
+                match result {
+                    gui::CompanionMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::CompanionMenuResult::NoResponse => {},
+                    gui::CompanionMenuResult::Follow => {
+                        companions::give_order(&mut self.ecs, CompanionOrder::Follow);
+                        newrunstate = RunState::Ticking;
+                    },
+                    gui::CompanionMenuResult::Stay => {
+                        companions::give_order(&mut self.ecs, CompanionOrder::Stay);
+                        newrunstate = RunState::Ticking;
+                    },
+                    gui::CompanionMenuResult::Attack => {
+                        companions::order_attack(&mut self.ecs);
+                        newrunstate = RunState::Ticking;
+                    },
+                    gui::CompanionMenuResult::Hold => {
+                        companions::give_order(&mut self.ecs, CompanionOrder::Hold);
+                        newrunstate = RunState::Ticking;
+                    },
+                }
             } RunState::SaveGame => {
                 saveload_sys::save_game(&mut self.ecs);
                 newrunstate = RunState::MainMenu { menu_selection: gui::MainMenuSelection::LoadGame };
             } RunState::NextLevel => {
-                self.goto_level(1);
+                self.take_stairs();
                 newrunstate = RunState::PreRun;
             } RunState::PreviousLevel => {
-                self.goto_level(-1);
+                self.take_stairs();
                 self.mapgen_next_state = Some(RunState::PreRun);
                 newrunstate = RunState::MapGeneration;
             } RunState::TownPortal => {
                 spawner::spawn_town_portal(&mut self.ecs);
-                let map_depth = self.ecs.fetch::<Map>().depth;
-                let dest_offset = 0 - (map_depth-1);
-                self.goto_level(dest_offset);
+                self.goto_level(&Staircase { level: LevelId::main(1), arrival: None }, None);
                 self.mapgen_next_state = Some(RunState::PreRun);
                 newrunstate = RunState::MapGeneration;
             } RunState::ShowRemoveEquipment => {
@@ -326,12 +389,26 @@ impl GameState for State {
                         newrunstate = RunState::Ticking;
                     },
                 }
+            } RunState::EnterSeed => {
+                let mut entry = std::mem::take(&mut self.seed_entry);
+                match gui::seed_entry(self, ctx, &mut entry) {
+                    gui::SeedEntryResult::NoResponse => {},
+                    gui::SeedEntryResult::Cancel => newrunstate = RunState::MainMenu { menu_selection: gui::MainMenuSelection::NewGame },
+                    gui::SeedEntryResult::Accept => {
+                        let seed = if entry.is_empty() { rltk::RandomNumberGenerator::new().next_u64() }
+                            else { map::seed_from_text(&entry) };
+                        self.new_run(seed);
+                        self.mapgen_next_state = Some(RunState::PreRun);
+                        newrunstate = RunState::MapGeneration;
+                    },
+                }
+                self.seed_entry = entry;
             } RunState::GameOver => {
                 let result = gui::game_over(ctx);
                 match result {
                     gui::GameOverResult::NoSelection => {},
                     gui::GameOverResult::QuitToMenu => {
-                        self.game_over_cleanup();
+                        self.new_run(rltk::RandomNumberGenerator::new().next_u64());
                         newrunstate = RunState::MainMenu { menu_selection: gui::MainMenuSelection::NewGame };
                     },
                 }
@@ -363,16 +440,20 @@ impl GameState for State {
                         newrunstate = self.mapgen_next_state.unwrap();
                     }
                 }
-            } RunState::TeleportingToOtherLevel { x, y, depth } => {
-                self.goto_level(depth-1);
-                let player_entity = self.ecs.fetch::<Entity>();
-                if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(*player_entity) {
-                    pos.x = x;
-                    pos.y = y;
+            } RunState::TeleportingToOtherLevel => {
+                let player_entity = *self.ecs.fetch::<Entity>();
+                let destination = self.ecs.write_storage::<OtherLevelPosition>().remove(player_entity);
+                if let Some(OtherLevelPosition { x, y, level }) = destination {
+                    self.goto_level(&Staircase { level, arrival: None }, None);
+                    if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(player_entity) {
+                        pos.x = x;
+                        pos.y = y;
+                    }
+                    let mut ppos = self.ecs.fetch_mut::<rltk::Point>();
+                    ppos.x = x;
+                    ppos.y = y;
                 }
-                let mut ppos = self.ecs.fetch_mut::<rltk::Point>();
-                ppos.x = x;
-                ppos.y = y;
+                map::gather_companions(&mut self.ecs);
                 self.mapgen_next_state = Some(RunState::PreRun);
                 newrunstate = RunState::MapGeneration;
             }
@@ -386,19 +467,36 @@ impl GameState for State {
 }
 
 impl State {
-    fn goto_level (&mut self, offset:i32) {
+    /* `from` is the stairs the player took, if they took any */
+    fn goto_level (&mut self, to: &Staircase, from: Option<usize>) {
         freeze_level_entities(&mut self.ecs);
 
         /* Build a new map and place the player */
-        let current_depth = self.ecs.fetch::<Map>().depth;
-        self.generate_world_map(current_depth+offset, offset);
+        self.generate_world_map(to, from);
+        map::gather_companions(&mut self.ecs);
 
         /* Notify the player */
         let mut gamelog = self.ecs.fetch_mut::<gamelog::GameLog>();
         gamelog.entries.push("You change level.".to_string());
     }
 
-    fn game_over_cleanup (&mut self) {
+    /* Like goto_level, but anything chasing the player at the stairs comes after them */
+    fn take_stairs (&mut self) {
+        let (to, from) = {
+            let map = self.ecs.fetch::<Map>();
+            let player_pos = self.ecs.fetch::<Point>();
+            let idx = map.xy_idx(player_pos.x, player_pos.y);
+            (map::stairs_destination(&map, idx), idx)
+        };
+        if let Some(to) = to {
+            let pursuers = map::find_pursuers(&mut self.ecs);
+            self.goto_level(&to, Some(from));
+            map::send_pursuers(&mut self.ecs, &pursuers);
+        }
+    }
+
+    /// Clears out the last game and sets up a fresh one from the given seed
+    fn new_run (&mut self, seed: u64) {
         /* Delete Everything */
         let mut to_delete = Vec::new();
         for e in self.ecs.entities().join() {
@@ -413,15 +511,18 @@ impl State {
             let mut player_entity_writer = self.ecs.write_resource::<Entity>();
             *player_entity_writer = player_entity; 
         }
-        self.ecs.insert(map::MasterDungeonMap::new());
-        self.generate_world_map(1, 0);
+        let dungeon_master = map::MasterDungeonMap::new(seed);
+        self.ecs.insert(dungeon_master.gameplay_rng(0));
+        self.ecs.insert(dungeon_master);
+        self.ecs.insert(GameClock::new());
+        self.generate_world_map(&Staircase { level: LevelId::main(1), arrival: None }, None);
     }
 
-    fn generate_world_map (&mut self, new_depth:i32, offset:i32) {
+    fn generate_world_map (&mut self, to: &Staircase, from: Option<usize>) {
         self.mapgen_index = 0;
         self.mapgen_timer = 0.0;
         self.mapgen_history.clear();
-        let map_building_info = map::level_transition(&mut self.ecs, new_depth, offset);
+        let map_building_info = map::level_transition(&mut self.ecs, to, from);
         if let Some(history) = map_building_info {
             self.mapgen_history = history;
         } else {
@@ -431,8 +532,100 @@ impl State {
 }
 /* }}} */
 // -- Main -- {{{
+/// Registers every component the game stores on a fresh world
+fn register_components (ecs: &mut World) {
+    ecs.register::<Position>();
+    ecs.register::<Renderable>();
+    ecs.register::<Player>();
+    ecs.register::<Viewshed>();
+    ecs.register::<Name>();
+    ecs.register::<BlocksTile>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<SufferDamage>();
+    ecs.register::<InBackpack>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<Item>();
+    ecs.register::<Consumable>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<WantsToUseItem>();
+    ecs.register::<WantsToDropItem>();
+    ecs.register::<Ranged>();
+    ecs.register::<InflictsDamage>();
+    ecs.register::<AreaOfEffect>();
+    ecs.register::<Confusion>();
+    ecs.register::<SimpleMarker<SerializeMe>>();
+    ecs.register::<SerializationHelper>();
+    ecs.register::<Equippable>();
+    ecs.register::<Equipped>();
+    ecs.register::<MeleeWeapon>();
+    ecs.register::<Wearable>();
+    ecs.register::<WantsToRemoveEquipment>();
+    ecs.register::<ParticleLifetime>();
+    ecs.register::<HungerClock>();
+    ecs.register::<ProvidesFood>();
+    ecs.register::<MagicMapper>();
+    ecs.register::<Hidden>();
+    ecs.register::<EntryTrigger>();
+    ecs.register::<EntityMoved>();
+    ecs.register::<SingleActivation>();
+    ecs.register::<BlocksVisibility>();
+    ecs.register::<Door>();
+    ecs.register::<Quips>();
+    ecs.register::<Attributes>();
+    ecs.register::<Skills>();
+    ecs.register::<Pools>();
+    ecs.register::<NaturalAttackDefense>();
+    ecs.register::<LootTable>();
+    ecs.register::<OtherLevelPosition>();
+    ecs.register::<LightSource>();
+    ecs.register::<Initiative>();
+    ecs.register::<MyTurn>();
+    ecs.register::<Faction>();
+    ecs.register::<Personality>();
+    ecs.register::<Morale>();
+    ecs.register::<Surrendered>();
+    ecs.register::<GoldPile>();
+    ecs.register::<OwnedBy>();
+    ecs.register::<Offshoot>();
+    ecs.register::<Companion>();
+    ecs.register::<Hireling>();
+    ecs.register::<Tameable>();
+    ecs.register::<Pursuer>();
+    ecs.register::<Reputation>();
+    ecs.register::<Durability>();
+    ecs.register::<Humanoid>();
+    ecs.register::<Locomotion>();
+    ecs.register::<Abilities>();
+    ecs.register::<WantsToUseAbility>();
+    ecs.register::<ChosenAbility>();
+    ecs.register::<Held>();
+    ecs.register::<Unique>();
+    ecs.register::<TileSize>();
+    ecs.register::<TownGuard>();
+    ecs.register::<Wanted>();
+    ecs.register::<CrimeCommitted>();
+    ecs.register::<Schedule>();
+    ecs.register::<WantsToApproach>();
+    ecs.register::<WantsToFlee>();
+    ecs.register::<MoveMode>();
+    ecs.register::<Chasing>();
+    ecs.register::<EquipmentChanged>();
+    ecs.register::<Vendor>();
+    ecs.register::<TownPortal>();
+    ecs.register::<TeleportTo>();
+    ecs.register::<ApplyMove>();
+    ecs.register::<ApplyTeleport>();
+    ecs.register::<MagicItem>();
+    ecs.register::<DMSerializationHelper>();
+}
+
 fn main () -> rltk::BError {
     use rltk::RltkBuilder;
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("mapgen") {
+        std::process::exit(mapgen_cli::run(&args[2..]));
+    }
+
     let context = RltkBuilder::simple(80,60)
         .unwrap().with_title("Shadorogue").build()?;
     /* RETRO Feel: context needs `mut` */
@@ -443,84 +636,31 @@ fn main () -> rltk::BError {
         mapgen_index: 0,
         mapgen_history: Vec::new(),
         mapgen_timer: 0.0,
+        seed_entry: String::new(),
     };
 
-    gs.ecs.insert(map::MasterDungeonMap::new());
+    /* A throwaway world to sit behind the menu, until a new game picks its seed */
+    let dungeon_master = map::MasterDungeonMap::new(rltk::RandomNumberGenerator::new().next_u64());
+    gs.ecs.insert(dungeon_master.gameplay_rng(0));
+    gs.ecs.insert(dungeon_master);
+    gs.ecs.insert(GameClock::new());
     gs.ecs.insert(Map::new(1, 64, 64, "New Map"));
 
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.register::<SufferDamage>();
-    gs.ecs.register::<InBackpack>();
-    gs.ecs.register::<WantsToPickupItem>();
-    gs.ecs.register::<Item>();
-    gs.ecs.register::<Consumable>();
-    gs.ecs.register::<ProvidesHealing>();
-    gs.ecs.register::<WantsToUseItem>();
-    gs.ecs.register::<WantsToDropItem>();
-    gs.ecs.register::<Ranged>();
-    gs.ecs.register::<InflictsDamage>();
-    gs.ecs.register::<AreaOfEffect>();
-    gs.ecs.register::<Confusion>();
-    gs.ecs.register::<SimpleMarker<SerializeMe>>();
-    gs.ecs.register::<SerializationHelper>();
-    gs.ecs.register::<Equippable>();
-    gs.ecs.register::<Equipped>();
-    gs.ecs.register::<MeleeWeapon>();
-    gs.ecs.register::<Wearable>();
-    gs.ecs.register::<WantsToRemoveEquipment>();
-    gs.ecs.register::<ParticleLifetime>();
-    gs.ecs.register::<HungerClock>();
-    gs.ecs.register::<ProvidesFood>();
-    gs.ecs.register::<MagicMapper>();
-    gs.ecs.register::<Hidden>();
-    gs.ecs.register::<EntryTrigger>();
-    gs.ecs.register::<EntityMoved>();
-    gs.ecs.register::<SingleActivation>();
-    gs.ecs.register::<BlocksVisibility>();
-    gs.ecs.register::<Door>();
-    gs.ecs.register::<Quips>();
-    gs.ecs.register::<Attributes>();
-    gs.ecs.register::<Skills>();
-    gs.ecs.register::<Pools>();
-    gs.ecs.register::<NaturalAttackDefense>();
-    gs.ecs.register::<LootTable>();
-    gs.ecs.register::<OtherLevelPosition>();
-    gs.ecs.register::<LightSource>();
-    gs.ecs.register::<Initiative>();
-    gs.ecs.register::<MyTurn>();
-    gs.ecs.register::<Faction>();
-    gs.ecs.register::<WantsToApproach>();
-    gs.ecs.register::<WantsToFlee>();
-    gs.ecs.register::<MoveMode>();
-    gs.ecs.register::<Chasing>();
-    gs.ecs.register::<EquipmentChanged>();
-    gs.ecs.register::<Vendor>();
-    gs.ecs.register::<TownPortal>();
-    gs.ecs.register::<TeleportTo>();
-    gs.ecs.register::<ApplyMove>();
-    gs.ecs.register::<ApplyTeleport>();
-    gs.ecs.register::<MagicItem>();
-    gs.ecs.register::<DMSerializationHelper>();
+    register_components(&mut gs.ecs);
     gs.ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
 
     raws::load_raws();
 
     gs.ecs.insert(Point::new(0, 0));
-    gs.ecs.insert(rltk::RandomNumberGenerator::new());
     let player_entity = spawner::player(&mut gs.ecs, 0, 0);
     gs.ecs.insert(player_entity);
     gs.ecs.insert(RunState::MapGeneration{});
     gs.ecs.insert(gamelog::GameLog { entries : vec!["Welcome to Roguelike".to_string()] });
     gs.ecs.insert(particle_sys::ParticleBuilder::new());
+    gs.ecs.insert(ai::FlowFields::new());
     gs.ecs.insert(rex_assets::RexAssets::new());
 
-    gs.generate_world_map(1, 0);
+    gs.generate_world_map(&Staircase { level: LevelId::main(1), arrival: None }, None);
 
     rltk::main_loop(context, gs)
 }
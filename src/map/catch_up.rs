@@ -0,0 +1,276 @@
+use specs::prelude::*;
+use rltk::Point;
+use std::collections::HashSet;
+use super::{Map, Pathing};
+use crate::components::{Position, Pools, HungerClock, HungerState, MoveMode, Movement, Viewshed,
+    Locomotion, TileSize};
+
+const TURNS_PER_REGEN: i32 = 10;
+const TURNS_PER_WANDER_STEP: i32 = 10;
+const MAX_WANDER: i32 = 10;
+const TURNS_PER_RESPAWN: i32 = 500;
+const MAX_RESPAWNS: i32 = 3;
+const HUNGER_STAGE_TURNS: i32 = 200;
+/* Respawns stay out of sight of the stairs the player is standing on */
+const RESPAWN_MIN_DISTANCE: f32 = 12.0;
+
+/// Cheaply fast-forwards a level the player just returned to by `elapsed` turns
+pub fn simulate (ecs: &mut World, thawed: &[Entity], elapsed: i32) {
+    /* Nothing has indexed the thawed level yet, and moves are checked against it */
+    crate::MapIndexingSystem{}.run_now(ecs);
+    regenerate(ecs, thawed, elapsed);
+    digest(ecs, thawed, elapsed);
+    wander(ecs, thawed, elapsed);
+    respawn(ecs, elapsed);
+}
+
+fn regenerate (ecs: &mut World, thawed: &[Entity], elapsed: i32) {
+    let mut pools = ecs.write_storage::<Pools>();
+    let regen = elapsed / TURNS_PER_REGEN;
+    for ent in thawed.iter() {
+        if let Some(pools) = pools.get_mut(*ent) {
+            if pools.hit_points.current < 1 { continue; }
+            pools.hit_points.current = i32::min(pools.hit_points.max, pools.hit_points.current + regen);
+            pools.mana.current = i32::min(pools.mana.max, pools.mana.current + regen);
+        }
+    };
+}
+
+fn digest (ecs: &mut World, thawed: &[Entity], elapsed: i32) {
+    let mut clocks = ecs.write_storage::<HungerClock>();
+    let mut pools = ecs.write_storage::<Pools>();
+    for ent in thawed.iter() {
+        if let Some(clock) = clocks.get_mut(*ent) {
+            clock.duration -= elapsed;
+            while clock.duration < 1 && clock.state != HungerState::Starving {
+                clock.state = match clock.state {
+                    HungerState::WellFed => HungerState::Normal,
+                    HungerState::Normal => HungerState::Hungry,
+                    _ => HungerState::Starving,
+                };
+                clock.duration += HUNGER_STAGE_TURNS;
+            };
+            /* Starvation wears them down, but doesn't kill off-screen */
+            if clock.duration < 1 {
+                if let Some(pools) = pools.get_mut(*ent) {
+                    pools.hit_points.current = i32::max(1, pools.hit_points.current + clock.duration);
+                }
+                clock.duration = 0;
+            }
+        }
+    };
+}
+
+fn wander (ecs: &mut World, thawed: &[Entity], elapsed: i32) {
+    let map = ecs.fetch::<Map>();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let move_modes = ecs.read_storage::<MoveMode>();
+    let locomotions = ecs.read_storage::<Locomotion>();
+    let sizes = ecs.read_storage::<TileSize>();
+    let player_pos = ecs.fetch::<Point>();
+    let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
+
+    let range = i32::min(MAX_WANDER, elapsed / TURNS_PER_WANDER_STEP);
+
+    for ent in thawed.iter() {
+        let (mode, pos) = match (move_modes.get(*ent), positions.get_mut(*ent)) {
+            (Some(mode), Some(pos)) => (mode, pos),
+            _ => continue,
+        };
+        let pathing = Pathing::of(*ent, &locomotions, &sizes);
+        let size = sizes.get(*ent);
+        /* Somewhere it fits, that doesn't put it on top of the player */
+        let fits = |x: i32, y: i32| {
+            map.can_stand(x, y, &pathing)
+                && !map.footprint(x, y, size).contains(&map.xy_idx(player_pos.x, player_pos.y))
+        };
+        let destination = match &mode.mode {
+            Movement::Random | Movement::RandomWaypoint { .. } => {
+                if range < 1 { continue; }
+                (0 .. 10).map(|_| {
+                    let x = pos.x + rng.roll_dice(1, range * 2 + 1) - (range + 1);
+                    let y = pos.y + rng.roll_dice(1, range * 2 + 1) - (range + 1);
+                    (x, y)
+                })
+                .find(|(x, y)| fits(*x, *y))
+            }
+            /* Guards drift back to their posts, patrols are somewhere along their route */
+            Movement::Guard { home, .. } => Some((*home as i32 % map.width, *home as i32 / map.width)),
+            Movement::Patrol { waypoints, next } if !waypoints.is_empty() =>
+                Some((waypoints[*next] as i32 % map.width, waypoints[*next] as i32 / map.width)),
+            _ => None,
+        };
+        if let Some((x, y)) = destination {
+            if (x, y) == (pos.x, pos.y) || !fits(x, y) { continue; }
+            crate::spatial::move_footprint(*ent, &map.footprint(pos.x, pos.y, size), &map.footprint(x, y, size));
+            pos.x = x;
+            pos.y = y;
+            if let Some(vs) = viewsheds.get_mut(*ent) {
+                vs.dirty = true;
+            }
+        }
+    };
+}
+
+fn respawn (ecs: &mut World, elapsed: i32) {
+    let depth = ecs.fetch::<Map>().depth;
+    /* The town doesn't grow monsters */
+    if depth < 2 { return; }
+
+    let mut spawns: Vec<(usize, String)> = Vec::new();
+    {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+        let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
+        let raws = crate::raws::RAWS.lock().unwrap();
+        let spawn_table = crate::raws::get_spawn_table_for_depth(&raws, &map.branch, depth);
+
+        /* Tiles taken by earlier respawns, which aren't in the index yet */
+        let mut taken: HashSet<usize> = HashSet::new();
+        for _i in 0 .. i32::min(MAX_RESPAWNS, elapsed / TURNS_PER_RESPAWN) {
+            /* Only wandering monsters move in; loot and traps don't */
+            let name = spawn_table.roll(&mut rng);
+            let (locomotion, size) = match crate::raws::get_mob_footprint(&raws, &name) {
+                Some(footprint) => footprint,
+                None => continue,
+            };
+            let (w, h) = size.map_or((1, 1), |size| (size.x, size.y));
+            let pathing = Pathing { locomotion, footprint: None };
+            for _attempt in 0 .. 20 {
+                let x = rng.roll_dice(1, map.width - 2);
+                let y = rng.roll_dice(1, map.height - 2);
+                let idx = map.xy_idx(x, y);
+                let far_enough = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, Point::new(x, y)) > RESPAWN_MIN_DISTANCE;
+                /* Nothing's spawned yet to be in its own way, so every tile it covers is checked alone */
+                let tiles = map.footprint(x, y, size.as_ref());
+                let fits = x + w < map.width && y + h < map.height
+                    && (0 .. h).all(|dy| (0 .. w).all(|dx| map.can_stand(x + dx, y + dy, &pathing)))
+                    && !tiles.iter().any(|t| taken.contains(t));
+                if far_enough && fits {
+                    taken.extend(tiles);
+                    spawns.push((idx, name));
+                    break;
+                }
+            };
+        };
+    }
+
+    for (idx, name) in spawns.iter() {
+        crate::spawner::spawn_entity(ecs, &(idx, name));
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{BlocksTile, Pool};
+    use crate::map::TileType;
+    use crate::test_support::{lock_globals, open_map, world};
+
+    /// A town-depth room (so nothing respawns) with a pool of deep water in the east
+    fn away () -> World {
+        let mut map = open_map(1, 20, 20);
+        for y in 1 .. 19 {
+            for x in 13 .. 19 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::DeepWater;
+            };
+        };
+        map.populate_blocked();
+        let mut ecs = world(map);
+        ecs.insert(Point::new(1, 1));
+        ecs
+    }
+
+    fn pools (current: i32, max: i32) -> Pools {
+        Pools {
+            hit_points: Pool { max, current }, mana: Pool { max, current },
+            xp: 0, level: 1, total_weight: 0.0, total_initiative_penalty: 0.0, gold: 0.0, god_mode: false,
+        }
+    }
+
+    fn position (ecs: &World, ent: Entity) -> (i32, i32) {
+        let positions = ecs.read_storage::<Position>();
+        let pos = positions.get(ent).unwrap();
+        (pos.x, pos.y)
+    }
+
+    #[test]
+    fn time_away_heals_and_hungers () {
+        let _globals = lock_globals();
+        let mut ecs = away();
+        let hurt = ecs.create_entity().with(pools(5, 20)).build();
+        let dead = ecs.create_entity().with(pools(0, 20)).build();
+        let hungry = ecs.create_entity().with(pools(10, 10))
+            .with(HungerClock { state: HungerState::WellFed, duration: 50 })
+            .build();
+        simulate(&mut ecs, &[hurt, dead, hungry], 100);
+        assert_eq!(ecs.read_storage::<Pools>().get(hurt).unwrap().hit_points.current, 15);
+        assert_eq!(ecs.read_storage::<Pools>().get(dead).unwrap().hit_points.current, 0);
+        let clock = ecs.read_storage::<HungerClock>().get(hungry).cloned().unwrap();
+        assert!(clock.state == HungerState::Normal && clock.duration == 150);
+
+        /* Starving wears a mob down to its last hit point, and no further */
+        simulate(&mut ecs, &[hungry], 2000);
+        let clock = ecs.read_storage::<HungerClock>().get(hungry).cloned().unwrap();
+        assert!(clock.state == HungerState::Starving && clock.duration == 0);
+        assert_eq!(ecs.read_storage::<Pools>().get(hungry).unwrap().hit_points.current, 1);
+    }
+
+    #[test]
+    fn guards_go_home_unless_someone_is_standing_there () {
+        let _globals = lock_globals();
+        let mut ecs = away();
+        let home = ecs.fetch::<Map>().xy_idx(5, 5);
+        let guard = ecs.create_entity().with(Position { x: 9, y: 9 })
+            .with(MoveMode { mode: Movement::Guard { home, radius: 2 } })
+            .build();
+        simulate(&mut ecs, &[guard], 100);
+        assert_eq!(position(&ecs, guard), (5, 5));
+
+        let other_home = ecs.fetch::<Map>().xy_idx(8, 8);
+        let blocked = ecs.create_entity().with(Position { x: 3, y: 9 })
+            .with(MoveMode { mode: Movement::Guard { home: other_home, radius: 2 } })
+            .build();
+        ecs.create_entity().with(Position { x: 8, y: 8 }).with(BlocksTile {}).build();
+        simulate(&mut ecs, &[blocked], 100);
+        assert_eq!(position(&ecs, blocked), (3, 9));
+    }
+
+    #[test]
+    fn wanderers_only_go_where_they_could_walk () {
+        let _globals = lock_globals();
+        let mut ecs = away();
+        let mut walkers = Vec::new();
+        let mut swimmers = Vec::new();
+        for i in 0 .. 17 {
+            walkers.push(ecs.create_entity().with(Position { x: 2 + (i % 3) * 4, y: 1 + (i / 3) * 3 })
+                .with(MoveMode { mode: Movement::Random })
+                .with(TileSize { x: 2, y: 2 })
+                .with(BlocksTile {})
+                .build());
+            swimmers.push(ecs.create_entity().with(Position { x: 15, y: 1 + i })
+                .with(MoveMode { mode: Movement::Random })
+                .with(Locomotion { swimming: true, ..Default::default() })
+                .build());
+        };
+        let everyone: Vec<Entity> = walkers.iter().chain(swimmers.iter()).copied().collect();
+        simulate(&mut ecs, &everyone, 100);
+
+        let map = ecs.fetch::<Map>();
+        let sizes = ecs.read_storage::<TileSize>();
+        let mut covered = HashSet::new();
+        for walker in walkers.iter() {
+            let (x, y) = position(&ecs, *walker);
+            for idx in map.footprint(x, y, sizes.get(*walker)).iter() {
+                assert!(map.tiles[*idx] == TileType::Floor, "a walker ended up at ({}, {})", x, y);
+                assert!(covered.insert(*idx), "two walkers share tile {}", idx);
+            };
+        };
+        for swimmer in swimmers.iter() {
+            let (x, y) = position(&ecs, *swimmer);
+            assert!(x > 0 && y > 0 && x < 19 && y < 19);
+        };
+    }
+}
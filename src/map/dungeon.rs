@@ -1,70 +1,295 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use serde::{Serialize, Deserialize};
 use super::{Map, TileType};
-use crate::components::{Position, Viewshed, OtherLevelPosition};
-use crate::map_builders::level_builder;
+use crate::components::{Position, Viewshed, OtherLevelPosition, Companion, CompanionOrder, BlocksTile,
+    Chasing, Pursuer};
+use crate::map_builders::{BuilderChain, MapValidator, named_builder};
+use crate::raws::{RAWS, RawMaster, get_branch, get_unique_lairs};
 use specs::prelude::*;
 use rltk::Point;
 
+/* Each kind of randomness in a run draws from its own stream, so that (say) one more fight
+   on depth 2 can't change what depth 3 looks like */
+const MAPGEN_STREAM: u64 = 1;
+const GAMEPLAY_STREAM: u64 = 2;
+const UNIQUE_STREAM: u64 = 3;
+
+/// How many times to rebuild a level that comes out broken before making do with it
+const MAX_MAPGEN_ATTEMPTS: i32 = 5;
+
+/// Mixes the run seed, a stream and a number (depth, turn) into a seed of its own, with the
+/// SplitMix64 finaliser so that neighbouring depths get unrelated streams
+pub fn derive_seed (seed: u64, stream: u64, n: i32) -> u64 {
+    let mut z = seed
+        ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (n as i64 as u64).wrapping_mul(0xD1B5_4A32_D192_ED03);
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Turns whatever the player typed into a seed: numbers are used as they are, anything else
+/// is hashed (FNV-1a, which unlike the std hasher is the same on every build)
+pub fn seed_from_text (text: &str) -> u64 {
+    if let Ok(seed) = text.trim().parse::<u64>() { return seed; }
+    text.trim().bytes().fold(0xCBF2_9CE4_8422_2325, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3))
+}
+
+/// The branch every run starts in, and that levels.json entries without a branch belong to
+pub const MAIN_BRANCH: &str = "main";
+
+/// A level of the dungeon: a branch and how deep it is. Depth counts from the town whichever
+/// branch it's down, so a branch leaving depth 3 starts at depth 4.
+#[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize, Debug)]
+pub struct LevelId {
+    pub branch: String,
+    pub depth: i32,
+}
+
+impl LevelId {
+    pub fn new (branch: &str, depth: i32) -> LevelId {
+        LevelId { branch: branch.to_string(), depth }
+    }
+
+    pub fn main (depth: i32) -> LevelId {
+        LevelId::new(MAIN_BRANCH, depth)
+    }
+}
+
+/// Written as the dungeon keys its maps, e.g. "main:4" or "mines:5"
+impl fmt::Display for LevelId {
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.branch, self.depth)
+    }
+}
+
+/// Where a staircase goes, and the tile it comes out on; that isn't known until the level
+/// at the other end has been built
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct Staircase {
+    pub level: LevelId,
+    pub arrival: Option<usize>,
+}
+
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct MasterDungeonMap {
-    maps: HashMap<i32, Map>,
+    seed: u64,
+    maps: HashMap<String, Map>,
     identified_items: HashSet<String>,
     scroll_mappings: HashMap<String, String>,
+    frozen_at: HashMap<String, i32>,
+    uniques: HashMap<String, UniqueState>,
+}
+
+/// Where a unique monster is in its one trip through the run
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum UniqueState {
+    Unspawned,
+    Alive { depth: i32 },
+    Dead,
 }
 
 impl MasterDungeonMap {
-    pub fn new () -> MasterDungeonMap {
+    pub fn new (seed: u64) -> MasterDungeonMap {
         MasterDungeonMap {
+            seed,
             maps: HashMap::new(),
-            pub identified_items: HashSet::new(),
-            pub scroll_mappings: HashMap::new(),
+            identified_items: HashSet::new(),
+            scroll_mappings: HashMap::new(),
+            frozen_at: HashMap::new(),
+            uniques: HashMap::new(),
         }
     }
 
+    pub fn seed (&self) -> u64 {
+        self.seed
+    }
+
+    /// The stream a level's map is built from: the same for a seed however you get there
+    pub fn mapgen_rng (&self, level: &LevelId) -> rltk::RandomNumberGenerator {
+        /* Side branches get streams of their own; the main dungeon keeps the seed as it is */
+        let seed = if level.branch == MAIN_BRANCH { self.seed } else { self.seed ^ seed_from_text(&level.branch) };
+        rltk::RandomNumberGenerator::seeded(derive_seed(seed, MAPGEN_STREAM, level.depth))
+    }
+
+    /// The stream for everything else, picked up from the turn a game was started or loaded on
+    pub fn gameplay_rng (&self, turn: i32) -> rltk::RandomNumberGenerator {
+        rltk::RandomNumberGenerator::seeded(derive_seed(self.seed, GAMEPLAY_STREAM, turn))
+    }
+
+    /* Lairs are rolled apart from the map itself, so adding a unique can't reshape every level */
+    fn unique_rng (&self, depth: i32) -> rltk::RandomNumberGenerator {
+        rltk::RandomNumberGenerator::seeded(derive_seed(self.seed, UNIQUE_STREAM, depth))
+    }
+
+    /// Remembers the game turn a level was left on
+    pub fn freeze_level (&mut self, level: &LevelId, turn: i32) {
+        self.frozen_at.insert(level.to_string(), turn);
+    }
+
+    /// How many turns have passed on a level since it was left
+    pub fn turns_since_frozen (&self, level: &LevelId, turn: i32) -> i32 {
+        self.frozen_at.get(&level.to_string()).map_or(0, |frozen| turn - frozen)
+    }
+
+    /// Whose lair is stamped into a level, if anyone's. Rolled a depth at a time from the seed
+    /// alone, so a seed's levels always come out the same: who has been met only decides
+    /// whether anyone is home. The odds rise as a unique runs out of levels, so each one gets
+    /// a lair somewhere in its range (unless another takes its last chance).
+    pub fn unique_lair (&self, raws: &RawMaster, level: &LevelId) -> Option<(String, Vec<String>)> {
+        if level.branch != MAIN_BRANCH { return None; }
+        let mut placed: Vec<String> = Vec::new();
+        for depth in 1 ..= level.depth {
+            let mut rng = self.unique_rng(depth);
+            let lair = get_unique_lairs(raws, depth).into_iter()
+                .filter(|(unique, _lair, _levels_left)| !placed.contains(unique))
+                .find(|(_unique, _lair, levels_left)| rng.roll_dice(1, *levels_left) == 1);
+            match lair {
+                Some((unique, lair, _levels_left)) if depth == level.depth => return Some((unique, lair)),
+                Some((unique, _lair, _levels_left)) => placed.push(unique),
+                None => {},
+            }
+        };
+        None
+    }
+
+    pub fn unique_state (&self, name: &str) -> UniqueState {
+        *self.uniques.get(name).unwrap_or(&UniqueState::Unspawned)
+    }
+
+    pub fn set_unique_state (&mut self, name: &str, state: UniqueState) {
+        self.uniques.insert(name.to_string(), state);
+    }
+
     pub fn store_map (&mut self, map: &Map) {
-        self.maps.insert(map.depth, map.clone());
+        self.maps.insert(map.level_id().to_string(), map.clone());
     }
 
-    pub fn get_map (&self, depth:i32) -> Option<Map> {
-        if self.maps.contains_key(&depth) {
-            let result = self.maps[&depth].clone();
-            Some(result)
-        } else { None }
+    pub fn get_map (&self, level: &LevelId) -> Option<Map> {
+        self.maps.get(&level.to_string()).cloned()
     }
+
+    /// Points the stairs at `idx` on a stored level somewhere
+    fn link_stairs (&mut self, level: &LevelId, idx: usize, to: Staircase) {
+        if let Some(map) = self.maps.get_mut(&level.to_string()) {
+            map.stairs.insert(idx, to);
+        }
+    }
+}
+
+/// Where the stairs at `idx` go: wherever they've been linked to, or else the next level
+/// down (or up) the same branch
+pub fn stairs_destination (map: &Map, idx: usize) -> Option<Staircase> {
+    if let Some(link) = map.stairs.get(&idx) { return Some(link.clone()); }
+    let depth = match map.tiles[idx] {
+        TileType::DownStairs => map.depth + 1,
+        TileType::UpStairs => map.depth - 1,
+        _ => return None,
+    };
+    Some(Staircase { level: LevelId::new(&map.branch, depth), arrival: None })
+}
+
+/// Where the up stairs at the top of a level lead if nobody came down them: back up the
+/// branch, or out of it to the level it leaves from
+fn way_up (level: &LevelId) -> Staircase {
+    let raws = RAWS.lock().unwrap();
+    let up = match get_branch(&raws, &level.branch) {
+        Some(branch) if level.depth <= branch.depth + 1 => LevelId::new(branch.parent(), branch.depth),
+        _ => LevelId::new(&level.branch, level.depth - 1),
+    };
+    Staircase { level: up, arrival: None }
 }
 
-pub fn level_transition (ecs: &mut World, new_depth:i32, offset:i32) -> Option<Vec<Map>> {
+/// Moves the player to the level `to` leads to, building it if it's new. `from` is the tile
+/// of the stairs they took on the level they're leaving, if they took any.
+pub fn level_transition (ecs: &mut World, to: &Staircase, from: Option<usize>) -> Option<Vec<Map>> {
     let dungeon_master = ecs.read_resource::<MasterDungeonMap>();
-    if dungeon_master.get_map(new_depth).is_some() {
+    if dungeon_master.get_map(&to.level).is_some() {
         std::mem::drop(dungeon_master);
-        transition_to_existing_map(ecs, new_depth, offset);
+        transition_to_existing_map(ecs, to, from);
         None
     } else {
         std::mem::drop(dungeon_master);
-        Some(transition_to_new_map(ecs, new_depth))
+        Some(transition_to_new_map(ecs, &to.level, from))
     }
 }
 
-fn transition_to_new_map (ecs: &mut World, new_depth:i32) -> Vec<Map> {
-    let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
-    let mut builder = level_builder(new_depth, &mut rng, 80, 50);
-    builder.build_map(&mut rng);
-    if new_depth > 1 {
-        if let Some(pos) = &builder.build_data.starting_position {
-            let up_idx = builder.build_data.map.xy_idx(pos.x, pos.y);
-            builder.build_data.map.tiles[up_idx] = TileType::UpStairs;            
+/// Puts in the stairs a built level gets whichever chain built it: up stairs where the player
+/// arrives (bar in the town), leading back the way they came or else up the branch, and no
+/// way on at the bottom of a side branch
+pub fn place_level_stairs (map: &mut Map, start: &Position, way_back: Option<Staircase>) {
+    let level = map.level_id();
+    if level.depth > 1 {
+        let up_idx = map.xy_idx(start.x, start.y);
+        map.tiles[up_idx] = TileType::UpStairs;
+        map.stairs.insert(up_idx, way_back.unwrap_or_else(|| way_up(&level)));
+    }
+    let bottom = get_branch(&RAWS.lock().unwrap(), &level.branch).is_some_and(|branch| level.depth >= branch.depth + branch.length);
+    if bottom {
+        /* Stairs into a branch further down are kept */
+        for idx in 0 .. map.tiles.len() {
+            if map.tiles[idx] == TileType::DownStairs && !map.stairs.contains_key(&idx) {
+                map.tiles[idx] = TileType::Floor;
+            }
+        };
+    }
+}
+
+/// Builds a level the one way both the game and `roguelike mapgen` do: with its own chain
+/// (or `builder`, if it names another), the lair its seed puts there, built again if it comes
+/// out broken, and given a start and stairs however it ends up. Any problems left are the
+/// last attempt's.
+pub fn build_level (dungeon: &MasterDungeonMap, level: &LevelId, builder: &str, size: Option<(i32, i32)>,
+    history: bool, way_back: Option<Staircase>) -> BuilderChain
+{
+    let lair = dungeon.unique_lair(&RAWS.lock().unwrap(), level);
+    build_level_with_lair(dungeon, level, builder, size, history, way_back, lair.as_ref())
+}
+
+/// `build_level` with the lair picked by the caller rather than the seed, so tests can try
+/// every lair a level might get
+pub(crate) fn build_level_with_lair (dungeon: &MasterDungeonMap, level: &LevelId, builder: &str, size: Option<(i32, i32)>,
+    history: bool, way_back: Option<Staircase>, lair: Option<&(String, Vec<String>)>) -> BuilderChain
+{
+    let mut rng = dungeon.mapgen_rng(level);
+    let mut attempts = 0;
+    let mut chain = loop {
+        let mut chain = named_builder(builder, level, &mut rng, size)
+            .unwrap_or_else(|| panic!("No builder chain named {}", builder));
+        if history { chain.record_history(); }
+        if let Some((unique, lair)) = lair {
+            chain.with_lair(unique, lair);
         }
+        chain.with(MapValidator::new());
+        chain.build_map(&mut rng);
+        attempts += 1;
+        if chain.build_data.problems.is_empty() || attempts >= MAX_MAPGEN_ATTEMPTS { break chain; }
+        rltk::console::log(format!("Warning: level {} came out broken, building it again", level));
+    };
+    if chain.build_data.starting_position.is_none() {
+        /* Still broken: anywhere the player can stand beats not having a level */
+        let map = &chain.build_data.map;
+        let idx = map.tiles.iter().position(|tt| super::tile_walkable(*tt)).unwrap_or((map.width + 1) as usize);
+        chain.build_data.starting_position = Some(Position { x: idx as i32 % map.width, y: idx as i32 / map.width });
     }
+    let start = chain.build_data.starting_position.clone().expect("Every level has a start");
+    place_level_stairs(&mut chain.build_data.map, &start, way_back);
+    chain
+}
+
+fn transition_to_new_map (ecs: &mut World, level: &LevelId, from: Option<usize>) -> Vec<Map> {
+    let previous_level = ecs.fetch::<Map>().level_id();
+    let way_back = from.map(|idx| Staircase { level: previous_level.clone(), arrival: Some(idx) });
+    let mut builder = build_level(&ecs.fetch::<MasterDungeonMap>(), level, "level", None, false, way_back);
+    let player_start = builder.build_data.starting_position.clone().expect("Every level has a start");
     let mapgen_history = builder.build_data.history.clone();
-    let player_start;
     {
         let mut worldmap_resource = ecs.write_resource::<Map>();
         *worldmap_resource = builder.build_data.map.clone();
-        player_start = builder.build_data.starting_position.as_mut().unwrap().clone();
     }
 
-    std::mem::drop(rng);
     builder.spawn_entities(ecs);
 
     let (player_x, player_y) = (player_start.x, player_start.y);
@@ -86,29 +311,40 @@ fn transition_to_new_map (ecs: &mut World, new_depth:i32) -> Vec<Map> {
 
     let mut dungeon_master = ecs.write_resource::<MasterDungeonMap>();
     dungeon_master.store_map(&builder.build_data.map);
+    if let Some(idx) = from {
+        let arrival = builder.build_data.map.xy_idx(player_x, player_y);
+        dungeon_master.link_stairs(&previous_level, idx, Staircase { level: level.clone(), arrival: Some(arrival) });
+    }
     mapgen_history
 }
 
-fn transition_to_existing_map (ecs: &mut World, new_depth:i32, offset:i32) {
-    let dungeon_master = ecs.read_resource::<MasterDungeonMap>();
-    let map = dungeon_master.get_map(new_depth).unwrap();
+fn transition_to_existing_map (ecs: &mut World, to: &Staircase, from: Option<usize>) {
+    let mut dungeon_master = ecs.write_resource::<MasterDungeonMap>();
+    let map = dungeon_master.get_map(&to.level).unwrap();
     let mut worldmap_resource = ecs.write_resource::<Map>();
     let player_entity = ecs.fetch::<Entity>();
+    let previous_level = worldmap_resource.level_id();
 
-    let w = map.width;
-    let stair_type = if offset < 0 { TileType::DownStairs } else { TileType::UpStairs };
-    for (idx, tt) in map.tiles.iter().enumerate() {
-        if *tt == stair_type {
-            let mut player_position = ecs.write_resource::<Point>();
-            *player_position = Point::new(idx as i32 % w, idx as i32 / w);
-            let mut position_components = ecs.write_storage::<Position>();
-            let player_pos_comp = position_components.get_mut(*player_entity);
-            if let Some(player_pos_comp) = player_pos_comp {
-                player_pos_comp.x = idx as i32 % w;
-                player_pos_comp.y = idx as i32 / w;
-            }
+    /* Come out where the stairs lead; failing that, on stairs back to where the player was,
+       or at least on some going the right way */
+    let stair_type = if to.level.depth < previous_level.depth { TileType::DownStairs } else { TileType::UpStairs };
+    let arrival = to.arrival
+        .or_else(|| map.stairs.iter().filter(|(_idx, link)| link.level == previous_level).map(|(idx, _link)| *idx).min())
+        .or_else(|| map.tiles.iter().rposition(|tt| *tt == stair_type));
+    if let Some(idx) = arrival {
+        let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+        let mut player_position = ecs.write_resource::<Point>();
+        *player_position = Point::new(x, y);
+        let mut position_components = ecs.write_storage::<Position>();
+        if let Some(player_pos_comp) = position_components.get_mut(*player_entity) {
+            player_pos_comp.x = x;
+            player_pos_comp.y = y;
         }
-    };
+        if let (Some(from), None) = (from, to.arrival) {
+            dungeon_master.link_stairs(&previous_level, from, Staircase { level: to.level.clone(), arrival: Some(idx) });
+        }
+    }
+    crate::spatial::set_size(map.tiles.len());
     *worldmap_resource = map;
 
     let mut viewshed_components = ecs.write_storage::<Viewshed>();
@@ -124,12 +360,16 @@ pub fn freeze_level_entities (ecs: &mut World) {
     let mut positions = ecs.write_storage::<Position>();
     let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
     let player_entity = ecs.fetch::<Entity>();
-    let map_depth = ecs.fetch::<Map>().depth;
+    let map = ecs.fetch::<Map>();
+    let level = map.level_id();
+    let companions = ecs.read_storage::<Companion>();
 
     let mut pos_to_delete: Vec<Entity> = Vec::new();
     for (ent,pos) in (&entities, &positions).join() {
-        if ent != *player_entity {
-            other_level_positions.insert(ent, OtherLevelPosition { x: pos.x, y: pos.y, depth: map_depth }).expect("Insert Fail");
+        /* Companions come along, unless told to stay */
+        let travelling = companions.get(ent).is_some_and(|c| c.order != CompanionOrder::Stay);
+        if ent != *player_entity && !travelling {
+            other_level_positions.insert(ent, OtherLevelPosition { x: pos.x, y: pos.y, level: level.clone() }).expect("Insert Fail");
             pos_to_delete.push(ent);
         }
     };
@@ -137,25 +377,245 @@ pub fn freeze_level_entities (ecs: &mut World) {
     for p in pos_to_delete.iter() {
         positions.remove(*p);
     };
+    /* Keep the level as it was left: what's been seen, and where the stairs now go */
+    let turn = ecs.fetch::<crate::GameClock>().turn;
+    let mut dungeon_master = ecs.write_resource::<MasterDungeonMap>();
+    dungeon_master.freeze_level(&level, turn);
+    dungeon_master.store_map(&map);
 }
 
 pub fn thaw_level_entities (ecs: &mut World) {
-    /* Obtain ECS access */
+    let level = ecs.fetch::<Map>().level_id();
+    let mut pos_to_delete: Vec<Entity> = Vec::new();
+    {
+        /* Obtain ECS access */
+        let entities = ecs.entities();
+        let mut positions = ecs.write_storage::<Position>();
+        let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+        let player_entity = ecs.fetch::<Entity>();
+
+        for (ent,pos) in (&entities, &other_level_positions).join() {
+            if ent != *player_entity && pos.level == level {
+                positions.insert(ent, Position { x: pos.x, y: pos.y }).expect("Insert Fail");
+                pos_to_delete.push(ent);
+            }
+        };
+
+        /* The player came back before whatever was following them arrived */
+        let mut pursuers = ecs.write_storage::<Pursuer>();
+        for p in pos_to_delete.iter() {
+            other_level_positions.remove(*p);
+            pursuers.remove(*p);
+        };
+    }
+
+    /* Life went on while the player was away */
+    let turn = ecs.fetch::<crate::GameClock>().turn;
+    let elapsed = ecs.fetch::<MasterDungeonMap>().turns_since_frozen(&level, turn);
+    if elapsed > 0 {
+        super::catch_up::simulate(ecs, &pos_to_delete, elapsed);
+    }
+}
+
+/// Mobs next to the player that are chasing them, about to be left behind on the stairs
+pub fn find_pursuers (ecs: &mut World) -> Vec<Entity> {
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let chasing = ecs.read_storage::<Chasing>();
+    let player_entity = ecs.fetch::<Entity>();
+    let player_pos = ecs.fetch::<Point>();
+
+    (&entities, &positions, &chasing).join()
+        .filter(|(_, pos, chase)| {
+            chase.target == *player_entity &&
+                rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, Point::new(pos.x, pos.y)) < 1.5
+        })
+        .map(|(ent, _, _)| ent)
+        .collect()
+}
+
+/// Sends the pursuers after the player: they reach the stairs the player arrived by a turn or two later
+pub fn send_pursuers (ecs: &mut World, pursuers: &[Entity]) {
+    let mut rng = ecs.write_resource::<rltk::RandomNumberGenerator>();
+    let mut pursuer_components = ecs.write_storage::<Pursuer>();
+    let clock = ecs.fetch::<crate::GameClock>();
+    let player_pos = ecs.fetch::<Point>();
+    let level = ecs.fetch::<Map>().level_id();
+
+    for pursuer in pursuers.iter() {
+        pursuer_components.insert(*pursuer, Pursuer {
+            x: player_pos.x,
+            y: player_pos.y,
+            level: level.clone(),
+            arrival_turn: clock.turn + rng.roll_dice(1, 2),
+        }).expect("Insert Fail");
+    };
+}
+
+/// Places travelling companions on free tiles around the player after a level change
+pub fn gather_companions (ecs: &mut World) {
     let entities = ecs.entities();
     let mut positions = ecs.write_storage::<Position>();
-    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let companions = ecs.read_storage::<Companion>();
+    let blockers = ecs.read_storage::<BlocksTile>();
     let player_entity = ecs.fetch::<Entity>();
-    let map_depth = ecs.fetch::<Map>().depth;
+    let map = ecs.fetch::<Map>();
 
-    let mut pos_to_delete: Vec<Entity> = Vec::new();
-    for (ent,pos) in (&entities, &other_level_positions).join() {
-        if ent != *player_entity && pos.depth == map_depth {
-            positions.insert(ent, Position { x: pos.x, y: pos.y }).expect("Insert Fail");
-            pos_to_delete.push(ent);
-        }
+    let player_pos = match positions.get(*player_entity) {
+        Some(pos) => Point::new(pos.x, pos.y),
+        None => return,
     };
+    let travellers: Vec<Entity> = (&entities, &companions, &positions).join().map(|(ent, _, _)| ent).collect();
+    if travellers.is_empty() { return; }
 
-    for p in pos_to_delete.iter() {
-        other_level_positions.remove(*p);
+    let mut occupied: HashSet<usize> = (&entities, &positions, &blockers).join()
+        .filter(|(ent, _, _)| !travellers.contains(ent))
+        .map(|(_, pos, _)| map.xy_idx(pos.x, pos.y))
+        .collect();
+    occupied.insert(map.xy_idx(player_pos.x, player_pos.y));
+
+    for companion in travellers.iter() {
+        let mut spot = None;
+        'search: for radius in 1 .. map.width {
+            for y in player_pos.y - radius ..= player_pos.y + radius {
+                for x in player_pos.x - radius ..= player_pos.x + radius {
+                    if x < 1 || x > map.width-2 || y < 1 || y > map.height-2 { continue; }
+                    let idx = map.xy_idx(x, y);
+                    if super::tile_walkable(map.tiles[idx]) && !occupied.contains(&idx) {
+                        spot = Some(idx);
+                        break 'search;
+                    }
+                };
+            };
+        };
+        if let Some(idx) = spot {
+            occupied.insert(idx);
+            if let Some(pos) = positions.get_mut(*companion) {
+                pos.x = idx as i32 % map.width;
+                pos.y = idx as i32 / map.width;
+            }
+            if let Some(vs) = viewsheds.get_mut(*companion) {
+                vs.dirty = true;
+            }
+        }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rolls (mut rng: rltk::RandomNumberGenerator) -> Vec<i32> {
+        (0 .. 8).map(|_| rng.roll_dice(1, 1000)).collect()
+    }
+
+    #[test]
+    fn derived_seeds_are_repeatable () {
+        assert_eq!(derive_seed(42, MAPGEN_STREAM, 3), derive_seed(42, MAPGEN_STREAM, 3));
+        let dungeon = MasterDungeonMap::new(42);
+        assert_eq!(rolls(dungeon.mapgen_rng(&LevelId::main(3))), rolls(MasterDungeonMap::new(42).mapgen_rng(&LevelId::main(3))));
+        assert_eq!(rolls(dungeon.gameplay_rng(100)), rolls(dungeon.gameplay_rng(100)));
+    }
+
+    #[test]
+    fn streams_depths_and_seeds_all_differ () {
+        let base = derive_seed(42, MAPGEN_STREAM, 3);
+        assert_ne!(base, derive_seed(42, MAPGEN_STREAM, 4));
+        assert_ne!(base, derive_seed(42, GAMEPLAY_STREAM, 3));
+        assert_ne!(base, derive_seed(43, MAPGEN_STREAM, 3));
+        assert_ne!(derive_seed(0, MAPGEN_STREAM, -1), derive_seed(0, MAPGEN_STREAM, 1));
+        let dungeon = MasterDungeonMap::new(42);
+        let branch = LevelId { branch: "mines".to_string(), depth: 3 };
+        assert_ne!(rolls(dungeon.mapgen_rng(&LevelId::main(3))), rolls(dungeon.mapgen_rng(&branch)));
+    }
+
+    #[test]
+    fn numbers_are_seeds_and_text_is_hashed () {
+        assert_eq!(seed_from_text("12345"), 12345);
+        assert_eq!(seed_from_text("  12345\n"), 12345);
+        /* FNV-1a, so these can't change between builds */
+        assert_eq!(seed_from_text(""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(seed_from_text("a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(seed_from_text(" hello "), seed_from_text("hello"));
+        assert_ne!(seed_from_text("hello"), seed_from_text("Hello"));
+        assert_ne!(seed_from_text("-1"), seed_from_text("1"));
+    }
+
+    #[test]
+    fn uniques_start_unmet_and_stay_as_left () {
+        let mut dungeon = MasterDungeonMap::new(1);
+        assert!(dungeon.unique_state("Grub") == UniqueState::Unspawned);
+        dungeon.set_unique_state("Grub", UniqueState::Alive { depth: 4 });
+        dungeon.set_unique_state("Ember", UniqueState::Dead);
+        assert!(dungeon.unique_state("Grub") == UniqueState::Alive { depth: 4 });
+        assert!(dungeon.unique_state("Ember") == UniqueState::Dead);
+    }
+
+    #[test]
+    fn each_lair_is_placed_once_from_the_seed () {
+        let _globals = crate::test_support::lock_globals();
+        let raws = RAWS.lock().unwrap();
+        for seed in 0 .. 20 {
+            let dungeon = MasterDungeonMap::new(seed);
+            let lairs: Vec<String> = (1 ..= 10)
+                .filter_map(|depth| dungeon.unique_lair(&raws, &LevelId::main(depth)).map(|(unique, _lair)| unique))
+                .collect();
+            assert_eq!(lairs.iter().filter(|unique| *unique == "Grubnash the Cruel").count(), 1);
+            assert_eq!(lairs.iter().filter(|unique| *unique == "Sszarak the Drowned").count(), 1);
+            /* Asking again, or out of order, changes nothing */
+            let again = MasterDungeonMap::new(seed);
+            assert_eq!(again.unique_lair(&raws, &LevelId::main(5)), dungeon.unique_lair(&raws, &LevelId::main(5)));
+            assert_eq!(dungeon.unique_lair(&raws, &LevelId::new("mines", 4)), None);
+        };
+    }
+
+    /// A strip of floor in the mines (as the real levels.json lays them out) with the way
+    /// down at the east end
+    fn mine (depth: i32) -> Map {
+        let mut map = Map::new(depth, 20, 5, "Mine");
+        map.branch = "mines".to_string();
+        for x in 1 .. 19 {
+            let idx = map.xy_idx(x, 2);
+            map.tiles[idx] = TileType::Floor;
+        };
+        let stairs = map.xy_idx(18, 2);
+        map.tiles[stairs] = TileType::DownStairs;
+        map
+    }
+
+    #[test]
+    fn stairs_lead_up_and_down_their_branch () {
+        let _globals = crate::test_support::lock_globals();
+        let mut map = mine(5);
+        let start = Position { x: 1, y: 2 };
+        place_level_stairs(&mut map, &start, None);
+        let (up, down) = (map.xy_idx(1, 2), map.xy_idx(18, 2));
+        assert!(stairs_destination(&map, up) == Some(Staircase { level: LevelId::new("mines", 4), arrival: None }));
+        assert!(stairs_destination(&map, down) == Some(Staircase { level: LevelId::new("mines", 6), arrival: None }));
+        assert!(stairs_destination(&map, map.xy_idx(5, 2)).is_none());
+
+        /* The top of the branch leads back out to where it leaves the main dungeon, or back
+           where the player came from if they took other stairs */
+        let mut top = mine(4);
+        place_level_stairs(&mut top, &start, None);
+        assert!(stairs_destination(&top, up).is_some_and(|to| to.level == LevelId::main(3)));
+        let mut linked = mine(4);
+        let back = Staircase { level: LevelId::main(3), arrival: Some(42) };
+        place_level_stairs(&mut linked, &start, Some(back.clone()));
+        assert!(stairs_destination(&linked, up) == Some(back));
+    }
+
+    #[test]
+    fn the_bottom_of_a_branch_has_no_way_on () {
+        let _globals = crate::test_support::lock_globals();
+        let mut map = mine(6);
+        /* ...bar stairs into a deeper branch, which are linked up front */
+        let side = map.xy_idx(10, 2);
+        map.tiles[side] = TileType::DownStairs;
+        map.stairs.insert(side, Staircase { level: LevelId::new("deeper", 7), arrival: None });
+        place_level_stairs(&mut map, &Position { x: 1, y: 2 }, None);
+        assert!(map.tiles[map.xy_idx(18, 2)] == TileType::Floor);
+        assert!(map.tiles[side] == TileType::DownStairs);
+    }
+}
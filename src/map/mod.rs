@@ -1,13 +1,27 @@
 use rltk::{ BaseMap, Algorithm2D, Point };
 use serde::{Serialize, Deserialize};
-use std::collections::HashSet;
+use std::collections::{HashSet, HashMap};
+use specs::prelude::{Entity, ReadStorage};
+use crate::{Rect, Locomotion, TileSize, Position};
 
 mod tiletype;
-pub use tiletype::{TileType, tile_walkable, tile_opaque, tile_cost};
+pub use tiletype::{TileType, tile_walkable, tile_passable, tile_opaque, tile_cost};
 mod themes;
 pub use themes::*;
 pub mod dungeon;
 pub use dungeon::*;
+mod catch_up;
+
+/* Levels whose chain doesn't say how big it is */
+pub const DEFAULT_MAP_WIDTH: i32 = 80;
+pub const DEFAULT_MAP_HEIGHT: i32 = 50;
+/* Anything smaller hasn't room for the builders to work in */
+pub const MIN_MAP_SIZE: i32 = 20;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize, Debug)]
+pub enum BuildingTag {
+    Pub, Temple, Blacksmith, Clothier, Alchemist, PlayerHouse, Hovel, Abandoned, Unassigned
+}
 
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct Map {
@@ -22,6 +36,18 @@ pub struct Map {
     pub name: String,
     pub outdoors: bool,
     pub light: Vec<rltk::RGB>,
+    pub buildings: Vec<(BuildingTag, Rect)>,
+    #[serde(default)]
+    pub theme: MapTheme,
+    #[serde(default = "main_branch")]
+    pub branch: String,
+    /* Stairs that go somewhere other than straight down (or up) the branch */
+    #[serde(default)]
+    pub stairs: HashMap<usize, Staircase>,
+}
+
+fn main_branch () -> String {
+    MAIN_BRANCH.to_string()
 }
 
 impl Map {
@@ -29,16 +55,81 @@ impl Map {
         (y as usize * self.width as usize) + x as usize
     }
 
-    fn is_exit_valid(&self, x:i32, y:i32) -> bool {
-        if x < 1 || x > self.width-1 || y < 1 || y > self.height-1 { return false; }
-        let idx = self.xy_idx(x, y);
-        !crate::spatial::is_blocked(idx)
+    pub fn level_id (&self) -> LevelId {
+        LevelId::new(&self.branch, self.depth)
+    }
+
+    fn is_exit_valid(&self, x:i32, y:i32, pathing: &Pathing) -> bool {
+        self.can_stand(x, y, pathing)
+    }
+
+    fn exits_for (&self, idx: usize, pathing: &Pathing) -> rltk::SmallVec<[(usize, f32); 10]> {
+        let mut exits = rltk::SmallVec::new();
+        let x = idx as i32 % self.width;
+        let y = idx as i32 / self.width;
+        let w = self.width as usize;
+        let tt = self.tiles[idx];
+
+        /* Normal Directions */
+        if self.is_exit_valid(x-1, y, pathing) { exits.push((idx-1, tile_cost(tt))) };
+        if self.is_exit_valid(x+1, y, pathing) { exits.push((idx+1, tile_cost(tt))) };
+        if self.is_exit_valid(x, y-1, pathing) { exits.push((idx-w, tile_cost(tt))) };
+        if self.is_exit_valid(x, y+1, pathing) { exits.push((idx+w, tile_cost(tt))) };
+
+        /* Diagonals */
+        if self.is_exit_valid(x-1, y-1, pathing) { exits.push(((idx-w)-1, tile_cost(tt) * 1.45)) };
+        if self.is_exit_valid(x+1, y-1, pathing) { exits.push(((idx-w)+1, tile_cost(tt) * 1.45)) };
+        if self.is_exit_valid(x-1, y+1, pathing) { exits.push(((idx+w)-1, tile_cost(tt) * 1.45)) };
+        if self.is_exit_valid(x+1, y+1, pathing) { exits.push(((idx+w)+1, tile_cost(tt) * 1.45)) };
+        exits
+    }
+
+    /// True if something getting about this way could stand with its top-left corner here
+    pub fn can_stand (&self, x: i32, y: i32, pathing: &Pathing) -> bool {
+        let (w, h) = pathing.footprint.map_or((1, 1), |(size, _)| (size.x, size.y));
+        if x < 1 || x + w - 1 > self.width-1 || y < 1 || y + h - 1 > self.height-1 { return false; }
+        for ty in y .. y + h {
+            for tx in x .. x + w {
+                let idx = self.xy_idx(tx, ty);
+                let blocked = match pathing.footprint {
+                    Some((_, mover)) => crate::spatial::is_blocked_for_except(idx, &pathing.locomotion, mover),
+                    None => crate::spatial::is_blocked_for(idx, &pathing.locomotion),
+                };
+                if blocked { return false; }
+            };
+        };
+        true
+    }
+
+    /// Every tile covered by something standing at x, y
+    pub fn footprint (&self, x: i32, y: i32, size: Option<&TileSize>) -> Vec<usize> {
+        let (w, h) = size.map_or((1, 1), |size| (size.x, size.y));
+        let mut tiles = Vec::new();
+        for ty in y .. y + h {
+            for tx in x .. x + w {
+                if tx >= 0 && tx < self.width && ty >= 0 && ty < self.height {
+                    tiles.push(self.xy_idx(tx, ty));
+                }
+            };
+        };
+        tiles
+    }
+
+    /// A view of the map for pathing something that doesn't simply walk, or is too big
+    /// to fit everywhere
+    pub fn for_pathing (&self, pathing: Pathing) -> PathingMap<'_> {
+        PathingMap { map: self, pathing }
     }
 
     pub fn populate_blocked (&mut self) {
         crate::spatial::populate_blocked_from_map(self);
     }
 
+    /// Index into `buildings` of the building containing the given tile
+    pub fn building_at (&self, x: i32, y: i32) -> Option<usize> {
+        self.buildings.iter().position(|(_, r)| x >= r.x1 && x < r.x2 && y >= r.y1 && y < r.y2)
+    }
+
     pub fn clear_content_index (&mut self) {
         crate::spatial::clear();
     }
@@ -59,6 +150,10 @@ impl Map {
             name: name.to_string(),
             outdoors: true,
             light: vec![rltk::RGB::from_f32(0.0, 0.0, 0.0); map_tile_count],
+            buildings: Vec::new(),
+            theme: MapTheme::Default,
+            branch: main_branch(),
+            stairs: HashMap::new(),
         }
     }
 }
@@ -80,24 +175,7 @@ impl BaseMap for Map {
     }
 
     fn get_available_exits(&self, idx:usize) -> rltk::SmallVec<[(usize, f32); 10]> {
-        let mut exits = rltk::SmallVec::new();
-        let x = idx as i32 % self.width;
-        let y = idx as i32 / self.width;
-        let w = self.width as usize;
-        let tt = self.tiles[idx as usize];
-
-        /* Normal Directions */
-        if self.is_exit_valid(x-1, y) { exits.push((idx-1, tile_cost(tt))) };
-        if self.is_exit_valid(x+1, y) { exits.push((idx+1, tile_cost(tt))) };
-        if self.is_exit_valid(x, y-1) { exits.push((idx-w, tile_cost(tt))) };
-        if self.is_exit_valid(x, y+1) { exits.push((idx+w, tile_cost(tt))) };
-
-        /* Diagonals */
-        if self.is_exit_valid(x-1, y-1) { exits.push(((idx-w)-1, tile_cost(tt) * 1.45)) };
-        if self.is_exit_valid(x+1, y-1) { exits.push(((idx-w)+1, tile_cost(tt) * 1.45)) };
-        if self.is_exit_valid(x-1, y+1) { exits.push(((idx+w)-1, tile_cost(tt) * 1.45)) };
-        if self.is_exit_valid(x+1, y+1) { exits.push(((idx+w)+1, tile_cost(tt) * 1.45)) };
-        exits
+        self.exits_for(idx, &Pathing::default())
     }
 
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
@@ -107,6 +185,52 @@ impl BaseMap for Map {
         rltk::DistanceAlg::Pythagoras.distance2d(p1, p2)
     }
 }
+
+/// How something gets about: what it can cross and, if it covers more than one tile, how
+/// big it is (and who it is, so it doesn't get in its own way)
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Pathing {
+    pub locomotion: Locomotion,
+    pub footprint: Option<(TileSize, Entity)>,
+}
+
+impl Pathing {
+    pub fn of (ent: Entity, locomotions: &ReadStorage<Locomotion>, sizes: &ReadStorage<TileSize>) -> Pathing {
+        Pathing {
+            locomotion: locomotions.get(ent).copied().unwrap_or_default(),
+            footprint: sizes.get(ent).map(|size| (*size, ent)),
+        }
+    }
+}
+
+/// How far apart the nearest tiles of two things are, either of which may be bigger than a tile
+pub fn footprint_distance (a: &Position, a_size: Option<&TileSize>, b: &Position, b_size: Option<&TileSize>) -> f32 {
+    let (aw, ah) = a_size.map_or((1, 1), |size| (size.x, size.y));
+    let (bw, bh) = b_size.map_or((1, 1), |size| (size.x, size.y));
+    let dx = i32::max(0, i32::max(b.x - (a.x + aw - 1), a.x - (b.x + bw - 1)));
+    let dy = i32::max(0, i32::max(b.y - (a.y + ah - 1), a.y - (b.y + bh - 1)));
+    rltk::DistanceAlg::Pythagoras.distance2d(Point::new(0, 0), Point::new(dx, dy))
+}
+
+/// The map as seen by a flyer, swimmer, phaser, heavy walker or something big, for pathfinding
+pub struct PathingMap<'a> {
+    pub map: &'a Map,
+    pub pathing: Pathing,
+}
+
+impl<'a> BaseMap for PathingMap<'a> {
+    fn is_opaque(&self, idx:usize) -> bool {
+        self.map.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx:usize) -> rltk::SmallVec<[(usize, f32); 10]> {
+        self.map.exits_for(idx, &self.pathing)
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.map.get_pathing_distance(idx1, idx2)
+    }
+}
 // Treelikes {{{
 // /// Generates a map with solid bounds and 400 randomly placed walls.
 // pub fn new_map_test () -> Vec<TileType> {
@@ -135,3 +259,120 @@ impl BaseMap for Map {
 //     map
 // }
 /* }}} */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{lock_globals, open_map};
+    use specs::prelude::*;
+
+    /// Floor on the west bank, deep water to the east
+    fn bank () -> Map {
+        let mut map = open_map(1, 12, 8);
+        for y in 1 .. 7 {
+            for x in 6 .. 11 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::DeepWater;
+            };
+        };
+        map.populate_blocked();
+        map
+    }
+
+    fn on (locomotion: Locomotion) -> Pathing {
+        Pathing { locomotion, footprint: None }
+    }
+
+    #[test]
+    fn only_swimmers_and_flyers_stand_in_deep_water () {
+        let _globals = lock_globals();
+        let map = bank();
+        let walker = on(Locomotion::default());
+        let swimmer = on(Locomotion { swimming: true, ..Default::default() });
+        let phaser = on(Locomotion { phasing: true, ..Default::default() });
+        assert!(map.can_stand(3, 3, &walker) && !map.can_stand(8, 3, &walker));
+        assert!(map.can_stand(8, 3, &swimmer) && !map.can_stand(0, 3, &swimmer));
+        /* Phasers go through walls, but not off the edge of the map */
+        assert!(map.can_stand(3, 7, &phaser) && !map.can_stand(-1, 3, &phaser));
+    }
+
+    #[test]
+    fn exits_follow_the_movers_locomotion () {
+        let _globals = lock_globals();
+        let map = bank();
+        let shore = map.xy_idx(5, 3);
+        let walker_exits = map.exits_for(shore, &on(Locomotion::default()));
+        let flyer_exits = map.exits_for(shore, &on(Locomotion { flying: true, ..Default::default() }));
+        assert_eq!(walker_exits.len(), 5);
+        assert_eq!(flyer_exits.len(), 8);
+        assert!(walker_exits.iter().all(|(idx, _)| map.tiles[*idx] == TileType::Floor));
+    }
+
+    #[test]
+    fn blockers_stop_every_locomotion () {
+        let _globals = lock_globals();
+        let map = bank();
+        let mut ecs = World::new();
+        let rock = ecs.create_entity().build();
+        crate::spatial::index_entity(rock, map.xy_idx(8, 3), true);
+        assert!(!map.can_stand(8, 3, &on(Locomotion { swimming: true, ..Default::default() })));
+        assert!(map.can_stand(8, 4, &on(Locomotion { swimming: true, ..Default::default() })));
+    }
+
+    #[test]
+    fn footprints_cover_their_size_and_stop_at_the_edge () {
+        let _globals = lock_globals();
+        let map = bank();
+        let two = TileSize { x: 2, y: 2 };
+        assert_eq!(map.footprint(3, 3, None), vec![map.xy_idx(3, 3)]);
+        assert_eq!(map.footprint(3, 3, Some(&two)),
+            vec![map.xy_idx(3, 3), map.xy_idx(4, 3), map.xy_idx(3, 4), map.xy_idx(4, 4)]);
+        assert_eq!(map.footprint(11, 7, Some(&two)), vec![map.xy_idx(11, 7)]);
+    }
+
+    #[test]
+    fn distance_is_measured_between_nearest_edges () {
+        let two = TileSize { x: 2, y: 2 };
+        let golem = Position { x: 3, y: 3 };
+        assert_eq!(footprint_distance(&golem, Some(&two), &Position { x: 5, y: 4 }, None), 1.0);
+        assert_eq!(footprint_distance(&golem, Some(&two), &Position { x: 4, y: 4 }, None), 0.0);
+        assert_eq!(footprint_distance(&golem, Some(&two), &Position { x: 1, y: 3 }, None), 2.0);
+        assert_eq!(footprint_distance(&Position { x: 8, y: 3 }, None, &golem, Some(&two)), 4.0);
+        assert!((footprint_distance(&golem, Some(&two), &Position { x: 5, y: 5 }, None) - 2f32.sqrt()).abs() < 0.001);
+    }
+
+    #[test]
+    fn big_creatures_need_room_for_every_tile () {
+        let _globals = lock_globals();
+        let map = bank();
+        let mut ecs = World::new();
+        let golem = ecs.create_entity().build();
+        let rock = ecs.create_entity().build();
+        let two = Pathing { locomotion: Locomotion::default(), footprint: Some((TileSize { x: 2, y: 2 }, golem)) };
+        for idx in map.footprint(2, 2, Some(&TileSize { x: 2, y: 2 })).iter() {
+            crate::spatial::index_entity(golem, *idx, true);
+        };
+        /* Half in the water, or standing where it already is, and shuffling over itself */
+        assert!(!map.can_stand(5, 2, &two));
+        assert!(map.can_stand(2, 2, &two) && map.can_stand(3, 3, &two));
+        crate::spatial::index_entity(rock, map.xy_idx(4, 4), true);
+        assert!(!map.can_stand(3, 3, &two) && map.can_stand(1, 1, &two));
+    }
+
+    #[test]
+    fn moving_a_footprint_moves_what_it_blocks () {
+        let _globals = lock_globals();
+        let map = bank();
+        let mut ecs = World::new();
+        let golem = ecs.create_entity().build();
+        let size = TileSize { x: 2, y: 2 };
+        let from = map.footprint(1, 1, Some(&size));
+        let to = map.footprint(2, 1, Some(&size));
+        for idx in from.iter() {
+            crate::spatial::index_entity(golem, *idx, true);
+        };
+        crate::spatial::move_footprint(golem, &from, &to);
+        assert!(!crate::spatial::is_blocked(map.xy_idx(1, 1)) && !crate::spatial::is_blocked(map.xy_idx(1, 2)));
+        assert!(to.iter().all(|idx| crate::spatial::is_blocked(*idx)));
+    }
+}
@@ -1,5 +1,11 @@
 use super::{Map, TileType};
 use rltk::RGB;
+use serde::{Serialize, Deserialize};
+
+/// How a level is drawn, picked per depth range in levels.json
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MapTheme { #[default] Default, Forest, Limestone, LimestoneTransition }
 
 fn wall_glyph (map: &Map, x:i32, y:i32) -> rltk::FontCharType {
     if x < 1 || x > map.width-2 || y < 1 || y > map.height-2 as i32 { return 35; }
@@ -37,16 +43,15 @@ fn is_revealed_and_wall (map: &Map, x:i32, y:i32) -> bool {
 }
 
 pub fn tile_glyph (idx: usize, map: &Map) -> (rltk::FontCharType, RGB, RGB) {
-    let (glyph, mut fg, mut bg) = match map.depth {
-        2 => get_forest_glyph(idx, map),
-        3 => get_limestone_cavern_glyph(idx, map),
-        4 => get_limestone_cavern_glyph(idx, map),
-        5 => {
+    let (glyph, mut fg, mut bg) = match map.theme {
+        MapTheme::Forest => get_forest_glyph(idx, map),
+        MapTheme::Limestone => get_limestone_cavern_glyph(idx, map),
+        MapTheme::LimestoneTransition => {
             let x = idx as i32 % map.width;
             if x < map.width / 2 { get_limestone_cavern_glyph(idx, map) }
             else { get_tile_glyph_default(idx, map) }
         },
-        _ => get_tile_glyph_default(idx, map),
+        MapTheme::Default => get_tile_glyph_default(idx, map),
     };
 
     if map.bloodstains.contains(&idx) { bg = RGB::from_f32(0.75, 0., 0.); }
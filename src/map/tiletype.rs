@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use crate::Locomotion;
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub enum TileType {
@@ -26,6 +27,16 @@ pub fn tile_walkable (tt: TileType) -> bool {
     }
 }
 
+/// Whether something that gets about the given way can enter the tile
+pub fn tile_passable (tt: TileType, locomotion: &Locomotion) -> bool {
+    match tt {
+        TileType::Wall | TileType::Stalactite | TileType::Stalagmite => locomotion.phasing,
+        TileType::DeepWater => locomotion.flying || locomotion.swimming || locomotion.phasing,
+        TileType::Bridge => !locomotion.heavy || locomotion.flying,
+        _ => tile_walkable(tt),
+    }
+}
+
 pub fn tile_opaque (tt: TileType) -> bool {
     match tt {
         TileType::Wall | TileType::Stalactite | TileType::Stalagmite => true,
@@ -41,3 +52,26 @@ pub fn tile_cost (tt: TileType) -> f32 {
         _ => 1.0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_way_of_getting_about_has_its_own_terrain () {
+        let walker = Locomotion::default();
+        let flyer = Locomotion { flying: true, ..Default::default() };
+        let swimmer = Locomotion { swimming: true, ..Default::default() };
+        let phaser = Locomotion { phasing: true, ..Default::default() };
+        let golem = Locomotion { heavy: true, ..Default::default() };
+        let heavy_flyer = Locomotion { heavy: true, flying: true, ..Default::default() };
+
+        assert!(tile_passable(TileType::Floor, &walker) && !tile_passable(TileType::DeepWater, &walker));
+        assert!(tile_passable(TileType::DeepWater, &flyer) && !tile_passable(TileType::Wall, &flyer));
+        assert!(tile_passable(TileType::DeepWater, &swimmer) && !tile_passable(TileType::Stalagmite, &swimmer));
+        assert!(tile_passable(TileType::Wall, &phaser) && tile_passable(TileType::Stalactite, &phaser));
+        assert!(tile_passable(TileType::DeepWater, &phaser));
+        assert!(!tile_passable(TileType::Bridge, &golem) && tile_passable(TileType::Road, &golem));
+        assert!(tile_passable(TileType::Bridge, &heavy_flyer) && tile_passable(TileType::Bridge, &walker));
+    }
+}
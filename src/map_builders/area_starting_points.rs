@@ -1,4 +1,5 @@
-use super::{MetaMapBuilder, BuilderMap, Position, TileType};
+use super::{MetaMapBuilder, BuilderMap, Position, TileType, Map};
+use crate::map::tile_walkable;
 
 #[allow(dead_code)]
 pub enum XStart { LEFT, CENTER, RIGHT }
@@ -39,7 +40,8 @@ impl AreaStartingPosition {
 
         let mut available_floors: Vec<(usize, f32)> = Vec::new();
         for (idx, tiletype) in build_data.map.tiles.iter().enumerate() {
-            if *tiletype == TileType::Floor {
+            /* A speck of floor with no way off it is no place to start */
+            if *tiletype == TileType::Floor && AreaStartingPosition::has_exit(&build_data.map, idx) {
                 available_floors.push((
                     idx,
                     rltk::DistanceAlg::Pythagoras.distance2d(
@@ -58,4 +60,14 @@ impl AreaStartingPosition {
         let start_y = available_floors[0].0 as i32 / build_data.map.width;
         build_data.starting_position = Some(Position { x: start_x, y:start_y });
     }
+
+    fn has_exit (map: &Map, idx: usize) -> bool {
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        (-1 ..= 1).any(|dy| (-1 ..= 1).any(|dx| {
+            let (nx, ny) = (x + dx, y + dy);
+            (dx != 0 || dy != 0) && nx > 0 && nx < map.width - 1 && ny > 0 && ny < map.height - 1
+                && tile_walkable(map.tiles[map.xy_idx(nx, ny)])
+        }))
+    }
 }
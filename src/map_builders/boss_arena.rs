@@ -0,0 +1,207 @@
+use super::{MetaMapBuilder, BuilderMap, TileType};
+use rltk::Point;
+
+const ARENA_RADIUS: i32 = 6;
+/* Thicker than a diagonal step, so nothing squeezes between the wall's corners */
+const WALL_THICKNESS: f32 = 1.5;
+
+/// Rings the down stairs in a walled arena, with the level's boss standing guard and a
+/// single way in from the player's side
+pub struct BossArena {
+    boss: String,
+}
+
+impl MetaMapBuilder for BossArena {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl BossArena {
+    #[allow(dead_code)]
+    pub fn new (boss: &str) -> Box<BossArena> {
+        Box::new(BossArena { boss: boss.to_string() })
+    }
+
+    fn build (&mut self, _rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let width = build_data.map.width;
+        let height = build_data.map.height;
+        let stairs = match build_data.map.tiles.iter().position(|tt| *tt == TileType::DownStairs) {
+            Some(idx) => idx,
+            None => {
+                rltk::console::log(format!("Warning: no stairs to build {}'s arena around", self.boss));
+                return;
+            }
+        };
+        let start = match &build_data.starting_position {
+            Some(start) => start.clone(),
+            None => {
+                rltk::console::log(format!("Warning: no start to open {}'s arena towards", self.boss));
+                return;
+            }
+        };
+        let start_idx = build_data.map.xy_idx(start.x, start.y);
+
+        /* Keep the whole arena on the map */
+        let center = Point::new(
+            (stairs as i32 % width).clamp(ARENA_RADIUS + 1, width - ARENA_RADIUS - 2),
+            (stairs as i32 / width).clamp(ARENA_RADIUS + 1, height - ARENA_RADIUS - 2),
+        );
+        let distance = |x: i32, y: i32| rltk::DistanceAlg::Pythagoras.distance2d(center, Point::new(x, y));
+        /* A start inside the arena would be walled in with the boss; leave it open */
+        let walled = distance(start.x, start.y) > ARENA_RADIUS as f32 + 2.0;
+
+        build_data.map.tiles[stairs] = TileType::Floor;
+        let outside = ARENA_RADIUS as f32 - 0.5 + WALL_THICKNESS;
+        build_data.spawn_list.retain(|(idx, _name)| distance(*idx as i32 % width, *idx as i32 / width) >= outside);
+        for y in center.y - ARENA_RADIUS - 1 ..= center.y + ARENA_RADIUS + 1 {
+            for x in center.x - ARENA_RADIUS - 1 ..= center.x + ARENA_RADIUS + 1 {
+                let idx = build_data.map.xy_idx(x, y);
+                let d = distance(x, y);
+                if d < ARENA_RADIUS as f32 - 0.5 {
+                    build_data.map.tiles[idx] = TileType::Floor;
+                } else if d < outside && walled {
+                    build_data.map.tiles[idx] = TileType::Wall;
+                }
+            };
+        };
+        /* Pillars to duck behind */
+        let pillar = ARENA_RADIUS / 2;
+        for (dx, dy) in [(-pillar, -pillar), (pillar, -pillar), (-pillar, pillar), (pillar, pillar)].iter() {
+            let idx = build_data.map.xy_idx(center.x + dx, center.y + dy);
+            build_data.map.tiles[idx] = TileType::Wall;
+        };
+        let stairs_idx = build_data.map.xy_idx(center.x, center.y);
+        build_data.map.tiles[stairs_idx] = TileType::DownStairs;
+        build_data.take_snapshot();
+
+        /* Everywhere the player can still reach with the arena walled off, to tunnel the way in towards */
+        build_data.map.populate_blocked();
+        let dijkstra_map = rltk::DijkstraMap::new(width as usize, height as usize, &[start_idx], &build_data.map, 1000.0);
+
+        /* Dig the way in from the arena towards the start, until it meets ground the player
+           could already reach */
+        let to_start = rltk::line2d(rltk::LineAlg::Bresenham, center, Point::new(start.x, start.y));
+        let mut boss_spot = None;
+        for step in to_start.iter().skip(1) {
+            let idx = build_data.map.xy_idx(step.x, step.y);
+            if boss_spot.is_none() { boss_spot = Some(idx); }
+            if distance(step.x, step.y) >= outside && dijkstra_map.map[idx] < f32::MAX {
+                break;
+            }
+            if build_data.map.tiles[idx] != TileType::DownStairs {
+                build_data.map.tiles[idx] = TileType::Floor;
+            }
+        };
+
+        /* The boss waits between the player and the way down */
+        let boss_idx = boss_spot.unwrap_or(stairs_idx);
+        build_data.spawn_list.push((boss_idx, self.boss.clone()));
+        build_data.take_snapshot();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::{BuilderChain, reachable_from};
+    use crate::components::Position;
+    use crate::test_support::lock_globals;
+
+    /// An open hall with the stairs by the east wall, an imp beside them and a rat by the start
+    fn hall () -> BuilderChain {
+        let mut chain = BuilderChain::new(5, 40, 30, "Hall");
+        let map = &mut chain.build_data.map;
+        for y in 1 .. 29 {
+            for x in 1 .. 39 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            };
+        };
+        let stairs = map.xy_idx(36, 15);
+        map.tiles[stairs] = TileType::DownStairs;
+        let (imp, rat) = (map.xy_idx(35, 16), map.xy_idx(5, 10));
+        chain.build_data.spawn_list = vec![(imp, "Imp".to_string()), (rat, "Rat".to_string())];
+        chain.build_data.starting_position = Some(Position { x: 3, y: 15 });
+        chain
+    }
+
+    fn arena (chain: &mut BuilderChain) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        BossArena::new("Ember").build_map(&mut rng, &mut chain.build_data);
+    }
+
+    #[test]
+    fn the_boss_guards_stairs_moved_inside_the_arena () {
+        let _globals = lock_globals();
+        let mut chain = hall();
+        arena(&mut chain);
+        let map = &chain.build_data.map;
+        /* Pulled in from the wall so the whole ring fits */
+        let stairs = map.xy_idx(40 - ARENA_RADIUS - 2, 15);
+        assert!(map.tiles[stairs] == TileType::DownStairs);
+        assert_eq!(map.tiles.iter().filter(|tt| **tt == TileType::DownStairs).count(), 1);
+        let names: Vec<&str> = chain.build_data.spawn_list.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(names, vec!["Rat", "Ember"]);
+        let start = map.xy_idx(3, 15);
+        let reachable = reachable_from(map, start);
+        assert!(reachable[stairs] && reachable[chain.build_data.spawn_list[1].0]);
+    }
+
+    #[test]
+    fn the_tunnel_is_the_only_way_in () {
+        let _globals = lock_globals();
+        let mut chain = hall();
+        arena(&mut chain);
+        let center = Point::new(40 - ARENA_RADIUS - 2, 15);
+        let map = &mut chain.build_data.map;
+        /* Fill in the tunnel where it passes through the ring */
+        for step in rltk::line2d(rltk::LineAlg::Bresenham, center, Point::new(3, 15)).iter() {
+            let d = rltk::DistanceAlg::Pythagoras.distance2d(center, *step);
+            if d > ARENA_RADIUS as f32 - 0.5 && d < ARENA_RADIUS as f32 - 0.5 + WALL_THICKNESS {
+                let idx = map.xy_idx(step.x, step.y);
+                map.tiles[idx] = TileType::Wall;
+            }
+        };
+        let stairs = map.xy_idx(center.x, center.y);
+        assert!(!reachable_from(map, map.xy_idx(3, 15))[stairs]);
+    }
+
+    #[test]
+    fn a_start_inside_the_ring_is_not_walled_in () {
+        let _globals = lock_globals();
+        let mut chain = hall();
+        chain.build_data.starting_position = Some(Position { x: 30, y: 14 });
+        arena(&mut chain);
+        let map = &chain.build_data.map;
+        let walls = map.tiles.iter().enumerate()
+            .filter(|(idx, tt)| **tt == TileType::Wall && (*idx as i32 % 40) > 0 && (*idx as i32 % 40) < 39
+                && (*idx as i32 / 40) > 0 && (*idx as i32 / 40) < 29)
+            .count();
+        /* Just the pillars */
+        assert_eq!(walls, 4);
+    }
+
+    #[test]
+    fn no_stairs_means_no_arena () {
+        let _globals = lock_globals();
+        let mut chain = hall();
+        let stairs = chain.build_data.map.xy_idx(36, 15);
+        chain.build_data.map.tiles[stairs] = TileType::Floor;
+        let before = chain.build_data.map.tiles.clone();
+        arena(&mut chain);
+        assert!(chain.build_data.map.tiles == before);
+        assert_eq!(chain.build_data.spawn_list.len(), 2);
+    }
+
+    #[test]
+    fn no_start_leaves_the_map_for_the_validator () {
+        let _globals = lock_globals();
+        let mut chain = hall();
+        chain.build_data.starting_position = None;
+        let before = chain.build_data.map.tiles.clone();
+        arena(&mut chain);
+        assert!(chain.build_data.map.tiles == before);
+        assert!(crate::map_builders::validate_map(&chain.build_data).contains(&crate::map_builders::MapProblem::NoStart));
+    }
+}
@@ -0,0 +1,115 @@
+use super::{MetaMapBuilder, BuilderMap, TileType};
+use crate::map::{LevelId, Staircase};
+
+/// Adds a second way down, into a side branch: as far as it can be from both the start and
+/// the level's own down stairs, so the two don't sit side by side
+pub struct BranchStairs {
+    to: LevelId,
+}
+
+impl MetaMapBuilder for BranchStairs {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl BranchStairs {
+    #[allow(dead_code)]
+    pub fn new (to: LevelId) -> Box<BranchStairs> {
+        Box::new(BranchStairs { to })
+    }
+
+    fn build (&mut self, _rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let start = match &build_data.starting_position {
+            Some(start) => build_data.map.xy_idx(start.x, start.y),
+            None => {
+                rltk::console::log(format!("Warning: no start to find the way down to {} from", self.to));
+                return;
+            }
+        };
+        let (width, height) = (build_data.map.width as usize, build_data.map.height as usize);
+        build_data.map.populate_blocked();
+        let from_start = rltk::DijkstraMap::new(width, height, &[start], &build_data.map, 1000.0);
+        let stairs: Vec<usize> = build_data.map.tiles.iter().enumerate()
+            .filter(|(_idx, tt)| **tt == TileType::DownStairs)
+            .map(|(idx, _tt)| idx)
+            .collect();
+        let from_stairs = rltk::DijkstraMap::new(width, height, &stairs, &build_data.map, 1000.0);
+
+        /* Floor the player can reach, furthest from whichever of the two is nearer */
+        let spot = build_data.map.tiles.iter().enumerate()
+            .filter(|(idx, tt)| **tt == TileType::Floor && *idx != start && from_start.map[*idx] < f32::MAX)
+            .map(|(idx, _tt)| (idx, f32::min(from_start.map[idx], from_stairs.map[idx])))
+            .fold(None, |best: Option<(usize, f32)>, (idx, distance)| match best {
+                Some((_, furthest)) if furthest >= distance => best,
+                _ => Some((idx, distance)),
+            });
+        match spot {
+            Some((idx, _distance)) => {
+                build_data.map.tiles[idx] = TileType::DownStairs;
+                build_data.map.stairs.insert(idx, Staircase { level: self.to.clone(), arrival: None });
+                build_data.spawn_list.retain(|(spawn_idx, _name)| *spawn_idx != idx);
+            }
+            None => rltk::console::log(format!("Warning: nowhere reachable to put the way down to {}", self.to)),
+        }
+        build_data.take_snapshot();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::{BuilderChain, reachable_from};
+    use crate::components::Position;
+    use crate::test_support::lock_globals;
+
+    /// A corridor with the start at the west end, the down stairs at the east end, and a dead
+    /// end running south from the middle
+    fn corridor () -> BuilderChain {
+        let mut chain = BuilderChain::new(3, 30, 20, "Corridor");
+        let map = &mut chain.build_data.map;
+        for x in 1 .. 29 {
+            let idx = map.xy_idx(x, 5);
+            map.tiles[idx] = TileType::Floor;
+        };
+        for y in 6 .. 19 {
+            let idx = map.xy_idx(14, y);
+            map.tiles[idx] = TileType::Floor;
+        };
+        /* A pocket nobody can get to */
+        let sealed = map.xy_idx(25, 15);
+        map.tiles[sealed] = TileType::Floor;
+        let stairs = map.xy_idx(28, 5);
+        map.tiles[stairs] = TileType::DownStairs;
+        chain.build_data.starting_position = Some(Position { x: 1, y: 5 });
+        let dead_end = map.xy_idx(14, 18);
+        chain.build_data.spawn_list.push((dead_end, "Rat".to_string()));
+        chain
+    }
+
+    #[test]
+    fn branch_stairs_go_far_from_the_start_and_the_way_down () {
+        let _globals = lock_globals();
+        let mut chain = corridor();
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        BranchStairs::new(LevelId::new("mines", 4)).build_map(&mut rng, &mut chain.build_data);
+        let map = &chain.build_data.map;
+        let (dead_end, stairs) = (map.xy_idx(14, 18), map.xy_idx(28, 5));
+        assert!(map.tiles[dead_end] == TileType::DownStairs && map.tiles[stairs] == TileType::DownStairs);
+        assert!(map.stairs.get(&dead_end) == Some(&Staircase { level: LevelId::new("mines", 4), arrival: None }));
+        assert!(!map.stairs.contains_key(&stairs));
+        assert!(chain.build_data.spawn_list.is_empty());
+        assert!(reachable_from(map, map.xy_idx(1, 5))[dead_end]);
+    }
+
+    #[test]
+    fn no_start_means_no_branch_stairs () {
+        let _globals = lock_globals();
+        let mut chain = corridor();
+        chain.build_data.starting_position = None;
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        BranchStairs::new(LevelId::new("mines", 4)).build_map(&mut rng, &mut chain.build_data);
+        assert!(chain.build_data.map.stairs.is_empty());
+        assert_eq!(chain.build_data.map.tiles.iter().filter(|tt| **tt == TileType::DownStairs).count(), 1);
+    }
+}
@@ -1,4 +1,5 @@
 use super::{MetaMapBuilder, BuilderMap, TileType};
+use crate::map::tile_walkable;
 
 pub struct CullUnreachable {}
 
@@ -30,5 +31,8 @@ impl CullUnreachable {
                 }
             }
         };
+        /* Anything spawned in what's now solid rock goes with it */
+        let tiles = &build_data.map.tiles;
+        build_data.spawn_list.retain(|(idx, _name)| tile_walkable(tiles[*idx]));
     }
 }
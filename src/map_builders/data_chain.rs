@@ -0,0 +1,395 @@
+use super::{BuilderChain, InitialMapBuilder, MetaMapBuilder, Symmetry,
+    SimpleMapBuilder, BspDungeonBuilder, BspInteriorBuilder, CellularAutomataBuilder, DrunkardsWalkBuilder,
+    DrunkardSettings, DrunkSpawnMode, MazeBuilder, DLABuilder, VoronoiBuilder, PrefabBuilder, TownBuilder,
+    WaveformCollapseBuilder, WaveformSettings, WaveformModel, WaveformSample, WaveformPin,
+    RoomSorter, RoomSort, RoomDrawer, RoomExploder, RoomCornerRounder,
+    DoglegCorridors, BspCorridors, NearestCorridors, StraightLineCorridors, CorridorSpawner,
+    RoomBasedStartingPosition, AreaStartingPosition, XStart, YStart, AreaEndingPosition, XEnd, YEnd,
+    RoomBasedStairs, DistantExit, RoomBasedSpawner, VoronoiSpawning, CullUnreachable, DoorPlacement,
+    YellowBrickRoad, CaveDecorator, CaveTransition, RiverBuilder, OutdoorBuilder, BiomeSpawning, BiomeDecorator,
+    MapValidator};
+use crate::map::{LevelId, DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT, MIN_MAP_SIZE};
+use crate::raws::{RawMaster, BuilderChainDef, ChainStep, BuilderStep, PrefabKind, get_levels_for_depth, get_builder_chain,
+    get_prefab, get_prefabs_of_kind};
+
+/* Chains can include each other; this deep and it's assumed they're going round in circles */
+const MAX_NESTING: i32 = 8;
+
+fn warn (chain: &str, message: String) {
+    rltk::console::log(format!("Warning: builder chain {}: {}", chain, message));
+}
+
+/// Picks an index, with each weight giving its odds
+fn pick_weighted (rng: &mut rltk::RandomNumberGenerator, weights: &[i32]) -> usize {
+    let total: i32 = weights.iter().map(|w| i32::max(0, *w)).sum();
+    if total < 1 { return 0; }
+    let mut roll = rng.roll_dice(1, total) - 1;
+    for (i, weight) in weights.iter().enumerate() {
+        let weight = i32::max(0, *weight);
+        if roll < weight { return i; }
+        roll -= weight;
+    };
+    weights.len() - 1
+}
+
+/// Sets up whichever chain levels.json has for this level, themed to match
+pub fn data_level_builder (raws: &RawMaster, level: &LevelId, rng: &mut rltk::RandomNumberGenerator, size: Option<(i32, i32)>) -> Option<BuilderChain> {
+    let levels = get_levels_for_depth(raws, level);
+    let entry = match levels.len() {
+        0 => return None,
+        1 => levels[0],
+        _ => {
+            let weights: Vec<i32> = levels.iter().map(|entry| entry.weight.unwrap_or(1)).collect();
+            levels[pick_weighted(rng, &weights)]
+        }
+    };
+    let mut chain = data_builder(raws, &entry.chain, level, rng, size)?;
+    chain.build_data.map.theme = entry.theme.unwrap_or_default();
+    Some(chain)
+}
+
+/// Sets up the named chain from levels.json, rolling for any alternatives it has. The map is
+/// the size the chain asks for, unless a size is given.
+pub fn data_builder (raws: &RawMaster, name: &str, level: &LevelId, rng: &mut rltk::RandomNumberGenerator, size: Option<(i32, i32)>) -> Option<BuilderChain> {
+    let def = get_builder_chain(raws, name)?;
+    let (width, height) = size.unwrap_or((
+        i32::max(MIN_MAP_SIZE, def.width.unwrap_or(DEFAULT_MAP_WIDTH)),
+        i32::max(MIN_MAP_SIZE, def.height.unwrap_or(DEFAULT_MAP_HEIGHT)),
+    ));
+    let mut chain = BuilderChain::new(level.depth, width, height, def.map_name.as_deref().unwrap_or("New Map"));
+    chain.build_data.map.branch = level.branch.clone();
+    add_chain(raws, def, rng, &mut chain, 0);
+    if chain.starter.is_none() {
+        warn(name, "never picks a starting builder".to_string());
+        return None;
+    }
+    Some(chain)
+}
+
+fn add_chain (raws: &RawMaster, def: &BuilderChainDef, rng: &mut rltk::RandomNumberGenerator, chain: &mut BuilderChain, nesting: i32) {
+    if nesting > MAX_NESTING {
+        warn(&def.name, "includes chains too deeply (is it including itself?)".to_string());
+        return;
+    }
+    if let Some(starter) = &def.starter {
+        add_step(raws, &def.name, starter, true, rng, chain, nesting);
+    }
+    for step in def.builders.iter() {
+        add_step(raws, &def.name, step, false, rng, chain, nesting);
+    };
+}
+
+fn add_step (raws: &RawMaster, name: &str, step: &ChainStep, starting: bool, rng: &mut rltk::RandomNumberGenerator,
+    chain: &mut BuilderChain, nesting: i32)
+{
+    match step {
+        ChainStep::Builder(builder) if starting => {
+            match (initial_builder(raws, builder), &chain.starter) {
+                (Some(_), Some(_)) => warn(name, format!("already has a starting builder, so {} is skipped", builder.builder)),
+                (Some(initial), None) => chain.start_with(initial),
+                (None, _) => warn(name, format!("{} can't start a chain", builder.builder)),
+            }
+        }
+        ChainStep::Builder(builder) => {
+            match meta_builder(raws, builder, rng) {
+                Some(meta) => chain.with(meta),
+                None => warn(name, format!("{} isn't a builder that can follow another", builder.builder)),
+            }
+        }
+        ChainStep::OneOf { one_of } => {
+            if one_of.is_empty() { return; }
+            /* An alternative that can't be built (a prefab that didn't load, say) gives way to the others */
+            let weights: Vec<i32> = one_of.iter().map(|alternative| {
+                if alternative.steps.iter().all(|step| can_build(raws, step)) { alternative.weight.unwrap_or(1) } else { 0 }
+            }).collect();
+            if weights.iter().all(|weight| *weight < 1) {
+                warn(name, "has no alternative that can be built".to_string());
+            }
+            let picked = &one_of[pick_weighted(rng, &weights)];
+            for step in picked.steps.iter() {
+                add_step(raws, name, step, starting, rng, chain, nesting);
+            };
+        }
+        ChainStep::OneIn { one_in, steps } => {
+            if rng.roll_dice(1, i32::max(1, *one_in)) == 1 {
+                for step in steps.iter() {
+                    add_step(raws, name, step, starting, rng, chain, nesting);
+                };
+            }
+        }
+        ChainStep::Include { chain: included } => {
+            match get_builder_chain(raws, included) {
+                Some(def) => add_chain(raws, def, rng, chain, nesting + 1),
+                None => warn(name, format!("includes unknown chain {}", included)),
+            }
+        }
+    }
+}
+
+/// False for a step that would come out as nothing because a prefab it names is missing or
+/// of the wrong kind
+fn can_build (raws: &RawMaster, step: &ChainStep) -> bool {
+    match step {
+        ChainStep::Builder(builder) if builder.builder == "prefab" => prefab(raws, builder).is_some(),
+        ChainStep::OneOf { one_of } => one_of.iter().any(|alternative| alternative.steps.iter().all(|step| can_build(raws, step))),
+        _ => true,
+    }
+}
+
+fn symmetry (step: &BuilderStep) -> Option<Symmetry> {
+    match step.symmetry.as_deref() {
+        None => None,
+        Some("horizontal") => Some(Symmetry::Horizontal),
+        Some("vertical") => Some(Symmetry::Vertical),
+        Some("both") => Some(Symmetry::Both),
+        _ => Some(Symmetry::None),
+    }
+}
+
+/// A drunkard's walk from one of the presets, with anything the step sets laid over it
+fn drunkard (step: &BuilderStep) -> Box<DrunkardsWalkBuilder> {
+    let mut builder = match step.mode.as_deref() {
+        Some("open_halls") => DrunkardsWalkBuilder::open_halls(),
+        Some("winding_passages") => DrunkardsWalkBuilder::winding_passages(),
+        Some("fat_passages") => DrunkardsWalkBuilder::fat_passages(),
+        Some("fearful_symmetry") => DrunkardsWalkBuilder::fearful_symmetry(),
+        _ => DrunkardsWalkBuilder::open_area(),
+    };
+    let settings: &mut DrunkardSettings = &mut builder.settings;
+    match step.spawn_mode.as_deref() {
+        Some("starting_point") => settings.spawn_mode = DrunkSpawnMode::StartingPoint,
+        Some("random") => settings.spawn_mode = DrunkSpawnMode::Random,
+        _ => {},
+    }
+    if let Some(lifetime) = step.lifetime { settings.drunken_lifetime = lifetime; }
+    if let Some(floor_percent) = step.floor_percent { settings.floor_percent = floor_percent; }
+    if let Some(brush_size) = step.brush_size { settings.brush_size = brush_size; }
+    if let Some(symmetry) = symmetry(step) { settings.symmetry = symmetry; }
+    builder
+}
+
+fn dla (step: &BuilderStep) -> Box<DLABuilder> {
+    match step.mode.as_deref() {
+        Some("heavy_erosion") => DLABuilder::heavy_erosion(),
+        Some("walk_inwards") => DLABuilder::walk_inwards(),
+        Some("walk_outwards") => DLABuilder::walk_outwards(),
+        Some("central_attractor") => DLABuilder::central_attractor(),
+        Some("insectoid") => DLABuilder::insectoid(),
+        _ => DLABuilder::new(),
+    }
+}
+
+fn prefab (raws: &RawMaster, step: &BuilderStep) -> Option<Box<PrefabBuilder>> {
+    if step.mode.as_deref() == Some("vaults") {
+        return Some(PrefabBuilder::vaults(get_prefabs_of_kind(raws, PrefabKind::Vault)));
+    }
+    let prefab = get_prefab(raws, step.prefab.as_deref()?)?;
+    match (step.mode.as_deref(), prefab.kind) {
+        (Some("sectional"), PrefabKind::Section) => Some(PrefabBuilder::sectional(prefab.clone())),
+        (Some("constant"), PrefabKind::Level) => Some(PrefabBuilder::constant(prefab.clone())),
+        _ => None,
+    }
+}
+
+fn waveform_collapse (raws: &RawMaster, step: &BuilderStep) -> Box<WaveformCollapseBuilder> {
+    let model = match step.mode.as_deref() {
+        Some("overlapping") => WaveformModel::Overlapping { pattern_size: i32::max(2, step.pattern_size.unwrap_or(3)) },
+        _ => WaveformModel::Tiled { chunk_size: i32::max(2, step.pattern_size.unwrap_or(8)) },
+    };
+    let sample = match step.prefab.as_deref().and_then(|name| get_prefab(raws, name)) {
+        Some(prefab) => WaveformSample::Prefab(Box::new(prefab.clone())),
+        None => WaveformSample::PreviousMap,
+    };
+    let pins = step.pin.iter().filter_map(|pin| match pin.as_str() {
+        "stairs" => Some(WaveformPin::Stairs),
+        "start" => Some(WaveformPin::Start),
+        _ => None,
+    }).collect();
+    WaveformCollapseBuilder::with_settings(WaveformSettings { model, sample, pins })
+}
+
+fn initial_builder (raws: &RawMaster, step: &BuilderStep) -> Option<Box<dyn InitialMapBuilder>> {
+    match step.builder.as_str() {
+        "simple_map" => Some(SimpleMapBuilder::new()),
+        "bsp_dungeon" => Some(BspDungeonBuilder::new()),
+        "bsp_interior" => Some(BspInteriorBuilder::new()),
+        "cellular_automata" => Some(CellularAutomataBuilder::new()),
+        "drunkard" => Some(drunkard(step)),
+        "maze" => Some(MazeBuilder::new()),
+        "dla" => Some(dla(step)),
+        "voronoi" => match step.mode.as_deref() {
+            Some("manhattan") => Some(VoronoiBuilder::manhattan()),
+            Some("chebyshev") => Some(VoronoiBuilder::chebyshev()),
+            _ => Some(VoronoiBuilder::pythagoras()),
+        },
+        "prefab" => prefab(raws, step).map(|builder| builder as Box<dyn InitialMapBuilder>),
+        "town" => Some(TownBuilder::new()),
+        "outdoor" => Some(OutdoorBuilder::new()),
+        _ => None,
+    }
+}
+
+fn x_start (step: &BuilderStep, rng: &mut rltk::RandomNumberGenerator) -> XStart {
+    match step.x.as_deref() {
+        Some("left") => XStart::LEFT,
+        Some("right") => XStart::RIGHT,
+        Some("random") => match rng.roll_dice(1, 3) {
+            1 => XStart::LEFT,
+            2 => XStart::CENTER,
+            _ => XStart::RIGHT,
+        },
+        _ => XStart::CENTER,
+    }
+}
+
+fn y_start (step: &BuilderStep, rng: &mut rltk::RandomNumberGenerator) -> YStart {
+    match step.y.as_deref() {
+        Some("top") => YStart::TOP,
+        Some("bottom") => YStart::BOTTOM,
+        Some("random") => match rng.roll_dice(1, 3) {
+            1 => YStart::BOTTOM,
+            2 => YStart::CENTER,
+            _ => YStart::TOP,
+        },
+        _ => YStart::CENTER,
+    }
+}
+
+fn meta_builder (raws: &RawMaster, step: &BuilderStep, rng: &mut rltk::RandomNumberGenerator) -> Option<Box<dyn MetaMapBuilder>> {
+    match step.builder.as_str() {
+        "cellular_automata" => Some(CellularAutomataBuilder::new()),
+        "drunkard" => Some(drunkard(step)),
+        "dla" => Some(dla(step)),
+        "prefab" => prefab(raws, step).map(|builder| builder as Box<dyn MetaMapBuilder>),
+        "waveform_collapse" => Some(waveform_collapse(raws, step)),
+        "room_sorter" => Some(RoomSorter::new(match step.mode.as_deref() {
+            Some("rightmost") => RoomSort::RIGHTMOST,
+            Some("topmost") => RoomSort::TOPMOST,
+            Some("bottommost") => RoomSort::BOTTOMMOST,
+            Some("central") => RoomSort::CENTRAL,
+            _ => RoomSort::LEFTMOST,
+        })),
+        "room_draw" => Some(RoomDrawer::new()),
+        "room_exploder" => Some(RoomExploder::new()),
+        "room_corner_rounding" => Some(RoomCornerRounder::new()),
+        "corridors" => match step.mode.as_deref() {
+            Some("bsp") => Some(BspCorridors::new()),
+            Some("nearest") => Some(NearestCorridors::new()),
+            Some("straight") => Some(StraightLineCorridors::new()),
+            _ => Some(DoglegCorridors::new()),
+        },
+        "corridor_spawner" => Some(CorridorSpawner::new()),
+        "room_based_starting_position" => Some(RoomBasedStartingPosition::new()),
+        "area_starting_position" => {
+            let x = x_start(step, rng);
+            let y = y_start(step, rng);
+            Some(AreaStartingPosition::new(x, y))
+        }
+        "area_ending_position" => Some(AreaEndingPosition::new(
+            match step.x.as_deref() {
+                Some("left") => XEnd::LEFT,
+                Some("center") => XEnd::CENTER,
+                _ => XEnd::RIGHT,
+            },
+            match step.y.as_deref() {
+                Some("top") => YEnd::TOP,
+                Some("bottom") => YEnd::BOTTOM,
+                _ => YEnd::CENTER,
+            })),
+        "room_based_stairs" => Some(RoomBasedStairs::new()),
+        "distant_exit" => Some(DistantExit::new()),
+        "room_based_spawner" => Some(RoomBasedSpawner::new()),
+        "voronoi_spawning" => Some(VoronoiSpawning::new()),
+        "cull_unreachable" => Some(CullUnreachable::new()),
+        "door_placement" => Some(DoorPlacement::new()),
+        "yellow_brick_road" => Some(YellowBrickRoad::new()),
+        "cave_decorator" => Some(CaveDecorator::new()),
+        "cave_transition" => Some(CaveTransition::new()),
+        "rivers" => Some(RiverBuilder::with_water(step.rivers.unwrap_or(1), step.lakes.unwrap_or(0))),
+        "biome_spawning" => Some(BiomeSpawning::new()),
+        "biome_decorator" => Some(BiomeDecorator::new()),
+        "validate" => Some(MapValidator::new()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapTheme;
+    use crate::test_support::lock_globals;
+
+    fn raws_with_levels (levels: &str, chains: &str) -> RawMaster {
+        let json = format!(r#"{{ "levels" : [{}], "chains" : [{}] }}"#, levels, chains);
+        let mut raws = RawMaster::empty();
+        raws.load_levels(serde_json::from_str(&json).expect("Bad test levels"));
+        raws
+    }
+
+    fn build (raws: &RawMaster, name: &str, size: Option<(i32, i32)>) -> Option<BuilderChain> {
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        data_builder(raws, name, &LevelId::main(3), &mut rng, size)
+    }
+
+    #[test]
+    fn weights_set_the_odds () {
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let mut picked = [0; 3];
+        for _i in 0 .. 400 { picked[pick_weighted(&mut rng, &[1, 0, 3])] += 1; };
+        assert_eq!(picked[1], 0);
+        assert!(picked[0] > 50 && picked[2] > 250);
+        assert_eq!(pick_weighted(&mut rng, &[0, -2]), 0);
+    }
+
+    #[test]
+    fn a_chain_is_its_starter_and_builders () {
+        let _globals = lock_globals();
+        let raws = raws_with_levels("", r#"
+            { "name" : "caves", "map_name" : "Caves", "width" : 90, "height" : 10,
+              "starter" : { "builder" : "cellular_automata" },
+              "builders" : [ { "builder" : "cull_unreachable" }, { "builder" : "town" }, { "builder" : "distant_exit" } ] },
+            { "name" : "headless", "builders" : [ { "builder" : "distant_exit" } ] }"#);
+        let chain = build(&raws, "caves", None).unwrap();
+        /* The town can only start a chain, so it's left out */
+        assert!(chain.starter.is_some() && chain.builders.len() == 2);
+        let map = &chain.build_data.map;
+        assert!(map.name == "Caves" && map.width == 90 && map.height == MIN_MAP_SIZE && map.depth == 3);
+        assert_eq!(build(&raws, "caves", Some((50, 40))).unwrap().build_data.map.width, 50);
+        assert!(build(&raws, "headless", None).is_none() && build(&raws, "nowhere", None).is_none());
+    }
+
+    #[test]
+    fn includes_and_alternatives_expand_in_place () {
+        let _globals = lock_globals();
+        let raws = raws_with_levels("", r#"
+            { "name" : "finish", "builders" : [ { "builder" : "cull_unreachable" }, { "builder" : "distant_exit" } ] },
+            { "name" : "main", "starter" : { "one_of" : [
+                { "steps" : [ { "builder" : "prefab", "mode" : "constant", "prefab" : "missing" } ] },
+                { "weight" : 0, "steps" : [ { "builder" : "maze" } ] },
+                { "steps" : [ { "builder" : "simple_map" } ] } ] },
+              "builders" : [ { "chain" : "finish" }, { "one_in" : 1, "steps" : [ { "builder" : "room_draw" } ] } ] },
+            { "name" : "loop", "starter" : { "builder" : "simple_map" }, "builders" : [ { "chain" : "loop" } ] }"#);
+        for _i in 0 .. 10 {
+            let chain = build(&raws, "main", None).unwrap();
+            assert!(chain.starter.is_some() && chain.builders.len() == 3);
+        };
+        /* Going round in circles gives up rather than hanging */
+        assert!(build(&raws, "loop", None).unwrap().builders.is_empty());
+    }
+
+    #[test]
+    fn levels_pick_a_chain_and_its_theme () {
+        let _globals = lock_globals();
+        let raws = raws_with_levels(r#"
+            { "min_depth" : 1, "max_depth" : 1, "chain" : "woods", "theme" : "forest" },
+            { "min_depth" : 2, "chain" : "deep" }"#, r#"
+            { "name" : "woods", "map_name" : "Woods", "starter" : { "builder" : "cellular_automata" } },
+            { "name" : "deep", "map_name" : "Deep", "starter" : { "builder" : "bsp_dungeon" } }"#);
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let woods = data_level_builder(&raws, &LevelId::main(1), &mut rng, None).unwrap();
+        assert!(woods.build_data.map.name == "Woods" && woods.build_data.map.theme == MapTheme::Forest);
+        let deep = data_level_builder(&raws, &LevelId::main(9), &mut rng, None).unwrap();
+        assert!(deep.build_data.map.name == "Deep" && deep.build_data.map.theme == MapTheme::Default);
+        assert!(data_level_builder(&raws, &LevelId::new("mines", 4), &mut rng, None).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use super::{BuilderMap, MetaMapBuilder, TileType};
+use crate::map::tile_walkable;
 
 pub struct DistantExit {}
 
@@ -27,7 +28,7 @@ impl DistantExit {
             if *tile == TileType::Floor {
                 let distance_to_start = dijkstra_map.map[i];
                 /* Cant get to this tile: make it a wall */
-                if distance_to_start == std::f32::MAX {
+                if distance_to_start == f32::MAX && i != start_idx {
                     *tile = TileType::Wall;
                 } else {
                     /* If further away than current exit candidate, move exit */
@@ -38,9 +39,15 @@ impl DistantExit {
                 }
             }
         };
-        /* Place staircase */
-        let stairs_idx = exit_tile.0;
-        build_data.map.tiles[stairs_idx] = TileType::DownStairs;
+        let tiles = &build_data.map.tiles;
+        build_data.spawn_list.retain(|(idx, _name)| tile_walkable(tiles[*idx]));
+
+        /* Place staircase, if there's anywhere to walk to */
+        if exit_tile.1 > 0.0 {
+            build_data.map.tiles[exit_tile.0] = TileType::DownStairs;
+        } else {
+            rltk::console::log("Warning: nowhere reachable to put the exit");
+        }
         build_data.take_snapshot();
     }
 }
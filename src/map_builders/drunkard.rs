@@ -1,4 +1,5 @@
 use super::{MetaMapBuilder, InitialMapBuilder, BuilderMap, TileType, Position, Symmetry, paint};
+use crate::map::{DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT};
 
 #[derive(PartialEq, Copy, Clone)]
 #[allow(dead_code)]
@@ -13,7 +14,7 @@ pub struct DrunkardSettings {
 }
 
 pub struct DrunkardsWalkBuilder {
-    settings: DrunkardSettings,
+    pub settings: DrunkardSettings,
 }
 
 impl InitialMapBuilder for DrunkardsWalkBuilder {
@@ -110,6 +111,10 @@ impl DrunkardsWalkBuilder {
         let desired_floor_tiles = (self.settings.floor_percent * total_tiles as f32) as usize;
         let mut floor_tile_count = build_data.map.tiles.iter().filter(|a| **a == TileType::Floor).count();
         let mut digger_count = 0;
+        /* A walk wanders about the square root of its length, so on a bigger map it needs to
+           live longer by the ratio of areas to reach as far */
+        let default_area = DEFAULT_MAP_WIDTH * DEFAULT_MAP_HEIGHT;
+        let drunken_lifetime = self.settings.drunken_lifetime * i32::max(1, total_tiles / default_area);
         while floor_tile_count < desired_floor_tiles {
             let mut did_something = false;
             let mut drunk_x;
@@ -129,7 +134,7 @@ impl DrunkardsWalkBuilder {
                     }
                 },
             }
-            let mut drunk_life = self.settings.drunken_lifetime;
+            let mut drunk_life = drunken_lifetime;
 
             while drunk_life > 0 {
                 let drunk_idx = build_data.map.xy_idx(drunk_x, drunk_y);
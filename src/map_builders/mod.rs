@@ -1,7 +1,9 @@
 #[allow(unused_imports)]
 use std::cell::Cell;
 
-use super::{Map, Rect, TileType, Position, spawner, SHOW_MAPGEN_VISUALIZER};
+use super::{Map, Rect, TileType, Position, MoveMode, Movement, spawner, SHOW_MAPGEN_VISUALIZER};
+use crate::map::{LevelId, MAIN_BRANCH};
+use std::collections::HashMap;
 mod simple_map;
 #[allow(unused_imports)]
 use simple_map::SimpleMapBuilder;
@@ -16,7 +18,7 @@ mod cellular_automata;
 use cellular_automata::CellularAutomataBuilder;
 mod drunkard;
 #[allow(unused_imports)]
-use drunkard::DrunkardsWalkBuilder;
+use drunkard::{DrunkardsWalkBuilder, DrunkardSettings, DrunkSpawnMode};
 mod maze;
 #[allow(unused_imports)]
 use maze::MazeBuilder;
@@ -77,6 +79,8 @@ mod area_ending_point;
 mod distant_exit;
 mod cull_unreachable;
 mod voronoi_spawning;
+mod boss_arena;
+mod branch_stairs;
 #[allow(unused_imports)]
 use room_based_stairs::*;
 #[allow(unused_imports)]
@@ -93,15 +97,27 @@ use distant_exit::*;
 use cull_unreachable::*;
 #[allow(unused_imports)]
 use voronoi_spawning::*;
+#[allow(unused_imports)]
+use boss_arena::*;
+#[allow(unused_imports)]
+use branch_stairs::*;
 
 mod town;
-use town::town_builder;
-mod forest;
-use forest::forest_builder;
+use town::TownBuilder;
 mod yellowbrickroad;
 use yellowbrickroad::*;
 mod limestone_cavern;
 use limestone_cavern::*;
+mod rivers;
+#[allow(unused_imports)]
+use rivers::*;
+mod outdoor;
+#[allow(unused_imports)]
+use outdoor::*;
+mod data_chain;
+use data_chain::*;
+mod validation;
+pub use validation::*;
 
 pub trait InitialMapBuilder {
     fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap);
@@ -113,11 +129,15 @@ pub trait MetaMapBuilder {
 
 pub struct BuilderMap {
     pub spawn_list: Vec<(usize, String)>,
+    pub spawn_movement: HashMap<usize, Movement>,
     pub map: Map,
     pub starting_position: Option<Position>,
     pub rooms: Option<Vec<Rect>>,
     pub corridors: Option<Vec<Vec<usize>>>,
+    pub biomes: Option<Vec<Biome>>,
     pub history: Vec<Map>,
+    pub record_history: bool,
+    pub problems: Vec<MapProblem>,
     pub width: i32,
     pub height: i32,
 }
@@ -130,7 +150,7 @@ pub struct BuilderChain {
 
 impl BuilderMap {
     fn take_snapshot (&mut self) {
-        if SHOW_MAPGEN_VISUALIZER {
+        if self.record_history {
             let mut snapshot = self.map.clone();
             for v in snapshot.revealed_tiles.iter_mut() {
                 *v = true;
@@ -147,11 +167,15 @@ impl BuilderChain {
             builders: Vec::new(),
             build_data: BuilderMap {
                 spawn_list: Vec::new(),
+                spawn_movement: HashMap::new(),
                 map: Map::new(new_depth, width, height, name),
                 starting_position: None,
                 rooms: None,
                 corridors: None,
+                biomes: None,
                 history: Vec::new(),
+                record_history: SHOW_MAPGEN_VISUALIZER,
+                problems: Vec::new(),
                 width,
                 height,
             },
@@ -168,6 +192,16 @@ impl BuilderChain {
     pub fn with (&mut self, metabuilder: Box<dyn MetaMapBuilder>) {
         self.builders.push(metabuilder);
     }
+
+    /// Keeps a snapshot of every step, whether or not the visualizer is switched on
+    pub fn record_history (&mut self) {
+        self.build_data.record_history = true;
+    }
+
+    /// Stamps a unique monster's lair into the finished level
+    pub fn with_lair (&mut self, unique: &str, template: &[String]) {
+        self.builders.push(PrefabBuilder::lair(unique, template));
+    }
     
     pub fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator) {
         match &mut self.starter {
@@ -183,160 +217,46 @@ impl BuilderChain {
     }
 
     pub fn spawn_entities (&mut self, ecs: &mut World) {
+        let mut spawned: Vec<(usize, Entity)> = Vec::new();
         for ent in self.build_data.spawn_list.iter() {
-            spawner::spawn_entity(ecs, &(&ent.0, &ent.1));
+            if let Some(entity) = spawner::spawn_entity(ecs, &(&ent.0, &ent.1)) {
+                spawned.push((ent.0, entity));
+            }
         };
-    }
-}
-
-fn random_start_position (rng: &mut rltk::RandomNumberGenerator) -> (XStart, YStart) {
-    let x;
-    let xroll = rng.roll_dice(1, 3);
-    match xroll {
-        1 => x = XStart::LEFT,
-        2 => x = XStart::CENTER,
-        _ => x = XStart::RIGHT
-    }
-
-    let y;
-    let yroll = rng.roll_dice(1, 3);
-    match yroll {
-        1 => y = YStart::BOTTOM,
-        2 => y = YStart::CENTER,
-        _ => y = YStart::TOP
-    }
-    (x, y)
-}
-
-fn random_room_builder (rng: &mut rltk::RandomNumberGenerator, builder: &mut BuilderChain) {
-    let build_roll = rng.roll_dice(1, 3);
-    match build_roll {
-        1 => builder.start_with(SimpleMapBuilder::new()),
-        2 => builder.start_with(BspDungeonBuilder::new()),
-        _ => builder.start_with(BspInteriorBuilder::new()),
-    }
-
-    if build_roll != 3 {
-        let sort_roll = rng.roll_dice(1, 5);
-        match sort_roll {
-            1 => builder.with(RoomSorter::new(RoomSort::LEFTMOST)),
-            2 => builder.with(RoomSorter::new(RoomSort::RIGHTMOST)),
-            3 => builder.with(RoomSorter::new(RoomSort::TOPMOST)),
-            4 => builder.with(RoomSorter::new(RoomSort::BOTTOMMOST)),
-            _ => builder.with(RoomSorter::new(RoomSort::CENTRAL)),
-        }
-
-        builder.with(RoomDrawer::new());
-
-        let corridor_roll = rng.roll_dice(1, 2);
-        match corridor_roll {
-            1 => builder.with(DoglegCorridors::new()),
-            _ => builder.with(BspCorridors::new()),
-        }
 
-        let modifier_roll = rng.roll_dice(1, 6);
-        match modifier_roll {
-            1 => builder.with(RoomExploder::new()),
-            2 => builder.with(RoomCornerRounder::new()),
-            _ => {},
+        /* Prefabs can hand their occupants a patrol route or a post to guard */
+        {
+            let mut move_modes = ecs.write_storage::<MoveMode>();
+            for (idx, entity) in spawned.iter() {
+                if let (Some(movement), Some(mode)) = (self.build_data.spawn_movement.get(idx), move_modes.get_mut(*entity)) {
+                    mode.mode = movement.clone();
+                }
+            };
         }
+        spawner::assign_leaders(ecs, &spawned);
     }
-    let corridor_roll = rng.roll_dice(1, 4);
-    match corridor_roll {
-        1 => builder.with(DoglegCorridors::new()),
-        2 => builder.with(NearestCorridors::new()),
-        3 => builder.with(StraightLineCorridors::new()),
-        _ => builder.with(BspCorridors::new()),
-    }
-
-    let cspawn_roll = rng.roll_dice(1, 2);
-    if cspawn_roll == 1 {
-        builder.with(CorridorSpawner::new());
-    }
-
-    let start_roll = rng.roll_dice(1, 2);
-    match start_roll {
-        1 => builder.with(RoomBasedStartingPosition::new()),
-        _ => {
-            let (start_x, start_y) = random_start_position(rng);
-            builder.with(AreaStartingPosition::new(start_x, start_y));
-        },
-    }
-
-    let exit_roll = rng.roll_dice(1, 2);
-    match exit_roll {
-        1 => builder.with(RoomBasedStairs::new()),
-        _ => builder.with(DistantExit::new()),
-    }
-
-    let spawn_roll = rng.roll_dice(1, 2);
-    match spawn_roll {
-        1 => builder.with(RoomBasedSpawner::new()),
-        _ => builder.with(VoronoiSpawning::new()),
-    }
-}
-
-fn random_shape_builder (rng: &mut rltk::RandomNumberGenerator, builder: &mut BuilderChain) {
-    let builder_roll = rng.roll_dice(1, 16);
-    match builder_roll {
-        1  => builder.start_with(CellularAutomataBuilder::new()),
-        2  => builder.start_with(DrunkardsWalkBuilder::open_area()),
-        3  => builder.start_with(DrunkardsWalkBuilder::open_halls()),
-        4  => builder.start_with(DrunkardsWalkBuilder::winding_passages()),
-        5  => builder.start_with(DrunkardsWalkBuilder::fat_passages()),
-        6  => builder.start_with(DrunkardsWalkBuilder::fearful_symmetry()),
-        7  => builder.start_with(MazeBuilder::new()),
-        8  => builder.start_with(DLABuilder::walk_inwards()),
-        9  => builder.start_with(DLABuilder::walk_outwards()),
-        10 => builder.start_with(DLABuilder::central_attractor()),
-        11 => builder.start_with(DLABuilder::insectoid()),
-        12 => builder.start_with(VoronoiBuilder::pythagoras()),
-        13 => builder.start_with(VoronoiBuilder::manhattan()),
-        _  => builder.start_with(PrefabBuilder::constant(prefab_builder::prefab_levels::WFC_POPULATED)),
-    }
-    builder.with(AreaStartingPosition::new(XStart::CENTER, YStart::CENTER));
-    builder.with(CullUnreachable::new());
-
-    let (start_x, start_y) = random_start_position(rng);
-    builder.with(AreaStartingPosition::new(start_x, start_y));
-
-    builder.with(VoronoiSpawning::new());
-    builder.with(DistantExit::new());
 }
 
-pub fn level_builder (new_depth:i32, rng: &mut rltk::RandomNumberGenerator, width:i32, height:i32) -> BuilderChain {
-    rltk::console::log(format!("Depth: {}", new_depth));
-    match new_depth {
-        1 => town_builder(new_depth, rng, width, height),
-        2 => forest_builder(new_depth, rng, width, height),
-        3 => limestone_cavern_builder(new_depth, rng, width, height),
-        4 => limestone_deep_cavern_builder(new_depth, rng, width, height),
-        5 => limestone_transition_builder(new_depth, rng, width, height),
-        _ => random_builder(new_depth, rng, width, height),
+pub fn level_builder (level: &LevelId, rng: &mut rltk::RandomNumberGenerator, size: Option<(i32, i32)>) -> BuilderChain {
+    rltk::console::log(format!("Level: {}", level));
+    let raws = crate::raws::RAWS.lock().unwrap();
+    let mut chain = data_level_builder(&raws, level, rng, size)
+        .unwrap_or_else(|| panic!("levels.json has no usable builder chain for level {}", level));
+    /* Bosses guard the way down the main dungeon */
+    if level.branch == MAIN_BRANCH {
+        if let Some(boss) = crate::raws::get_boss_for_depth(&raws, level.depth) {
+            chain.with(BossArena::new(&boss));
+        }
     }
+    for branch in crate::raws::get_branches_from(&raws, level) {
+        chain.with(BranchStairs::new(branch));
+    };
+    chain
 }
 
-pub fn random_builder (new_depth:i32, rng: &mut rltk::RandomNumberGenerator, width:i32, height:i32) -> BuilderChain {
-    let mut builder = BuilderChain::new(new_depth, width, height, "New Map");
-    let type_roll = rng.roll_dice(1, 2);
-    match type_roll {
-        1 => random_room_builder(rng, &mut builder),
-        _ => random_shape_builder(rng, &mut builder),
-    }
-
-    if rng.roll_dice(1, 5) == 1 {
-        builder.with(WaveformCollapseBuilder::new());
-        let (start_x, start_y) = random_start_position(rng);
-        builder.with(AreaStartingPosition::new(start_x, start_y));
-        builder.with(VoronoiSpawning::new());
-        builder.with(DistantExit::new());
-    }
-
-    if rng.roll_dice(1, 20) == 1 {
-        builder.with(PrefabBuilder::sectional(prefab_builder::prefab_sections::UNDERGROUND_FORT));
-    }
-    builder.with(DoorPlacement::new());
-    builder.with(PrefabBuilder::vaults());
-
-    builder
+/// The chain levels.json gives a name to, or with "level" whatever the level would get;
+/// for tools that want to look at one builder at a time
+pub fn named_builder (name: &str, level: &LevelId, rng: &mut rltk::RandomNumberGenerator, size: Option<(i32, i32)>) -> Option<BuilderChain> {
+    if name == "level" { return Some(level_builder(level, rng, size)); }
+    data_builder(&crate::raws::RAWS.lock().unwrap(), name, level, rng, size)
 }
@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, VecDeque};
+use super::{InitialMapBuilder, MetaMapBuilder, BuilderMap, TileType, spawner};
+use crate::map::{Map, tile_walkable};
+
+/// What a stretch of the surface is like, read off how high and how wet it is
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub enum Biome { Grassland, Forest, Hills, Marsh, Lake }
+
+impl Biome {
+    /// The tag spawn table entries use to turn up here
+    pub fn tag (&self) -> &'static str {
+        match self {
+            Biome::Grassland => "grassland",
+            Biome::Forest => "forest",
+            Biome::Hills => "hills",
+            Biome::Marsh => "marsh",
+            Biome::Lake => "lake",
+        }
+    }
+}
+
+/* How much of the map each biome gets: lakes on the lowest ground, hills on the highest,
+   then the wettest of what's left is marsh and the next wettest forest */
+const LAKE_SHARE : f32 = 0.08;
+const HILL_SHARE : f32 = 0.15;
+const MARSH_SHARE : f32 = 0.2;
+const FOREST_SHARE : f32 = 0.4;
+const TREE_PERCENT : i32 = 35;
+
+/// Fractal noise, in rough blobs about `1 / frequency` tiles across
+fn layered_noise (rng: &mut rltk::RandomNumberGenerator, frequency: f32) -> rltk::FastNoise {
+    let mut noise = rltk::FastNoise::seeded(rng.roll_dice(1, 65536) as u64);
+    noise.set_noise_type(rltk::NoiseType::SimplexFractal);
+    noise.set_fractal_type(rltk::FractalType::FBM);
+    noise.set_fractal_octaves(4);
+    noise.set_fractal_gain(0.5);
+    noise.set_fractal_lacunarity(2.0);
+    noise.set_frequency(frequency);
+    noise
+}
+
+/// The value the given share of `values` falls below
+fn share_below (values: &[f32], share: f32) -> f32 {
+    if values.is_empty() { return 0.0; }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let idx = ((sorted.len() - 1) as f32 * f32::clamp(share, 0.0, 1.0)) as usize;
+    sorted[idx]
+}
+
+/// Walls off everything that can't be walked to from the biggest stretch of open ground, so
+/// wherever the start goes the rest of the map is reachable
+fn keep_largest_region (map: &mut Map) {
+    let mut region = vec![0; map.tiles.len()];
+    let mut sizes = vec![0];
+    for first in 0 .. map.tiles.len() {
+        if region[first] != 0 || !tile_walkable(map.tiles[first]) { continue; }
+        let label = sizes.len();
+        sizes.push(0);
+        region[first] = label;
+        let mut open = VecDeque::new();
+        open.push_back(first);
+        while let Some(idx) = open.pop_front() {
+            sizes[label] += 1;
+            let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].iter() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 { continue; }
+                let next = map.xy_idx(nx, ny);
+                if region[next] == 0 && tile_walkable(map.tiles[next]) {
+                    region[next] = label;
+                    open.push_back(next);
+                }
+            };
+        };
+    };
+    let largest = (1 .. sizes.len()).max_by_key(|label| sizes[*label]).unwrap_or(0);
+    for (tile, label) in map.tiles.iter_mut().zip(region.iter()) {
+        if *label != largest && tile_walkable(*tile) { *tile = TileType::Wall; }
+    };
+}
+
+/// Open country laid out by elevation and moisture noise. Walkable ground is left as floor for
+/// the steps after to find their way around; `BiomeDecorator` dresses it afterwards.
+pub struct OutdoorBuilder {}
+
+impl InitialMapBuilder for OutdoorBuilder {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl OutdoorBuilder {
+    #[allow(dead_code)]
+    pub fn new () -> Box<OutdoorBuilder> {
+        Box::new(OutdoorBuilder{})
+    }
+
+    fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let (width, height) = (build_data.map.width, build_data.map.height);
+        let elevation_noise = layered_noise(rng, 0.03);
+        let moisture_noise = layered_noise(rng, 0.04);
+        let mut elevation = vec![0.0; build_data.map.tiles.len()];
+        let mut moisture = vec![0.0; build_data.map.tiles.len()];
+        let mut inside = Vec::new();
+        for y in 1 .. height - 1 {
+            for x in 1 .. width - 1 {
+                let idx = build_data.map.xy_idx(x, y);
+                elevation[idx] = elevation_noise.get_noise(x as f32, y as f32);
+                moisture[idx] = moisture_noise.get_noise(x as f32, y as f32);
+                inside.push(idx);
+            };
+        };
+
+        /* Cut-offs are taken from this map's own spread of values, so every map gets its share of each */
+        let heights: Vec<f32> = inside.iter().map(|idx| elevation[*idx]).collect();
+        let deep_line = share_below(&heights, LAKE_SHARE / 2.0);
+        let lake_line = share_below(&heights, LAKE_SHARE);
+        let hill_line = share_below(&heights, 1.0 - HILL_SHARE);
+        let peak_line = share_below(&heights, 1.0 - HILL_SHARE / 4.0);
+        let lowland: Vec<f32> = inside.iter()
+            .filter(|idx| elevation[**idx] >= lake_line && elevation[**idx] < hill_line)
+            .map(|idx| moisture[*idx])
+            .collect();
+        let marsh_line = share_below(&lowland, 1.0 - MARSH_SHARE);
+        let forest_line = share_below(&lowland, 1.0 - MARSH_SHARE - FOREST_SHARE);
+
+        let mut biomes = vec![Biome::Grassland; build_data.map.tiles.len()];
+        for idx in inside.iter() {
+            let (ground, wet) = (elevation[*idx], moisture[*idx]);
+            let biome = if ground < lake_line { Biome::Lake }
+                else if ground >= hill_line { Biome::Hills }
+                else if wet >= marsh_line { Biome::Marsh }
+                else if wet >= forest_line { Biome::Forest }
+                else { Biome::Grassland };
+            biomes[*idx] = biome;
+            build_data.map.tiles[*idx] = match biome {
+                Biome::Lake if ground < deep_line => TileType::DeepWater,
+                Biome::Hills if ground >= peak_line => TileType::Wall,
+                Biome::Forest if rng.roll_dice(1, 100) <= TREE_PERCENT => TileType::Wall,
+                Biome::Grassland if rng.roll_dice(1, 25) == 1 => TileType::Wall,
+                _ => TileType::Floor,
+            };
+        };
+        build_data.biomes = Some(biomes);
+        build_data.take_snapshot();
+        keep_largest_region(&mut build_data.map);
+        build_data.take_snapshot();
+    }
+}
+
+/// Spawns by biome: the map is cut into cells, and each rolls on the spawn table entries
+/// tagged for whichever biome covers most of it (and the ones not tagged at all)
+pub struct BiomeSpawning {}
+
+impl MetaMapBuilder for BiomeSpawning {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl BiomeSpawning {
+    #[allow(dead_code)]
+    pub fn new () -> Box<BiomeSpawning> {
+        Box::new(BiomeSpawning{})
+    }
+
+    fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let biomes = match &build_data.biomes {
+            Some(biomes) => biomes.clone(),
+            None => {
+                rltk::console::log("Warning: biome spawning on a map with no biomes");
+                return;
+            }
+        };
+        /* Ordered, so the same seed always spawns the same things */
+        let mut areas: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+        let mut noise = rltk::FastNoise::seeded(rng.roll_dice(1, 65536) as u64);
+        noise.set_noise_type(rltk::NoiseType::Cellular);
+        noise.set_frequency(0.08);
+        noise.set_cellular_distance_function(rltk::CellularDistanceFunction::Manhattan);
+
+        for y in 1 .. build_data.map.height-1 {
+            for x in 1 .. build_data.map.width-1 {
+                let idx = build_data.map.xy_idx(x, y);
+                let tt = build_data.map.tiles[idx];
+                if tile_walkable(tt) && tt != TileType::DownStairs && tt != TileType::UpStairs {
+                    let cell = (noise.get_noise(x as f32, y as f32) * 10240.0) as i32;
+                    areas.entry(cell).or_default().push(idx);
+                }
+            };
+        };
+        for area in areas.values() {
+            let mut counts: BTreeMap<Biome, i32> = BTreeMap::new();
+            for idx in area.iter() {
+                *counts.entry(biomes[*idx]).or_default() += 1;
+            };
+            let biome = counts.iter().max_by_key(|(_biome, count)| **count).map_or(Biome::Grassland, |(biome, _count)| *biome);
+            spawner::spawn_biome_region(&build_data.map, rng, area, build_data.map.depth, biome.tag(), &mut build_data.spawn_list);
+        };
+    }
+}
+
+/// Turns the bare floor an outdoor map is laid out with into each biome's own ground
+pub struct BiomeDecorator {}
+
+impl MetaMapBuilder for BiomeDecorator {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl BiomeDecorator {
+    #[allow(dead_code)]
+    pub fn new () -> Box<BiomeDecorator> {
+        Box::new(BiomeDecorator{})
+    }
+
+    fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let biomes = match &build_data.biomes {
+            Some(biomes) => biomes,
+            None => {
+                rltk::console::log("Warning: decorating biomes on a map with no biomes");
+                return;
+            }
+        };
+        for (tile, biome) in build_data.map.tiles.iter_mut().zip(biomes.iter()) {
+            if *tile != TileType::Floor { continue; }
+            *tile = match biome {
+                Biome::Grassland => TileType::Grass,
+                Biome::Forest if rng.roll_dice(1, 4) == 1 => TileType::Grass,
+                Biome::Forest => TileType::Floor,
+                Biome::Hills => TileType::Gravel,
+                Biome::Marsh if rng.roll_dice(1, 2) == 1 => TileType::ShallowWater,
+                Biome::Marsh => TileType::Grass,
+                Biome::Lake => TileType::ShallowWater,
+            };
+        };
+        build_data.take_snapshot();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::{BuilderChain, reachable_from};
+    use crate::test_support::lock_globals;
+
+    fn open (map: &mut Map, x1: i32, y1: i32, x2: i32, y2: i32) {
+        for y in y1 ..= y2 {
+            for x in x1 ..= x2 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            };
+        };
+    }
+
+    #[test]
+    fn only_the_largest_region_is_kept () {
+        let _globals = lock_globals();
+        let mut map = Map::new(1, 20, 10, "Regions");
+        open(&mut map, 1, 1, 8, 8);
+        open(&mut map, 11, 1, 13, 3);
+        open(&mut map, 16, 6, 18, 8);
+        /* Touching corners join up, the same as walking does */
+        open(&mut map, 15, 5, 15, 5);
+        let kept = map.tiles.clone();
+        keep_largest_region(&mut map);
+        assert!(map.tiles[map.xy_idx(4, 4)] == TileType::Floor);
+        assert!(map.tiles[map.xy_idx(12, 2)] == TileType::Wall);
+        assert!(map.tiles[map.xy_idx(15, 5)] == TileType::Wall);
+        assert!(map.tiles[map.xy_idx(17, 7)] == TileType::Wall);
+        assert_eq!(map.tiles.iter().filter(|tt| **tt == TileType::Floor).count(), 64);
+
+        /* Water, trees and the rest of the big region are untouched */
+        let mut map = Map::new(1, 20, 10, "Regions");
+        map.tiles = kept;
+        let deep = map.xy_idx(10, 5);
+        map.tiles[deep] = TileType::DeepWater;
+        open(&mut map, 9, 1, 10, 1);
+        keep_largest_region(&mut map);
+        assert!(map.tiles[deep] == TileType::DeepWater);
+        assert!(map.tiles[map.xy_idx(12, 2)] == TileType::Floor);
+    }
+
+    #[test]
+    fn the_cut_offs_split_values_by_share () {
+        let values: Vec<f32> = (0 .. 101).map(|v| v as f32).collect();
+        assert_eq!(share_below(&values, 0.0), 0.0);
+        assert_eq!(share_below(&values, 0.25), 25.0);
+        assert_eq!(share_below(&values, 1.0), 100.0);
+        assert_eq!(share_below(&values, 2.0), 100.0);
+        assert_eq!(share_below(&[], 0.5), 0.0);
+    }
+
+    #[test]
+    fn outdoor_maps_are_all_one_piece () {
+        let _globals = lock_globals();
+        for seed in 0 .. 10 {
+            let mut chain = BuilderChain::new(1, 60, 40, "Outdoor");
+            let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+            OutdoorBuilder::new().build(&mut rng, &mut chain.build_data);
+            let map = &chain.build_data.map;
+            let first = map.tiles.iter().position(|tt| tile_walkable(*tt)).unwrap();
+            let reached = reachable_from(map, first);
+            for (idx, tt) in map.tiles.iter().enumerate() {
+                assert!(!tile_walkable(*tt) || reached[idx], "seed {} leaves {} cut off", seed, idx);
+            };
+            let biomes = chain.build_data.biomes.as_ref().unwrap();
+            assert!(biomes.contains(&Biome::Lake) && biomes.contains(&Biome::Hills));
+        };
+    }
+}
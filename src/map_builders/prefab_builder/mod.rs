@@ -1,22 +1,38 @@
-use super::{InitialMapBuilder, MetaMapBuilder, BuilderMap, TileType, Position};
-use std::collections::HashSet;
+use super::{InitialMapBuilder, MetaMapBuilder, BuilderMap, TileType, Position, Movement, DistantExit, MapProblem, validate_map,
+    seal_edges};
+use crate::tile_walkable;
+use crate::raws::{Prefab, LegendEntry, HorizontalPlacement, VerticalPlacement, default_legend};
+use std::collections::{HashSet, HashMap};
 
-pub mod prefab_levels;
-pub mod prefab_sections;
-pub mod prefab_rooms;
+const CAMP_GUARD_RADIUS: i32 = 3;
+/* Divides evenly by every rarity from 1 to 10 */
+const RARITY_SHARES: i32 = 2520;
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Clone)]
 #[allow(dead_code)]
 pub enum PrefabMode {
     RexLevel { template: &'static str },
-    Constant { level: prefab_levels::PrefabLevel },
-    Sectional { section: prefab_sections::PrefabSection },
-    RoomVaults,
+    Constant { level: Prefab },
+    Sectional { section: Prefab },
+    RoomVaults { vaults: Vec<Prefab> },
+    Lair { unique: String, template: Vec<String> },
 }
 
 #[allow(dead_code)]
 pub struct PrefabBuilder {
     mode: PrefabMode,
+    placed: Vec<usize>,
+    waypoints: Vec<usize>,
+}
+
+/// The map as it was before a prefab was drawn on it
+struct SavedMap {
+    tiles: Vec<TileType>,
+    spawn_list: Vec<(usize, String)>,
+    spawn_movement: HashMap<usize, Movement>,
+    placed: usize,
+    waypoints: usize,
+    stranded: usize,
 }
 
 impl MetaMapBuilder for PrefabBuilder {
@@ -33,93 +49,121 @@ impl InitialMapBuilder for PrefabBuilder {
 }
 
 impl PrefabBuilder {
-    #[allow(dead_code)]
-    pub fn new () -> Box<PrefabBuilder> {
-        Box::new(PrefabBuilder {
-            mode: PrefabMode::RoomVaults,
-        })
-    }
-
     #[allow(dead_code)]
     pub fn rex_level (template: &'static str) -> Box<PrefabBuilder> {
         Box::new(PrefabBuilder {
             mode: PrefabMode::RexLevel { template },
+            placed: Vec::new(),
+            waypoints: Vec::new(),
         })
     }
 
     #[allow(dead_code)]
-    pub fn constant (level: prefab_levels::PrefabLevel) -> Box<PrefabBuilder> {
+    pub fn constant (level: Prefab) -> Box<PrefabBuilder> {
         Box::new(PrefabBuilder {
             mode: PrefabMode::Constant { level },
+            placed: Vec::new(),
+            waypoints: Vec::new(),
         })
     }
 
     #[allow(dead_code)]
-    pub fn sectional (section : prefab_sections::PrefabSection) -> Box<PrefabBuilder> {
+    pub fn sectional (section: Prefab) -> Box<PrefabBuilder> {
         Box::new(PrefabBuilder {
             mode: PrefabMode::Sectional { section },
+            placed: Vec::new(),
+            waypoints: Vec::new(),
         })
     }
 
+    /// Scatters a few of these vaults, whichever suit the depth, over open ground
     #[allow(dead_code)]
-    pub fn vaults () -> Box<PrefabBuilder> {
+    pub fn vaults (vaults: Vec<Prefab>) -> Box<PrefabBuilder> {
         Box::new(PrefabBuilder{
-            mode : PrefabMode::RoomVaults,
+            mode : PrefabMode::RoomVaults { vaults },
+            placed: Vec::new(),
+            waypoints: Vec::new(),
+        })
+    }
+
+    /// A unique's lair, from a raws template; `U` marks where the unique waits
+    pub fn lair (unique: &str, template: &[String]) -> Box<PrefabBuilder> {
+        Box::new(PrefabBuilder {
+            mode: PrefabMode::Lair { unique: unique.to_string(), template: template.to_vec() },
+            placed: Vec::new(),
+            waypoints: Vec::new(),
         })
     }
 
     fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
-        match self.mode {
+        match self.mode.clone() {
             PrefabMode::RexLevel { template } => self.load_rex_map(&template, build_data),
             PrefabMode::Constant { level } => self.load_ascii_map(&level, build_data),
             PrefabMode::Sectional { section } => self.apply_sectional(&section, rng, build_data),
-            PrefabMode::RoomVaults => self.apply_room_vaults(rng, build_data),
+            PrefabMode::RoomVaults { vaults } => self.apply_room_vaults(&vaults, rng, build_data),
+            PrefabMode::Lair { unique, template } => self.apply_lair(&unique, &template, rng, build_data),
         }
+        self.assign_movement(build_data);
         build_data.take_snapshot();
     }
 
-    fn char_to_map (&mut self, ch: char, idx: usize, build_data: &mut BuilderMap) {
-        match ch {
-            ' ' => build_data.map.tiles[idx] = TileType::Floor,
-            '#' => build_data.map.tiles[idx] = TileType::Wall,
-            '@' => {
+    /// Mobs placed by a prefab with waypoints patrol them; around a watch fire they stand guard
+    fn assign_movement (&mut self, build_data: &mut BuilderMap) {
+        let placed: HashSet<usize> = self.placed.drain(..).collect();
+        let width = build_data.map.width;
+        let point = |idx: usize| rltk::Point::new(idx as i32 % width, idx as i32 / width);
+
+        /* Walk the waypoints in a loop around their middle, rather than in reading order */
+        if !self.waypoints.is_empty() {
+            let n = self.waypoints.len() as f32;
+            let cx = self.waypoints.iter().map(|idx| point(*idx).x as f32).sum::<f32>() / n;
+            let cy = self.waypoints.iter().map(|idx| point(*idx).y as f32).sum::<f32>() / n;
+            let angle = |idx: usize| (point(idx).y as f32 - cy).atan2(point(idx).x as f32 - cx);
+            self.waypoints.sort_by(|a, b| angle(*a).partial_cmp(&angle(*b)).unwrap());
+        }
+        let guarded = build_data.spawn_list.iter().any(|(idx, name)| placed.contains(idx) && name == "Watch Fire");
+
+        for (idx, _name) in build_data.spawn_list.iter().filter(|(idx, _)| placed.contains(idx)) {
+            if !self.waypoints.is_empty() {
+                let next = (0 .. self.waypoints.len())
+                    .min_by_key(|i| {
+                        let (a, b) = (point(*idx), point(self.waypoints[*i]));
+                        (a.x - b.x).abs() + (a.y - b.y).abs()
+                    })
+                    .unwrap_or(0);
+                build_data.spawn_movement.insert(*idx, Movement::Patrol { waypoints: self.waypoints.clone(), next });
+            } else if guarded {
+                build_data.spawn_movement.insert(*idx, Movement::Guard { home: *idx, radius: CAMP_GUARD_RADIUS });
+            }
+        };
+        self.waypoints.clear();
+    }
+
+    /// Draws one glyph, as the prefab's legend has it (or as any prefab would, without one)
+    fn char_to_map (&mut self, ch: char, prefab: Option<&Prefab>, idx: usize, build_data: &mut BuilderMap) {
+        self.placed.push(idx);
+        let meaning = match prefab {
+            Some(prefab) => prefab.meaning(ch),
+            None => default_legend(ch),
+        };
+        match meaning {
+            Some(LegendEntry::Tile(tt)) => build_data.map.tiles[idx] = tt,
+            Some(LegendEntry::Start) => {
                 let x = idx as i32 % build_data.map.width;
                 let y = idx as i32 / build_data.map.width;
                 build_data.map.tiles[idx] = TileType::Floor;
-                build_data.starting_position = Some(Position { x:x as i32, y:y as i32 });
-            },
-            '>' => build_data.map.tiles[idx] = TileType::DownStairs,
-            'g' => {
-                build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Goblin".to_string()));
-            },
-            'o' => {
-                build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Orc".to_string()));
-            },
-            '^' => {
-                build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Bear Trap".to_string()));
+                build_data.starting_position = Some(Position { x, y });
             },
-            '%' => {
+            Some(LegendEntry::Waypoint) => {
                 build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Rations".to_string()));
+                self.waypoints.push(idx);
             },
-            '!' => {
+            Some(LegendEntry::Spawn(name)) => {
                 build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Health Potion".to_string()));
+                build_data.spawn_list.push((idx, name));
             },
-            '≈' => build_data.map.tiles[idx] = TileType::DeepWater,
-            'O' => {
-                build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Orc Leader".to_string()));
-            },
-            '☼' => {
-                build_data.map.tiles[idx] = TileType::Floor;
-                build_data.spawn_list.push((idx, "Watch Fire".to_string()));
-            },
-            _ => {
-                rltk::console::log(format!("Unknown glyph loading map: {}", (ch as u8) as char));
+            None => {
+                rltk::console::log(format!("Unknown glyph loading map: {}", ch));
             },
         }
     }
@@ -135,33 +179,29 @@ impl PrefabBuilder {
                     let cell = layer.get(x, y).unwrap();
                     if x < build_data.map.width as usize && y < build_data.map.height as usize {
                         let idx = build_data.map.xy_idx(x as i32, y as i32);
-                        self.char_to_map(cell.ch as u8 as char, idx, build_data);
+                        self.char_to_map(cell.ch as u8 as char, None, idx, build_data);
                     }
                 };
             };
         };
     }
 
-    fn read_ascii_to_vec (template: &str) -> Vec<char> {
-        let mut string_vec: Vec<char> = template.chars().filter(|a| *a != 'r' && *a != '\n').collect();
-        for c in string_vec.iter_mut() { if *c as u8 == 160u8 { *c = ' '; } };
-        string_vec
-    }
-
     #[allow(dead_code)]
-    fn load_ascii_map (&mut self, level: &prefab_levels::PrefabLevel, build_data: &mut BuilderMap) {
-        let string_vec = PrefabBuilder::read_ascii_to_vec(level.template);
-
+    fn load_ascii_map (&mut self, level: &Prefab, build_data: &mut BuilderMap) {
         let mut i = 0;
         for ty in 0 .. level.height {
             for tx in 0 .. level.width {
                 if tx < build_data.map.width as usize && ty < build_data.map.height as usize {
                     let idx = build_data.map.xy_idx(tx as i32, ty as i32);
-                    self.char_to_map(string_vec[i], idx, build_data);
+                    self.char_to_map(level.glyphs[i], Some(level), idx, build_data);
                 }
                 i += 1;
             };
         };
+        /* A level drawn for a bigger map gets cut off, and the cut is left open */
+        if level.width > build_data.map.width as usize || level.height > build_data.map.height as usize {
+            seal_edges(&mut build_data.map);
+        }
     }
 
     fn apply_previous_iteration <F>(&mut self, mut filter: F, _rng: &mut
@@ -174,13 +214,15 @@ impl PrefabBuilder {
             let y = *idx as i32 / width;
             filter(x, y)
         });
+        build_data.spawn_movement.retain(|idx, _movement| {
+            let x = *idx as i32 % width;
+            let y = *idx as i32 / width;
+            filter(x, y)
+        });
         build_data.take_snapshot();
     }
 
-    fn apply_sectional (&mut self, section: &prefab_sections::PrefabSection, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
-        use prefab_sections::*;
-        let string_vec = PrefabBuilder::read_ascii_to_vec(section.template);
-
+    fn apply_sectional (&mut self, section: &Prefab, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
         /* Place new section */
         let chunk_x;
         match section.placement.0 {
@@ -196,6 +238,7 @@ impl PrefabBuilder {
             VerticalPlacement::Bottom => chunk_y = (build_data.map.height - 1) - section.height as i32,
         }
 
+        let saved = self.save(build_data);
         self.apply_previous_iteration(|x,y| {
             x < chunk_x || x > (chunk_x + section.width as i32) || y < chunk_y || y > (chunk_y + section.height as i32)
         }, rng, build_data);
@@ -203,26 +246,39 @@ impl PrefabBuilder {
         let mut i = 0;
         for ty in 0 .. section.height {
             for tx in 0 .. section.width {
-                if tx > 0 && tx < build_data.map.width as usize -1 && ty < build_data.map.height as usize -1 && ty > 0 {
-                    let idx = build_data.map.xy_idx(tx as i32 + chunk_x, ty as i32 + chunk_y);
-                    self.char_to_map(string_vec[i], idx, build_data);
+                let (x, y) = (tx as i32 + chunk_x, ty as i32 + chunk_y);
+                if x > 0 && x < build_data.map.width - 1 && y > 0 && y < build_data.map.height - 1 {
+                    let idx = build_data.map.xy_idx(x, y);
+                    self.char_to_map(section.glyphs[i], Some(section), idx, build_data);
                 }
                 i += 1;
             };
         };
         build_data.take_snapshot();
-    }
 
-    fn apply_room_vaults (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
-        use prefab_rooms::*;
+        /* The section landed on the way down, or walled it off: find another. If it walled
+           in the player instead, it doesn't fit here */
+        if self.cuts_off(&saved, build_data) {
+            let start_walkable = build_data.starting_position.as_ref()
+                .is_some_and(|start| tile_walkable(build_data.map.tiles[build_data.map.xy_idx(start.x, start.y)]));
+            if start_walkable {
+                for tt in build_data.map.tiles.iter_mut() {
+                    if *tt == TileType::DownStairs { *tt = TileType::Floor; }
+                };
+                DistantExit::new().build_map(rng, build_data);
+            } else {
+                self.restore(saved, build_data);
+            }
+        }
+    }
 
+    fn apply_room_vaults (&mut self, vaults: &[Prefab], rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
         self.apply_previous_iteration(|_x,_y| true, rng, build_data);
 
         let vault_roll = rng.roll_dice(1, 6) + build_data.map.depth;
         if vault_roll < 4 { return; }
 
-        let master_vault_list = vec![TOTALLY_NOT_TRAP, CHECKERBOARD, SILLY_SMILE];
-        let mut possible_vaults : Vec<&PrefabRoom> = master_vault_list.iter()
+        let mut possible_vaults : Vec<&Prefab> = vaults.iter()
             .filter(|v| { build_data.map.depth >= v.first_depth && build_data.map.depth <= v.last_depth })
             .collect();
         if possible_vaults.is_empty() { return; }
@@ -231,38 +287,15 @@ impl PrefabBuilder {
         let mut used_tiles: HashSet<usize> = HashSet::new();
 
         for _i in 0 .. n_vaults {
-            let vault_idx = if possible_vaults.len() == 1 { 0 }
-                else { (rng.roll_dice(1, possible_vaults.len() as i32)-1) as usize };
+            /* A vault of rarity 3 gets a third of the share of a rarity 1 vault */
+            let vault_idx = if possible_vaults.len() == 1 { 0 } else {
+                let share = |v: &Prefab| RARITY_SHARES / v.rarity;
+                let mut roll = rng.roll_dice(1, possible_vaults.iter().map(|v| share(v)).sum()) - 1;
+                possible_vaults.iter().position(|v| { roll -= share(v); roll < 0 }).unwrap_or(0)
+            };
             let vault = possible_vaults[vault_idx];
 
-            let mut vault_positions: Vec<Position> = Vec::new();
-
-            let mut idx = 0usize;
-            loop {
-                let x = (idx % build_data.map.width as usize) as i32;
-                let y = (idx / build_data.map.width as usize) as i32;
-                if x > 1 && (x+vault.width as i32) < build_data.map.width-2 && y > 1 &&
-                    (y+vault.height as i32) < build_data.map.height-2 {
-                    let mut possible = true;
-                    for ty in 0 .. vault.height as i32 {
-                        for tx in 0 .. vault.width as i32 {
-                            let idx = build_data.map.xy_idx(tx+x, ty+y);
-                            if build_data.map.tiles[idx] != TileType::Floor {
-                                possible = false;
-                            }
-                            if used_tiles.contains(&idx) {
-                                possible = false;
-                            }
-                        };
-                    };
-                    if possible {
-                        vault_positions.push(Position { x, y });
-                        break;
-                    }
-                }
-                idx += 1;
-                if idx >= build_data.map.tiles.len()-1 { break; }
-            };
+            let vault_positions = PrefabBuilder::vault_positions(vault.width, vault.height, &used_tiles, build_data);
 
             if !vault_positions.is_empty() {
                 let pos_idx = if vault_positions.len() == 1 { 0 }
@@ -272,30 +305,191 @@ impl PrefabBuilder {
                 let chunk_x = pos.x;
                 let chunk_y = pos.y;
 
+                let saved = self.save(build_data);
                 let width = build_data.map.width;
-                let height = build_data.map.height;
                 build_data.spawn_list.retain(|e| {
                     let idx = e.0 as i32;
                     let x = idx % width;
-                    let y = idx / height;
+                    let y = idx / width;
                     x < chunk_x || x > chunk_x + vault.width as i32 || y < chunk_y ||
                         y > chunk_y + vault.height as i32
                 });
 
-                let string_vec = PrefabBuilder::read_ascii_to_vec(vault.template);
                 let mut i = 0;
                 for ty in 0 .. vault.height {
                     for tx in 0 .. vault.width {
                         let idx = build_data.map.xy_idx(tx as i32 + chunk_x, ty as i32 + chunk_y);
-                        self.char_to_map(string_vec[i], idx, build_data);
-                        used_tiles.insert(idx);
+                        self.char_to_map(vault.glyphs[i], Some(vault), idx, build_data);
                         i += 1;
 
                     };
                 };
-                build_data.take_snapshot();
+                if self.cuts_off(&saved, build_data) {
+                    self.restore(saved, build_data);
+                } else {
+                    for ty in 0 .. vault.height as i32 {
+                        for tx in 0 .. vault.width as i32 {
+                            used_tiles.insert(build_data.map.xy_idx(tx + chunk_x, ty + chunk_y));
+                        };
+                    };
+                    build_data.take_snapshot();
+                }
                 possible_vaults.remove(vault_idx);
             }
         };
     }
+
+    /// Every spot a prefab of this size fits on open ground, clear of the stairs, the
+    /// player's start and anything already placed
+    fn vault_positions (width: usize, height: usize, used_tiles: &HashSet<usize>, build_data: &BuilderMap) -> Vec<Position> {
+        let start = build_data.starting_position.as_ref().map(|pos| build_data.map.xy_idx(pos.x, pos.y));
+        let mut positions = Vec::new();
+        for y in 2 .. build_data.map.height - 2 - height as i32 {
+            for x in 2 .. build_data.map.width - 2 - width as i32 {
+                let mut possible = true;
+                for ty in 0 .. height as i32 {
+                    for tx in 0 .. width as i32 {
+                        let idx = build_data.map.xy_idx(tx+x, ty+y);
+                        let tile = build_data.map.tiles[idx];
+                        if !tile_walkable(tile) || tile == TileType::DownStairs || tile == TileType::UpStairs
+                            || used_tiles.contains(&idx) || start == Some(idx) {
+                            possible = false;
+                        }
+                    };
+                };
+                if possible {
+                    positions.push(Position { x, y });
+                }
+            };
+        };
+        positions
+    }
+
+    fn apply_lair (&mut self, unique: &str, template: &[String], rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let width = template.first().map_or(0, |row| row.chars().count());
+        let height = template.len();
+        let positions = PrefabBuilder::vault_positions(width, height, &HashSet::new(), build_data);
+        if positions.is_empty() {
+            rltk::console::log(format!("Warning: no room for {}'s lair", unique));
+            return;
+        }
+        let pos = &positions[(rng.roll_dice(1, positions.len() as i32)-1) as usize];
+
+        let saved = self.save(build_data);
+        let map_width = build_data.map.width;
+        build_data.spawn_list.retain(|(idx, _name)| {
+            let x = *idx as i32 % map_width;
+            let y = *idx as i32 / map_width;
+            x < pos.x || x >= pos.x + width as i32 || y < pos.y || y >= pos.y + height as i32
+        });
+
+        for (ty, row) in template.iter().enumerate() {
+            for (tx, ch) in row.chars().enumerate() {
+                let idx = build_data.map.xy_idx(pos.x + tx as i32, pos.y + ty as i32);
+                if ch == 'U' {
+                    self.placed.push(idx);
+                    build_data.map.tiles[idx] = TileType::Floor;
+                    build_data.spawn_list.push((idx, unique.to_string()));
+                } else {
+                    self.char_to_map(ch, None, idx, build_data);
+                }
+            };
+        };
+        if self.cuts_off(&saved, build_data) {
+            rltk::console::log(format!("Warning: {}'s lair would wall off the stairs", unique));
+            self.restore(saved, build_data);
+        }
+    }
+
+    /// What a prefab is about to draw over, so it can be put back
+    fn save (&self, build_data: &BuilderMap) -> SavedMap {
+        SavedMap {
+            tiles: build_data.map.tiles.clone(),
+            spawn_list: build_data.spawn_list.clone(),
+            spawn_movement: build_data.spawn_movement.clone(),
+            placed: self.placed.len(),
+            waypoints: self.waypoints.len(),
+            stranded: PrefabBuilder::stranded(build_data),
+        }
+    }
+
+    fn restore (&mut self, saved: SavedMap, build_data: &mut BuilderMap) {
+        build_data.map.tiles = saved.tiles;
+        build_data.spawn_list = saved.spawn_list;
+        build_data.spawn_movement = saved.spawn_movement;
+        self.placed.truncate(saved.placed);
+        self.waypoints.truncate(saved.waypoints);
+    }
+
+    /// How many ways the player is stuck: a start they can't stand on, or stairs they can't reach
+    fn stranded (build_data: &BuilderMap) -> usize {
+        validate_map(build_data).iter()
+            .filter(|problem| matches!(problem, MapProblem::StartNotWalkable{..} | MapProblem::StairsUnreachable{..}))
+            .count()
+    }
+
+    /// Did drawing the prefab wall the player off from the stairs, or the stairs away?
+    fn cuts_off (&self, saved: &SavedMap, build_data: &BuilderMap) -> bool {
+        let had_stairs = saved.tiles.contains(&TileType::DownStairs);
+        let has_stairs = build_data.map.tiles.contains(&TileType::DownStairs);
+        (had_stairs && !has_stairs) || PrefabBuilder::stranded(build_data) > saved.stranded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::BuilderChain;
+    use crate::test_support::lock_globals;
+    use crate::map::{Map, MIN_MAP_SIZE};
+
+    fn prefab (text: &str) -> Prefab {
+        Prefab::parse("test", text, |file| Err(format!("no {}", file))).unwrap()
+    }
+
+    fn edges_solid (map: &Map) -> bool {
+        (0 .. map.tiles.len()).all(|idx| {
+            let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+            let edge = x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1;
+            !edge || map.tiles[idx] == TileType::Wall
+        })
+    }
+
+    #[test]
+    fn levels_drawn_for_bigger_maps_are_sealed_where_cut () {
+        let _globals = lock_globals();
+        let mut chain = BuilderChain::new(1, MIN_MAP_SIZE, MIN_MAP_SIZE, "Cut");
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        /* All floor, padded out from nothing */
+        PrefabBuilder::constant(prefab("kind: level\nsize: 30x25\n---\n"))
+            .build(&mut rng, &mut chain.build_data);
+        let map = &chain.build_data.map;
+        assert!(edges_solid(map));
+        assert!(map.tiles[map.xy_idx(10, 10)] == TileType::Floor);
+    }
+
+    #[test]
+    fn sections_wider_than_the_map_stay_inside_it () {
+        let _globals = lock_globals();
+        let mut chain = BuilderChain::new(1, MIN_MAP_SIZE, MIN_MAP_SIZE, "Narrow");
+        let map = &mut chain.build_data.map;
+        for y in 1 .. MIN_MAP_SIZE - 1 {
+            for x in 1 .. MIN_MAP_SIZE - 1 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            };
+        };
+        let stairs = map.xy_idx(15, 15);
+        map.tiles[stairs] = TileType::DownStairs;
+        chain.build_data.starting_position = Some(Position { x: 3, y: 15 });
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        PrefabBuilder::sectional(prefab("kind: section\nsize: 25x3\nplacement: right top\n~ = shallow_water\n---\n~~~~~~~~~~~~~~~~~~~~~~~~~\n\n~~~~~~~~~~~~~~~~~~~~~~~~~"))
+            .build(&mut rng, &mut chain.build_data);
+        let map = &chain.build_data.map;
+        assert!(edges_solid(map));
+        /* The section hangs off the west side and its top row is on the edge, so only the
+           bottom row of water lands, all the way across */
+        assert!(map.tiles[map.xy_idx(1, 2)] == TileType::ShallowWater && map.tiles[map.xy_idx(18, 2)] == TileType::ShallowWater);
+        assert!(map.tiles[map.xy_idx(1, 1)] == TileType::Floor && map.tiles[map.xy_idx(5, 3)] == TileType::Floor);
+    }
 }
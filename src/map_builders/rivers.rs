@@ -0,0 +1,262 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use super::{MetaMapBuilder, BuilderMap, TileType, reachable_from};
+use crate::map::{Map, tile_walkable};
+
+/* A tile of bridge is worth this far round by road (off the road, steps count double) */
+const CROSSING_COST : i32 = 12;
+
+pub struct RiverBuilder {
+    pub rivers: i32,
+    pub lakes: i32,
+}
+
+impl MetaMapBuilder for RiverBuilder {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl RiverBuilder {
+    #[allow(dead_code)]
+    pub fn new () -> Box<RiverBuilder> {
+        Box::new(RiverBuilder { rivers: 1, lakes: 1 })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_water (rivers: i32, lakes: i32) -> Box<RiverBuilder> {
+        Box::new(RiverBuilder { rivers, lakes })
+    }
+
+    /// Floods a tile, unless it's on the edge, stairs or the start. Banks never make deep
+    /// water shallow again.
+    fn flood (map: &mut Map, start: Option<usize>, x: i32, y: i32, water: TileType) {
+        if x < 1 || y < 1 || x > map.width - 2 || y > map.height - 2 { return; }
+        let idx = map.xy_idx(x, y);
+        match map.tiles[idx] {
+            TileType::DownStairs | TileType::UpStairs | TileType::DeepWater => {}
+            _ if Some(idx) == start => {}
+            _ => map.tiles[idx] = water,
+        }
+    }
+
+    /// A river from one side of the map to the other, wandering as it goes: deep in the
+    /// middle, with shallow banks
+    fn river (&self, rng: &mut rltk::RandomNumberGenerator, map: &mut Map, start: Option<usize>) {
+        let across = rng.roll_dice(1, 2) == 1;
+        let (length, breadth) = if across { (map.width, map.height) } else { (map.height, map.width) };
+        let mut centre = breadth / 4 + rng.roll_dice(1, i32::max(1, breadth / 2));
+        let mut heading = rng.roll_dice(1, 3) - 2;
+        let mut deep = rng.roll_dice(1, 2);
+        for along in 1 .. length - 1 {
+            if rng.roll_dice(1, 6) == 1 { heading = rng.roll_dice(1, 3) - 2; }
+            if rng.roll_dice(1, 2) == 1 { centre += heading; }
+            if centre < 4 || centre > breadth - 5 {
+                heading = -heading;
+                centre = i32::clamp(centre, 4, i32::max(4, breadth - 5));
+            }
+            if rng.roll_dice(1, 8) == 1 { deep = rng.roll_dice(1, 2); }
+            let bank = deep + 1;
+            for offset in -bank ..= bank {
+                let water = if offset.abs() <= deep { TileType::DeepWater } else { TileType::ShallowWater };
+                let (x, y) = if across { (along, centre + offset) } else { (centre + offset, along) };
+                RiverBuilder::flood(map, start, x, y, water);
+            };
+        };
+    }
+
+    /// A few overlapping pools, deep where they're well in from the shore
+    fn lake (&self, rng: &mut rltk::RandomNumberGenerator, map: &mut Map, start: Option<usize>) {
+        let size = i32::max(2, i32::min(6, i32::min(map.width, map.height) / 6));
+        let x = rng.roll_dice(1, map.width - 2);
+        let y = rng.roll_dice(1, map.height - 2);
+        let mut pools = vec![(x, y, size)];
+        for _ in 0 .. 2 {
+            let offset = rng.roll_dice(1, size + 1) - 1;
+            let (dx, dy) = (rng.roll_dice(1, 3) - 2, rng.roll_dice(1, 3) - 2);
+            pools.push((x + dx * offset, y + dy * offset, i32::max(1, size * 2 / 3)));
+        };
+        for (px, py, radius) in pools.iter() {
+            for ty in py - radius ..= py + radius {
+                for tx in px - radius ..= px + radius {
+                    let distance = rltk::DistanceAlg::Pythagoras.distance2d(rltk::Point::new(*px, *py), rltk::Point::new(tx, ty));
+                    if distance > *radius as f32 { continue; }
+                    let water = if distance < (*radius - 1) as f32 { TileType::DeepWater } else { TileType::ShallowWater };
+                    RiverBuilder::flood(map, start, tx, ty, water);
+                };
+            };
+        };
+    }
+
+    /// The cheapest way from start to end over land or deep water, keeping to the road where
+    /// there is one
+    fn crossing_path (map: &Map, start: usize, end: usize) -> Option<Vec<usize>> {
+        let mut cost = vec![i32::MAX; map.tiles.len()];
+        let mut came_from = vec![usize::MAX; map.tiles.len()];
+        let mut open = BinaryHeap::new();
+        cost[start] = 0;
+        open.push(Reverse((0, start)));
+        while let Some(Reverse((so_far, idx))) = open.pop() {
+            if idx == end { break; }
+            if so_far > cost[idx] { continue; }
+            let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].iter() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 { continue; }
+                let next = map.xy_idx(nx, ny);
+                let step = match map.tiles[next] {
+                    TileType::Road | TileType::Bridge => 1,
+                    TileType::DeepWater => CROSSING_COST,
+                    tt if tile_walkable(tt) => 2,
+                    _ => continue,
+                };
+                if so_far + step < cost[next] {
+                    cost[next] = so_far + step;
+                    came_from[next] = idx;
+                    open.push(Reverse((so_far + step, next)));
+                }
+            };
+        };
+        if cost[end] == i32::MAX { return None; }
+        let mut path = vec![end];
+        while *path.last().unwrap() != start {
+            path.push(came_from[*path.last().unwrap()]);
+        };
+        Some(path)
+    }
+
+    /// Bridges (or now and then a ford) wherever the water has cut the start off from a way down
+    fn cross (&self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap, start: usize) {
+        let exits: Vec<usize> = build_data.map.tiles.iter().enumerate()
+            .filter(|(_idx, tt)| **tt == TileType::DownStairs)
+            .map(|(idx, _tt)| idx)
+            .collect();
+        for exit in exits.iter() {
+            if reachable_from(&build_data.map, start)[*exit] { continue; }
+            let crossing = if rng.roll_dice(1, 3) == 1 { TileType::ShallowWater } else { TileType::Bridge };
+            match RiverBuilder::crossing_path(&build_data.map, start, *exit) {
+                Some(path) => for idx in path.iter() {
+                    if build_data.map.tiles[*idx] == TileType::DeepWater {
+                        build_data.map.tiles[*idx] = crossing;
+                    }
+                },
+                None => rltk::console::log("Warning: no way across the water to the exit, even with a bridge"),
+            }
+        };
+    }
+
+    fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let start = build_data.starting_position.as_ref().map(|pos| build_data.map.xy_idx(pos.x, pos.y));
+        for _ in 0 .. self.rivers {
+            self.river(rng, &mut build_data.map, start);
+            build_data.take_snapshot();
+        };
+        for _ in 0 .. self.lakes {
+            self.lake(rng, &mut build_data.map, start);
+            build_data.take_snapshot();
+        };
+        let tiles = &build_data.map.tiles;
+        build_data.spawn_list.retain(|(idx, _name)| tile_walkable(tiles[*idx]));
+
+        /* Without a start there's nothing to keep joined up; a later step has to see to it */
+        if let Some(start) = start {
+            self.cross(rng, build_data, start);
+            build_data.take_snapshot();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::BuilderChain;
+    use crate::components::Position;
+    use crate::test_support::lock_globals;
+
+    /// An open room with stairs on the east side and the start on the west, and deep water
+    /// `width` tiles wide between them
+    fn moat (width: i32) -> BuilderChain {
+        let mut chain = BuilderChain::new(1, 30, 12, "Moat");
+        let map = &mut chain.build_data.map;
+        for y in 1 .. 11 {
+            for x in 1 .. 29 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = if x >= 14 && x < 14 + width { TileType::DeepWater } else { TileType::Floor };
+            };
+        };
+        let stairs = map.xy_idx(26, 5);
+        map.tiles[stairs] = TileType::DownStairs;
+        chain.build_data.starting_position = Some(Position { x: 3, y: 5 });
+        chain
+    }
+
+    fn count (map: &Map, tile: TileType) -> usize {
+        map.tiles.iter().filter(|tt| **tt == tile).count()
+    }
+
+    #[test]
+    fn cut_off_stairs_get_a_crossing () {
+        let _globals = lock_globals();
+        for seed in 0 .. 10 {
+            let mut chain = moat(3);
+            let (start, stairs) = (chain.build_data.map.xy_idx(3, 5), chain.build_data.map.xy_idx(26, 5));
+            assert!(!reachable_from(&chain.build_data.map, start)[stairs]);
+            let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+            RiverBuilder::new().cross(&mut rng, &mut chain.build_data, start);
+            let map = &chain.build_data.map;
+            assert!(reachable_from(map, start)[stairs]);
+            /* Straight across, not along the river */
+            assert_eq!(count(map, TileType::Bridge) + count(map, TileType::ShallowWater), 3);
+        };
+    }
+
+    #[test]
+    fn a_reachable_exit_is_left_alone () {
+        let _globals = lock_globals();
+        let mut chain = moat(0);
+        let before = chain.build_data.map.tiles.clone();
+        let start = chain.build_data.map.xy_idx(3, 5);
+        RiverBuilder::new().cross(&mut rltk::RandomNumberGenerator::seeded(1), &mut chain.build_data, start);
+        assert!(chain.build_data.map.tiles == before);
+    }
+
+    #[test]
+    fn crossings_follow_the_road () {
+        let _globals = lock_globals();
+        let mut chain = moat(1);
+        let map = &mut chain.build_data.map;
+        /* Going round by the road, which crosses well north of the straight line */
+        for x in 3 ..= 26 {
+            let idx = map.xy_idx(x, 2);
+            if map.tiles[idx] == TileType::Floor { map.tiles[idx] = TileType::Road; }
+        };
+        for y in 2 ..= 5 {
+            for x in [3, 26].iter() {
+                let idx = map.xy_idx(*x, y);
+                if map.tiles[idx] == TileType::Floor { map.tiles[idx] = TileType::Road; }
+            };
+        };
+        let path = RiverBuilder::crossing_path(map, map.xy_idx(3, 5), map.xy_idx(26, 5)).unwrap();
+        let crossing = path.iter().find(|idx| **idx as i32 % map.width == 14).unwrap();
+        assert!(*crossing as i32 / map.width <= 3);
+    }
+
+    #[test]
+    fn water_never_covers_the_start_stairs_or_edge () {
+        let _globals = lock_globals();
+        for seed in 0 .. 20 {
+            let mut chain = moat(0);
+            let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+            RiverBuilder::with_water(2, 2).build(&mut rng, &mut chain.build_data);
+            let map = &chain.build_data.map;
+            let (start, stairs) = (map.xy_idx(3, 5), map.xy_idx(26, 5));
+            assert!(map.tiles[stairs] == TileType::DownStairs);
+            assert!(tile_walkable(map.tiles[start]));
+            assert!(reachable_from(map, start)[stairs], "seed {}", seed);
+            for x in 0 .. map.width {
+                assert!(map.tiles[map.xy_idx(x, 0)] == TileType::Wall);
+                assert!(map.tiles[map.xy_idx(x, map.height - 1)] == TileType::Wall);
+            };
+        };
+    }
+}
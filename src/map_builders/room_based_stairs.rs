@@ -16,7 +16,18 @@ impl RoomBasedStairs {
 
     fn build (&mut self, _rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
         if let Some(rooms) = &build_data.rooms {
-            let stairs_pos = rooms[rooms.len()-1].center();
+            /* The last room the player can actually walk to, if we know where they start */
+            let mut reachable = None;
+            if let Some(start) = &build_data.starting_position {
+                let start_idx = build_data.map.xy_idx(start.x, start.y);
+                build_data.map.populate_blocked();
+                let dijkstra_map = rltk::DijkstraMap::new(build_data.map.width as usize, build_data.map.height as usize, &[start_idx], &build_data.map, 1000.0);
+                reachable = Some(dijkstra_map);
+            }
+            let stairs_pos = rooms.iter().rev()
+                .map(|room| room.center())
+                .find(|pos| reachable.as_ref().is_none_or(|dijkstra_map| dijkstra_map.map[build_data.map.xy_idx(pos.0, pos.1)] < f32::MAX))
+                .unwrap_or_else(|| rooms[rooms.len()-1].center());
             let stairs_idx = build_data.map.xy_idx(stairs_pos.0, stairs_pos.1);
             build_data.map.tiles[stairs_idx] = TileType::DownStairs;
             build_data.take_snapshot();
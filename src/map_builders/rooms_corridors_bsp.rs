@@ -31,7 +31,11 @@ impl BspCorridors {
             let start_y = room.y1 + (rng.roll_dice(1, i32::abs(room.y1 - room.y2))-1);
             let end_x = next_room.x1 + (rng.roll_dice(1, i32::abs(next_room.x1 - next_room.x2))-1);
             let end_y = next_room.y1 + (rng.roll_dice(1, i32::abs(next_room.y1 - next_room.y2))-1);
-            let corridor = draw_corridor(&mut build_data.map, start_x, start_y, end_x, end_y);
+            /* A room's rectangle can include the wall at the edge of the map; keep off it */
+            let (width, height) = (build_data.map.width, build_data.map.height);
+            let clamp_x = |x: i32| i32::max(1, i32::min(width - 2, x));
+            let clamp_y = |y: i32| i32::max(1, i32::min(height - 2, y));
+            let corridor = draw_corridor(&mut build_data.map, clamp_x(start_x), clamp_y(start_y), clamp_x(end_x), clamp_y(end_y));
             corridors.push(corridor);
             build_data.take_snapshot();
         };
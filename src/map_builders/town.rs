@@ -1,16 +1,7 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 
-use super::{InitialMapBuilder, BuilderMap, BuilderChain, Position, TileType};
-
-enum BuildingTag {
-    Pub, Temple, Blacksmith, Clothier, Alchemist, PlayerHouse, Hovel, Abandoned, Unassigned
-}
-
-pub fn town_builder (new_depth:i32, _rng: &mut rltk::RandomNumberGenerator, width:i32, height:i32) -> BuilderChain {
-    let mut chain = BuilderChain::new(new_depth, width, height, "The Town of Shadoville");
-    chain.start_with(TownBuilder::new());
-    chain
-}
+use super::{InitialMapBuilder, BuilderMap, Position, TileType, seal_edges};
+use crate::{map::BuildingTag, Rect};
 
 pub struct TownBuilder {}
 
@@ -63,9 +54,9 @@ impl TownBuilder {
     }
 
     fn town_walls (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap)
-        -> (HashSet<usize>, i32)
+        -> (BTreeSet<usize>, i32)
     {
-        let mut available_building_tiles: HashSet<usize> = HashSet::new();
+        let mut available_building_tiles: BTreeSet<usize> = BTreeSet::new();
         let wall_gap_y = rng.roll_dice(1, build_data.height-9) + 5;
         for y in 1 .. build_data.map.height-2 {
             if !(y > wall_gap_y-4 && y < wall_gap_y+4) {
@@ -102,7 +93,7 @@ impl TownBuilder {
     }
 
     fn buildings (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap,
-        available_building_tiles: &mut HashSet<usize>) -> Vec<(i32, i32, i32, i32)>
+        available_building_tiles: &mut BTreeSet<usize>) -> Vec<(i32, i32, i32, i32)>
     {
         let mut buildings: Vec<(i32, i32, i32, i32)> = Vec::new();
         let mut n_buildings = 0;
@@ -246,7 +237,7 @@ impl TownBuilder {
         let player_idx = build_data.map.xy_idx(building.0 + (building.2 / 2), 
             building.1 + (building.3 / 2));
 
-        let mut to_place: Vec<&str> = vec!["Barkeep", "Shady Salesman", "Patron", "Patron", "Table", "Chair", "Table", "Chair"];
+        let mut to_place: Vec<&str> = vec!["Barkeep", "Shady Salesman", "Sellsword", "Patron", "Patron", "Table", "Chair", "Table", "Chair"];
         self.random_building_spawn(building, build_data, rng, &mut to_place, player_idx);
     }
 
@@ -324,7 +315,7 @@ impl TownBuilder {
     }
 
     fn spawn_townsfolk (&mut self, build_data: &mut BuilderMap, rng: &mut rltk::RandomNumberGenerator,
-        available_building_tiles: &mut HashSet<usize>)
+        available_building_tiles: &mut BTreeSet<usize>)
     {
         for idx in available_building_tiles.iter() {
             if rng.roll_dice(1, 10) == 1 {
@@ -360,6 +351,7 @@ impl TownBuilder {
     {
         for (i, building) in buildings.iter().enumerate() {
             let build_type = &building_index[i].2;
+            build_data.map.buildings.push((*build_type, Rect::new(building.0, building.1, building.2, building.3)));
             match build_type {
                 BuildingTag::Pub => self.build_pub(&building, build_data, rng),
                 BuildingTag::Temple => self.build_temple(&building, build_data, rng),
@@ -378,11 +370,12 @@ impl TownBuilder {
         self.grass_layer(build_data);
         self.water_and_piers(rng, build_data);
         let (mut available_building_tiles, wall_gap_y) = self.town_walls(rng, build_data);
+        seal_edges(&mut build_data.map);
         let mut buildings = self.buildings(rng, build_data, &mut available_building_tiles);
         let doors = self.add_doors(rng, build_data, &mut buildings, wall_gap_y);
         self.add_paths(build_data, &doors);
 
-        for y in wall_gap_y-3 .. wall_gap_y + 4 {
+        for y in i32::max(1, wall_gap_y-3) .. i32::min(build_data.height-1, wall_gap_y + 4) {
             let exit_idx = build_data.map.xy_idx(build_data.width-2, y);
             build_data.map.tiles[exit_idx] = TileType::DownStairs;
         };
@@ -27,6 +27,20 @@ pub fn apply_vertical_tunnel (map : &mut Map, y1:i32, y2:i32, x:i32) -> Vec<usiz
     corridor
 }
 
+/// Walls in the outermost ring of the map, so nothing can walk off it
+pub fn seal_edges (map: &mut Map) {
+    for x in 0 .. map.width {
+        let (top, bottom) = (map.xy_idx(x, 0), map.xy_idx(x, map.height - 1));
+        map.tiles[top] = TileType::Wall;
+        map.tiles[bottom] = TileType::Wall;
+    };
+    for y in 0 .. map.height {
+        let (left, right) = (map.xy_idx(0, y), map.xy_idx(map.width - 1, y));
+        map.tiles[left] = TileType::Wall;
+        map.tiles[right] = TileType::Wall;
+    };
+}
+
 #[allow(dead_code)]
 #[derive(PartialEq, Copy, Clone)]
 pub enum Symmetry { None, Horizontal, Vertical, Both }
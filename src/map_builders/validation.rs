@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+use std::fmt;
+use super::{MetaMapBuilder, BuilderMap, TileType};
+use crate::map::{Map, tile_walkable};
+
+/// Something wrong with a finished map that would trip up (or strand) the player
+#[derive(PartialEq, Clone, Debug)]
+pub enum MapProblem {
+    NoStart,
+    StartNotWalkable { x: i32, y: i32 },
+    NoDownStairs,
+    StairsUnreachable { x: i32, y: i32 },
+    SpawnOutOfBounds { idx: usize, name: String },
+    SpawnNotWalkable { x: i32, y: i32, name: String },
+    EdgeNotSolid { x: i32, y: i32 },
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapProblem::NoStart => write!(f, "no starting position"),
+            MapProblem::StartNotWalkable { x, y } => write!(f, "the start ({}, {}) isn't walkable", x, y),
+            MapProblem::NoDownStairs => write!(f, "no down stairs"),
+            MapProblem::StairsUnreachable { x, y } => write!(f, "the down stairs ({}, {}) can't be reached from the start", x, y),
+            MapProblem::SpawnOutOfBounds { idx, name } => write!(f, "{} spawns off the map (tile {})", name, idx),
+            MapProblem::SpawnNotWalkable { x, y, name } => write!(f, "{} spawns in a wall at ({}, {})", name, x, y),
+            MapProblem::EdgeNotSolid { x, y } => write!(f, "the map edge is open at ({}, {})", x, y),
+        }
+    }
+}
+
+/// Every tile that can be walked to from the given one, going the way the map's own
+/// pathing does (any of eight directions, never onto the outermost ring)
+pub(crate) fn reachable_from (map: &Map, start_idx: usize) -> Vec<bool> {
+    let mut reached = vec![false; map.tiles.len()];
+    let mut open = VecDeque::new();
+    reached[start_idx] = true;
+    open.push_back(start_idx);
+    while let Some(idx) = open.pop_front() {
+        let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 { continue; }
+            let next = map.xy_idx(nx, ny);
+            if !reached[next] && tile_walkable(map.tiles[next]) {
+                reached[next] = true;
+                open.push_back(next);
+            }
+        };
+    };
+    reached
+}
+
+/// Checks a finished map: a walkable start, down stairs that can be reached from it, every
+/// spawn on a walkable tile of the map, and nothing walkable around the edge
+pub fn validate_map (build_data: &BuilderMap) -> Vec<MapProblem> {
+    let map = &build_data.map;
+    let mut problems = Vec::new();
+
+    for y in 0 .. map.height {
+        for x in 0 .. map.width {
+            if (x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1) && tile_walkable(map.tiles[map.xy_idx(x, y)]) {
+                problems.push(MapProblem::EdgeNotSolid { x, y });
+            }
+        };
+    };
+
+    for (idx, name) in build_data.spawn_list.iter() {
+        if *idx >= map.tiles.len() {
+            problems.push(MapProblem::SpawnOutOfBounds { idx: *idx, name: name.clone() });
+        } else if !tile_walkable(map.tiles[*idx]) {
+            problems.push(MapProblem::SpawnNotWalkable { x: *idx as i32 % map.width, y: *idx as i32 / map.width, name: name.clone() });
+        }
+    };
+
+    let start = match &build_data.starting_position {
+        Some(start) if start.x >= 0 && start.x < map.width && start.y >= 0 && start.y < map.height => start,
+        _ => {
+            problems.push(MapProblem::NoStart);
+            return problems;
+        }
+    };
+    let start_idx = map.xy_idx(start.x, start.y);
+    if !tile_walkable(map.tiles[start_idx]) {
+        problems.push(MapProblem::StartNotWalkable { x: start.x, y: start.y });
+    }
+
+    let stairs: Vec<usize> = map.tiles.iter().enumerate()
+        .filter(|(_idx, tt)| **tt == TileType::DownStairs)
+        .map(|(idx, _tt)| idx)
+        .collect();
+    if stairs.is_empty() {
+        problems.push(MapProblem::NoDownStairs);
+    } else {
+        let reached = reachable_from(map, start_idx);
+        for idx in stairs.iter().filter(|idx| !reached[**idx]) {
+            problems.push(MapProblem::StairsUnreachable { x: *idx as i32 % map.width, y: *idx as i32 / map.width });
+        };
+    }
+    problems
+}
+
+/// Runs `validate_map` over the finished map, noting what it finds in `build_data.problems`
+/// (and the log) for whoever asked for the map to decide what to do about it
+pub struct MapValidator {}
+
+impl MetaMapBuilder for MapValidator {
+    fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        self.build(rng, build_data);
+    }
+}
+
+impl MapValidator {
+    #[allow(dead_code)]
+    pub fn new () -> Box<MapValidator> {
+        Box::new(MapValidator{})
+    }
+
+    fn build (&mut self, _rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
+        build_data.problems = validate_map(build_data);
+        for problem in build_data.problems.iter() {
+            rltk::console::log(format!("Warning: {} (depth {}): {}", build_data.map.name, build_data.map.depth, problem));
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::{BuilderChain, named_builder};
+    use crate::map::{MasterDungeonMap, LevelId, MAIN_BRANCH, build_level_with_lair};
+    use crate::raws::{RAWS, get_builder_chain_names, get_branch_levels, get_unique_lairs};
+    use crate::components::Position;
+    use crate::test_support::lock_globals;
+
+    /* A quick look by default; MAPGEN_TEST_SEEDS=200 (say) for a proper search */
+    const DEFAULT_SEEDS: u64 = 10;
+    const DEEPEST: i32 = 12;
+
+    fn seeds () -> u64 {
+        std::env::var("MAPGEN_TEST_SEEDS").ok().and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_SEEDS)
+    }
+
+    fn describe (what: &str, level: &LevelId, seed: u64, problems: &[MapProblem]) -> Vec<String> {
+        problems.iter().map(|problem| format!("{} at {}, seed {}: {}", what, level, seed, problem)).collect()
+    }
+
+    #[test]
+    fn every_level_is_valid () {
+        let _globals = lock_globals();
+        let mut levels: Vec<LevelId> = (1 ..= DEEPEST).map(LevelId::main).collect();
+        levels.extend(get_branch_levels(&RAWS.lock().unwrap()));
+        let mut failures = Vec::new();
+        for level in levels.iter() {
+            /* Built as the game builds them, retries and all, with no lair and with each one
+               the level could get */
+            let lairs: Vec<(String, Vec<String>)> = if level.branch != MAIN_BRANCH { Vec::new() } else {
+                get_unique_lairs(&RAWS.lock().unwrap(), level.depth).into_iter()
+                    .map(|(unique, lair, _levels_left)| (unique, lair))
+                    .collect()
+            };
+            for seed in 0 .. seeds() {
+                let dungeon = MasterDungeonMap::new(seed);
+                for lair in std::iter::once(None).chain(lairs.iter().map(Some)) {
+                    let chain = build_level_with_lair(&dungeon, level, "level", None, false, None, lair);
+                    let what = lair.map_or("level".to_string(), |(unique, _lair)| format!("level with {}'s lair", unique));
+                    failures.extend(describe(&what, level, seed, &chain.build_data.problems));
+                };
+            };
+        };
+        assert!(failures.is_empty(), "{} problems:\n{}", failures.len(), failures.join("\n"));
+    }
+
+    #[test]
+    fn every_chain_is_valid () {
+        let _globals = lock_globals();
+        let names = get_builder_chain_names(&RAWS.lock().unwrap());
+        let mut failures = Vec::new();
+        for name in names.iter() {
+            for seed in 0 .. seeds() {
+                let level = LevelId::main(1 + (seed as i32 % DEEPEST));
+                let mut rng = MasterDungeonMap::new(seed).mapgen_rng(&level);
+                /* Chains that only dress up someone else's map can't be built on their own */
+                let mut chain = match named_builder(name, &level, &mut rng, None) {
+                    Some(chain) => chain,
+                    None => break,
+                };
+                chain.with(MapValidator::new());
+                chain.build_map(&mut rng);
+                /* Pieces of a level (rooms without a start, say) still have to be sound */
+                let problems: Vec<MapProblem> = chain.build_data.problems.iter()
+                    .filter(|problem| **problem != MapProblem::NoStart)
+                    .cloned()
+                    .collect();
+                failures.extend(describe(name, &level, seed, &problems));
+            };
+        };
+        assert!(failures.is_empty(), "{} problems:\n{}", failures.len(), failures.join("\n"));
+    }
+
+    #[test]
+    fn broken_maps_are_caught () {
+        let _globals = lock_globals();
+        let mut chain = BuilderChain::new(1, 20, 10, "Broken");
+        let map = &mut chain.build_data.map;
+        for y in 1 .. 9 {
+            for x in 1 .. 8 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            };
+        };
+        let (stairs, hole, wall) = (map.xy_idx(15, 5), map.xy_idx(0, 3), map.xy_idx(12, 2));
+        map.tiles[stairs] = TileType::DownStairs;
+        map.tiles[hole] = TileType::Floor;
+        chain.build_data.spawn_list.push((wall, "Goblin".to_string()));
+        chain.build_data.spawn_list.push((500, "Orc".to_string()));
+
+        assert_eq!(validate_map(&chain.build_data).first(), Some(&MapProblem::EdgeNotSolid { x: 0, y: 3 }));
+        assert!(validate_map(&chain.build_data).contains(&MapProblem::NoStart));
+
+        chain.build_data.starting_position = Some(Position { x: 12, y: 2 });
+        let problems = validate_map(&chain.build_data);
+        assert!(problems.contains(&MapProblem::SpawnNotWalkable { x: 12, y: 2, name: "Goblin".to_string() }));
+        assert!(problems.contains(&MapProblem::SpawnOutOfBounds { idx: 500, name: "Orc".to_string() }));
+        assert!(problems.contains(&MapProblem::StartNotWalkable { x: 12, y: 2 }));
+        assert!(problems.contains(&MapProblem::StairsUnreachable { x: 15, y: 5 }));
+
+        chain.build_data.starting_position = Some(Position { x: 4, y: 4 });
+        let problems = validate_map(&chain.build_data);
+        assert!(!problems.contains(&MapProblem::StartNotWalkable { x: 4, y: 4 }));
+        assert!(problems.contains(&MapProblem::StairsUnreachable { x: 15, y: 5 }));
+        let map = &mut chain.build_data.map;
+        map.tiles[stairs] = TileType::Wall;
+        assert!(validate_map(&chain.build_data).contains(&MapProblem::NoDownStairs));
+    }
+}
@@ -1,5 +1,5 @@
 use super::{BuilderMap, MetaMapBuilder, TileType, spawner};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 pub struct VoronoiSpawning {}
 
@@ -16,7 +16,8 @@ impl VoronoiSpawning {
     }
 
     fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
-        let mut noise_areas: HashMap<i32, Vec<usize>> = HashMap::new();
+        /* Ordered, so the same seed always spawns the same things */
+        let mut noise_areas: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
         let mut noise = rltk::FastNoise::seeded(rng.roll_dice(1, 65536) as u64);
         noise.set_noise_type(rltk::NoiseType::Cellular);
         noise.set_frequency(0.08);
@@ -28,11 +29,7 @@ impl VoronoiSpawning {
                 if build_data.map.tiles[idx] == TileType::Floor {
                     let cell_value_f = noise.get_noise(x as f32, y as f32) * 10240.0;
                     let cell_value = cell_value_f as i32;
-                    if noise_areas.contains_key(&cell_value) {
-                        noise_areas.get_mut(&cell_value).unwrap().push(idx);
-                    } else {
-                        noise_areas.insert(cell_value, vec![idx]);
-                    }
+                    noise_areas.entry(cell_value).or_default().push(idx);
                 }
             };
         };
@@ -1,7 +1,8 @@
 use super::{TileType, Map, MapChunk, tile_idx_in_chunk};
-use std::collections::HashSet;
+use std::collections::HashMap;
 
-pub fn build_patterns (map: &Map, chunk_size:i32, include_flipping: bool, dedupe: bool) -> Vec<Vec<TileType>> {
+/// Cuts the map into chunks (and their mirror images), each with how often it came up
+pub fn build_patterns (map: &Map, chunk_size:i32, include_flipping: bool, dedupe: bool) -> Vec<(Vec<TileType>, i32)> {
     let chunks_x = map.width / chunk_size;
     let chunks_y = map.height / chunk_size;
     let mut patterns = Vec::new();
@@ -54,14 +55,51 @@ pub fn build_patterns (map: &Map, chunk_size:i32, include_flipping: bool, dedupe
             }
         };
     };
-    /* Dedupe */
-    if dedupe {
-        rltk::console::log(format!("Pre de-duplication, there are {} patterns", patterns.len()));
-        let set: HashSet<Vec<TileType>> = patterns.drain(..).collect();
-        patterns.extend(set.into_iter());
-        rltk::console::log(format!("There are {} patterns", patterns.len()));
+    count_patterns(patterns, dedupe)
+}
+
+/// Every pattern_size square of the map (and its mirror images), each with how often it came up
+pub fn build_overlapping_patterns (map: &Map, pattern_size:i32, include_flipping: bool) -> Vec<(Vec<TileType>, i32)> {
+    let mut patterns = Vec::new();
+    for start_y in 0 ..= map.height - pattern_size {
+        for start_x in 0 ..= map.width - pattern_size {
+            let flips: &[(bool, bool)] = if include_flipping { &[(false, false), (true, false), (false, true), (true, true)] }
+                else { &[(false, false)] };
+            for (flip_x, flip_y) in flips.iter() {
+                let mut pattern = Vec::new();
+                for y in 0 .. pattern_size {
+                    for x in 0 .. pattern_size {
+                        let x = if *flip_x { pattern_size - (x+1) } else { x };
+                        let y = if *flip_y { pattern_size - (y+1) } else { y };
+                        pattern.push(map.tiles[map.xy_idx(start_x + x, start_y + y)]);
+                    };
+                };
+                patterns.push(pattern);
+            };
+        };
+    };
+    count_patterns(patterns, true)
+}
+
+fn count_patterns (patterns: Vec<Vec<TileType>>, dedupe: bool) -> Vec<(Vec<TileType>, i32)> {
+    if !dedupe {
+        return patterns.into_iter().map(|pattern| (pattern, 1)).collect();
     }
-    patterns
+    rltk::console::log(format!("Pre de-duplication, there are {} patterns", patterns.len()));
+    /* Keeps the first of each, so the patterns come out in the same order every run */
+    let mut counted: Vec<(Vec<TileType>, i32)> = Vec::new();
+    let mut seen: HashMap<Vec<TileType>, usize> = HashMap::new();
+    for pattern in patterns {
+        match seen.get(&pattern) {
+            Some(i) => counted[*i].1 += 1,
+            None => {
+                seen.insert(pattern.clone(), counted.len());
+                counted.push((pattern, 1));
+            }
+        }
+    };
+    rltk::console::log(format!("There are {} patterns", counted.len()));
+    counted
 }
 
 pub fn render_pattern_to_map (map: &mut Map, chunk: &MapChunk, chunk_size:i32, start_x:i32, start_y:i32) {
@@ -101,12 +139,13 @@ pub fn render_pattern_to_map (map: &mut Map, chunk: &MapChunk, chunk_size:i32, s
     };
 }
 
-pub fn patterns_to_constraints (patterns: Vec<Vec<TileType>>, chunk_size:i32) -> Vec<MapChunk> {
+pub fn patterns_to_constraints (patterns: Vec<(Vec<TileType>, i32)>, chunk_size:i32) -> Vec<MapChunk> {
     /* Move into new constraints object */
     let mut constraints : Vec<MapChunk> = Vec::new();
-    for p in patterns {
+    for (p, frequency) in patterns {
         let mut new_chunk = MapChunk {
             pattern: p,
+            frequency,
             exits: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
             has_exits: true,
             compatible_with: [Vec::new(), Vec::new(), Vec::new(), Vec::new()]
@@ -146,6 +185,8 @@ pub fn patterns_to_constraints (patterns: Vec<Vec<TileType>>, chunk_size:i32) ->
         };
         if n_exits == 0 {
             new_chunk.has_exits = false;
+            /* Solid chunks fit beside anything, so weighting them by how much of the sample is rock would bury the map */
+            new_chunk.frequency = 1;
         }
         constraints.push(new_chunk);
     };
@@ -195,3 +236,34 @@ pub fn patterns_to_constraints (patterns: Vec<Vec<TileType>>, chunk_size:i32) ->
     };
     constraints
 }
+
+/// Overlapping patterns fit side by side when they agree wherever they overlap, one tile along
+pub fn overlapping_constraints (patterns: Vec<(Vec<TileType>, i32)>, pattern_size:i32) -> Vec<MapChunk> {
+    let agrees = |a: &[TileType], b: &[TileType], dx: i32, dy: i32| {
+        for y in i32::max(0, dy) .. i32::min(pattern_size, pattern_size + dy) {
+            for x in i32::max(0, dx) .. i32::min(pattern_size, pattern_size + dx) {
+                if a[tile_idx_in_chunk(pattern_size, x, y)] != b[tile_idx_in_chunk(pattern_size, x - dx, y - dy)] { return false; }
+            };
+        };
+        true
+    };
+
+    let mut constraints: Vec<MapChunk> = patterns.into_iter().map(|(pattern, frequency)| MapChunk {
+        pattern,
+        frequency,
+        exits: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+        has_exits: false,
+        compatible_with: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+    }).collect();
+    for i in 0 .. constraints.len() {
+        for j in 0 .. constraints.len() {
+            /* North, South, West, East, as with chunks */
+            for (dir, (dx, dy)) in [(0, -1), (0, 1), (-1, 0), (1, 0)].iter().enumerate() {
+                if agrees(&constraints[i].pattern, &constraints[j].pattern, *dx, *dy) {
+                    constraints[i].compatible_with[dir].push(j);
+                }
+            };
+        };
+    };
+    constraints
+}
@@ -1,4 +1,6 @@
-use super::{MetaMapBuilder, Map, TileType, BuilderMap};
+use super::{MetaMapBuilder, Map, TileType, BuilderMap, Rect, seal_edges};
+use crate::map::tile_walkable;
+use crate::raws::{Prefab, LegendEntry};
 
 mod utils;
 use utils::*;
@@ -7,7 +9,40 @@ use constraints::*;
 mod solver;
 use solver::*;
 
-pub struct WaveformCollapseBuilder {}
+/// How the sample is cut up: into chunks laid side by side, or into small squares taken at
+/// every tile that overlap their neighbours (slower, but follows the sample far more closely)
+#[derive(PartialEq, Copy, Clone)]
+#[allow(dead_code)]
+pub enum WaveformModel { Tiled { chunk_size: i32 }, Overlapping { pattern_size: i32 } }
+
+/// Where the patterns come from. A prefab is only drawn out when the builder runs, since
+/// making a map resizes the spatial index under whatever map is being built.
+#[allow(dead_code)]
+pub enum WaveformSample { PreviousMap, Map(Box<Map>), Prefab(Box<Prefab>) }
+
+/// A tile that has to come out as it is. The patterns around it are made to agree on
+/// whether it can be walked on, then it's drawn over the result.
+#[derive(Clone)]
+#[allow(dead_code)]
+pub enum WaveformPin {
+    /* Wherever the previous map had stairs */
+    Stairs,
+    /* Wherever the previous map had the player start */
+    Start,
+    Tile { idx: usize, tile: TileType },
+    /* A patch of floor kept for a vault, say */
+    Area { rect: Rect, tile: TileType },
+}
+
+pub struct WaveformSettings {
+    pub model: WaveformModel,
+    pub sample: WaveformSample,
+    pub pins: Vec<WaveformPin>,
+}
+
+pub struct WaveformCollapseBuilder {
+    pub settings: WaveformSettings,
+}
 
 impl MetaMapBuilder for WaveformCollapseBuilder {
     fn build_map (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
@@ -18,26 +53,139 @@ impl MetaMapBuilder for WaveformCollapseBuilder {
 impl WaveformCollapseBuilder {
     #[allow(dead_code)]
     pub fn new () -> Box<WaveformCollapseBuilder> {
-        Box::new(WaveformCollapseBuilder{})
+        WaveformCollapseBuilder::with_settings(WaveformSettings {
+            model: WaveformModel::Tiled { chunk_size: 8 },
+            sample: WaveformSample::PreviousMap,
+            pins: Vec::new(),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn overlapping () -> Box<WaveformCollapseBuilder> {
+        WaveformCollapseBuilder::with_settings(WaveformSettings {
+            model: WaveformModel::Overlapping { pattern_size: 3 },
+            sample: WaveformSample::PreviousMap,
+            pins: Vec::new(),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_settings (settings: WaveformSettings) -> Box<WaveformCollapseBuilder> {
+        Box::new(WaveformCollapseBuilder { settings })
+    }
+
+    /// A sample map drawn from a prefab; anything that isn't a tile is taken as floor
+    fn prefab_sample (prefab: &Prefab) -> Map {
+        let mut sample = Map::new(0, prefab.width as i32, prefab.height as i32, &prefab.name);
+        for (tile, glyph) in sample.tiles.iter_mut().zip(prefab.glyphs.iter()) {
+            *tile = match prefab.meaning(*glyph) {
+                Some(LegendEntry::Tile(tt)) => tt,
+                _ => TileType::Floor,
+            };
+        };
+        sample
+    }
+
+    /// The tiles the pins ask for, read off the map as it was before it's replaced
+    fn pinned_tiles (&self, build_data: &BuilderMap) -> Vec<(usize, TileType)> {
+        let map = &build_data.map;
+        let mut pinned = Vec::new();
+        for pin in self.settings.pins.iter() {
+            match pin {
+                WaveformPin::Stairs => pinned.extend(map.tiles.iter().enumerate()
+                    .filter(|(_idx, tt)| **tt == TileType::DownStairs)
+                    .map(|(idx, tt)| (idx, *tt))),
+                WaveformPin::Start => if let Some(start) = &build_data.starting_position {
+                    pinned.push((map.xy_idx(start.x, start.y), TileType::Floor));
+                },
+                WaveformPin::Tile { idx, tile } => pinned.push((*idx, *tile)),
+                WaveformPin::Area { rect, tile } => for y in rect.y1 .. rect.y2 {
+                    for x in rect.x1 .. rect.x2 {
+                        pinned.push((map.xy_idx(x, y), *tile));
+                    };
+                },
+            }
+        };
+        /* Nothing on the edge, which gets sealed anyway */
+        pinned.retain(|(idx, _tile)| {
+            let (x, y) = (*idx as i32 % map.width, *idx as i32 / map.width);
+            *idx < map.tiles.len() && x > 0 && y > 0 && x < map.width - 1 && y < map.height - 1
+        });
+        pinned
     }
 
     fn build (&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
-        const CHUNK_SIZE : i32 = 8;
+        const MAX_ATTEMPTS : i32 = 10;
+        let depth = build_data.map.depth;
+        let theme = build_data.map.theme;
+        let branch = build_data.map.branch.clone();
         build_data.take_snapshot();
 
-        let patterns = build_patterns(&build_data.map, CHUNK_SIZE, true, true);
-        let constraints = patterns_to_constraints(patterns, CHUNK_SIZE);
-        self.render_tile_gallery(&constraints, CHUNK_SIZE, build_data);
+        let mut pinned = self.pinned_tiles(build_data);
+        let prefab_sample;
+        let sample = match &self.settings.sample {
+            WaveformSample::PreviousMap => &build_data.map,
+            WaveformSample::Map(map) => &**map,
+            WaveformSample::Prefab(prefab) => {
+                prefab_sample = WaveformCollapseBuilder::prefab_sample(prefab);
+                &prefab_sample
+            }
+        };
+        let (constraints, pattern_size, stride) = match self.settings.model {
+            WaveformModel::Tiled { chunk_size } => {
+                let patterns = build_patterns(sample, chunk_size, true, true);
+                (patterns_to_constraints(patterns, chunk_size), chunk_size, chunk_size)
+            }
+            WaveformModel::Overlapping { pattern_size } => {
+                let patterns = build_overlapping_patterns(sample, pattern_size, true);
+                (overlapping_constraints(patterns, pattern_size), pattern_size, 1)
+            }
+        };
+        self.render_tile_gallery(&constraints, pattern_size, build_data);
 
-        build_data.map = Map::new(build_data.map.depth, build_data.width, build_data.height, &build_data.map.name);
+        let mut attempts = 0;
         loop {
-            let mut solver = Solver::new(constraints.clone(), CHUNK_SIZE, &build_data.map);
+            build_data.map = Map::new(depth, build_data.width, build_data.height, &build_data.map.name);
+            let mut solver = Solver::new(constraints.clone(), pattern_size, stride, &build_data.map);
+            for (idx, tile) in pinned.iter() {
+                let (x, y) = (*idx as i32 % build_data.map.width, *idx as i32 / build_data.map.width);
+                for (cell_x, cell_y, offset) in solver.cells_covering(x, y) {
+                    solver.restrict(cell_x, cell_y, |chunk| tile_walkable(chunk.pattern[offset]) == tile_walkable(*tile));
+                };
+            };
+            if !solver.possible && !pinned.is_empty() {
+                rltk::console::log("Warning: waveform collapse can't keep its pinned tiles with these patterns, so it's dropping them");
+                pinned.clear();
+                continue;
+            }
+
+            /* Overlapping patterns take a pick per tile; don't keep a picture of every one */
+            let snapshot_every = if stride == 1 { build_data.map.width } else { 1 };
+            let mut iterations = 0;
             while !solver.iteration(&mut build_data.map, rng) {
-                build_data.take_snapshot();
+                iterations += 1;
+                if iterations % snapshot_every == 0 && build_data.record_history {
+                    solver.render(&mut build_data.map);
+                    build_data.take_snapshot();
+                }
             };
             build_data.take_snapshot();
-            if solver.possible { break; } /* If hit a impossible condition: try again */
+            attempts += 1;
+            /* If hit a impossible condition, or came out nearly solid rock: try again */
+            let floors = build_data.map.tiles.iter().filter(|tt| **tt == TileType::Floor).count();
+            if (solver.possible && floors * 10 >= build_data.map.tiles.len()) || attempts >= MAX_ATTEMPTS { break; }
+        };
+        /* Stairs copied over from the old map lead nowhere, and chunks can leave the edge open */
+        for tt in build_data.map.tiles.iter_mut() {
+            if *tt == TileType::DownStairs || *tt == TileType::UpStairs { *tt = TileType::Floor; }
         };
+        for (idx, tile) in pinned.iter() {
+            build_data.map.tiles[*idx] = *tile;
+        };
+        seal_edges(&mut build_data.map);
+        build_data.map.theme = theme;
+        build_data.map.branch = branch;
+        build_data.take_snapshot();
         build_data.spawn_list.clear();
     }
 
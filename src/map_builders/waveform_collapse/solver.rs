@@ -1,214 +1,397 @@
-use super::{MapChunk, Map};
-use std::collections::HashSet;
+use super::{MapChunk, Map, TileType};
 
+/* Gives up on a map that keeps painting itself into corners */
+const MAX_BACKTRACKS : i32 = 1000;
+
+/* North, South, West, East: the order of MapChunk::compatible_with */
+const DIRECTIONS : [(i32, i32); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+
+fn opposite (dir: usize) -> usize {
+    match dir {
+        0 => 1,
+        1 => 0,
+        2 => 3,
+        _ => 2,
+    }
+}
+
+/// A pattern picked for a cell, and how far back to undo if it leads nowhere
+struct Decision {
+    cell: usize,
+    pattern: usize,
+    trail_len: usize,
+}
+
+/// Fills a grid of cells with patterns that fit their neighbours, lowest entropy cell first, with
+/// common patterns picked more often. Cells sit `stride` tiles apart: a chunk apart for tiled
+/// patterns, one tile apart for overlapping ones. A pick that leaves some cell with nothing
+/// is undone and struck off, rather than the whole map being thrown away.
 pub struct Solver {
     constraints: Vec<MapChunk>,
-    chunk_size: i32,
-    chunks: Vec<Option<usize>>,
-    chunks_x: usize,
-    chunks_y: usize,
-    remaining: Vec<(usize, i32)>,
+    pattern_size: i32,
+    stride: i32,
+    cells_x: usize,
+    cells_y: usize,
+    /* Which patterns fit in direction d of each pattern */
+    propagator: Vec<[Vec<usize>; 4]>,
+    /* Per cell and pattern: still possible? */
+    wave: Vec<bool>,
+    weights: Vec<f64>,
+    weight_logs: Vec<f64>,
+    remaining: Vec<usize>,
+    sum_weights: Vec<f64>,
+    sum_weight_logs: Vec<f64>,
+    entropies: Vec<f64>,
+    /* Per direction, then cell and pattern: how many patterns the neighbour that way could still have beside it */
+    supports: [Vec<i32>; 4],
+    trail: Vec<(usize, usize)>,
+    decisions: Vec<Decision>,
+    pending: Vec<(usize, usize)>,
+    contradiction: bool,
+    pub backtracks: i32,
     pub possible: bool,
 }
 
 impl Solver {
-    pub fn new (constraints: Vec<MapChunk>, chunk_size:i32, map: &Map) -> Solver {
-        let chunks_x = (map.width / chunk_size) as usize;
-        let chunks_y = (map.height / chunk_size) as usize;
-        let mut remaining : Vec<(usize, i32)> = Vec::new();
-        for i in 0 .. (chunks_x*chunks_y) {
-            remaining.push((i, 0));
+    pub fn new (constraints: Vec<MapChunk>, pattern_size: i32, stride: i32, map: &Map) -> Solver {
+        let cells_x = if map.width < pattern_size { 0 } else { ((map.width - pattern_size) / stride + 1) as usize };
+        let cells_y = if map.height < pattern_size { 0 } else { ((map.height - pattern_size) / stride + 1) as usize };
+        let n_patterns = constraints.len();
+
+        /* Chunks are placed beside whichever neighbour came first, so either one accepting the other will do */
+        let mut fits = vec![[false; 4]; n_patterns * n_patterns];
+        for (p, chunk) in constraints.iter().enumerate() {
+            for (dir, compatible) in chunk.compatible_with.iter().enumerate() {
+                for q in compatible.iter() {
+                    fits[p * n_patterns + q][dir] = true;
+                    fits[q * n_patterns + p][opposite(dir)] = true;
+                };
+            };
+        };
+        let mut propagator: Vec<[Vec<usize>; 4]> = vec![[Vec::new(), Vec::new(), Vec::new(), Vec::new()]; n_patterns];
+        for (p, compatible) in propagator.iter_mut().enumerate() {
+            for q in 0 .. n_patterns {
+                for (dir, list) in compatible.iter_mut().enumerate() {
+                    if fits[p * n_patterns + q][dir] { list.push(q); }
+                };
+            };
+        };
+
+        let weights: Vec<f64> = constraints.iter().map(|c| f64::from(i32::max(1, c.frequency))).collect();
+        let weight_logs: Vec<f64> = weights.iter().map(|w| w * w.ln()).collect();
+        let sum_weights: f64 = weights.iter().sum();
+        let sum_weight_logs: f64 = weight_logs.iter().sum();
+        let mut supports = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        for (dir, support) in supports.iter_mut().enumerate() {
+            let counts: Vec<i32> = propagator.iter().map(|compatible| compatible[dir].len() as i32).collect();
+            for _cell in 0 .. cells_x * cells_y {
+                support.extend_from_slice(&counts);
+            };
         };
 
-        Solver {
+        let mut solver = Solver {
             constraints,
-            chunk_size,
-            chunks: vec![None; chunks_x*chunks_y],
-            chunks_x,
-            chunks_y,
-            remaining,
+            pattern_size,
+            stride,
+            cells_x,
+            cells_y,
+            propagator,
+            wave: vec![true; cells_x * cells_y * n_patterns],
+            weights,
+            weight_logs,
+            remaining: vec![n_patterns; cells_x * cells_y],
+            sum_weights: vec![sum_weights; cells_x * cells_y],
+            sum_weight_logs: vec![sum_weight_logs; cells_x * cells_y],
+            entropies: vec![sum_weights.ln() - sum_weight_logs / sum_weights; cells_x * cells_y],
+            supports,
+            trail: Vec::new(),
+            decisions: Vec::new(),
+            pending: Vec::new(),
+            contradiction: n_patterns == 0,
+            backtracks: 0,
             possible: true,
-        }
+        };
+
+        /* Patterns that nothing can sit beside can't go anywhere with a neighbour on that side */
+        for cell in 0 .. cells_x * cells_y {
+            for p in 0 .. n_patterns {
+                for dir in 0 .. 4 {
+                    if solver.supports[dir][cell * n_patterns + p] == 0 && solver.neighbor(cell, dir).is_some() && solver.wave[cell * n_patterns + p] {
+                        solver.ban(cell, p);
+                    }
+                };
+            };
+        };
+        solver
     }
 
-    fn chunk_idx (&self, x:usize, y:usize) -> usize {
-        ((y * self.chunks_x) + x) as usize
+    fn neighbor (&self, cell: usize, dir: usize) -> Option<usize> {
+        let x = (cell % self.cells_x) as i32 + DIRECTIONS[dir].0;
+        let y = (cell / self.cells_x) as i32 + DIRECTIONS[dir].1;
+        if x < 0 || y < 0 || x >= self.cells_x as i32 || y >= self.cells_y as i32 { return None; }
+        Some(y as usize * self.cells_x + x as usize)
     }
 
-    fn count_neighbors (&self, chunk_x:usize, chunk_y:usize) -> i32 {
-        let mut neighbors = 0;
-        if chunk_x > 0 {
-            let left_idx = self.chunk_idx(chunk_x-1, chunk_y);
-            match self.chunks[left_idx] {
-                None => {},
-                Some(_) => {
-                    neighbors += 1;
-                },
+    /// Rules a pattern out of a cell, noting anything that no longer has a neighbour to fit beside
+    fn ban (&mut self, cell: usize, pattern: usize) {
+        let n_patterns = self.constraints.len();
+        self.wave[cell * n_patterns + pattern] = false;
+        self.remaining[cell] -= 1;
+        self.sum_weights[cell] -= self.weights[pattern];
+        self.sum_weight_logs[cell] -= self.weight_logs[pattern];
+        self.entropies[cell] = self.sum_weights[cell].ln() - self.sum_weight_logs[cell] / self.sum_weights[cell];
+        self.trail.push((cell, pattern));
+        if self.remaining[cell] == 0 { self.contradiction = true; }
+
+        for dir in 0 .. 4 {
+            if let Some(neighbor) = self.neighbor(cell, dir) {
+                let supports = &mut self.supports[opposite(dir)][neighbor * n_patterns .. (neighbor+1) * n_patterns];
+                let wave = &self.wave[neighbor * n_patterns .. (neighbor+1) * n_patterns];
+                for q in self.propagator[pattern][dir].iter() {
+                    supports[*q] -= 1;
+                    if supports[*q] == 0 && wave[*q] {
+                        self.pending.push((neighbor, *q));
+                    }
+                };
             }
-        }
-        if chunk_x < self.chunks_x-1 {
-            let right_idx = self.chunk_idx(chunk_x+1, chunk_y);
-            match self.chunks[right_idx] {
-                None => {},
-                Some(_) => {
-                    neighbors += 1;
-                },
+        };
+    }
+
+    /// Puts back everything ruled out since the trail was this long
+    fn undo (&mut self, trail_len: usize) {
+        let n_patterns = self.constraints.len();
+        while self.trail.len() > trail_len {
+            let (cell, pattern) = self.trail.pop().unwrap();
+            self.wave[cell * n_patterns + pattern] = true;
+            self.remaining[cell] += 1;
+            self.sum_weights[cell] += self.weights[pattern];
+            self.sum_weight_logs[cell] += self.weight_logs[pattern];
+            self.entropies[cell] = self.sum_weights[cell].ln() - self.sum_weight_logs[cell] / self.sum_weights[cell];
+            for dir in 0 .. 4 {
+                if let Some(neighbor) = self.neighbor(cell, dir) {
+                    let supports = &mut self.supports[opposite(dir)][neighbor * n_patterns .. (neighbor+1) * n_patterns];
+                    for q in self.propagator[pattern][dir].iter() {
+                        supports[*q] += 1;
+                    };
+                }
+            };
+        };
+        self.pending.clear();
+        self.contradiction = false;
+    }
+
+    fn propagate (&mut self) {
+        let n_patterns = self.constraints.len();
+        while !self.contradiction {
+            match self.pending.pop() {
+                None => break,
+                Some((cell, pattern)) => if self.wave[cell * n_patterns + pattern] { self.ban(cell, pattern); },
             }
-        }
-        if chunk_y > 0 {
-            let up_idx = self.chunk_idx(chunk_x, chunk_y-1);
-            match self.chunks[up_idx] {
-                None => {},
-                Some(_) => {
-                    neighbors += 1;
-                },
+        };
+    }
+
+    /// Undoes the last pick and rules it out; false if there's nothing left to undo
+    fn backtrack (&mut self) -> bool {
+        match self.decisions.pop() {
+            None => false,
+            Some(decision) => {
+                self.backtracks += 1;
+                self.undo(decision.trail_len);
+                self.ban(decision.cell, decision.pattern);
+                true
             }
         }
-        if chunk_y < self.chunks_y-1 {
-            let down_idx = self.chunk_idx(chunk_x, chunk_y+1);
-            match self.chunks[down_idx] {
-                None => {},
-                Some(_) => {
-                    neighbors += 1;
-                },
+    }
+
+    /// Keeps only the patterns that pass the test in a cell, before anything is picked
+    pub fn restrict<F> (&mut self, cell_x: usize, cell_y: usize, allowed: F) where F: Fn(&MapChunk) -> bool {
+        let cell = cell_y * self.cells_x + cell_x;
+        let n_patterns = self.constraints.len();
+        for p in 0 .. n_patterns {
+            if self.wave[cell * n_patterns + p] && !allowed(&self.constraints[p]) {
+                self.ban(cell, p);
             }
-        }
-        neighbors
+        };
+        self.propagate();
+        if self.contradiction { self.possible = false; }
+    }
+
+    /// The cells (and where in their pattern) covering a map tile
+    pub fn cells_covering (&self, x: i32, y: i32) -> Vec<(usize, usize, usize)> {
+        let mut covering = Vec::new();
+        for cell_y in 0 .. self.cells_y {
+            for cell_x in 0 .. self.cells_x {
+                let (dx, dy) = (x - cell_x as i32 * self.stride, y - cell_y as i32 * self.stride);
+                if dx >= 0 && dy >= 0 && dx < self.pattern_size && dy < self.pattern_size {
+                    covering.push((cell_x, cell_y, (dy * self.pattern_size + dx) as usize));
+                }
+            };
+        };
+        covering
     }
 
+    /// Makes one pick (undoing earlier ones if it has to); true once the solver is finished,
+    /// successfully or not
     pub fn iteration (&mut self, map: &mut Map, rng: &mut rltk::RandomNumberGenerator) -> bool {
-        if self.remaining.is_empty() { return true; }
-        
-        /* Populate neighbor count of remaining list */
-        let mut remain_copy = self.remaining.clone();
-        let mut neighbors_exist = false;
-        for r in remain_copy.iter_mut() {
-            let idx = r.0;
-            let chunk_x = idx % self.chunks_x;
-            let chunk_y = idx / self.chunks_x;
-            let neighbor_count = self.count_neighbors(chunk_x, chunk_y);
-            if neighbor_count > 0 { neighbors_exist = true; }
-            *r = (r.0, neighbor_count);
-        };
-        remain_copy.sort_by(|a,b| b.1.cmp(&a.1));
-        self.remaining = remain_copy;
-
-        /* Pick random chunk not dealt with yet, get index, remove from remaining list */
-        let remaining_index = if !neighbors_exist {
-            (rng.roll_dice(1, self.remaining.len() as i32)-1) as usize
-        } else { 0usize };
-        let chunk_index = self.remaining[remaining_index].0;
-        self.remaining.remove(remaining_index);
- 
-        let chunk_x = chunk_index % self.chunks_x;
-        let chunk_y = chunk_index / self.chunks_x;
-        let mut neighbors = 0;
-        let mut options : Vec<Vec<usize>> = Vec::new();
-        
-        if chunk_x > 0 {
-            let left_idx = self.chunk_idx(chunk_x-1, chunk_y);
-            match self.chunks[left_idx] {
-                None => {},
-                Some(nt) => {
-                    neighbors += 1;
-                    options.push(self.constraints[nt].compatible_with[3].clone());
-                },
-            }
-        }
-        if chunk_x < self.chunks_x-1 {
-            let right_idx = self.chunk_idx(chunk_x+1, chunk_y);
-            match self.chunks[right_idx] {
-                None => {},
-                Some(nt) => {
-                    neighbors += 1;
-                    options.push(self.constraints[nt].compatible_with[2].clone());
-                },
+        if !self.possible { return true; }
+        self.propagate();
+        while self.contradiction {
+            if self.backtracks >= MAX_BACKTRACKS || !self.backtrack() {
+                rltk::console::log("Oh no! It's not possible!");
+                self.possible = false;
+                self.render(map);
+                return true;
             }
-        }
-        if chunk_y > 0 {
-            let up_idx = self.chunk_idx(chunk_x, chunk_y-1);
-            match self.chunks[up_idx] {
-                None => {},
-                Some(nt) => {
-                    neighbors += 1;
-                    options.push(self.constraints[nt].compatible_with[1].clone());
-                },
+            self.propagate();
+        };
+
+        /* Lowest entropy first, so the cells with the fewest ways to go wrong go first */
+        let mut lowest = f64::MAX;
+        let mut candidates: Vec<usize> = Vec::new();
+        for cell in 0 .. self.remaining.len() {
+            if self.remaining[cell] < 2 { continue; }
+            let entropy = self.entropies[cell];
+            if entropy < lowest - 1e-9 {
+                lowest = entropy;
+                candidates.clear();
             }
+            if entropy < lowest + 1e-9 { candidates.push(cell); }
+        };
+        if candidates.is_empty() {
+            self.render(map);
+            return true;
         }
-        if chunk_y < self.chunks_y-1 {
-            let down_idx = self.chunk_idx(chunk_x, chunk_y+1);
-            match self.chunks[down_idx] {
-                None => {},
-                Some(nt) => {
-                    neighbors += 1;
-                    options.push(self.constraints[nt].compatible_with[0].clone());
-                },
+        let cell = candidates[(rng.roll_dice(1, candidates.len() as i32)-1) as usize];
+
+        /* Pick by how often each pattern turned up in the sample */
+        let n_patterns = self.constraints.len();
+        let options: Vec<usize> = (0 .. n_patterns).filter(|p| self.wave[cell * n_patterns + p]).collect();
+        let total: i32 = options.iter().map(|p| i32::max(1, self.constraints[*p].frequency)).sum();
+        let mut roll = rng.roll_dice(1, total) - 1;
+        let mut pattern = options[options.len() - 1];
+        for p in options.iter() {
+            let weight = i32::max(1, self.constraints[*p].frequency);
+            if roll < weight {
+                pattern = *p;
+                break;
             }
-        }
+            roll -= weight;
+        };
 
-        if neighbors == 0 {
-            /* Nothing nearby, can have anything */
-            let new_chunk_idx = (rng.roll_dice(1, self.constraints.len() as i32)-1) as usize;
-            self.chunks[chunk_index] = Some(new_chunk_idx);
-            let left_x = chunk_x as i32 * self.chunk_size as i32;
-            let right_x = (chunk_x as i32+1) * self.chunk_size as i32;
-            let top_y = chunk_y as i32 * self.chunk_size as i32;
-            let bottom_y = (chunk_y as i32+1) * self.chunk_size as i32;
-
-            let mut i : usize = 0;
-            for y in top_y .. bottom_y {
-                for x in left_x .. right_x {
+        self.decisions.push(Decision { cell, pattern, trail_len: self.trail.len() });
+        for p in options.into_iter().filter(|p| *p != pattern) {
+            self.ban(cell, p);
+        };
+        false
+    }
+
+    /// Draws every cell that has come down to one pattern
+    pub fn render (&self, map: &mut Map) {
+        let n_patterns = self.constraints.len();
+        for cell in 0 .. self.remaining.len() {
+            if self.remaining[cell] != 1 { continue; }
+            let pattern = match (0 .. n_patterns).find(|p| self.wave[cell * n_patterns + p]) {
+                Some(pattern) => &self.constraints[pattern].pattern,
+                None => continue,
+            };
+            let left_x = (cell % self.cells_x) as i32 * self.stride;
+            let top_y = (cell / self.cells_x) as i32 * self.stride;
+            let mut i = 0;
+            for y in top_y .. top_y + self.pattern_size {
+                for x in left_x .. left_x + self.pattern_size {
                     let mapidx = map.xy_idx(x, y);
-                    let tile = self.constraints[new_chunk_idx].pattern[i];
+                    let tile: TileType = pattern[i];
                     map.tiles[mapidx] = tile;
                     i += 1;
                 };
             };
-        } else {
-            /* There are neighbors: attempt compatibility */
-            let mut options_to_check : HashSet<usize> = HashSet::new();
-            for o in options.iter() {
-                for i in o.iter() {
-                    options_to_check.insert(*i);
-                };
-            };
+        };
+    }
+}
 
-            let mut possible_options : Vec<usize> = Vec::new();
-            for new_chunk_idx in options_to_check.iter() {
-                let mut possible = true;
-                for o in options.iter() {
-                    if !o.contains(new_chunk_idx) { possible = false; }
-                };
-                if possible {
-                    possible_options.push(*new_chunk_idx);
-                }
-            };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::lock_globals;
 
-            if possible_options.is_empty() {
-                rltk::console::log("Oh no! It's not possible!");
-                self.possible = false;
-                return true;
-            } else {
-                let new_chunk_idx = if possible_options.len() == 1 { 0 }
-                    else { rng.roll_dice(1, possible_options.len() as i32)-1 };
-
-                self.chunks[chunk_index] = Some(possible_options[new_chunk_idx as usize]);
-                let left_x = chunk_x as i32 * self.chunk_size as i32;
-                let right_x = (chunk_x as i32+1) * self.chunk_size as i32;
-                let top_y = chunk_y as i32 * self.chunk_size as i32;
-                let bottom_y = (chunk_y as i32+1) * self.chunk_size as i32;
-
-                let mut i: usize = 0;
-                for y in top_y .. bottom_y {
-                    for x in left_x .. right_x {
-                        let mapidx = map.xy_idx(x, y);
-                        let tile = self.constraints[new_chunk_idx as usize].pattern[i];
-                        map.tiles[mapidx] = tile;
-                        i += 1;
-                    };
-                };
-            }
+    /// A one-tile pattern that can only have the given patterns beside it, east and west
+    fn chunk (tile: TileType, beside: Vec<usize>) -> MapChunk {
+        MapChunk {
+            pattern: vec![tile],
+            frequency: 1,
+            exits: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+            has_exits: false,
+            compatible_with: [Vec::new(), Vec::new(), beside.clone(), beside],
         }
-        false
+    }
+
+    /// A row of cells where floor and wall have to alternate
+    fn alternating (map: &Map) -> Solver {
+        Solver::new(vec![chunk(TileType::Floor, vec![1]), chunk(TileType::Wall, vec![0])], 1, 1, map)
+    }
+
+    #[test]
+    fn fills_each_cell_with_something_that_fits () {
+        let _globals = lock_globals();
+        let mut map = Map::new(1, 6, 1, "test");
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let mut solver = alternating(&map);
+        while !solver.iteration(&mut map, &mut rng) {};
+        assert!(solver.possible);
+        for x in 1 .. 6 {
+            assert!(map.tiles[x - 1] != map.tiles[x]);
+        };
+    }
+
+    #[test]
+    fn undo_puts_back_everything_banned () {
+        let _globals = lock_globals();
+        let map = Map::new(1, 4, 1, "test");
+        let mut solver = alternating(&map);
+        let before = (solver.wave.clone(), solver.remaining.clone(), solver.supports.clone(), solver.entropies.clone());
+
+        /* A wall first means floor, wall, floor after it */
+        solver.ban(0, 0);
+        solver.propagate();
+        assert!(!solver.contradiction);
+        assert_eq!(solver.remaining, vec![1, 1, 1, 1]);
+        assert!(solver.wave[2] && !solver.wave[3]);
+
+        solver.undo(0);
+        assert_eq!((solver.wave.clone(), solver.remaining.clone(), solver.supports.clone(), solver.entropies.clone()), before);
+        assert!(solver.trail.is_empty() && solver.pending.is_empty());
+    }
+
+    #[test]
+    fn backtracking_strikes_off_the_last_pick () {
+        let _globals = lock_globals();
+        let mut map = Map::new(1, 4, 1, "test");
+        let mut rng = rltk::RandomNumberGenerator::seeded(7);
+        let mut solver = alternating(&map);
+        assert!(!solver.iteration(&mut map, &mut rng));
+        let (cell, pattern) = (solver.decisions[0].cell, solver.decisions[0].pattern);
+        solver.propagate();
+        assert!(solver.remaining.iter().all(|r| *r == 1));
+
+        assert!(solver.backtrack());
+        assert_eq!(solver.backtracks, 1);
+        assert!(solver.decisions.is_empty());
+        assert!(!solver.wave[cell * 2 + pattern]);
+        assert!(solver.wave[cell * 2 + 1 - pattern]);
+        /* Nothing left to undo */
+        assert!(!solver.backtrack());
+    }
+
+    #[test]
+    fn a_contradiction_with_nothing_to_undo_is_impossible () {
+        let _globals = lock_globals();
+        let mut map = Map::new(1, 4, 1, "test");
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let mut solver = alternating(&map);
+        solver.restrict(0, 0, |chunk| chunk.pattern[0] == TileType::Floor);
+        assert!(solver.possible);
+        solver.restrict(1, 0, |chunk| chunk.pattern[0] == TileType::Floor);
+        assert!(!solver.possible);
+        assert!(solver.iteration(&mut map, &mut rng));
     }
 }
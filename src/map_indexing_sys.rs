@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use super::{Map, Position, BlocksTile, Pools, spatial};
+use super::{Map, Position, BlocksTile, Pools, TileSize, spatial};
 
 pub struct MapIndexingSystem { }
 
@@ -10,10 +10,11 @@ impl<'a> System<'a> for MapIndexingSystem {
         ReadStorage<'a, BlocksTile>,
         ReadStorage<'a, Pools>,
         Entities<'a>,
+        ReadStorage<'a, TileSize>,
     );
 
     fn run (&mut self, data: Self::SystemData) {
-        let (map, position, blockers, pools, entities) = data;
+        let (map, position, blockers, pools, entities, sizes) = data;
 
         spatial::clear();
         spatial::populate_blocked_from_map(&*map);
@@ -25,8 +26,9 @@ impl<'a> System<'a> for MapIndexingSystem {
                 }
             }
             if alive {
-                let idx = map.xy_idx(pos.x, pos.y);
-                spatial::index_entity(ent, idx, blockers.get(ent).is_some());
+                for idx in map.footprint(pos.x, pos.y, sizes.get(ent)).iter() {
+                    spatial::index_entity(ent, *idx, blockers.get(ent).is_some());
+                };
             }
         };
     }
@@ -0,0 +1,493 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use serde::Serialize;
+use crate::map::{Map, MasterDungeonMap, TileType, LevelId, tile_walkable, seed_from_text, tile_glyph,
+    build_level, stairs_destination, DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT, MIN_MAP_SIZE, MAIN_BRANCH};
+use crate::map_builders::{BuilderChain, MapProblem};
+use crate::raws::{RAWS, get_builder_chain_names, get_branch};
+
+/* `roguelike mapgen ...`: builds levels without opening a window, for looking at what a
+   builder makes (and how often it goes wrong) without playing down to it */
+
+const USAGE: &str = "usage: roguelike mapgen [options]
+    --depth N        depth to build (default 1)
+    --branch NAME    branch of the dungeon the depth is down (default main)
+    --builder NAME   level (default), or any chain named in raws/levels.json
+    --seed SEED      run seed, a number or any text (default 0)
+    --width N        map width (default: whatever the chain asks for, or 80)
+    --height N       map height (default: whatever the chain asks for, or 50)
+    --format F       ascii (default), json or png
+    --out PATH       where to write the map (default mapgen_d<depth>_<seed>.<ext>)
+    --history        include every step of the build, not just the result
+    --scale N        pixels per tile for png (default 8)
+    --batch N        build N levels from consecutive seeds and print statistics
+    --help           show this and stop";
+
+#[derive(PartialEq, Copy, Clone)]
+enum Format { Ascii, Json, Png }
+
+impl Format {
+    fn extension (self) -> &'static str {
+        match self {
+            Format::Ascii => "txt",
+            Format::Json => "json",
+            Format::Png => "png",
+        }
+    }
+}
+
+struct Options {
+    depth: i32,
+    branch: String,
+    builder: String,
+    seed: u64,
+    width: Option<i32>,
+    height: Option<i32>,
+    format: Format,
+    out: Option<String>,
+    history: bool,
+    help: bool,
+    scale: u32,
+    batch: Option<u32>,
+}
+
+fn parse_options (args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        depth: 1, branch: MAIN_BRANCH.to_string(), builder: "level".to_string(), seed: 0, width: None, height: None,
+        format: Format::Ascii, out: None, history: false, help: false, scale: 8, batch: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--history" {
+            options.history = true;
+            continue;
+        }
+        if arg == "--help" || arg == "-h" {
+            options.help = true;
+            continue;
+        }
+        let value = args.next().ok_or(format!("{} needs a value", arg))?;
+        let number = || value.parse::<i32>().map_err(|_| format!("{} expects a number, not '{}'", arg, value));
+        match arg.as_str() {
+            "--depth" => options.depth = number()?,
+            "--branch" => options.branch = value.clone(),
+            "--builder" => options.builder = value.clone(),
+            "--seed" => options.seed = seed_from_text(value),
+            "--width" => options.width = Some(number()?),
+            "--height" => options.height = Some(number()?),
+            "--out" => options.out = Some(value.clone()),
+            "--scale" => options.scale = number()?.max(1) as u32,
+            "--batch" => options.batch = Some(number()?.max(1) as u32),
+            "--format" => options.format = match value.as_str() {
+                "ascii" => Format::Ascii,
+                "json" => Format::Json,
+                "png" => Format::Png,
+                _ => return Err(format!("unknown format '{}'", value)),
+            },
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    };
+
+    if options.width.is_some_and(|w| w < MIN_MAP_SIZE) || options.height.is_some_and(|h| h < MIN_MAP_SIZE) {
+        return Err(format!("maps have to be at least {}x{}", MIN_MAP_SIZE, MIN_MAP_SIZE));
+    }
+    Ok(options)
+}
+
+impl Options {
+    fn level (&self) -> LevelId {
+        LevelId::new(&self.branch, self.depth)
+    }
+
+    /// The size asked for on the command line, if any; otherwise each chain picks its own
+    fn size (&self) -> Option<(i32, i32)> {
+        if self.width.is_none() && self.height.is_none() { return None; }
+        Some((self.width.unwrap_or(DEFAULT_MAP_WIDTH), self.height.unwrap_or(DEFAULT_MAP_HEIGHT)))
+    }
+}
+
+/// Builds one level the way the game would for this seed and level
+fn build (options: &Options, seed: u64) -> BuilderChain {
+    /* Builder names are checked up front */
+    build_level(&MasterDungeonMap::new(seed), &options.level(), &options.builder, options.size(), options.history, None)
+}
+
+fn tile_char (tt: TileType) -> char {
+    match tt {
+        TileType::Wall => '#',
+        TileType::Floor => '.',
+        TileType::DownStairs => '>',
+        TileType::UpStairs => '<',
+        TileType::Road => '=',
+        TileType::Grass => '"',
+        TileType::Gravel => ';',
+        TileType::ShallowWater => '~',
+        TileType::DeepWater => 'W',
+        TileType::WoodFloor => '_',
+        TileType::Bridge => '+',
+        TileType::Stalactite => 'v',
+        TileType::Stalagmite => '^',
+    }
+}
+
+fn ascii_rows (map: &Map) -> Vec<String> {
+    map.tiles.chunks(map.width as usize)
+        .map(|row| row.iter().map(|tt| tile_char(*tt)).collect())
+        .collect()
+}
+
+fn write_ascii (out: &mut dyn Write, chain: &BuilderChain) -> std::io::Result<()> {
+    let data = &chain.build_data;
+    for (i, snapshot) in data.history.iter().enumerate() {
+        writeln!(out, "-- step {} --", i + 1)?;
+        for row in ascii_rows(snapshot) { writeln!(out, "{}", row)?; }
+    };
+    writeln!(out, "-- {} ({}) --", data.map.name, data.map.level_id())?;
+    let mut rows = ascii_rows(&data.map);
+    if let Some(start) = &data.starting_position {
+        let row = &mut rows[start.y as usize];
+        row.replace_range(start.x as usize .. start.x as usize + 1, "@");
+    }
+    for row in rows { writeln!(out, "{}", row)?; }
+    for (idx, name) in data.spawn_list.iter() {
+        writeln!(out, "spawn {},{} {}", *idx as i32 % data.map.width, *idx as i32 / data.map.width, name)?;
+    };
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct XY { x: i32, y: i32 }
+
+#[derive(Serialize)]
+struct SpawnDump<'a> { x: i32, y: i32, name: &'a str }
+
+#[derive(Serialize)]
+struct ExitDump { x: i32, y: i32, tile: TileType, to: Option<String> }
+
+#[derive(Serialize)]
+struct MapDump<'a> {
+    name: &'a str,
+    branch: &'a str,
+    depth: i32,
+    seed: u64,
+    width: i32,
+    height: i32,
+    tiles: &'a [TileType],
+    spawns: Vec<SpawnDump<'a>>,
+    start: Option<XY>,
+    exits: Vec<ExitDump>,
+    history: Vec<&'a [TileType]>,
+}
+
+fn write_json (out: &mut dyn Write, chain: &BuilderChain, seed: u64) -> std::io::Result<()> {
+    let data = &chain.build_data;
+    let map = &data.map;
+    let dump = MapDump {
+        name: &map.name,
+        branch: &map.branch,
+        depth: map.depth,
+        seed,
+        width: map.width,
+        height: map.height,
+        tiles: &map.tiles,
+        spawns: data.spawn_list.iter()
+            .map(|(idx, name)| SpawnDump { x: *idx as i32 % map.width, y: *idx as i32 / map.width, name })
+            .collect(),
+        start: data.starting_position.as_ref().map(|pos| XY { x: pos.x, y: pos.y }),
+        exits: map.tiles.iter().enumerate()
+            .filter(|(_idx, tt)| **tt == TileType::DownStairs || **tt == TileType::UpStairs)
+            .map(|(idx, tt)| ExitDump {
+                x: idx as i32 % map.width,
+                y: idx as i32 / map.width,
+                tile: *tt,
+                to: stairs_destination(map, idx).map(|to| to.level.to_string()),
+            })
+            .collect(),
+        history: data.history.iter().map(|snapshot| snapshot.tiles.as_slice()).collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &dump)?;
+    writeln!(out)
+}
+
+/// One block of the tile's in-game colour per tile, with spawns in red and the start in white
+fn write_png (path: &str, map: &Map, spawns: &[(usize, String)], start: Option<usize>, scale: u32) -> Result<(), String> {
+    let mut lit = map.clone();
+    lit.revealed_tiles.iter_mut().for_each(|t| *t = true);
+    lit.visible_tiles.iter_mut().for_each(|t| *t = true);
+    lit.outdoors = true;
+
+    let (width, height) = (map.width as u32 * scale, map.height as u32 * scale);
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    for idx in 0 .. map.tiles.len() {
+        let colour = if Some(idx) == start {
+            rltk::RGB::named(rltk::WHITE)
+        } else if spawns.iter().any(|(spawn_idx, _name)| *spawn_idx == idx) {
+            rltk::RGB::named(rltk::RED)
+        } else {
+            tile_glyph(idx, &lit).1
+        };
+        let (tx, ty) = (idx as u32 % map.width as u32, idx as u32 / map.width as u32);
+        for py in ty * scale .. (ty + 1) * scale {
+            for px in tx * scale .. (tx + 1) * scale {
+                let p = ((py * width + px) * 3) as usize;
+                pixels[p] = (colour.r * 255.0) as u8;
+                pixels[p + 1] = (colour.g * 255.0) as u8;
+                pixels[p + 2] = (colour.b * 255.0) as u8;
+            }
+        };
+    };
+
+    let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+fn export (options: &Options) -> Result<(), String> {
+    let chain = build(options, options.seed);
+    let path = options.out.clone()
+        .unwrap_or(format!("mapgen_d{}_{}.{}", options.depth, options.seed, options.format.extension()));
+    let data = &chain.build_data;
+
+    if options.format == Format::Png {
+        /* Steps go in files of their own next to the result: map_000.png, map_001.png ... */
+        let stem = path.trim_end_matches(".png");
+        for (i, snapshot) in data.history.iter().enumerate() {
+            write_png(&format!("{}_{:03}.png", stem, i), snapshot, &[], None, options.scale)?;
+        };
+        let start = data.starting_position.as_ref().map(|pos| data.map.xy_idx(pos.x, pos.y));
+        write_png(&path, &data.map, &data.spawn_list, start, options.scale)?;
+    } else {
+        let file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        match options.format {
+            Format::Json => write_json(&mut out, &chain, options.seed),
+            _ => write_ascii(&mut out, &chain),
+        }.and_then(|_| out.flush()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    println!("Wrote {} ({} steps)", path, data.history.len());
+    for problem in data.problems.iter() {
+        println!("Invalid: {}", problem);
+    };
+    Ok(())
+}
+
+/// Running min / total / max of one statistic over the batch
+struct Tally { min: f32, total: f32, max: f32 }
+
+impl Tally {
+    fn new () -> Tally {
+        Tally { min: f32::MAX, total: 0.0, max: f32::MIN }
+    }
+
+    fn add (&mut self, value: f32) {
+        self.min = f32::min(self.min, value);
+        self.max = f32::max(self.max, value);
+        self.total += value;
+    }
+
+    fn report (&self, label: &str, count: u32) {
+        if count == 0 { return; }
+        println!("{:<14} min {:>7.1}  mean {:>7.1}  max {:>7.1}", label, self.min, self.total / count as f32, self.max);
+    }
+}
+
+fn problem_kind (problem: &MapProblem) -> &'static str {
+    match problem {
+        MapProblem::NoStart => "no start",
+        MapProblem::StartNotWalkable { .. } => "a start in a wall",
+        MapProblem::NoDownStairs => "no way down",
+        MapProblem::StairsUnreachable { .. } => "unreachable stairs",
+        MapProblem::SpawnOutOfBounds { .. } => "spawns off the map",
+        MapProblem::SpawnNotWalkable { .. } => "spawns in walls",
+        MapProblem::EdgeNotSolid { .. } => "open edges",
+    }
+}
+
+fn batch (options: &Options, runs: u32) {
+    let mut floor = Tally::new();
+    let mut rooms = Tally::new();
+    let mut spawns = Tally::new();
+    let mut spawn_names: BTreeMap<String, u32> = BTreeMap::new();
+    let mut problems: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+    let (mut built, mut with_rooms, mut failed) = (0, 0, Vec::new());
+
+    for i in 0 .. runs {
+        let seed = options.seed.wrapping_add(i as u64);
+        /* A builder that panics is what we're here to find, so note the seed and carry on */
+        let chain = match std::panic::catch_unwind(|| build(options, seed)) {
+            Ok(chain) => chain,
+            Err(_) => {
+                failed.push(seed);
+                /* ...unless it took a shared lock down with it, and every seed after would fail too */
+                if RAWS.is_poisoned() || crate::spatial::is_poisoned() {
+                    println!("seed {} panicked holding the raws or spatial lock; stopping the batch there", seed);
+                    break;
+                }
+                continue;
+            }
+        };
+        let data = &chain.build_data;
+        built += 1;
+        let walkable = data.map.tiles.iter().filter(|tt| tile_walkable(**tt)).count();
+        floor.add(walkable as f32 * 100.0 / data.map.tiles.len() as f32);
+        if let Some(room_list) = &data.rooms {
+            rooms.add(room_list.len() as f32);
+            with_rooms += 1;
+        }
+        spawns.add(data.spawn_list.len() as f32);
+        for (_idx, name) in data.spawn_list.iter() {
+            *spawn_names.entry(name.clone()).or_insert(0) += 1;
+        };
+        let kinds: BTreeSet<&str> = data.problems.iter().map(problem_kind).collect();
+        for kind in kinds {
+            problems.entry(kind).or_default().push(seed);
+        };
+    };
+
+    let size = match options.size() {
+        Some((width, height)) => format!("{}x{}", width, height),
+        None => "own size".to_string(),
+    };
+    println!("{} x '{}' at {}, {}, seeds {}..{}", runs, options.builder, options.level(),
+        size, options.seed, options.seed.wrapping_add(runs as u64 - 1));
+    floor.report("floor %", built);
+    rooms.report("rooms", with_rooms);
+    spawns.report("spawns", built);
+    println!("{} of {} had rooms", with_rooms, built);
+    if !failed.is_empty() {
+        println!("{} panicked, on seeds {:?}", failed.len(), failed);
+    }
+    for (kind, seeds) in problems.iter() {
+        println!("{} had {}, on seeds {:?}", seeds.len(), kind, seeds);
+    };
+    for (name, count) in spawn_names.iter() {
+        println!("{:>7.2} per level  {}", *count as f32 / built.max(1) as f32, name);
+    };
+}
+
+/// Entry point for the `mapgen` subcommand; returns the process exit code
+pub fn run (args: &[String]) -> i32 {
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return 0;
+    }
+    crate::raws::load_raws();
+    let chains = get_builder_chain_names(&RAWS.lock().unwrap());
+    if options.builder != "level" && !chains.contains(&options.builder) {
+        eprintln!("unknown builder '{}' (try level, {})", options.builder, chains.join(", "));
+        return 2;
+    }
+    if options.branch != MAIN_BRANCH && get_branch(&RAWS.lock().unwrap(), &options.branch).is_none() {
+        eprintln!("unknown branch '{}'", options.branch);
+        return 2;
+    }
+
+    match options.batch {
+        Some(runs) => { batch(&options, runs); 0 }
+        None => match export(&options) {
+            Ok(()) => 0,
+            Err(e) => { eprintln!("{}", e); 1 }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Position;
+    use crate::test_support::lock_globals;
+
+    fn parse (args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(|arg| arg.to_string()).collect();
+        parse_options(&args)
+    }
+
+    /// A tiny walled room with stairs, a start and one spawn
+    fn closet () -> BuilderChain {
+        let mut chain = BuilderChain::new(2, 5, 4, "Closet");
+        let map = &mut chain.build_data.map;
+        for x in 1 .. 4 {
+            let idx = map.xy_idx(x, 1);
+            map.tiles[idx] = TileType::Floor;
+        };
+        let stairs = map.xy_idx(3, 2);
+        map.tiles[stairs] = TileType::DownStairs;
+        chain.build_data.starting_position = Some(Position { x: 1, y: 1 });
+        chain.build_data.spawn_list.push((map.xy_idx(2, 1), "Rat".to_string()));
+        chain
+    }
+
+    #[test]
+    fn defaults_build_the_first_level () {
+        let options = parse("").unwrap();
+        assert!(options.depth == 1 && options.branch == MAIN_BRANCH && options.builder == "level");
+        assert!(options.seed == 0 && options.format == Format::Ascii && options.scale == 8);
+        assert!(options.out.is_none() && options.batch.is_none() && !options.history && !options.help);
+        assert!(options.size().is_none());
+    }
+
+    #[test]
+    fn options_are_read_in_any_order () {
+        let options = parse("--format png --depth 4 --history --seed 17 --width 100 --scale 0 --batch 20 --out x.png").unwrap();
+        assert!(options.format == Format::Png && options.depth == 4 && options.history);
+        assert!(options.seed == 17 && options.scale == 1 && options.batch == Some(20));
+        assert_eq!(options.out.as_deref(), Some("x.png"));
+        /* One side given, the other falls back to the default */
+        assert_eq!(options.size(), Some((100, DEFAULT_MAP_HEIGHT)));
+        assert_eq!(parse("--seed hello").unwrap().seed, seed_from_text("hello"));
+        assert!(parse("-h").unwrap().help);
+    }
+
+    #[test]
+    fn bad_options_are_explained () {
+        assert_eq!(parse("--depth").err(), Some("--depth needs a value".to_string()));
+        assert_eq!(parse("--depth deep").err(), Some("--depth expects a number, not 'deep'".to_string()));
+        assert_eq!(parse("--format gif").err(), Some("unknown format 'gif'".to_string()));
+        assert_eq!(parse("--colour red").err(), Some("unknown option '--colour'".to_string()));
+        assert!(parse(&format!("--height {}", MIN_MAP_SIZE - 1)).is_err());
+        assert!(parse(&format!("--height {}", MIN_MAP_SIZE)).is_ok());
+    }
+
+    #[test]
+    fn tallies_keep_the_range_and_mean () {
+        let mut tally = Tally::new();
+        for value in [3.0, 1.0, 8.0].iter() { tally.add(*value); };
+        assert!(tally.min == 1.0 && tally.max == 8.0 && tally.total == 12.0);
+    }
+
+    #[test]
+    fn ascii_marks_the_start_and_lists_spawns () {
+        let _globals = lock_globals();
+        let chain = closet();
+        let mut out = Vec::new();
+        write_ascii(&mut out, &chain).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines, vec!["-- Closet (main:2) --", "#####", "#@..#", "###>#", "#####", "spawn 2,1 Rat"]);
+    }
+
+    #[test]
+    fn json_holds_the_whole_level () {
+        let _globals = lock_globals();
+        let chain = closet();
+        let mut out = Vec::new();
+        write_json(&mut out, &chain, 99).unwrap();
+        let dump: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert!(dump["seed"] == 99 && dump["width"] == 5 && dump["height"] == 4 && dump["depth"] == 2);
+        assert_eq!(dump["tiles"].as_array().unwrap().len(), 20);
+        assert!(dump["start"]["x"] == 1 && dump["spawns"][0]["name"] == "Rat");
+        assert!(dump["exits"][0]["x"] == 3 && dump["exits"][0]["tile"] == "DownStairs");
+    }
+}
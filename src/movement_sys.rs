@@ -1,6 +1,6 @@
 use specs::prelude::*;
 use super::{Map, Position, BlocksTile, ApplyMove, ApplyTeleport, OtherLevelPosition,
-    EntityMoved, Viewshed, RunState};
+    EntityMoved, Viewshed, RunState, Held, TileSize};
 
 pub struct MovementSystem {}
 
@@ -18,39 +18,45 @@ impl<'a> System<'a> for MovementSystem {
         WriteStorage<'a, Viewshed>,
         ReadExpect<'a, Entity>,
         WriteExpect<'a, RunState>,
+        ReadStorage<'a, Held>,
+        ReadStorage<'a, TileSize>,
     );
 
     fn run (&mut self, data : Self::SystemData) {
         let (mut map, mut position, blockers, entities, mut apply_move, 
             mut apply_teleport, mut other_level, mut moved,
-            mut viewsheds, player_entity, mut runstate) = data;
+            mut viewsheds, player_entity, mut runstate, held, sizes) = data;
 
         for (ent, teleport) in (&entities, &apply_teleport).join() {
-            if teleport.dest_depth == map.depth {
+            if teleport.dest_level == map.level_id() {
                 apply_move.insert(ent, ApplyMove { dest_idx: map.xy_idx(teleport.dest_x, teleport.dest_y) })
                     .expect("Unable to insert");
             } else if ent == *player_entity {
-                *runstate = RunState::TeleportingToOtherLevel { x: teleport.dest_x, y: teleport.dest_y, depth: teleport.dest_depth };
+                /* The player's position on the other level is picked up once it's been loaded */
+                other_level.insert(ent, OtherLevelPosition {
+                    x: teleport.dest_x,
+                    y: teleport.dest_y,
+                    level: teleport.dest_level.clone(),
+                }).expect("Unable to insert");
+                *runstate = RunState::TeleportingToOtherLevel;
             } else if let Some(pos) = position.get(ent) {
-                let idx = map.xy_idx(pos.x, pos.y);
-                let dest_idx = map.xy_idx(teleport.dest_x, teleport.dest_y);
-                crate::spatial::move_entity(ent, idx, dest_idx);
+                crate::spatial::move_footprint(ent, &map.footprint(pos.x, pos.y, sizes.get(ent)),
+                    &map.footprint(teleport.dest_x, teleport.dest_y, sizes.get(ent)));
                 other_level.insert(ent, OtherLevelPosition {
                     x: teleport.dest_x,
                     y: teleport.dest_y,
-                    depth: teleport.dest_depth 
+                    level: teleport.dest_level.clone(),
                 }).expect("Unable to insert");
                 position.remove(ent);
             }
         };
         apply_teleport.clear();
 
-        for (ent, movement, mut pos) in (&entities, &apply_move, &mut position).join() {
-            let start_idx = map.xy_idx(pos.x, pos.y);
-            let dest_idx = movement.dest_idx as usize;
-            crate::spatial::move_entity(ent, start_idx, dest_idx);
+        for (ent, movement, pos, _held) in (&entities, &apply_move, &mut position, !&held).join() {
+            let from = map.footprint(pos.x, pos.y, sizes.get(ent));
             pos.x = movement.dest_idx as i32 % map.width;
             pos.y = movement.dest_idx as i32 / map.width;
+            crate::spatial::move_footprint(ent, &from, &map.footprint(pos.x, pos.y, sizes.get(ent)));
             if let Some(vs) = viewsheds.get_mut(ent) {
                 vs.dirty = true;
             }
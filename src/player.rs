@@ -4,9 +4,19 @@ use std::cmp::{min, max};
 use super::{Player, State, Map, Viewshed, RunState, Pools, WantsToMelee,
     Position, Item, gamelog::GameLog, WantsToPickupItem, TileType, Faction,
     HungerClock, HungerState, EntityMoved, Door, BlocksTile, BlocksVisibility,
-    Renderable, raws::Reaction, Vendor, VendorMode};
+    Renderable, raws::Reaction, Vendor, VendorMode, Surrendered, Schedule, GameClock, Hireling, Tameable, Held,
+    TileSize, TownGuard, Wanted, Name};
 
 pub fn try_move_player (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState {
+    let (result, tame_target) = move_player(delta_x, delta_y, ecs);
+    if let Some(animal) = tame_target {
+        crate::companions::try_tame(ecs, animal);
+    }
+    result
+}
+
+/// Moves (or bumps) the player, also returning a beaten creature they tried to tame
+fn move_player (delta_x: i32, delta_y: i32, ecs: &mut World) -> (RunState, Option<Entity>) {
     let players = ecs.write_storage::<Player>();
     let mut positions = ecs.write_storage::<Position>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
@@ -21,17 +31,47 @@ pub fn try_move_player (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState
     let mut renderables = ecs.write_storage::<Renderable>();
     let factions = ecs.write_storage::<Faction>();
     let vendors = ecs.read_storage::<Vendor>();
+    let surrendered = ecs.read_storage::<Surrendered>();
+    let schedules = ecs.read_storage::<Schedule>();
+    let clock = ecs.fetch::<GameClock>();
+    let hirelings = ecs.read_storage::<Hireling>();
+    let tameables = ecs.read_storage::<Tameable>();
+    let held = ecs.read_storage::<Held>();
+    let sizes = ecs.read_storage::<TileSize>();
+    let town_guards = ecs.read_storage::<TownGuard>();
+    let wanted = ecs.read_storage::<Wanted>();
+    let names = ecs.read_storage::<Name>();
     let mut result = RunState::AwaitingInput;
+    let mut tame_target: Option<Entity> = None;
 
     let mut swap_entities: Vec<(Entity, i32, i32)> = Vec::new();
 
     for (ent, _player, pos, viewshed) in (&entities, &players, &mut positions, &mut viewsheds).join() {
-        if pos.x+delta_x < 1 || pos.x+delta_x > map.width-1 || pos.y+delta_y < 1 || pos.y+delta_y > map.height-1 { return RunState::AwaitingInput; }
+        if pos.x+delta_x < 1 || pos.x+delta_x > map.width-1 || pos.y+delta_y < 1 || pos.y+delta_y > map.height-1 { return (RunState::AwaitingInput, None); }
         let dest_idx = map.xy_idx(pos.x+delta_x, pos.y+delta_y);
         
         result = crate::spatial::for_each_tile_content_with_gamemode(dest_idx, |potential_target| {
             if let Some(_vendor) = vendors.get(potential_target) {
-                return Some(RunState::ShowVendor { vendor: potential_target, mode: VendorMode::Sell });
+                let at_post = match schedules.get(potential_target) {
+                    Some(schedule) => crate::ai::is_at_post(schedule, &map, &clock, pos.x+delta_x, pos.y+delta_y),
+                    None => true,
+                };
+                if at_post {
+                    return Some(RunState::ShowVendor { vendor: potential_target, mode: VendorMode::Sell });
+                }
+                let name = names.get(potential_target).map_or("The vendor", |n| n.name.as_str());
+                ecs.fetch_mut::<GameLog>().entries.push(format!("{} is closed for business until later.", name));
+            }
+            if town_guards.get(potential_target).is_some() && wanted.get(ent).is_some() {
+                /* Turning yourself in: the guard deals with you on their turn */
+                return Some(RunState::Ticking);
+            }
+            if hirelings.get(potential_target).is_some() {
+                return Some(RunState::ShowHire { hireling: potential_target });
+            }
+            if tameables.get(potential_target).is_some() && surrendered.get(potential_target).is_some() {
+                tame_target = Some(potential_target);
+                return Some(RunState::Ticking);
             }
             let mut hostile = true;
             if combat_stats.get(potential_target).is_some() {
@@ -40,8 +80,13 @@ pub fn try_move_player (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState
                         &crate::raws::RAWS.lock().unwrap());
                     if reaction != Reaction::Attack { hostile = false; }
                 }
+                if surrendered.get(potential_target).is_some() { hostile = false; }
             }
-            if !hostile {
+            if !hostile && sizes.get(potential_target).is_some() {
+                /* Too big to trade places with */
+                ecs.fetch_mut::<GameLog>().entries.push("There's no room to squeeze past.".to_string());
+                return Some(RunState::AwaitingInput);
+            } else if !hostile {
                 /* Move bystander */
                 swap_entities.push((potential_target, pos.x, pos.y));
 
@@ -75,7 +120,10 @@ pub fn try_move_player (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState
             None
         });
 
-        if !crate::spatial::is_blocked(dest_idx) {
+        if !crate::spatial::is_blocked(dest_idx) && held.get(ent).is_some() {
+            ecs.fetch_mut::<GameLog>().entries.push("You struggle, but can't break free!".to_string());
+            result = RunState::Ticking;
+        } else if !crate::spatial::is_blocked(dest_idx) {
             let old_idx = map.xy_idx(pos.x, pos.y);
             pos.x = min(map.width-1, max(0, pos.x + delta_x));
             pos.y = min(map.height-1, max(0, pos.y + delta_y));
@@ -107,7 +155,59 @@ pub fn try_move_player (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState
             result = RunState::Ticking;
         }
     };
-    result
+    (result, tame_target)
+}
+
+/// Anyone (or anything) with hit points on the tile next to the player
+fn neighbour (delta_x: i32, delta_y: i32, ecs: &World) -> Option<Entity> {
+    let player = *ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let pos = *ecs.fetch::<Point>();
+    let (x, y) = (pos.x + delta_x, pos.y + delta_y);
+    if x < 1 || x > map.width-1 || y < 1 || y > map.height-1 { return None; }
+    let pools = ecs.read_storage::<Pools>();
+    let mut found = None;
+    crate::spatial::for_each_tile_content(map.xy_idx(x, y), |ent| {
+        if ent != player && pools.get(ent).is_some() { found = Some(ent); }
+    });
+    found
+}
+
+/// Attacks whoever is there, friend or foe
+fn attack_in_direction (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState {
+    match neighbour(delta_x, delta_y, ecs) {
+        Some(target) => {
+            let player = *ecs.fetch::<Entity>();
+            ecs.write_storage::<WantsToMelee>().insert(player, WantsToMelee { target }).expect("Add target failed");
+            RunState::Ticking
+        }
+        None => RunState::AwaitingInput,
+    }
+}
+
+/// Goes for whoever's pockets are there
+fn steal_in_direction (delta_x: i32, delta_y: i32, ecs: &mut World) -> RunState {
+    match neighbour(delta_x, delta_y, ecs) {
+        Some(victim) => RunState::ShowSteal { victim },
+        None => {
+            ecs.fetch_mut::<GameLog>().entries.push("There's nobody there to rob.".to_string());
+            RunState::AwaitingInput
+        }
+    }
+}
+
+fn direction_of (key: VirtualKeyCode) -> Option<(i32, i32)> {
+    match key {
+        VirtualKeyCode::Left | VirtualKeyCode::H => Some((-1, 0)),
+        VirtualKeyCode::Right | VirtualKeyCode::L => Some((1, 0)),
+        VirtualKeyCode::Up | VirtualKeyCode::K => Some((0, -1)),
+        VirtualKeyCode::Down | VirtualKeyCode::J => Some((0, 1)),
+        VirtualKeyCode::Y => Some((-1, -1)),
+        VirtualKeyCode::U => Some((1, -1)),
+        VirtualKeyCode::N => Some((1, 1)),
+        VirtualKeyCode::B => Some((-1, 1)),
+        _ => None,
+    }
 }
 
 fn get_item (ecs: &mut World) {
@@ -252,6 +352,11 @@ pub fn player_input (gs: &mut State, ctx: &mut Rltk) -> RunState {
             return use_consumable_hotkey(gs, key-1);
         }
     }
+    /* Shift to attack anyone at all, even the peaceful; control to pick their pockets */
+    if let Some((delta_x, delta_y)) = ctx.key.and_then(direction_of) {
+        if ctx.shift { return attack_in_direction(delta_x, delta_y, &mut gs.ecs); }
+        if ctx.control { return steal_in_direction(delta_x, delta_y, &mut gs.ecs); }
+    }
     match ctx.key {
         None => { return RunState::AwaitingInput } /* null */
         Some(key) => match key {
@@ -276,6 +381,7 @@ pub fn player_input (gs: &mut State, ctx: &mut Rltk) -> RunState {
             VirtualKeyCode::I => return RunState::ShowInventory,
             VirtualKeyCode::D => return RunState::ShowDropItem,
             VirtualKeyCode::R => return RunState::ShowRemoveEquipment,
+            VirtualKeyCode::C => return RunState::ShowCompanionOrders,
             VirtualKeyCode::Space => return skip_turn(&mut gs.ecs),
             /* Level Change */
             VirtualKeyCode::Period => { if try_next_level(&mut gs.ecs) { return RunState::NextLevel; } },
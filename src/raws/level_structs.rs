@@ -0,0 +1,92 @@
+use serde::Deserialize;
+use crate::map::{MapTheme, MAIN_BRANCH};
+
+#[derive(Deserialize, Debug)]
+pub struct LevelRaws {
+    pub levels: Vec<LevelEntry>,
+    #[serde(default)]
+    pub branches: Vec<BranchDef>,
+    pub chains: Vec<BuilderChainDef>,
+}
+
+/// Which chain builds the levels in a depth range (of the main dungeon, unless it names a
+/// branch). Where entries overlap, one is picked by weight.
+#[derive(Deserialize, Debug)]
+pub struct LevelEntry {
+    pub chain: String,
+    pub branch: Option<String>,
+    pub min_depth: i32,
+    pub max_depth: Option<i32>,
+    pub theme: Option<MapTheme>,
+    pub weight: Option<i32>,
+}
+
+impl LevelEntry {
+    pub fn branch (&self) -> &str {
+        self.branch.as_deref().unwrap_or(MAIN_BRANCH)
+    }
+}
+
+/// A side branch: stairs down into it from `depth` of the branch it hangs off (the main
+/// dungeon, unless `from` says otherwise), and `length` levels below that
+#[derive(Deserialize, Debug)]
+pub struct BranchDef {
+    pub name: String,
+    pub from: Option<String>,
+    pub depth: i32,
+    pub length: i32,
+}
+
+impl BranchDef {
+    pub fn parent (&self) -> &str {
+        self.from.as_deref().unwrap_or(MAIN_BRANCH)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BuilderChainDef {
+    pub name: String,
+    pub map_name: Option<String>,
+    /* Only the chain a level starts from decides its size */
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub starter: Option<ChainStep>,
+    #[serde(default)]
+    pub builders: Vec<ChainStep>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ChainStep {
+    OneOf { one_of: Vec<ChainAlternative> },
+    OneIn { one_in: i32, steps: Vec<ChainStep> },
+    Include { chain: String },
+    Builder(Box<BuilderStep>),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChainAlternative {
+    pub weight: Option<i32>,
+    #[serde(default)]
+    pub steps: Vec<ChainStep>,
+}
+
+/// One builder and its settings; which settings mean anything depends on the builder
+#[derive(Deserialize, Debug)]
+pub struct BuilderStep {
+    pub builder: String,
+    pub mode: Option<String>,
+    pub prefab: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+    pub spawn_mode: Option<String>,
+    pub lifetime: Option<i32>,
+    pub floor_percent: Option<f32>,
+    pub brush_size: Option<i32>,
+    pub symmetry: Option<String>,
+    pub pattern_size: Option<i32>,
+    pub rivers: Option<i32>,
+    pub lakes: Option<i32>,
+    #[serde(default)]
+    pub pin: Vec<String>,
+}
@@ -9,6 +9,10 @@ pub struct Mob {
     pub blocks_tile: bool,
     pub vision_range: i32,
     pub movement: String,
+    pub movement_radius: Option<i32>,
+    pub locomotion: Option<Vec<String>>,
+    pub size: Option<i32>,
+    pub leader: Option<String>,
     pub quips: Option<Vec<String>>,
     pub attributes: MobAttributes,
     pub skills: Option<HashMap<String, i32>>,
@@ -17,11 +21,19 @@ pub struct Mob {
     pub mana: Option<i32>,
     pub equipped: Option<Vec<String>>,
     pub natural: Option<MobNatural>,
+    pub abilities: Option<Vec<MobAbility>>,
     pub loot_table: Option<String>,
     pub light: Option<MobLight>,
     pub faction: Option<String>,
+    pub personality: Option<String>,
     pub gold: Option<String>,
     pub vendor: Option<Vec<String>>,
+    pub hire_cost: Option<f32>,
+    pub tameable: Option<bool>,
+    pub humanoid: Option<bool>,
+    pub town_guard: Option<bool>,
+    pub morale: Option<MobMorale>,
+    pub schedule: Option<Vec<MobSchedule>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -51,3 +63,27 @@ pub struct MobLight {
     pub color: String,
 }
 
+
+#[derive(Deserialize, Debug)]
+pub struct MobMorale {
+    pub base: f32,
+    pub surrender: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MobSchedule {
+    pub start: i32,
+    pub end: i32,
+    pub location: String,
+    pub post: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MobAbility {
+    pub name: String,
+    pub trigger: Option<String>,
+    pub chance: f32,
+    pub cooldown: i32,
+    pub range: f32,
+    pub effects: HashMap<String, String>,
+}
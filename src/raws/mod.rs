@@ -10,12 +10,20 @@ mod prop_structs;
 mod spawn_table_structs;
 mod loot_structs;
 mod faction_structs;
+mod personality_structs;
+mod unique_structs;
+mod level_structs;
+mod prefab_structs;
 use item_structs::*;
 use mob_structs::*;
 use prop_structs::*;
 use spawn_table_structs::*;
 use loot_structs::*;
 pub use faction_structs::*;
+use personality_structs::*;
+use unique_structs::*;
+pub use level_structs::*;
+pub use prefab_structs::*;
 
 #[derive(Deserialize, Debug)]
 pub struct Raws {
@@ -25,6 +33,8 @@ pub struct Raws {
     pub spawn_table: Vec<SpawnTableEntry>,
     pub loot_tables: Vec<LootTable>,
     pub faction_table: Vec<FactionInfo>,
+    pub personalities: Vec<PersonalityInfo>,
+    pub uniques: Vec<UniqueMob>,
 }
 
 lazy_static! {
@@ -32,6 +42,11 @@ lazy_static! {
 }
 
 rltk::embedded_resource!(RAW_FILE, "../../raws/spawns.json");
+rltk::embedded_resource!(LEVEL_FILE, "../../raws/levels.json");
+
+/* Prefabs are built in like the JSON, so they're there whatever directory the game is run
+   from; build.rs makes the table from whatever is in resources/prefabs */
+include!(concat!(env!("OUT_DIR"), "/prefab_files.rs"));
 
 pub fn load_raws () {
     rltk::link_resource!(RAW_FILE, "../../raws/spawns.json");
@@ -43,4 +58,49 @@ pub fn load_raws () {
     rltk::console::log(format!("{:?}", decoder));
 
     RAWS.lock().unwrap().load(decoder);
+
+    rltk::link_resource!(LEVEL_FILE, "../../raws/levels.json");
+    let level_data = rltk::embedding::EMBED.lock()
+        .get_resource("../../raws/levels.json".to_string()).unwrap();
+    let level_string = std::str::from_utf8(level_data).expect("Unable to convert to a valid UTF-8 string");
+    let levels: LevelRaws = serde_json::from_str(level_string).expect("Unable to parse levels JSON");
+    RAWS.lock().unwrap().load_prefabs(load_prefab_files());
+    RAWS.lock().unwrap().load_levels(levels);
+}
+
+/// Every built-in `.txt` prefab, in name order so that picks between them come out the same
+/// for the same seed
+fn load_prefab_files () -> Vec<Prefab> {
+    let mut files: Vec<&(&str, &[u8])> = PREFAB_FILES.iter()
+        .filter(|(file, _data)| file.ends_with(".txt"))
+        .collect();
+    files.sort_by_key(|(file, _data)| *file);
+
+    let mut prefabs = Vec::new();
+    for (file, data) in files.iter() {
+        let name = file.trim_end_matches(".txt");
+        let prefab = std::str::from_utf8(data)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Prefab::parse(name, text, read_xp_glyphs));
+        match prefab {
+            Ok(prefab) => prefabs.push(prefab),
+            Err(e) => rltk::console::log(format!("Warning: prefab {} {}", name, e)),
+        }
+    };
+    prefabs
+}
+
+/// The glyphs of a built-in REX Paint file's first layer, as rows
+fn read_xp_glyphs (file: &str) -> Result<Vec<Vec<char>>, String> {
+    let data = PREFAB_FILES.iter().find(|(name, _data)| *name == file)
+        .map(|(_name, data)| *data)
+        .ok_or_else(|| format!("uses {}, which isn't one of the built-in prefab files", file))?;
+    let xp = rltk::rex::XpFile::read(&mut std::io::Cursor::new(data)).map_err(|e| format!("can't read {}: {}", file, e))?;
+    let layer = xp.layers.first().ok_or_else(|| format!("{} has no layers", file))?;
+    Ok((0 .. layer.height).map(|y| {
+        (0 .. layer.width).map(|x| match layer.get(x, y) {
+            Some(cell) if cell.ch != 0 => rltk::to_char(cell.ch as u8),
+            _ => ' ',
+        }).collect()
+    }).collect())
 }
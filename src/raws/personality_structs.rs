@@ -0,0 +1,18 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct PersonalityInfo {
+    pub name: String,
+    pub actions: HashMap<String, UtilityCurve>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UtilityCurve {
+    pub weight: f32,
+    pub curve: String,
+}
+
+/// How an action's input (0..1) is shaped before its weight is applied
+#[derive(PartialEq, Copy, Clone)]
+pub enum Curve { Constant, Linear, Inverse, Quadratic, Sqrt, Logistic }
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use crate::map::TileType;
+
+/// How a prefab goes onto a map: a vault dropped somewhere open, a section stamped at a
+/// fixed spot, or a whole level
+#[derive(PartialEq, Copy, Clone)]
+pub enum PrefabKind { Vault, Section, Level }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HorizontalPlacement { Left, Center, Right }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum VerticalPlacement { Top, Center, Bottom }
+
+/// What a glyph in a prefab stands for; anything that isn't a tile is stood on floor
+#[derive(PartialEq, Clone)]
+pub enum LegendEntry {
+    Tile(TileType),
+    Start,
+    Waypoint,
+    Spawn(String),
+}
+
+#[derive(PartialEq, Clone)]
+pub struct Prefab {
+    pub name: String,
+    pub kind: PrefabKind,
+    pub width: usize,
+    pub height: usize,
+    pub first_depth: i32,
+    pub last_depth: i32,
+    pub placement: (HorizontalPlacement, VerticalPlacement),
+    pub rarity: i32,
+    pub legend: HashMap<char, LegendEntry>,
+    pub glyphs: Vec<char>,
+}
+
+impl Prefab {
+    /// Reads a prefab file: `key: value` header lines and `glyph = meaning` legend lines, then
+    /// `---` and the map itself. A header `xp: file.xp` takes the map from REX Paint instead.
+    pub fn parse <F>(name: &str, text: &str, read_xp: F) -> Result<Prefab, String>
+        where F: Fn(&str) -> Result<Vec<Vec<char>>, String>
+    {
+        let mut prefab = Prefab {
+            name: name.to_string(),
+            kind: PrefabKind::Vault,
+            width: 0,
+            height: 0,
+            first_depth: 0,
+            last_depth: i32::MAX,
+            placement: (HorizontalPlacement::Center, VerticalPlacement::Center),
+            rarity: 1,
+            legend: HashMap::new(),
+            glyphs: Vec::new(),
+        };
+        let mut size = None;
+        let mut xp = None;
+        let mut lines = text.lines();
+        for line in lines.by_ref() {
+            let line = line.trim_end();
+            if line == "---" { break; }
+            if line.is_empty() || line.starts_with("//") { continue; }
+
+            let mut chars = line.chars();
+            if let (Some(glyph), Some(meaning)) = (chars.next(), chars.as_str().strip_prefix(" = ")) {
+                prefab.legend.insert(glyph, legend_entry(meaning.trim()));
+                continue;
+            }
+            let (key, value) = line.split_once(':').ok_or_else(|| format!("can't make sense of '{}'", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "kind" => prefab.kind = match value {
+                    "vault" => PrefabKind::Vault,
+                    "section" => PrefabKind::Section,
+                    "level" => PrefabKind::Level,
+                    _ => return Err(format!("unknown kind {}", value)),
+                },
+                "size" => size = Some(pair(value, 'x').ok_or_else(|| format!("size should look like 6x4, not {}", value))?),
+                "depth" => {
+                    let (first, last) = pair(value, '-').ok_or_else(|| format!("depth should look like 2-10, not {}", value))?;
+                    prefab.first_depth = first as i32;
+                    prefab.last_depth = last as i32;
+                }
+                "placement" => prefab.placement = placement(value).ok_or_else(|| format!("placement should look like right top, not {}", value))?,
+                "rarity" => prefab.rarity = value.parse().ok().filter(|rarity| (1 ..= 10).contains(rarity))
+                    .ok_or_else(|| format!("rarity should be from 1 (common) to 10 (rare), not {}", value))?,
+                "xp" => xp = Some(value.to_string()),
+                _ => return Err(format!("unknown header {}", key.trim())),
+            }
+        };
+
+        let rows: Vec<Vec<char>> = match xp {
+            Some(file) => read_xp(&file)?,
+            None => lines.map(|line| line.chars().filter(|c| *c != '\r').map(|c| if c == '\u{a0}' { ' ' } else { c }).collect()).collect(),
+        };
+        /* Editors like to strip trailing spaces, so rows (and the map) can come up short of the size */
+        let (width, height) = size.unwrap_or_else(|| (rows.iter().map(|row| row.len()).max().unwrap_or(0), rows.len()));
+        if width == 0 || height == 0 {
+            return Err("has no map".to_string());
+        }
+        if rows.len() > height || rows.iter().any(|row| row.len() > width) {
+            return Err(format!("is bigger than its size of {}x{}", width, height));
+        }
+        prefab.width = width;
+        prefab.height = height;
+        for y in 0 .. height {
+            for x in 0 .. width {
+                prefab.glyphs.push(rows.get(y).and_then(|row| row.get(x)).copied().unwrap_or(' '));
+            };
+        };
+
+        if let Some(unknown) = prefab.glyphs.iter().find(|glyph| prefab.meaning(**glyph).is_none()) {
+            return Err(format!("doesn't say what '{}' means", unknown));
+        }
+        Ok(prefab)
+    }
+
+    /// The file's own legend, falling back on the glyphs every prefab knows
+    pub fn meaning (&self, glyph: char) -> Option<LegendEntry> {
+        self.legend.get(&glyph).cloned().or_else(|| default_legend(glyph))
+    }
+}
+
+/// Glyphs that mean the same everywhere, including lair templates (which have no legend)
+pub fn default_legend (glyph: char) -> Option<LegendEntry> {
+    match glyph {
+        ' ' => Some(LegendEntry::Tile(TileType::Floor)),
+        '#' => Some(LegendEntry::Tile(TileType::Wall)),
+        '>' => Some(LegendEntry::Tile(TileType::DownStairs)),
+        '≈' => Some(LegendEntry::Tile(TileType::DeepWater)),
+        '@' => Some(LegendEntry::Start),
+        '*' => Some(LegendEntry::Waypoint),
+        'g' => Some(LegendEntry::Spawn("Goblin".to_string())),
+        'o' => Some(LegendEntry::Spawn("Orc".to_string())),
+        'O' => Some(LegendEntry::Spawn("Orc Leader".to_string())),
+        '^' => Some(LegendEntry::Spawn("Bear Trap".to_string())),
+        '%' => Some(LegendEntry::Spawn("Rations".to_string())),
+        '!' => Some(LegendEntry::Spawn("Health Potion".to_string())),
+        '☼' => Some(LegendEntry::Spawn("Watch Fire".to_string())),
+        _ => None,
+    }
+}
+
+fn legend_entry (meaning: &str) -> LegendEntry {
+    match meaning {
+        "start" => LegendEntry::Start,
+        "waypoint" => LegendEntry::Waypoint,
+        "floor" => LegendEntry::Tile(TileType::Floor),
+        "wall" => LegendEntry::Tile(TileType::Wall),
+        "down_stairs" => LegendEntry::Tile(TileType::DownStairs),
+        "road" => LegendEntry::Tile(TileType::Road),
+        "grass" => LegendEntry::Tile(TileType::Grass),
+        "gravel" => LegendEntry::Tile(TileType::Gravel),
+        "shallow_water" => LegendEntry::Tile(TileType::ShallowWater),
+        "deep_water" => LegendEntry::Tile(TileType::DeepWater),
+        "wood_floor" => LegendEntry::Tile(TileType::WoodFloor),
+        "bridge" => LegendEntry::Tile(TileType::Bridge),
+        "stalactite" => LegendEntry::Tile(TileType::Stalactite),
+        "stalagmite" => LegendEntry::Tile(TileType::Stalagmite),
+        _ => LegendEntry::Spawn(meaning.to_string()),
+    }
+}
+
+fn pair (value: &str, separator: char) -> Option<(usize, usize)> {
+    let (a, b) = value.split_once(separator)?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+fn placement (value: &str) -> Option<(HorizontalPlacement, VerticalPlacement)> {
+    let mut words = value.split_whitespace();
+    let horizontal = match words.next()? {
+        "left" => HorizontalPlacement::Left,
+        "center" => HorizontalPlacement::Center,
+        "right" => HorizontalPlacement::Right,
+        _ => return None,
+    };
+    let vertical = match words.next()? {
+        "top" => VerticalPlacement::Top,
+        "center" => VerticalPlacement::Center,
+        "bottom" => VerticalPlacement::Bottom,
+        _ => return None,
+    };
+    Some((horizontal, vertical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_xp (file: &str) -> Result<Vec<Vec<char>>, String> {
+        Err(format!("no {}", file))
+    }
+
+    fn parse (text: &str) -> Result<Prefab, String> {
+        Prefab::parse("test", text, no_xp)
+    }
+
+    #[test]
+    fn header_and_legend_are_read () {
+        let prefab = parse("// A comment\nkind: section\nsize: 4x2\ndepth: 3-7\nplacement: right top\nrarity: 4\n\
+            ~ = shallow_water\nk = Kobold\n---\n#~k\n @").unwrap();
+        assert!(prefab.kind == PrefabKind::Section);
+        assert_eq!((prefab.width, prefab.height, prefab.first_depth, prefab.last_depth, prefab.rarity), (4, 2, 3, 7, 4));
+        assert!(prefab.placement == (HorizontalPlacement::Right, VerticalPlacement::Top));
+        assert!(prefab.meaning('~') == Some(LegendEntry::Tile(TileType::ShallowWater)));
+        assert!(prefab.meaning('k') == Some(LegendEntry::Spawn("Kobold".to_string())));
+        assert!(prefab.meaning('@') == Some(LegendEntry::Start));
+        /* Short rows are padded out to the size with floor */
+        assert_eq!(prefab.glyphs, vec!['#', '~', 'k', ' ', ' ', '@', ' ', ' ']);
+    }
+
+    #[test]
+    fn the_legend_can_override_the_defaults () {
+        let prefab = parse("g = wall\n---\ng#").unwrap();
+        assert!(prefab.meaning('g') == Some(LegendEntry::Tile(TileType::Wall)));
+        assert_eq!((prefab.width, prefab.height), (2, 1));
+        assert!(prefab.kind == PrefabKind::Vault);
+    }
+
+    #[test]
+    fn bad_headers_are_reported () {
+        assert_eq!(parse("kind: room\n---\n#").err(), Some("unknown kind room".to_string()));
+        assert_eq!(parse("colour: red\n---\n#").err(), Some("unknown header colour".to_string()));
+        assert_eq!(parse("rarity: 11\n---\n#").err(), Some("rarity should be from 1 (common) to 10 (rare), not 11".to_string()));
+        assert_eq!(parse("depth: deep\n---\n#").err(), Some("depth should look like 2-10, not deep".to_string()));
+        assert_eq!(parse("placement: up\n---\n#").err(), Some("placement should look like right top, not up".to_string()));
+        assert_eq!(parse("nonsense\n---\n#").err(), Some("can't make sense of 'nonsense'".to_string()));
+    }
+
+    #[test]
+    fn maps_must_fit_their_size_and_legend () {
+        assert_eq!(parse("size: 6x4\n---\n#######").err(), Some("is bigger than its size of 6x4".to_string()));
+        assert_eq!(parse("size: 2x1\n---\n#\n#").err(), Some("is bigger than its size of 2x1".to_string()));
+        assert_eq!(parse("size: 4x4\n---\n").err(), None);
+        assert_eq!(parse("---\n").err(), Some("has no map".to_string()));
+        assert_eq!(parse("---\n#?").err(), Some("doesn't say what '?' means".to_string()));
+    }
+
+    #[test]
+    fn xp_maps_come_from_the_reader () {
+        assert_eq!(parse("xp: missing.xp\n---\n").err(), Some("no missing.xp".to_string()));
+        let prefab = Prefab::parse("test", "xp: wall.xp\n", |_| Ok(vec![vec!['#', '#']])).unwrap();
+        assert_eq!(prefab.glyphs, vec!['#', '#']);
+    }
+}
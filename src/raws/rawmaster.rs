@@ -3,9 +3,11 @@ use regex::Regex;
 use specs::prelude::*;
 use specs::saveload::{MarkedBuilder, SimpleMarker};
 use crate::components::*;
-use crate::{attr_bonus, npc_hp, mana_at_level};
+use crate::{attr_bonus, npc_hp, mana_at_level, map::{BuildingTag, MasterDungeonMap, UniqueState, LevelId, MIN_MAP_SIZE,
+    MAIN_BRANCH}};
 use crate::random_table::RandomTable;
-use super::{Raws, faction_structs::Reaction};
+use super::{Raws, faction_structs::Reaction, personality_structs::Curve, LevelRaws, LevelEntry, BranchDef, BuilderChainDef, ChainStep, BuilderStep,
+    Prefab, PrefabKind, LegendEntry};
 
 pub enum SpawnType {
     AtPosition { x: i32, y: i32 },
@@ -13,6 +15,13 @@ pub enum SpawnType {
     Carried { by: Entity },
 }
 
+pub const COMPANION_FACTION: &str = "Companions";
+const DEFAULT_GUARD_RADIUS: i32 = 4;
+const DEFAULT_FOLLOW_DISTANCE: i32 = 2;
+const ITEM_DURABILITY: i32 = 100;
+const MOB_GEAR_MIN_WEAR: i32 = 10;
+const MOB_GEAR_MAX_WEAR: i32 = 71;
+
 pub fn parse_dice_string (dice: &str) -> (i32, i32, i32) {
     lazy_static! {
         static ref DICE_RE: Regex = Regex::new(r"(\d+)d(\d+)([\+\-]\d+)?").unwrap();
@@ -41,6 +50,12 @@ pub struct RawMaster {
     prop_index: HashMap<String, usize>,
     loot_index: HashMap<String, usize>,
     faction_index: HashMap<String, HashMap<String, Reaction>>,
+    personality_index: HashMap<String, HashMap<String, (f32, Curve)>>,
+    unique_index: HashMap<String, usize>,
+    levels: LevelRaws,
+    chain_index: HashMap<String, usize>,
+    prefabs: Vec<Prefab>,
+    prefab_index: HashMap<String, usize>,
 }
 
 impl RawMaster {
@@ -53,12 +68,20 @@ impl RawMaster {
                 spawn_table: Vec::new(),
                 loot_tables: Vec::new(),
                 faction_table: Vec::new(),
+                personalities: Vec::new(),
+                uniques: Vec::new(),
             },
             item_index: HashMap::new(),
             mob_index: HashMap::new(),
             prop_index: HashMap::new(),
             loot_index: HashMap::new(),
             faction_index: HashMap::new(),
+            personality_index: HashMap::new(),
+            unique_index: HashMap::new(),
+            levels: LevelRaws { levels: Vec::new(), branches: Vec::new(), chains: Vec::new() },
+            chain_index: HashMap::new(),
+            prefabs: Vec::new(),
+            prefab_index: HashMap::new(),
         }
     }
     
@@ -103,19 +126,248 @@ impl RawMaster {
             };
             self.faction_index.insert(faction.name.clone(), reactions);
         };
+        for personality in self.raws.personalities.iter() {
+            let mut actions: HashMap<String, (f32, Curve)> = HashMap::new();
+            for (action, curve) in personality.actions.iter() {
+                let shape = match curve.curve.as_str() {
+                    "constant" => Curve::Constant,
+                    "linear" => Curve::Linear,
+                    "inverse" => Curve::Inverse,
+                    "quadratic" => Curve::Quadratic,
+                    "sqrt" => Curve::Sqrt,
+                    "logistic" => Curve::Logistic,
+                    _ => {
+                        rltk::console::log(format!("Warning: unknown curve [{}] for {} in personality {}; it will never be chosen",
+                            curve.curve, action, personality.name));
+                        continue;
+                    }
+                };
+                actions.insert(action.clone(), (curve.weight, shape));
+            };
+            self.personality_index.insert(personality.name.clone(), actions);
+        };
+        for mob in self.raws.mobs.iter_mut() {
+            let name = &mob.name;
+            if let Some(schedule) = mob.schedule.as_mut() {
+                schedule.retain(|entry| {
+                    let known = string_to_schedule_location(&entry.location).is_some();
+                    if !known {
+                        rltk::console::log(format!("Warning: {} is scheduled at unknown location [{}]; the entry is dropped", name, entry.location));
+                    }
+                    known
+                });
+            }
+        };
+        for mob in self.raws.mobs.iter() {
+            if let Some(personality) = &mob.personality {
+                if !self.personality_index.contains_key(personality) {
+                    rltk::console::log(format!("Warning: {} has unknown personality {}", mob.name, personality));
+                }
+            }
+            if let Some(leader) = &mob.leader {
+                if !self.mob_index.contains_key(leader) {
+                    rltk::console::log(format!("Warning: {} follows unknown mob {}", mob.name, leader));
+                }
+            }
+            for ability in mob.abilities.iter().flatten() {
+                if let Some(summon) = ability.effects.get("summon") {
+                    if !self.mob_index.contains_key(summon) {
+                        rltk::console::log(format!("Warning: {}'s {} summons unknown mob {}", mob.name, ability.name, summon));
+                    }
+                }
+            };
+        };
+
+        for (i,unique) in self.raws.uniques.iter().enumerate() {
+            if !self.mob_index.contains_key(&unique.name) {
+                rltk::console::log(format!("Warning: unique {} is not a mob", unique.name));
+            }
+            for item in unique.loot.iter().flatten() {
+                if !self.item_index.contains_key(item) {
+                    rltk::console::log(format!("Warning: {} carries unknown item {}", unique.name, item));
+                }
+            };
+            match (&unique.lair, unique.boss_depth) {
+                (Some(lair), None) => {
+                    let width = lair.first().map_or(0, |row| row.chars().count());
+                    if lair.iter().any(|row| row.chars().count() != width) {
+                        rltk::console::log(format!("Warning: {}'s lair has ragged rows", unique.name));
+                    }
+                    if lair.iter().map(|row| row.matches('U').count()).sum::<usize>() != 1 {
+                        rltk::console::log(format!("Warning: {}'s lair needs exactly one U", unique.name));
+                    }
+                    if unique.min_depth.is_none() || unique.max_depth.is_none() {
+                        rltk::console::log(format!("Warning: {} has a lair but no depth range", unique.name));
+                    }
+                }
+                (None, Some(_)) => {},
+                _ => rltk::console::log(format!("Warning: {} needs either a lair or a boss depth", unique.name)),
+            }
+            self.unique_index.insert(unique.name.clone(), i);
+        };
 
         for spawn in self.raws.spawn_table.iter() {
             if !used_names.contains(&spawn.name) {
                 rltk::console::log(format!("Warning: Spawn table references unspecified entity {}", spawn.name));
             }
+            if self.unique_index.contains_key(&spawn.name) {
+                rltk::console::log(format!("Warning: Spawn table rolls for unique {}", spawn.name));
+            }
+        };
+    }
+
+    pub fn load_levels (&mut self, levels: LevelRaws) {
+        self.levels = levels;
+        self.chain_index = HashMap::new();
+        for (i,chain) in self.levels.chains.iter().enumerate() {
+            if self.chain_index.insert(chain.name.clone(), i).is_some() {
+                rltk::console::log(format!("Warning: duplicate builder chain name in levels [{}]", chain.name));
+            }
+        };
+        for chain in self.levels.chains.iter() {
+            if chain.width.is_some_and(|w| w < MIN_MAP_SIZE) || chain.height.is_some_and(|h| h < MIN_MAP_SIZE) {
+                rltk::console::log(format!("Warning: builder chain {} is smaller than {}x{}, which is as small as maps go", chain.name, MIN_MAP_SIZE, MIN_MAP_SIZE));
+            }
+            let steps = chain.starter.iter().chain(chain.builders.iter());
+            for step in steps {
+                self.check_chain_step(&chain.name, step);
+            };
+        };
+        let mut branches: HashSet<&str> = HashSet::new();
+        branches.insert(MAIN_BRANCH);
+        for branch in self.levels.branches.iter() {
+            if !branches.insert(&branch.name) {
+                rltk::console::log(format!("Warning: duplicate branch name in levels [{}]", branch.name));
+            }
+        };
+        for branch in self.levels.branches.iter() {
+            if !branches.contains(branch.parent()) {
+                rltk::console::log(format!("Warning: branch {} leaves from unknown branch {}", branch.name, branch.parent()));
+            }
+            if branch.length < 1 {
+                rltk::console::log(format!("Warning: branch {} has no levels (length {})", branch.name, branch.length));
+            }
+            if !self.levels.levels.iter().any(|level| level.branch() == branch.name) {
+                rltk::console::log(format!("Warning: no level entry builds branch {}", branch.name));
+            }
+        };
+        for level in self.levels.levels.iter() {
+            if !self.chain_index.contains_key(&level.chain) {
+                rltk::console::log(format!("Warning: depths {}+ use unknown builder chain {}", level.min_depth, level.chain));
+            }
+            if !branches.contains(level.branch()) {
+                rltk::console::log(format!("Warning: depths {}+ are in unknown branch {}", level.min_depth, level.branch()));
+            }
+        };
+        for spawn in self.raws.spawn_table.iter() {
+            if let Some(branch) = spawn.branch.as_deref().filter(|branch| !branches.contains(branch)) {
+                rltk::console::log(format!("Warning: spawn table puts {} in unknown branch {}", spawn.name, branch));
+            }
         };
+        if !self.levels.levels.iter().any(|level| level.branch() == MAIN_BRANCH && level.max_depth.is_none()) {
+            rltk::console::log("Warning: no level entry is open-ended, so the deepest levels will reuse the last one".to_string());
+        }
     }
+
+    pub fn load_prefabs (&mut self, prefabs: Vec<Prefab>) {
+        self.prefabs = prefabs;
+        self.prefab_index = HashMap::new();
+        for (i,prefab) in self.prefabs.iter().enumerate() {
+            self.prefab_index.insert(prefab.name.clone(), i);
+            let mut glyphs: Vec<char> = prefab.glyphs.clone();
+            glyphs.sort_unstable();
+            glyphs.dedup();
+            for glyph in glyphs.iter() {
+                if let Some(LegendEntry::Spawn(name)) = prefab.meaning(*glyph) {
+                    if !self.item_index.contains_key(&name) && !self.mob_index.contains_key(&name) && !self.prop_index.contains_key(&name) {
+                        rltk::console::log(format!("Warning: prefab {} places unknown entity {} for '{}'", prefab.name, name, glyph));
+                    }
+                }
+            };
+        };
+    }
+
+    fn check_prefab_step (&self, chain: &str, step: &BuilderStep) {
+        let kind = match step.mode.as_deref() {
+            Some("vaults") => return,
+            Some("sectional") => PrefabKind::Section,
+            Some("constant") => PrefabKind::Level,
+            _ => {
+                rltk::console::log(format!("Warning: builder chain {} has a prefab step without a mode of vaults, sectional or constant", chain));
+                return;
+            }
+        };
+        match step.prefab.as_ref().map(|name| (name, self.prefab_index.get(name))) {
+            None => rltk::console::log(format!("Warning: builder chain {} has a prefab step that doesn't name its prefab", chain)),
+            Some((name, None)) => rltk::console::log(format!("Warning: builder chain {} uses unknown prefab {}", chain, name)),
+            Some((name, Some(idx))) => if self.prefabs[*idx].kind != kind {
+                rltk::console::log(format!("Warning: builder chain {} uses prefab {} the wrong way (check its kind)", chain, name));
+            },
+        }
+    }
+
+    fn check_waveform_step (&self, chain: &str, step: &BuilderStep) {
+        if let Some(name) = step.prefab.as_ref().filter(|name| !self.prefab_index.contains_key(*name)) {
+            rltk::console::log(format!("Warning: builder chain {} samples unknown prefab {}", chain, name));
+        }
+        if step.pattern_size.is_some_and(|size| size < 2) {
+            rltk::console::log(format!("Warning: builder chain {} has a waveform collapse pattern_size under 2", chain));
+        }
+        for pin in step.pin.iter().filter(|pin| *pin != "stairs" && *pin != "start") {
+            rltk::console::log(format!("Warning: builder chain {} pins {}, which waveform collapse doesn't know (try stairs or start)", chain, pin));
+        }
+    }
+
+    fn check_chain_step (&self, chain: &str, step: &ChainStep) {
+        match step {
+            ChainStep::Include { chain: included } => {
+                if !self.chain_index.contains_key(included) {
+                    rltk::console::log(format!("Warning: builder chain {} includes unknown chain {}", chain, included));
+                }
+            }
+            ChainStep::OneOf { one_of } => {
+                for alternative in one_of.iter() {
+                    if alternative.weight.unwrap_or(1) < 1 {
+                        rltk::console::log(format!("Warning: builder chain {} has an alternative that can never be picked", chain));
+                    }
+                    for step in alternative.steps.iter() { self.check_chain_step(chain, step); }
+                };
+            }
+            ChainStep::OneIn { one_in, steps } => {
+                if *one_in < 1 {
+                    rltk::console::log(format!("Warning: builder chain {} has a one_in of {}", chain, one_in));
+                }
+                for step in steps.iter() { self.check_chain_step(chain, step); }
+            }
+            ChainStep::Builder(builder) => match builder.builder.as_str() {
+                "prefab" => self.check_prefab_step(chain, builder),
+                "waveform_collapse" => self.check_waveform_step(chain, builder),
+                _ => {}
+            },
+        }
+    }
+}
+
+/// What can turn up at a depth down a branch. A branch with no spawn table of its own uses
+/// the main dungeon's.
+pub fn get_spawn_table_for_depth (raws: &RawMaster, branch: &str, depth: i32) -> RandomTable {
+    spawn_table(raws, branch, depth, None)
+}
+
+/// The spawn table for one biome of an outdoor level: what's tagged for it, and what isn't tagged at all
+pub fn get_spawn_table_for_biome (raws: &RawMaster, branch: &str, depth: i32, biome: &str) -> RandomTable {
+    spawn_table(raws, branch, depth, Some(biome))
 }
 
-pub fn get_spawn_table_for_depth (raws: &RawMaster, depth: i32) -> RandomTable {
+fn spawn_table (raws: &RawMaster, branch: &str, depth: i32, biome: Option<&str>) -> RandomTable {
     use super::SpawnTableEntry;
+    let own_table = raws.raws.spawn_table.iter().any(|a| a.branch.as_deref() == Some(branch));
+    let table = if own_table { branch } else { MAIN_BRANCH };
     let available_options: Vec<&SpawnTableEntry> = raws.raws.spawn_table.iter()
-        .filter(|a| depth >= a.min_depth && depth <= a.max_depth).collect();
+        .filter(|a| a.branch.as_deref().unwrap_or(MAIN_BRANCH) == table)
+        .filter(|a| depth >= a.min_depth && depth <= a.max_depth)
+        .filter(|a| biome.is_none_or(|biome| a.tags.is_empty() || a.tags.iter().any(|tag| tag == biome)))
+        .collect();
     let mut rt = RandomTable::new();
 
     for e in available_options.iter() {
@@ -140,6 +392,43 @@ fn spawn_position <'a>(pos: SpawnType, new_entity: EntityBuilder<'a>, tag: &str,
     }
 }
 
+fn parse_locomotion (modes: &[String], key: &str) -> Locomotion {
+    let mut locomotion = Locomotion::default();
+    for mode in modes.iter() {
+        match mode.as_str() {
+            "flying" => locomotion.flying = true,
+            "swimming" => locomotion.swimming = true,
+            "phasing" => locomotion.phasing = true,
+            "heavy" => locomotion.heavy = true,
+            _ => rltk::console::log(format!("Warning: unknown locomotion [{}] for {}", mode, key)),
+        }
+    };
+    locomotion
+}
+
+/// Finds somewhere near (x, y) with room for every tile of a big creature, preferring
+/// spots that still cover (x, y) itself
+fn fit_footprint (map: &crate::map::Map, x: i32, y: i32, size: &TileSize, locomotion: &Locomotion) -> Option<(i32, i32)> {
+    let fits = |ax: i32, ay: i32| {
+        ax > 0 && ay > 0 && ax + size.x < map.width && ay + size.y < map.height
+            && map.footprint(ax, ay, Some(size)).iter()
+                .all(|idx| crate::map::tile_passable(map.tiles[*idx], locomotion))
+    };
+    for dy in 0 .. size.y {
+        for dx in 0 .. size.x {
+            if fits(x - dx, y - dy) { return Some((x - dx, y - dy)); }
+        };
+    };
+    for radius in 1 ..= i32::max(size.x, size.y) + 2 {
+        for ay in y - radius ..= y + radius {
+            for ax in x - radius ..= x + radius {
+                if fits(ax, ay) { return Some((ax, ay)); }
+            };
+        };
+    };
+    None
+}
+
 fn get_renderable_component (renderable: &super::item_structs::Renderable) -> crate::components::Renderable {
     crate::components::Renderable {
         glyph: rltk::to_cp437(renderable.glyph.chars().next().unwrap()),
@@ -165,6 +454,21 @@ pub fn string_to_slot (slot: &str) -> EquipmentSlot {
     }
 }
 
+/// None if the location is unknown; Some(None) is the mob's own home
+fn string_to_schedule_location (location: &str) -> Option<Option<BuildingTag>> {
+    match location {
+        "Pub" => Some(Some(BuildingTag::Pub)),
+        "Temple" => Some(Some(BuildingTag::Temple)),
+        "Blacksmith" => Some(Some(BuildingTag::Blacksmith)),
+        "Clothier" => Some(Some(BuildingTag::Clothier)),
+        "Alchemist" => Some(Some(BuildingTag::Alchemist)),
+        "PlayerHouse" => Some(Some(BuildingTag::PlayerHouse)),
+        "Hovel" => Some(Some(BuildingTag::Hovel)),
+        "Home" => Some(None),
+        _ => None,
+    }
+}
+
 pub fn spawn_named_item (raws: &RawMaster, ecs: &mut World, key: &str, pos: SpawnType) -> Option<Entity> {
     if raws.item_index.contains_key(key) {
         let item_template = &raws.raws.items[raws.item_index[key]];
@@ -206,6 +510,7 @@ pub fn spawn_named_item (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spaw
         
         if let Some(weapon) = &item_template.weapon {
             eb = eb.with(Equippable { slot: EquipmentSlot::Melee });
+            eb = eb.with(Durability { current: ITEM_DURABILITY, max: ITEM_DURABILITY });
             let (n_dice, die_type, bonus) = parse_dice_string(&weapon.base_damage);
             let mut wpn = MeleeWeapon {
                 attribute: WeaponAttribute::Might,
@@ -225,6 +530,7 @@ pub fn spawn_named_item (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spaw
             let slot = string_to_slot(&wearable.slot);
             eb = eb.with(Equippable { slot });
             eb = eb.with(Wearable { slot, armor_class: wearable.armor_class });
+            eb = eb.with(Durability { current: ITEM_DURABILITY, max: ITEM_DURABILITY });
         }
 
         if let Some(magic) = &item_template.magic {
@@ -233,7 +539,7 @@ pub fn spawn_named_item (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spaw
                 "legendary" => MagicItemClass::Legendary,
                 _ => MagicItemClass::Common,
             };
-            eb = eb.with(MagicItem { class });
+            eb = eb.with(MagicItem { class, naming: magic.naming.clone() });
         }
 
         return Some(eb.build());
@@ -241,9 +547,85 @@ pub fn spawn_named_item (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spaw
     None
 }
 
+fn build_ability (raw: &super::MobAbility, mob: &str) -> Ability {
+    let trigger = match raw.trigger.as_deref() {
+        Some("hurt") => AbilityTrigger::Hurt,
+        _ => AbilityTrigger::Turn,
+    };
+    let mut area = 0;
+    let mut effects = Vec::new();
+    let count = raw.effects.get("count").map_or(1, |c| c.parse::<i32>().unwrap());
+    for (effect, value) in raw.effects.iter() {
+        match effect.as_str() {
+            "damage" => {
+                let (n_dice, die_type, bonus) = parse_dice_string(value);
+                effects.push(AbilityEffect::Damage { n_dice, die_type, bonus });
+            }
+            "area_of_effect" => area = value.parse::<i32>().unwrap(),
+            "confusion" => effects.push(AbilityEffect::Confuse { turns: value.parse::<i32>().unwrap() }),
+            "hold" => effects.push(AbilityEffect::Hold { turns: value.parse::<i32>().unwrap() }),
+            "summon" => effects.push(AbilityEffect::Summon { name: value.clone(), count }),
+            "split" => effects.push(AbilityEffect::Split { mob: mob.to_string() }),
+            "count" => {},
+            _ => rltk::console::log(format!("Warning: ability effect {} not implemented (on {})", effect, mob)),
+        }
+    };
+    Ability {
+        name: raw.name.clone(),
+        trigger,
+        chance: raw.chance,
+        cooldown: raw.cooldown,
+        cooldown_left: 0,
+        range: raw.range,
+        area,
+        effects,
+    }
+}
+
 pub fn spawn_named_mob (raws: &RawMaster, ecs: &mut World, key: &str, pos: SpawnType) -> Option<Entity> {
+    spawn_mob(raws, ecs, key, pos, false)
+}
+
+/// A copy of a mob split off from another: never unique, and with no gold, gear or loot
+pub fn spawn_offshoot (raws: &RawMaster, ecs: &mut World, key: &str, pos: SpawnType) -> Option<Entity> {
+    spawn_mob(raws, ecs, key, pos, true)
+}
+
+fn spawn_mob (raws: &RawMaster, ecs: &mut World, key: &str, pos: SpawnType, offshoot: bool) -> Option<Entity> {
     if raws.mob_index.contains_key(key) {
+        let unique = !offshoot && raws.unique_index.contains_key(key);
         let mob_template = &raws.raws.mobs[raws.mob_index[key]];
+        let locomotion = mob_template.locomotion.as_ref().map(|modes| parse_locomotion(modes, key));
+        let size = mob_template.size.filter(|n| *n > 1).map(|n| TileSize { x: n, y: n });
+        /* Big creatures shuffle over until every tile they cover has room for them */
+        let mut pos = pos;
+        if let (Some(size), SpawnType::AtPosition { x, y }) = (&size, &pos) {
+            let fitted = fit_footprint(&ecs.fetch::<crate::map::Map>(), *x, *y, size, &locomotion.unwrap_or_default());
+            match fitted {
+                Some((x, y)) => pos = SpawnType::AtPosition { x, y },
+                None => {
+                    rltk::console::log(format!("Warning: no room for {} near ({}, {})", key, x, y));
+                    return None;
+                }
+            }
+        }
+        /* Uniques only ever turn up once per run */
+        if unique {
+            let depth = ecs.fetch::<crate::map::Map>().depth;
+            let mut dungeon = ecs.fetch_mut::<MasterDungeonMap>();
+            if dungeon.unique_state(key) != UniqueState::Unspawned { return None; }
+            dungeon.set_unique_state(key, UniqueState::Alive { depth });
+        }
+        /* Where it ended up standing, once any shuffling over is done */
+        let home = match pos {
+            SpawnType::AtPosition { x, y } => Some(ecs.fetch::<crate::map::Map>().xy_idx(x, y)),
+            _ => None,
+        };
+        /* Rolled from the run's own stream, so a seed always hands out the same purses */
+        let gold = mob_template.gold.as_ref().filter(|_| !offshoot).map_or(0.0, |gold| {
+            let (n, d, b) = parse_dice_string(gold);
+            (ecs.write_resource::<rltk::RandomNumberGenerator>().roll_dice(n, d) + b) as f32
+        });
         let mut eb = ecs.create_entity().marked::<SimpleMarker<SerializeMe>>();
         eb = spawn_position(pos, eb, key, raws);
 
@@ -259,6 +641,12 @@ pub fn spawn_named_mob (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spawn
         match mob_template.movement.as_ref() {
             "random" => eb = eb.with(MoveMode { mode: Movement::Random }),
             "random_waypoint" => eb = eb.with(MoveMode { mode: Movement::RandomWaypoint { path: None } }),
+            /* Nowhere on the map to guard, so it stays put */
+            "guard" => eb = eb.with(MoveMode { mode: match home {
+                Some(home) => Movement::Guard { home, radius: mob_template.movement_radius.unwrap_or(DEFAULT_GUARD_RADIUS) },
+                None => Movement::Static,
+            }}),
+            /* "follow" stays put until the map builder finds it a leader */
             _ => eb = eb.with(MoveMode { mode: Movement::Static }),
         }
 
@@ -301,11 +689,7 @@ pub fn spawn_named_mob (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spawn
             mana: Pool { current: mob_mana, max: mob_mana },
             total_weight: 0.0,
             total_initiative_penalty: 0.0,
-            gold: if let Some(gold) = &mob_template.gold {
-                let mut rng = rltk::RandomNumberGenerator::new();
-                let (n, d, b) = parse_dice_string(&gold);
-                (rng.roll_dice(n, d) + b) as f32
-            } else { 0.0 },
+            gold,
             god_mode: false,
         };
         eb = eb.with(pools);
@@ -347,7 +731,7 @@ pub fn spawn_named_mob (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spawn
             }
             eb = eb.with(nature);
         }
-        if let Some(loot) = &mob_template.loot_table {
+        if let (false, Some(loot)) = (offshoot, &mob_template.loot_table) {
             eb = eb.with(LootTable { table: loot.clone() });
         }
         if let Some(light) = &mob_template.light {
@@ -356,22 +740,94 @@ pub fn spawn_named_mob (raws: &RawMaster, ecs: &mut World, key: &str, pos: Spawn
 
         eb = eb.with(Initiative { current: 2 });
 
+        eb = eb.with(Personality {
+            name: mob_template.personality.clone().unwrap_or_else(|| "Default".to_string())
+        });
+
         if let Some(faction) = &mob_template.faction {
             eb = eb.with(Faction { name: faction.clone() });
         } else {
             eb = eb.with(Faction { name: "Mindless".to_string() });
         }
 
+        /* Mindless things never lose their nerve */
+        if mob_template.faction.is_some() {
+            let (base, can_surrender) = match &mob_template.morale {
+                Some(morale) => (morale.base, morale.surrender.unwrap_or(false)),
+                None => (10.0, false),
+            };
+            eb = eb.with(Morale { base, current: base, broken: false, cowering: false, can_surrender });
+        }
+
         eb = eb.with(EquipmentChanged{});
 
         if let Some(vendor) = &mob_template.vendor {
-            eb = eb.with(Vendor { categories: vendor.clone() });
+            eb = eb.with(Vendor { categories: vendor.clone(), restock_turn: 0 });
+        }
+
+        if let Some(cost) = mob_template.hire_cost {
+            eb = eb.with(Hireling { cost });
+        }
+
+        if mob_template.tameable.unwrap_or(false) {
+            eb = eb.with(Tameable {});
+        }
+
+        if let Some(abilities) = &mob_template.abilities {
+            eb = eb.with(Abilities { abilities: abilities.iter().map(|a| build_ability(a, key)).collect() });
+        }
+
+        if let Some(locomotion) = locomotion {
+            eb = eb.with(locomotion);
+        }
+
+        if let Some(size) = size {
+            eb = eb.with(size);
+        }
+
+        if mob_template.humanoid.unwrap_or(false) {
+            eb = eb.with(Humanoid {});
+        }
+
+        if mob_template.town_guard.unwrap_or(false) {
+            eb = eb.with(TownGuard {});
+        }
+
+        if let Some(schedule) = &mob_template.schedule {
+            /* Unknown locations were dropped at load */
+            let entries = schedule.iter().filter_map(|entry| Some(ScheduleEntry {
+                start_hour: entry.start,
+                end_hour: entry.end,
+                location: string_to_schedule_location(&entry.location)?,
+                post: entry.post.unwrap_or(false),
+            })).collect();
+            eb = eb.with(Schedule { entries, home: None });
+        }
+
+        if unique {
+            eb = eb.with(Unique { announced: false });
+        }
+
+        if offshoot {
+            eb = eb.with(Offshoot {});
         }
 
         let new_mob = eb.build();
-        if let Some(wielding) = &mob_template.equipped {
+        if let (false, Some(wielding)) = (offshoot, &mob_template.equipped) {
             for tag in wielding.iter() {
-                spawn_named_entity(raws, ecs, tag, SpawnType::Equipped { by: new_mob });
+                /* Monsters don't look after their kit */
+                if let Some(item) = spawn_named_entity(raws, ecs, tag, SpawnType::Equipped { by: new_mob }) {
+                    let wear = ecs.write_resource::<rltk::RandomNumberGenerator>().range(MOB_GEAR_MIN_WEAR, MOB_GEAR_MAX_WEAR);
+                    if let Some(durability) = ecs.write_storage::<Durability>().get_mut(item) {
+                        durability.current = durability.max - wear;
+                    }
+                }
+            };
+        }
+        /* A unique's treasure is carried, so it always drops when they fall */
+        if let (true, Some(unique)) = (unique, raws.unique_index.get(key)) {
+            for tag in raws.raws.uniques[*unique].loot.iter().flatten() {
+                spawn_named_entity(raws, ecs, tag, SpawnType::Carried { by: new_mob });
             };
         }
 
@@ -461,7 +917,44 @@ pub fn get_item_drop (raws: &RawMaster, rng: &mut rltk::RandomNumberGenerator, t
     None
 }
 
+pub fn is_mob (raws: &RawMaster, key: &str) -> bool {
+    raws.mob_index.contains_key(key)
+}
+
+/// How a mob gets about and how big it is, for finding it room before it's spawned
+pub fn get_mob_footprint (raws: &RawMaster, key: &str) -> Option<(Locomotion, Option<TileSize>)> {
+    if !raws.mob_index.contains_key(key) { return None; }
+    let mob_template = &raws.raws.mobs[raws.mob_index[key]];
+    let locomotion = mob_template.locomotion.as_ref().map(|modes| parse_locomotion(modes, key));
+    let size = mob_template.size.filter(|n| *n > 1).map(|n| TileSize { x: n, y: n });
+    Some((locomotion.unwrap_or_default(), size))
+}
+
+/// Which mob (if any) this one follows around, and how closely
+pub fn get_mob_leader (raws: &RawMaster, key: &str) -> Option<(String, i32)> {
+    if !raws.mob_index.contains_key(key) { return None; }
+    let mob_template = &raws.raws.mobs[raws.mob_index[key]];
+    if mob_template.movement != "follow" { return None; }
+    mob_template.leader.as_ref().map(|leader| {
+        (leader.clone(), mob_template.movement_radius.unwrap_or(DEFAULT_FOLLOW_DISTANCE))
+    })
+}
+
 pub fn faction_reaction (my_faction: &str, their_faction: &str, raws: &RawMaster) -> Reaction {
+    /* Companions side with the player: they fight whoever would fight the player, and are treated as the player */
+    if my_faction == COMPANION_FACTION {
+        return match their_faction {
+            "Player" | COMPANION_FACTION => Reaction::Ignore,
+            _ => match faction_reaction(their_faction, "Player", raws) {
+                Reaction::Attack => Reaction::Attack,
+                _ => Reaction::Ignore,
+            },
+        };
+    }
+    if their_faction == COMPANION_FACTION {
+        return faction_reaction(my_faction, "Player", raws);
+    }
+
     if raws.faction_index.contains_key(my_faction) {
         let mf = &raws.faction_index[my_faction];
         if mf.contains_key(their_faction) {
@@ -475,6 +968,28 @@ pub fn faction_reaction (my_faction: &str, their_faction: &str, raws: &RawMaster
     Reaction::Ignore
 }
 
+/// Law-abiding folk: attacking or robbing them is a crime
+pub fn is_peaceful_faction (faction: &str, raws: &RawMaster) -> bool {
+    raws.raws.faction_table.iter().any(|f| f.name == faction && f.peaceful.unwrap_or(false))
+}
+
+/// Scores an action for a personality, feeding `input` (0..1) through the action's curve.
+/// Actions a personality doesn't list are never chosen.
+pub fn utility_score (personality: &str, action: &str, input: f32, raws: &RawMaster) -> f32 {
+    if let Some((weight, curve)) = raws.personality_index.get(personality).and_then(|actions| actions.get(action)) {
+        let x = input.clamp(0.0, 1.0);
+        let response = match curve {
+            Curve::Constant => 1.0,
+            Curve::Linear => x,
+            Curve::Inverse => 1.0 - x,
+            Curve::Quadratic => x * x,
+            Curve::Sqrt => x.sqrt(),
+            Curve::Logistic => 1.0 / (1.0 + f32::exp(-10.0 * (x - 0.5))),
+        };
+        weight * response
+    } else { 0.0 }
+}
+
 pub fn get_vendor_items (categories: &[String], raws: &RawMaster) -> Vec<(String, f32)> {
     let mut result: Vec<(String, f32)> = Vec::new();
     for item in raws.raws.items.iter() {
@@ -486,3 +1001,241 @@ pub fn get_vendor_items (categories: &[String], raws: &RawMaster) -> Vec<(String
     };
     result
 }
+
+pub fn is_unique (raws: &RawMaster, key: &str) -> bool {
+    raws.unique_index.contains_key(key)
+}
+
+pub fn get_unique_announcement (raws: &RawMaster, key: &str) -> Option<String> {
+    raws.unique_index.get(key).map(|idx| raws.raws.uniques[*idx].announcement.clone())
+}
+
+/// Uniques that may make their lair at this depth, with how many levels (including this
+/// one) they have left to turn up on
+pub fn get_unique_lairs (raws: &RawMaster, depth: i32) -> Vec<(String, Vec<String>, i32)> {
+    raws.raws.uniques.iter()
+        .filter_map(|unique| match (&unique.lair, unique.min_depth, unique.max_depth) {
+            (Some(lair), Some(min), Some(max)) if depth >= min && depth <= max =>
+                Some((unique.name.clone(), lair.clone(), max - depth + 1)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn get_boss_for_depth (raws: &RawMaster, depth: i32) -> Option<String> {
+    raws.raws.uniques.iter()
+        .find(|unique| unique.boss_depth == Some(depth))
+        .map(|unique| unique.name.clone())
+}
+
+/// The level entries covering this level; past the end of its branch, the deepest one there is
+pub fn get_levels_for_depth <'a>(raws: &'a RawMaster, level: &LevelId) -> Vec<&'a LevelEntry> {
+    let branch = raws.levels.levels.iter().filter(|entry| entry.branch() == level.branch);
+    let levels: Vec<&LevelEntry> = branch.clone()
+        .filter(|entry| level.depth >= entry.min_depth && entry.max_depth.is_none_or(|max| level.depth <= max))
+        .collect();
+    if !levels.is_empty() { return levels; }
+    branch.max_by_key(|entry| entry.min_depth).into_iter().collect()
+}
+
+pub fn get_branch<'a> (raws: &'a RawMaster, name: &str) -> Option<&'a BranchDef> {
+    raws.levels.branches.iter().find(|branch| branch.name == name)
+}
+
+/// The top levels of the branches with stairs down into them from this level
+pub fn get_branches_from (raws: &RawMaster, level: &LevelId) -> Vec<LevelId> {
+    raws.levels.branches.iter()
+        .filter(|branch| branch.parent() == level.branch && branch.depth == level.depth)
+        .map(|branch| LevelId::new(&branch.name, branch.depth + 1))
+        .collect()
+}
+
+/// Every level of every side branch, top to bottom
+pub fn get_branch_levels (raws: &RawMaster) -> Vec<LevelId> {
+    raws.levels.branches.iter()
+        .flat_map(|branch| (1 ..= branch.length).map(move |n| LevelId::new(&branch.name, branch.depth + n)))
+        .collect()
+}
+
+pub fn get_builder_chain<'a> (raws: &'a RawMaster, name: &str) -> Option<&'a BuilderChainDef> {
+    raws.chain_index.get(name).map(|idx| &raws.levels.chains[*idx])
+}
+
+pub fn get_builder_chain_names (raws: &RawMaster) -> Vec<String> {
+    raws.levels.chains.iter().map(|chain| chain.name.clone()).collect()
+}
+
+pub fn get_prefab<'a> (raws: &'a RawMaster, name: &str) -> Option<&'a Prefab> {
+    raws.prefab_index.get(name).map(|idx| &raws.prefabs[*idx])
+}
+
+pub fn get_prefabs_of_kind (raws: &RawMaster, kind: PrefabKind) -> Vec<Prefab> {
+    raws.prefabs.iter().filter(|prefab| prefab.kind == kind).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+    use crate::test_support::{lock_globals, open_map};
+
+    /// Raws holding only the given mobs and personalities, each a comma-separated list of JSON objects
+    fn raws_with (mobs: &str, personalities: &str) -> RawMaster {
+        let json = format!(r#"{{ "items" : [], "mobs" : [{}], "props" : [], "spawn_table" : [], "loot_tables" : [],
+            "faction_table" : [], "personalities" : [{}], "uniques" : [] }}"#, mobs, personalities);
+        let mut raws = RawMaster::empty();
+        raws.load(serde_json::from_str(&json).expect("Bad test raws"));
+        raws
+    }
+
+    #[test]
+    fn utility_curves_shape_the_weight () {
+        let raws = raws_with("", r#"{ "name" : "Test", "actions" : {
+            "constant" : { "weight" : 2.0, "curve" : "constant" },
+            "linear" : { "weight" : 2.0, "curve" : "linear" },
+            "inverse" : { "weight" : 2.0, "curve" : "inverse" },
+            "quadratic" : { "weight" : 2.0, "curve" : "quadratic" },
+            "sqrt" : { "weight" : 2.0, "curve" : "sqrt" },
+            "logistic" : { "weight" : 2.0, "curve" : "logistic" }
+        }}"#);
+        let score = |action: &str, input: f32| utility_score("Test", action, input, &raws);
+        assert_eq!(score("constant", 0.1), 2.0);
+        assert_eq!(score("linear", 0.25), 0.5);
+        assert_eq!(score("inverse", 0.25), 1.5);
+        assert_eq!(score("quadratic", 0.5), 0.5);
+        assert_eq!(score("sqrt", 0.25), 1.0);
+        assert_eq!(score("logistic", 0.5), 1.0);
+        assert!(score("logistic", 0.1) < 0.1 && score("logistic", 0.9) > 1.9);
+        /* Inputs outside 0 to 1 are clamped */
+        assert_eq!(score("linear", 3.0), 2.0);
+        assert_eq!(score("inverse", -1.0), 2.0);
+    }
+
+    #[test]
+    fn missing_actions_and_personalities_score_nothing () {
+        let raws = raws_with("", r#"{ "name" : "Test", "actions" : { "attack" : { "weight" : 1.0, "curve" : "constant" },
+            "flee" : { "weight" : 1.0, "curve" : "wobbly" } } }"#);
+        assert_eq!(utility_score("Test", "use_ability", 1.0, &raws), 0.0);
+        assert_eq!(utility_score("Nobody", "attack", 1.0, &raws), 0.0);
+        /* Dropped at load, rather than quietly scored as some other curve */
+        assert_eq!(utility_score("Test", "flee", 1.0, &raws), 0.0);
+    }
+
+    #[test]
+    fn unknown_schedule_locations_are_dropped_at_load () {
+        let raws = raws_with(r#"{ "name" : "Baker", "blocks_tile" : true, "vision_range" : 4, "movement" : "static",
+            "attributes" : {}, "schedule" : [
+                { "start" : 6, "end" : 18, "location" : "Bakery", "post" : true },
+                { "start" : 18, "end" : 6, "location" : "Home" } ] }"#, "");
+        let schedule = raws.raws.mobs[0].schedule.as_ref().unwrap();
+        assert!(schedule.len() == 1 && schedule[0].location == "Home");
+    }
+
+    #[test]
+    fn abilities_are_built_from_their_raw_effects () {
+        let raw = |json: &str| -> super::super::MobAbility { serde_json::from_str(json).expect("Bad test ability") };
+        let summon = build_ability(&raw(r#"{ "name" : "Call", "chance" : 0.5, "cooldown" : 8, "range" : 6.0,
+            "effects" : { "summon" : "Goblin", "count" : "3" } }"#), "Shaman");
+        assert!(summon.trigger == AbilityTrigger::Turn && summon.area == 0 && summon.cooldown_left == 0);
+        assert!(matches!(summon.effects.as_slice(), [AbilityEffect::Summon { name, count: 3 }] if name == "Goblin"));
+
+        let split = build_ability(&raw(r#"{ "name" : "Split", "trigger" : "hurt", "chance" : 1.0, "cooldown" : 0,
+            "range" : 0.0, "effects" : { "split" : "" } }"#), "Green Slime");
+        assert!(split.trigger == AbilityTrigger::Hurt);
+        assert!(matches!(split.effects.as_slice(), [AbilityEffect::Split { mob }] if mob == "Green Slime"));
+
+        let web = build_ability(&raw(r#"{ "name" : "Web", "chance" : 1.0, "cooldown" : 4, "range" : 5.0,
+            "effects" : { "hold" : "3", "area_of_effect" : "2" } }"#), "Spider");
+        assert_eq!(web.area, 2);
+        assert!(matches!(web.effects.as_slice(), [AbilityEffect::Hold { turns: 3 }]));
+    }
+
+    fn raws_with_uniques (uniques: &str) -> RawMaster {
+        let json = format!(r#"{{ "items" : [], "mobs" : [], "props" : [], "spawn_table" : [], "loot_tables" : [],
+            "faction_table" : [], "personalities" : [], "uniques" : [{}] }}"#, uniques);
+        let mut raws = RawMaster::empty();
+        raws.load(serde_json::from_str(&json).expect("Bad test raws"));
+        raws
+    }
+
+    #[test]
+    fn uniques_keep_to_their_depths () {
+        let raws = raws_with_uniques(r#"
+            { "name" : "Grub", "min_depth" : 3, "max_depth" : 5, "announcement" : "Hi", "lair" : [ "U" ] },
+            { "name" : "Ember", "boss_depth" : 10, "announcement" : "Roar" }"#);
+        assert!(is_unique(&raws, "Grub") && is_unique(&raws, "Ember") && !is_unique(&raws, "Rat"));
+        assert_eq!(get_unique_announcement(&raws, "Ember"), Some("Roar".to_string()));
+        assert!(get_unique_lairs(&raws, 2).is_empty() && get_unique_lairs(&raws, 6).is_empty());
+        /* The last chance is a sure thing */
+        let lairs = get_unique_lairs(&raws, 3);
+        assert!(lairs.len() == 1 && lairs[0].0 == "Grub" && lairs[0].2 == 3);
+        assert_eq!(get_unique_lairs(&raws, 5)[0].2, 1);
+        assert_eq!(get_boss_for_depth(&raws, 10), Some("Ember".to_string()));
+        assert_eq!(get_boss_for_depth(&raws, 5), None);
+    }
+
+    #[test]
+    fn big_creatures_shuffle_over_until_they_fit () {
+        let _globals = lock_globals();
+        /* A room with a pool in its north-west corner */
+        let mut map = open_map(1, 10, 10);
+        for y in 1 .. 3 {
+            for x in 1 .. 3 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::DeepWater;
+            };
+        };
+        let walker = Locomotion::default();
+        let two = TileSize { x: 2, y: 2 };
+        assert_eq!(fit_footprint(&map, 4, 4, &two, &walker), Some((4, 4)));
+        /* Against the east wall it keeps (8, 4) covered by stepping west */
+        assert_eq!(fit_footprint(&map, 8, 4, &two, &walker), Some((7, 4)));
+        /* Out of the pool, unless it can swim */
+        assert!(fit_footprint(&map, 1, 1, &two, &walker).is_some_and(|(x, y)| x >= 3 || y >= 3));
+        assert_eq!(fit_footprint(&map, 1, 1, &two, &Locomotion { swimming: true, ..Default::default() }), Some((1, 1)));
+        /* Far too big for the room */
+        assert_eq!(fit_footprint(&map, 4, 4, &TileSize { x: 9, y: 9 }, &walker), None);
+    }
+
+    #[test]
+    fn guards_watch_over_where_they_end_up () {
+        let _globals = lock_globals();
+        let raws = raws_with(r#"{ "name" : "Warden", "blocks_tile" : true, "vision_range" : 4, "movement" : "guard",
+            "size" : 2, "attributes" : {} }"#, "");
+        let mut ecs = crate::test_support::world(open_map(1, 10, 10));
+        let home = |ecs: &World, ent: Entity| match ecs.read_storage::<MoveMode>().get(ent).map(|m| &m.mode) {
+            Some(Movement::Guard { home, .. }) => Some(*home),
+            _ => None,
+        };
+        /* Shuffled off the east wall, and guarding the spot it was moved to */
+        let warden = spawn_named_mob(&raws, &mut ecs, "Warden", SpawnType::AtPosition { x: 8, y: 4 }).unwrap();
+        assert_eq!(home(&ecs, warden), Some(ecs.fetch::<crate::map::Map>().xy_idx(7, 4)));
+        /* Off the map there is nothing to guard */
+        let carried = spawn_named_mob(&raws, &mut ecs, "Warden", SpawnType::Carried { by: warden }).unwrap();
+        assert!(matches!(ecs.read_storage::<MoveMode>().get(carried).map(|m| &m.mode), Some(Movement::Static)));
+    }
+
+    #[test]
+    fn branches_hang_off_their_parent_level () {
+        let mut raws = RawMaster::empty();
+        raws.load_levels(serde_json::from_str(r#"{ "levels" : [
+                { "min_depth" : 1, "chain" : "main" },
+                { "branch" : "mines", "min_depth" : 4, "max_depth" : 4, "chain" : "mine top" },
+                { "branch" : "mines", "min_depth" : 5, "max_depth" : 5, "chain" : "mine bottom" },
+                { "branch" : "crypt", "min_depth" : 6, "chain" : "crypt" } ],
+            "branches" : [
+                { "name" : "mines", "depth" : 3, "length" : 2 },
+                { "name" : "crypt", "from" : "mines", "depth" : 5, "length" : 1 } ],
+            "chains" : [] }"#).expect("Bad test levels"));
+        assert_eq!(get_branches_from(&raws, &LevelId::main(3)), vec![LevelId::new("mines", 4)]);
+        assert_eq!(get_branches_from(&raws, &LevelId::new("mines", 5)), vec![LevelId::new("crypt", 6)]);
+        assert!(get_branches_from(&raws, &LevelId::main(5)).is_empty());
+        assert_eq!(get_branch_levels(&raws), vec![LevelId::new("mines", 4), LevelId::new("mines", 5), LevelId::new("crypt", 6)]);
+        assert_eq!(get_branch(&raws, "crypt").map(|branch| branch.parent()), Some("mines"));
+        /* Each branch only uses its own entries, and the deepest one carries on past the end */
+        let chains = |level: LevelId| -> Vec<String> { get_levels_for_depth(&raws, &level).iter().map(|e| e.chain.clone()).collect() };
+        assert_eq!(chains(LevelId::new("mines", 4)), vec!["mine top"]);
+        assert_eq!(chains(LevelId::new("mines", 9)), vec!["mine bottom"]);
+        assert_eq!(chains(LevelId::main(9)), vec!["main"]);
+    }
+}
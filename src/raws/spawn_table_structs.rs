@@ -6,5 +6,10 @@ pub struct SpawnTableEntry {
     pub weight: i32,
     pub min_depth: i32,
     pub max_depth: i32,
+    /* Which branch's table this is in; the main dungeon's, if none */
+    pub branch: Option<String>,
+    /* Biomes this turns up in on outdoor levels (anywhere, if none); other levels don't look */
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub add_map_depth_to_weight: Option<bool>,
 }
@@ -0,0 +1,12 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct UniqueMob {
+    pub name: String,
+    pub min_depth: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub boss_depth: Option<i32>,
+    pub announcement: String,
+    pub loot: Option<Vec<String>>,
+    pub lair: Option<Vec<String>>,
+}
@@ -39,12 +39,13 @@ pub fn save_game (ecs: &mut World) {
     /* Create Helper */
     let mapcopy = ecs.get_mut::<super::map::Map>().unwrap().clone();
     let dungeon_master = ecs.get_mut::<super::map::MasterDungeonMap>().unwrap().clone();
+    let clock = ecs.get_mut::<super::GameClock>().unwrap().clone();
     let savehelper = ecs.create_entity()
         .with(SerializationHelper { map: mapcopy })
         .marked::<SimpleMarker<SerializeMe>>()
         .build();
     let savehelper2 = ecs.create_entity()
-        .with(DMSerializationHelper { map: dungeon_master })
+        .with(DMSerializationHelper { map: dungeon_master, clock })
         .marked::<SimpleMarker<SerializeMe>>()
         .build();
 
@@ -64,7 +65,10 @@ pub fn save_game (ecs: &mut World) {
             OtherLevelPosition, DMSerializationHelper, LightSource, Initiative,
             MyTurn, Faction, WantsToApproach, WantsToFlee, MoveMode, Chasing,
             EquipmentChanged, Vendor, TownPortal, TeleportTo, ApplyMove, ApplyTeleport,
-            MagicItem
+            MagicItem, Morale, Surrendered, GoldPile, OwnedBy, Offshoot, Schedule, Personality,
+            Companion, Hireling, Tameable, Pursuer, Reputation, Durability, Humanoid, Locomotion,
+            Abilities, WantsToUseAbility, ChosenAbility, Held, Unique, TileSize,
+            TownGuard, Wanted, CrimeCommitted
         );
     }
     /* Cleanup */
@@ -104,7 +108,10 @@ pub fn load_game (ecs: &mut World) {
             OtherLevelPosition, DMSerializationHelper, LightSource, Initiative,
             MyTurn, Faction, WantsToApproach, WantsToFlee, MoveMode, Chasing,
             EquipmentChanged, Vendor, TownPortal, TeleportTo, ApplyMove, ApplyTeleport,
-            MagicItem
+            MagicItem, Morale, Surrendered, GoldPile, OwnedBy, Offshoot, Schedule, Personality,
+            Companion, Hireling, Tameable, Pursuer, Reputation, Durability, Humanoid, Locomotion,
+            Abilities, WantsToUseAbility, ChosenAbility, Held, Unique, TileSize,
+            TownGuard, Wanted, CrimeCommitted
         );
     }
 
@@ -125,6 +132,8 @@ pub fn load_game (ecs: &mut World) {
         for (e,h) in (&entities, &helper2).join() {
             let mut dungeonmaster = ecs.write_resource::<super::map::MasterDungeonMap>();
             *dungeonmaster = h.map.clone();
+            let mut clock = ecs.write_resource::<super::GameClock>();
+            *clock = h.clock.clone();
             deleteme2 = Some(e);
         };
         for (e,_p,pos) in (&entities, &player, &position).join() {
@@ -136,6 +145,10 @@ pub fn load_game (ecs: &mut World) {
     }
     ecs.delete_entity(deleteme.unwrap()).expect("Unable to delete helper");
     ecs.delete_entity(deleteme2.unwrap()).expect("Unable to delete helper");
+
+    /* Pick the run's gameplay stream back up from the turn it was saved on */
+    let rng = ecs.fetch::<super::map::MasterDungeonMap>().gameplay_rng(ecs.fetch::<super::GameClock>().turn);
+    ecs.insert(rng);
 }
 
 pub fn delete_save () {
@@ -1,17 +1,21 @@
 use std::sync::Mutex;
 use specs::prelude::*;
-use crate::{Map, tile_walkable, RunState};
+use crate::{Map, TileType, tile_walkable, tile_passable, Locomotion, RunState};
 
 struct SpatialMap {
     blocked : Vec<(bool, bool)>,
-    tile_content : Vec<Vec<(Entity, bool)>>
+    tiles : Vec<TileType>,
+    tile_content : Vec<Vec<(Entity, bool)>>,
+    revision : u64,
 }
 
 impl SpatialMap {
     fn new() -> Self {
         Self {
             blocked: Vec::new(),
-            tile_content: Vec::new()
+            tiles: Vec::new(),
+            tile_content: Vec::new(),
+            revision: 0,
         }
     }
 }
@@ -20,29 +24,45 @@ lazy_static! {
     static ref SPATIAL_MAP : Mutex<SpatialMap> = Mutex::new(SpatialMap::new());
 }
 
+/// True once something has panicked while holding the map; every lock after that fails
+pub fn is_poisoned () -> bool {
+    SPATIAL_MAP.is_poisoned()
+}
+
 pub fn set_size (map_tile_count: usize) {
     let mut lock = SPATIAL_MAP.lock().unwrap();
     lock.blocked = vec![(false, false); map_tile_count];
+    lock.tiles = vec![TileType::Wall; map_tile_count];
     lock.tile_content = vec![Vec::new(); map_tile_count];
+    lock.revision += 1;
 }
 
 pub fn clear () {
     let mut lock = SPATIAL_MAP.lock().unwrap();
+    if lock.blocked.iter().any(|b| b.0 || b.1) { lock.revision += 1; }
     lock.blocked.iter_mut().for_each(|b| { b.0 = false; b.1 = false; });
     for content in lock.tile_content.iter_mut() { content.clear(); };
 }
 
 pub fn populate_blocked_from_map (map: &Map) {
     let mut lock = SPATIAL_MAP.lock().unwrap();
+    let mut changed = false;
     for (i, tile) in map.tiles.iter().enumerate() {
-        lock.blocked[i].0 = !tile_walkable(*tile);
+        let blocked = !tile_walkable(*tile);
+        if lock.blocked[i].0 != blocked || lock.tiles[i] != *tile { changed = true; }
+        lock.blocked[i].0 = blocked;
+        lock.tiles[i] = *tile;
     };
+    if changed { lock.revision += 1; }
 }
 
 pub fn index_entity (entity: Entity, idx: usize, blocks_tile: bool) {
     let mut lock = SPATIAL_MAP.lock().unwrap();
     lock.tile_content[idx].push((entity, blocks_tile));
-    if blocks_tile { lock.blocked[idx].1 = true; }
+    if blocks_tile && !lock.blocked[idx].1 {
+        lock.blocked[idx].1 = true;
+        lock.revision += 1;
+    }
 }
 
 pub fn is_blocked (idx: usize) -> bool {
@@ -50,6 +70,24 @@ pub fn is_blocked (idx: usize) -> bool {
     lock.blocked[idx].0 || lock.blocked[idx].1
 }
 
+/// Like `is_blocked`, but for something that doesn't simply walk
+pub fn is_blocked_for (idx: usize, locomotion: &Locomotion) -> bool {
+    let lock = SPATIAL_MAP.lock().unwrap();
+    !tile_passable(lock.tiles[idx], locomotion) || lock.blocked[idx].1
+}
+
+/// Like `is_blocked_for`, but something doesn't get in its own way
+pub fn is_blocked_for_except (idx: usize, locomotion: &Locomotion, mover: Entity) -> bool {
+    let lock = SPATIAL_MAP.lock().unwrap();
+    !tile_passable(lock.tiles[idx], locomotion) || lock.tile_content[idx].iter().any(|(e, blocks)| *blocks && *e != mover)
+}
+
+/// Goes up whenever a tile's terrain or blocked state changes, so pathing caches know
+/// when to rebuild.
+pub fn blocked_revision () -> u64 {
+    SPATIAL_MAP.lock().unwrap().revision
+}
+
 pub fn for_each_tile_content <F>(idx: usize, mut f: F)
     where F: FnMut(Entity) {
         let lock = SPATIAL_MAP.lock().unwrap();
@@ -66,19 +104,27 @@ pub fn for_each_tile_content_with_gamemode <F>(idx: usize, mut f: F) -> RunState
 }
 
 pub fn move_entity (ent: Entity, moving_from: usize, moving_to: usize) {
+    move_footprint(ent, &[moving_from], &[moving_to]);
+}
+
+/// Moves something that may cover several tiles from one set of tiles to another
+pub fn move_footprint (ent: Entity, moving_from: &[usize], moving_to: &[usize]) {
     let mut lock = SPATIAL_MAP.lock().unwrap();
     let mut entity_blocks = false;
-    lock.tile_content[moving_from].retain(|(e, blocks)| {
-        if *e == ent { entity_blocks = *blocks; false } else { true }
-    });
-    lock.tile_content[moving_to].push((ent, entity_blocks));
+    for idx in moving_from.iter() {
+        lock.tile_content[*idx].retain(|(e, blocks)| {
+            if *e == ent { entity_blocks = *blocks; false } else { true }
+        });
+    };
+    for idx in moving_to.iter() {
+        lock.tile_content[*idx].push((ent, entity_blocks));
+    };
 
-    let mut from_blocked = false;
-    let mut to_blocked = false;
-    lock.tile_content[moving_from].iter().for_each(|(_,blocks)| if *blocks { from_blocked = true; });
-    lock.tile_content[moving_to].iter().for_each(|(_,blocks)| if *blocks { to_blocked = true; });
-    lock.blocked[moving_from].1 = from_blocked;
-    lock.blocked[moving_to].1 = to_blocked;
+    for idx in moving_from.iter().chain(moving_to.iter()) {
+        let blocked = lock.tile_content[*idx].iter().any(|(_,blocks)| *blocks);
+        if lock.blocked[*idx].1 != blocked { lock.revision += 1; }
+        lock.blocked[*idx].1 = blocked;
+    };
 }
 
 pub fn remove_entity (entity: Entity, idx: usize) {
@@ -86,5 +132,6 @@ pub fn remove_entity (entity: Entity, idx: usize) {
     lock.tile_content[idx].retain(|(e,_)| *e != entity);
     let mut from_blocked = false;
     lock.tile_content[idx].iter().for_each(|(_,blocks)| if *blocks { from_blocked = true; });
+    if lock.blocked[idx].1 != from_blocked { lock.revision += 1; }
     lock.blocked[idx].1 = from_blocked;
 }
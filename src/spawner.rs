@@ -1,20 +1,39 @@
 use rltk::{ RGB, RandomNumberGenerator };
 use specs::prelude::*;
 use specs::saveload::{MarkedBuilder, SimpleMarker};
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 use crate::{attr_bonus, player_hp_at_level, mana_at_level};
 use super::{Player, Renderable, Name, Position, Viewshed, Rect, SerializeMe,
     random_table::RandomTable, HungerClock, HungerState, TileType, Map, raws::*,
     Attributes, Attribute, Skills, Skill, Pool, Pools, LightSource, Initiative,
-    Faction, EquipmentChanged, MasterDungeonMap, OtherLevelPosition, TeleportTo,
-    SingleActivation, EntryTrigger
+    Faction, EquipmentChanged, MasterDungeonMap, LevelId, OtherLevelPosition, TeleportTo,
+    SingleActivation, EntryTrigger, MoveMode, Movement, Reputation, Item, GoldPile, OwnedBy
 };
 
 const MAX_MONSTERS : i32 = 4;
 
+/// Coins left on the floor by `owner`, for whoever wants to pick them up. Made lazily, so systems can
+/// drop gold mid-turn.
+pub fn spawn_gold_pile (lazy: &LazyUpdate, entities: &Entities, x: i32, y: i32, amount: f32, owner: Entity) {
+    lazy.create_entity(entities)
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('$'),
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2
+        })
+        .with(Name { name: format!("{:.0} gold", amount) })
+        .with(Item { initiative_penalty: 0.0, weight_lbs: 0.0, base_value: amount })
+        .with(GoldPile { amount })
+        .with(OwnedBy { owner })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+}
+
 pub fn spawn_town_portal (ecs: &mut World) {
     let map = ecs.fetch::<Map>();
-    let player_depth = map.depth;
+    let player_level = map.level_id();
     let player_pos = ecs.fetch::<rltk::Point>();
     let player_x = player_pos.x;
     let player_y = player_pos.y;
@@ -22,7 +41,7 @@ pub fn spawn_town_portal (ecs: &mut World) {
     std::mem::drop(map);
 
     let dm = ecs.fetch::<MasterDungeonMap>();
-    let town_map = dm.get_map(1).unwrap();
+    let town_map = dm.get_map(&LevelId::main(1)).unwrap();
     let mut stairs_idx = 0;
     for (idx, tt) in town_map.tiles.iter().enumerate() {
         if *tt == TileType::DownStairs { stairs_idx = idx; }
@@ -32,7 +51,7 @@ pub fn spawn_town_portal (ecs: &mut World) {
     std::mem::drop(dm);
 
     ecs.create_entity()
-        .with(OtherLevelPosition { x: portal_x, y: portal_y, depth: 1 })
+        .with(OtherLevelPosition { x: portal_x, y: portal_y, level: LevelId::main(1) })
         .with(Renderable {
             glyph: rltk::to_cp437('♥'),
             fg: RGB::named(rltk::CYAN),
@@ -40,7 +59,7 @@ pub fn spawn_town_portal (ecs: &mut World) {
             render_order: 0
         })
         .with(EntryTrigger{})
-        .with(TeleportTo { x: player_x, y: player_y, depth: player_depth, player_only: true })
+        .with(TeleportTo { x: player_x, y: player_y, level: player_level, player_only: true })
         .with(Name { name: "Town Portal".to_string() })
         .with(SingleActivation{})
         .build();
@@ -62,9 +81,19 @@ pub fn spawn_room (map: &Map, rng: &mut RandomNumberGenerator, room: &Rect, map_
     spawn_region(map, rng, &possible_targets, map_depth, spawn_list);
 }
 
-pub fn spawn_region (_map: &Map, rng: &mut RandomNumberGenerator, area: &[usize], map_depth:i32, spawn_list: &mut Vec<(usize, String)>) {
-    let spawn_table = room_table(map_depth);
-    let mut spawn_points : HashMap<usize, String> = HashMap::new();
+pub fn spawn_region (map: &Map, rng: &mut RandomNumberGenerator, area: &[usize], map_depth:i32, spawn_list: &mut Vec<(usize, String)>) {
+    let spawn_table = room_table(&map.branch, map_depth);
+    spawn_from_table(rng, &spawn_table, area, map_depth, spawn_list);
+}
+
+/// Like spawn_region, but rolling only on what the spawn table tags for the given biome
+pub fn spawn_biome_region (map: &Map, rng: &mut RandomNumberGenerator, area: &[usize], map_depth:i32, biome: &str, spawn_list: &mut Vec<(usize, String)>) {
+    let spawn_table = get_spawn_table_for_biome(&RAWS.lock().unwrap(), &map.branch, map_depth, biome);
+    spawn_from_table(rng, &spawn_table, area, map_depth, spawn_list);
+}
+
+fn spawn_from_table (rng: &mut RandomNumberGenerator, spawn_table: &RandomTable, area: &[usize], map_depth:i32, spawn_list: &mut Vec<(usize, String)>) {
+    let mut spawn_points : BTreeMap<usize, String> = BTreeMap::new();
     let mut areas : Vec<usize> = Vec::from(area);
     {
         let num_spawns = i32::min(areas.len() as i32, rng.roll_dice(1, MAX_MONSTERS+3) + (map_depth-1) - 3);
@@ -85,20 +114,46 @@ pub fn spawn_region (_map: &Map, rng: &mut RandomNumberGenerator, area: &[usize]
 }
 
 /// Spawns a named entity at passed in location
-pub fn spawn_entity (ecs: &mut World, spawn: &(&usize, &String)) {
+pub fn spawn_entity (ecs: &mut World, spawn: &(&usize, &String)) -> Option<Entity> {
     let map = ecs.fetch::<Map>();
     let width = map.width as usize;
     let x = (*spawn.0 % width) as i32;
     let y = (*spawn.0 / width) as i32;
     std::mem::drop(map);
 
-    let spawn_result = spawn_named_entity(&RAWS.lock().unwrap(), ecs, &spawn.1, SpawnType::AtPosition { x,y });
-    if spawn_result.is_some() { return; }
-    rltk::console::log(format!("Warning: We don't know how to spawn [{}]!", spawn.1));
+    let raws = RAWS.lock().unwrap();
+    let spawn_result = spawn_named_entity(&raws, ecs, spawn.1, SpawnType::AtPosition { x,y });
+    /* Uniques already met elsewhere quietly stay away */
+    if spawn_result.is_none() && !is_unique(&raws, spawn.1) {
+        rltk::console::log(format!("Warning: We don't know how to spawn [{}]!", spawn.1));
+    }
+    spawn_result
+}
+
+/// Mobs that follow a leader latch onto the nearest one spawned alongside them
+pub fn assign_leaders (ecs: &mut World, spawned: &[(usize, Entity)]) {
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Name>();
+    let mut move_modes = ecs.write_storage::<MoveMode>();
+    let raws = RAWS.lock().unwrap();
+    let point = |idx: usize| rltk::Point::new(idx as i32 % map.width, idx as i32 / map.width);
+
+    for (idx, entity) in spawned.iter() {
+        let name = match names.get(*entity) { Some(name) => name, None => continue };
+        if let Some((leader_name, distance)) = get_mob_leader(&raws, &name.name) {
+            let leader = spawned.iter()
+                .filter(|(_, other)| other != entity && names.get(*other).is_some_and(|n| n.name == leader_name))
+                .map(|(other_idx, other)| (rltk::DistanceAlg::Pythagoras.distance2d(point(*idx), point(*other_idx)), *other))
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            if let (Some((_, leader)), Some(mode)) = (leader, move_modes.get_mut(*entity)) {
+                mode.mode = Movement::Follow { leader, distance };
+            }
+        }
+    };
 }
 
-fn room_table (map_depth: i32) -> RandomTable {
-    get_spawn_table_for_depth(&RAWS.lock().unwrap(), map_depth)
+fn room_table (branch: &str, map_depth: i32) -> RandomTable {
+    get_spawn_table_for_depth(&RAWS.lock().unwrap(), branch, map_depth)
 }
 
 /// Spawns the player and returns his entity object
@@ -145,6 +200,7 @@ pub fn player (ecs : &mut World, player_x:i32, player_y:i32) -> Entity {
         .with(LightSource { color: rltk::RGB::from_f32(1.0, 1.0, 0.5), range: 8 })
         .with(Initiative { current: 0 })
         .with(Faction { name: "Player".to_string() })
+        .with(Reputation { standings: HashMap::new() })
         .with(EquipmentChanged{})
         .marked::<SimpleMarker<SerializeMe>>()
         .build();
@@ -0,0 +1,42 @@
+//! Fixtures shared by the unit tests
+
+use std::sync::{Mutex, MutexGuard, Once};
+use specs::prelude::*;
+use specs::saveload::SimpleMarkerAllocator;
+use crate::{Map, TileType, SerializeMe, gamelog::GameLog};
+
+/* The spatial index and the raws belong to the whole program, so tests that use them take turns */
+static GLOBALS: Mutex<()> = Mutex::new(());
+static LOAD_RAWS: Once = Once::new();
+
+/// Held by any test that touches the spatial index or the raws, with the raws loaded
+pub fn lock_globals () -> MutexGuard<'static, ()> {
+    LOAD_RAWS.call_once(crate::raws::load_raws);
+    GLOBALS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A map that's all floor inside a one-tile wall, indexed with nothing in it
+pub fn open_map (depth: i32, width: i32, height: i32) -> Map {
+    let mut map = Map::new(depth, width, height, "Test");
+    for y in 1 .. height - 1 {
+        for x in 1 .. width - 1 {
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = TileType::Floor;
+        };
+    };
+    crate::spatial::clear();
+    map.populate_blocked();
+    map
+}
+
+/// A world holding `map`, with every component registered, a seeded dice roller and an
+/// empty log
+pub fn world (map: Map) -> World {
+    let mut ecs = World::new();
+    crate::register_components(&mut ecs);
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    ecs.insert(map);
+    ecs.insert(rltk::RandomNumberGenerator::seeded(1));
+    ecs.insert(GameLog { entries: Vec::new() });
+    ecs
+}
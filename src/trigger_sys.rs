@@ -62,7 +62,7 @@ impl<'a> System<'a> for TriggerSystem {
                                     apply_teleport.insert(ent, ApplyTeleport {
                                         dest_x : teleport.x,
                                         dest_y : teleport.y,
-                                        dest_depth : teleport.depth
+                                        dest_level : teleport.level.clone()
                                     }).expect("Unable to insert");
                                 }
                             }
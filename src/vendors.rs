@@ -0,0 +1,270 @@
+use specs::prelude::*;
+use crate::{Vendor, Item, Durability, MagicItem, MagicItemClass, InBackpack, Pools, Name, Faction,
+    Reputation, GameClock, EquipmentChanged, gamelog::GameLog,
+    gameclock::{TURNS_PER_HOUR, HOURS_PER_DAY}, raws::{RAWS, SpawnType}};
+
+/* Deliveries come in once a day */
+const RESTOCK_TURNS: i32 = TURNS_PER_HOUR * HOURS_PER_DAY;
+const STOCK_PER_ITEM: usize = 2;
+const VENDOR_PURSE: f32 = 250.0;
+const BUY_MARKUP: f32 = 1.2;
+const SELL_RATE: f32 = 0.8;
+/* Each point of standing moves prices by 1%. Friends never get a deal good enough
+   to buy low and sell straight back; enemies can be gouged a lot harder. */
+const REPUTATION_PRICE_STEP: f32 = 0.01;
+const MAX_FRIENDLY_DISCOUNT: f32 = 0.15;
+const MAX_HOSTILE_MARKUP: f32 = 0.5;
+const TRADE_REPUTATION: i32 = 1;
+const MAX_TRADE_REPUTATION: i32 = 15;
+
+fn name_of (ecs: &World, ent: Entity) -> String {
+    ecs.read_storage::<Name>().get(ent).map_or("someone".to_string(), |n| n.name.clone())
+}
+
+/// Everything a vendor has for sale, which is everything in their pack
+pub fn stock (ecs: &World, vendor: Entity) -> Vec<Entity> {
+    (&ecs.entities(), &ecs.read_storage::<InBackpack>(), &ecs.read_storage::<Item>()).join()
+        .filter(|(_, carried, _)| carried.owner == vendor)
+        .map(|(ent, _, _)| ent)
+        .collect()
+}
+
+/// Tops up a vendor's shelves and purse, if their delivery is due. Anything the player
+/// sold them stays on the shelves for buyback.
+pub fn restock (ecs: &mut World, vendor: Entity) {
+    let turn = ecs.fetch::<GameClock>().turn;
+    let categories = match ecs.write_storage::<Vendor>().get_mut(vendor) {
+        Some(shop) if turn >= shop.restock_turn => {
+            shop.restock_turn = turn + RESTOCK_TURNS;
+            shop.categories.clone()
+        }
+        _ => return,
+    };
+    if let Some(pools) = ecs.write_storage::<Pools>().get_mut(vendor) {
+        pools.gold = f32::max(pools.gold, VENDOR_PURSE);
+    }
+
+    let names = ecs.read_storage::<Name>();
+    let on_shelf: Vec<String> = stock(ecs, vendor).iter()
+        .filter_map(|item| names.get(*item).map(|n| n.name.clone()))
+        .collect();
+    std::mem::drop(names);
+
+    let raws = RAWS.lock().unwrap();
+    for (name, _value) in crate::raws::get_vendor_items(&categories, &raws).iter() {
+        let have = on_shelf.iter().filter(|n| *n == name).count();
+        for _i in have .. STOCK_PER_ITEM {
+            crate::raws::spawn_named_item(&raws, ecs, name, SpawnType::Carried { by: vendor });
+        };
+    };
+    ecs.write_storage::<EquipmentChanged>().insert(vendor, EquipmentChanged {}).expect("Unable to insert");
+}
+
+/// What an item is really worth: worn gear is worth less, rarer magic a lot more
+pub fn item_value (ecs: &World, item: Entity) -> f32 {
+    let base = ecs.read_storage::<Item>().get(item).map_or(0.0, |i| i.base_value);
+    let condition = ecs.read_storage::<Durability>().get(item)
+        .map_or(1.0, |d| i32::max(0, d.current) as f32 / d.max as f32);
+    let rarity = match ecs.read_storage::<MagicItem>().get(item) {
+        None => 1.0,
+        Some(magic) => match magic.class {
+            MagicItemClass::Common => 1.0,
+            MagicItemClass::Rare => 2.0,
+            MagicItemClass::Legendary => 4.0,
+        }
+    };
+    base * condition * rarity
+}
+
+/// How much better (positive) or worse (negative) than list price the player is treated
+fn reputation_modifier (ecs: &World, vendor: Entity) -> f32 {
+    let player = *ecs.fetch::<Entity>();
+    let standing = match (ecs.read_storage::<Faction>().get(vendor), ecs.read_storage::<Reputation>().get(player)) {
+        (Some(faction), Some(reputation)) => *reputation.standings.get(&faction.name).unwrap_or(&0),
+        _ => 0,
+    };
+    (standing as f32 * REPUTATION_PRICE_STEP).clamp(-MAX_HOSTILE_MARKUP, MAX_FRIENDLY_DISCOUNT)
+}
+
+/// What the vendor asks for an item in their stock
+pub fn buy_price (ecs: &World, vendor: Entity, item: Entity) -> f32 {
+    item_value(ecs, item) * BUY_MARKUP * (1.0 - reputation_modifier(ecs, vendor))
+}
+
+/// What the vendor offers for one of the player's items
+pub fn sell_price (ecs: &World, vendor: Entity, item: Entity) -> f32 {
+    item_value(ecs, item) * SELL_RATE * (1.0 + reputation_modifier(ecs, vendor))
+}
+
+fn transfer (ecs: &mut World, item: Entity, to: Entity, from: Entity) {
+    ecs.write_storage::<InBackpack>().insert(item, InBackpack { owner: to }).expect("Unable to insert");
+    let mut dirty = ecs.write_storage::<EquipmentChanged>();
+    dirty.insert(to, EquipmentChanged {}).expect("Unable to insert");
+    dirty.insert(from, EquipmentChanged {}).expect("Unable to insert");
+}
+
+/// Regular customers get treated a little better, up to a point
+fn improve_standing (ecs: &mut World, vendor: Entity) {
+    let player = *ecs.fetch::<Entity>();
+    let faction = match ecs.read_storage::<Faction>().get(vendor) {
+        Some(faction) => faction.name.clone(),
+        None => return,
+    };
+    if let Some(reputation) = ecs.write_storage::<Reputation>().get_mut(player) {
+        let standing = reputation.standings.entry(faction).or_insert(0);
+        if *standing < MAX_TRADE_REPUTATION {
+            *standing = i32::min(MAX_TRADE_REPUTATION, *standing + TRADE_REPUTATION);
+        }
+    }
+}
+
+/// Moves gold between two purses, if the payer can cover it
+fn pay (ecs: &mut World, payer: Entity, payee: Entity, amount: f32) -> bool {
+    let mut pools = ecs.write_storage::<Pools>();
+    match pools.get_mut(payer) {
+        Some(purse) if purse.gold >= amount => purse.gold -= amount,
+        _ => return false,
+    }
+    if let Some(purse) = pools.get_mut(payee) {
+        purse.gold += amount;
+    }
+    true
+}
+
+/// The player buys an item from the vendor's stock
+pub fn buy (ecs: &mut World, vendor: Entity, item: Entity) {
+    let player = *ecs.fetch::<Entity>();
+    let price = buy_price(ecs, vendor, item);
+    let name = name_of(ecs, item);
+    if !pay(ecs, player, vendor, price) {
+        ecs.fetch_mut::<GameLog>().entries.push(format!("You can't afford the {}.", name));
+        return;
+    }
+    transfer(ecs, item, player, vendor);
+    improve_standing(ecs, vendor);
+    ecs.fetch_mut::<GameLog>().entries.push(format!("You buy the {} for {:.1} gold.", name, price));
+}
+
+/// The player sells an item to the vendor, who keeps it on the shelves
+pub fn sell (ecs: &mut World, vendor: Entity, item: Entity) {
+    let player = *ecs.fetch::<Entity>();
+    let price = sell_price(ecs, vendor, item);
+    let name = name_of(ecs, item);
+    if !pay(ecs, vendor, player, price) {
+        let vendor_name = name_of(ecs, vendor);
+        ecs.fetch_mut::<GameLog>().entries.push(format!("The {} can't afford your {}.", vendor_name, name));
+        return;
+    }
+    transfer(ecs, item, vendor, player);
+    improve_standing(ecs, vendor);
+    ecs.fetch_mut::<GameLog>().entries.push(format!("You sell the {} for {:.1} gold.", name, price));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::Pool;
+    use crate::test_support::{lock_globals, open_map, world};
+
+    fn purse (gold: f32) -> Pools {
+        Pools {
+            hit_points: Pool { max: 10, current: 10 }, mana: Pool { max: 0, current: 0 },
+            xp: 0, level: 1, total_weight: 0.0, total_initiative_penalty: 0.0, gold, god_mode: false,
+        }
+    }
+
+    /// A player and a townsfolk vendor, each with a purse
+    fn shop (player_gold: f32, standing: i32) -> (World, Entity, Entity) {
+        let mut ecs = world(open_map(1, 10, 10));
+        let mut standings = HashMap::new();
+        standings.insert("Townsfolk".to_string(), standing);
+        let player = ecs.create_entity().with(purse(player_gold)).with(Reputation { standings }).build();
+        let vendor = ecs.create_entity().with(purse(VENDOR_PURSE))
+            .with(Faction { name: "Townsfolk".to_string() }).build();
+        ecs.insert(player);
+        (ecs, player, vendor)
+    }
+
+    fn item (ecs: &mut World, value: f32, owner: Entity) -> Entity {
+        ecs.create_entity()
+            .with(Item { initiative_penalty: 0.0, weight_lbs: 1.0, base_value: value })
+            .with(Name { name: "Dagger".to_string() })
+            .with(InBackpack { owner })
+            .build()
+    }
+
+    #[test]
+    fn vendors_buy_low_and_sell_high () {
+        let _globals = lock_globals();
+        let (mut ecs, _player, vendor) = shop(0.0, 0);
+        let dagger = item(&mut ecs, 10.0, vendor);
+        assert_eq!(buy_price(&ecs, vendor, dagger), 10.0 * BUY_MARKUP);
+        assert_eq!(sell_price(&ecs, vendor, dagger), 10.0 * SELL_RATE);
+    }
+
+    #[test]
+    fn wear_and_rarity_change_the_value () {
+        let _globals = lock_globals();
+        let (mut ecs, _player, vendor) = shop(0.0, 0);
+        let dagger = item(&mut ecs, 10.0, vendor);
+        ecs.write_storage::<Durability>().insert(dagger, Durability { current: 5, max: 10 }).unwrap();
+        assert_eq!(item_value(&ecs, dagger), 5.0);
+        ecs.write_storage::<MagicItem>().insert(dagger, MagicItem { class: MagicItemClass::Rare, naming: String::new() }).unwrap();
+        assert_eq!(item_value(&ecs, dagger), 10.0);
+        ecs.write_storage::<Durability>().get_mut(dagger).unwrap().current = -3;
+        assert_eq!(item_value(&ecs, dagger), 0.0);
+    }
+
+    #[test]
+    fn standing_moves_prices_within_limits () {
+        let _globals = lock_globals();
+        let (mut ecs, _player, vendor) = shop(0.0, 10);
+        let dagger = item(&mut ecs, 100.0, vendor);
+        assert!((buy_price(&ecs, vendor, dagger) - 100.0 * BUY_MARKUP * 0.9).abs() < 0.01);
+        assert!((sell_price(&ecs, vendor, dagger) - 100.0 * SELL_RATE * 1.1).abs() < 0.01);
+
+        let (mut ecs, _player, vendor) = shop(0.0, -100);
+        let dagger = item(&mut ecs, 100.0, vendor);
+        assert!((buy_price(&ecs, vendor, dagger) - 100.0 * BUY_MARKUP * (1.0 + MAX_HOSTILE_MARKUP)).abs() < 0.01);
+    }
+
+    #[test]
+    fn selling_and_buying_back_never_turns_a_profit () {
+        /* Even the best of friends */
+        let _globals = lock_globals();
+        let (mut ecs, player, vendor) = shop(50.0, 100);
+        let dagger = item(&mut ecs, 100.0, player);
+        sell(&mut ecs, vendor, dagger);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(dagger).unwrap().owner, vendor);
+        assert!(ecs.read_storage::<Pools>().get(player).unwrap().gold > 50.0);
+        assert!(stock(&ecs, vendor).contains(&dagger));
+
+        buy(&mut ecs, vendor, dagger);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(dagger).unwrap().owner, player);
+        assert!(ecs.read_storage::<Pools>().get(player).unwrap().gold < 50.0);
+        assert!(ecs.read_storage::<Pools>().get(vendor).unwrap().gold > VENDOR_PURSE);
+    }
+
+    #[test]
+    fn no_sale_without_the_gold () {
+        let _globals = lock_globals();
+        let (mut ecs, player, vendor) = shop(5.0, 0);
+        let dagger = item(&mut ecs, 100.0, vendor);
+        buy(&mut ecs, vendor, dagger);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(dagger).unwrap().owner, vendor);
+        assert_eq!(ecs.read_storage::<Pools>().get(player).unwrap().gold, 5.0);
+        assert_eq!(ecs.read_storage::<Reputation>().get(player).unwrap().standings["Townsfolk"], 0);
+    }
+
+    #[test]
+    fn trading_improves_standing_up_to_a_cap () {
+        let _globals = lock_globals();
+        let (mut ecs, player, vendor) = shop(1000.0, MAX_TRADE_REPUTATION - 1);
+        for _i in 0 .. 3 {
+            let dagger = item(&mut ecs, 1.0, vendor);
+            buy(&mut ecs, vendor, dagger);
+        };
+        assert_eq!(ecs.read_storage::<Reputation>().get(player).unwrap().standings["Townsfolk"], MAX_TRADE_REPUTATION);
+    }
+}
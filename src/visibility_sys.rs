@@ -1,10 +1,11 @@
 use specs::prelude::*;
-use super::{Viewshed, Position, Map, Player, Name, Hidden, gamelog::GameLog, BlocksVisibility};
+use super::{Viewshed, Position, Map, Player, Name, Hidden, gamelog::GameLog, BlocksVisibility, Unique};
 use rltk::{field_of_view, Point};
 
 pub struct VisibilitySystem { }
 
 impl<'a> System<'a> for VisibilitySystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         WriteExpect<'a, Map>,
         Entities<'a>,
@@ -16,11 +17,12 @@ impl<'a> System<'a> for VisibilitySystem {
         WriteExpect<'a, GameLog>,
         ReadStorage<'a, Name>,
         ReadStorage<'a, BlocksVisibility>,
+        WriteStorage<'a, Unique>,
         );
 
     fn run (&mut self, data : Self::SystemData) {
         let (mut map, entities, mut viewshed, pos, player, mut hidden, mut rng,
-            mut log, names, blocks_visibility) = data;
+            mut log, names, blocks_visibility, mut uniques) = data;
 
         map.view_blocked.clear();
         for (block_pos, _block) in (&pos, &blocks_visibility).join() {
@@ -60,5 +62,16 @@ impl<'a> System<'a> for VisibilitySystem {
                 }
             }
         };
+
+        /* Uniques make an entrance the first time the player lays eyes on them */
+        let raws = crate::raws::RAWS.lock().unwrap();
+        for (unique, unique_pos, name) in (&mut uniques, &pos, &names).join() {
+            if !unique.announced && map.visible_tiles[map.xy_idx(unique_pos.x, unique_pos.y)] {
+                unique.announced = true;
+                if let Some(announcement) = crate::raws::get_unique_announcement(&raws, &name.name) {
+                    log.entries.push(announcement);
+                }
+            }
+        };
     }
 }